@@ -0,0 +1,494 @@
+use crate::error::WriteError;
+use crate::options::WriterOptions;
+use crate::writer::{self, ColumnValues, PhysicalColumn};
+use crate::{CompiledSchema, ParquetField, ParquetRepetition};
+use arrow_array::{
+    make_array, Array, ArrayRef, BinaryArray, BooleanArray, Decimal128Array, FixedSizeBinaryArray,
+    Float32Array, Float64Array, Int32Array, Int64Array, ListArray, MapArray, RecordBatch,
+    StringArray, StructArray,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer};
+use arrow_schema::{ArrowError, DataType};
+use parquet::arrow::{parquet_to_arrow_schema, ArrowWriter};
+use parquet::schema::types::SchemaDescriptor;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Write records through arrow-rs instead of the column writers, building each row group into a
+/// `RecordBatch` of the schema's Arrow types and writing it with `ArrowWriter`. Values are
+/// converted as they are for the column writers, so the `coercion` and `strict` options apply, but
+/// `ArrowWriter` picks its own physical types, e.g. INT96 fields are written as INT64 timestamps.
+/// `ArrowWriter` writes its own pages, so files can't be encrypted
+pub(crate) fn write_arrow(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+) -> Result<Vec<u8>, WriteError> {
+    if options.encryption.is_some() {
+        return Err(WriteError::from(
+            "Encryption is not supported by the arrow writer".to_string(),
+        ));
+    }
+    let prepared = crate::prepare_records(schema, records, options, &mut HashSet::new())?;
+    let records = &prepared.records[..];
+    let schema = crate::resolve_schema(schema, records, options)?;
+
+    let descriptor = SchemaDescriptor::new(schema.message_type.clone());
+    let arrow_schema = match parquet_to_arrow_schema(&descriptor, None) {
+        Ok(s) => Arc::new(s),
+        Err(_) => {
+            return Err(WriteError::from(
+                "Error converting schema to Arrow".to_string(),
+            ))
+        }
+    };
+    let properties = options.writer_properties(&schema.message_type)?;
+    let output = writer::output_buffer(&schema.schema.fields, records.len(), options);
+    let mut arrow_writer =
+        match ArrowWriter::try_new(output, arrow_schema.clone(), Some(properties)) {
+            Ok(w) => w,
+            Err(_) => return Err(WriteError::from("Error creating writer".to_string())),
+        };
+
+    let fields = &schema.schema.fields;
+    for row_group in writer::row_groups(records.len(), options)? {
+        let offset = row_group.start;
+        let values = writer::column_values(fields, &records[row_group], options)
+            .map_err(|e| WriteError::from(e).offset_row(offset))?;
+        let columns = fields
+            .iter()
+            .zip(values.iter())
+            .zip(arrow_schema.fields().iter())
+            .map(|((field, values), arrow_field)| {
+                field_array(field, values, arrow_field.data_type(), options)
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.offset_row(offset))?;
+        let batch = match RecordBatch::try_new(arrow_schema.clone(), columns) {
+            Ok(batch) => batch,
+            Err(e) => {
+                return Err(WriteError::from(format!(
+                    "Error converting records to Arrow: {}",
+                    e
+                )))
+            }
+        };
+        // The batch is written in slices that are expected to fill the row group in progress up to
+        // `max_row_group_bytes`, going by the bytes per row it has taken so far
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let remaining = batch.num_rows() - offset;
+            let rows = match options.max_row_group_bytes {
+                Some(max_bytes) if arrow_writer.in_progress_rows() > 0 => {
+                    let room = max_bytes.saturating_sub(arrow_writer.in_progress_size());
+                    let bytes_per_row =
+                        arrow_writer.in_progress_size() / arrow_writer.in_progress_rows();
+                    (room / bytes_per_row.max(1)).clamp(1, remaining)
+                }
+                Some(_) => 1,
+                None => remaining,
+            };
+            if arrow_writer.write(&batch.slice(offset, rows)).is_err() {
+                return Err(WriteError::from("Error writing record batch".to_string()));
+            }
+            offset += rows;
+            let filled = options
+                .max_row_group_bytes
+                .is_some_and(|max_bytes| arrow_writer.in_progress_size() >= max_bytes);
+            if (filled || offset == batch.num_rows()) && arrow_writer.flush().is_err() {
+                return Err(WriteError::from("Error closing row group".to_string()));
+            }
+        }
+    }
+
+    match arrow_writer.into_inner() {
+        Ok(bytes_buffer) => Ok(bytes_buffer),
+        Err(_) => Err(WriteError::from("Error closing writer".to_string())),
+    }
+}
+
+/// The Arrow array of `field`'s values from every record in a row group, converted to its
+/// physical type as it would be for its column and then to `data_type`
+fn field_array(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    data_type: &DataType,
+    options: &WriterOptions,
+) -> Result<ArrayRef, WriteError> {
+    let column = writer::convert_column(field, values, options)?;
+    let (def_levels, rep_levels) = levels(&column);
+    let (def_levels, rep_levels) = match (def_levels, rep_levels) {
+        (Some(def_levels), Some(rep_levels)) => (def_levels, rep_levels),
+        (def_levels, _) => {
+            // Values of a field that isn't nested, present where they're defined
+            let slots = match def_levels {
+                Some(def_levels) => def_levels.iter().map(|&level| level == 1).collect(),
+                None => vec![true; values.len()],
+            };
+            return leaf_array(field, column, &slots, data_type);
+        }
+    };
+
+    // REPEATED fields are never null and have no null elements, LIST and MAP fields follow the
+    // levels of `writer::collect_list_values`
+    let (list_level, element_level, present_level) =
+        if field.repetition_type == Some(ParquetRepetition::Repeated) {
+            (0, 1, 1)
+        } else {
+            let empty_level = i16::from(writer::is_optional(field));
+            (empty_level, empty_level + 1, empty_level + 2)
+        };
+    let mut lengths = vec![];
+    let mut valid = vec![];
+    let mut slots = vec![];
+    for (&def_level, &rep_level) in def_levels.iter().zip(rep_levels) {
+        if rep_level == 0 {
+            lengths.push(0);
+            valid.push(def_level >= list_level);
+        }
+        if def_level >= element_level {
+            if let Some(length) = lengths.last_mut() {
+                *length += 1;
+            }
+            slots.push(def_level >= present_level);
+        }
+    }
+    let offsets = OffsetBuffer::from_lengths(lengths);
+    let nulls = Some(NullBuffer::from(valid)).filter(|nulls| nulls.null_count() > 0);
+
+    let converted = match data_type {
+        DataType::List(element) => ListArray::try_new(
+            element.clone(),
+            offsets,
+            leaf_array(field, column, &slots, element.data_type())?,
+            nulls,
+        )
+        .map(|array| Arc::new(array) as ArrayRef),
+        DataType::Map(entries, sorted) => {
+            let DataType::Struct(entry_fields) = entries.data_type() else {
+                return Err(unsupported(field, data_type));
+            };
+            let keys = writer::map_keys(field, values, options)?;
+            let keys = leaf_array(
+                field,
+                PhysicalColumn::ByteArray(keys),
+                &vec![true; slots.len()],
+                entry_fields[0].data_type(),
+            )?;
+            let values = leaf_array(field, column, &slots, entry_fields[1].data_type())?;
+            StructArray::try_new(entry_fields.clone(), vec![keys, values], None)
+                .and_then(|entries_array| {
+                    MapArray::try_new(entries.clone(), offsets, entries_array, nulls, *sorted)
+                })
+                .map(|array| Arc::new(array) as ArrayRef)
+        }
+        _ => return Err(unsupported(field, data_type)),
+    };
+    converted.map_err(|e| arrow_error(field, e))
+}
+
+/// The definition and repetition levels of a column
+fn levels(column: &PhysicalColumn) -> (Option<&Vec<i16>>, Option<&Vec<i16>>) {
+    fn of<T>(column: &ColumnValues<T>) -> (Option<&Vec<i16>>, Option<&Vec<i16>>) {
+        (column.def_levels.as_ref(), column.rep_levels.as_ref())
+    }
+    match column {
+        PhysicalColumn::Boolean(column) => of(column),
+        PhysicalColumn::Int32(column) => of(column),
+        PhysicalColumn::Int64(column) => of(column),
+        PhysicalColumn::Int96(column) => of(column),
+        PhysicalColumn::Float(column) => of(column),
+        PhysicalColumn::Double(column) => of(column),
+        PhysicalColumn::ByteArray(column) => of(column),
+        PhysicalColumn::FixedLenByteArray(column) => of(column),
+    }
+}
+
+/// An Arrow array of a column's values, with a slot for each element of `slots` that holds the
+/// next value when it's `true` and a null otherwise
+fn leaf_array(
+    field: &ParquetField,
+    column: PhysicalColumn,
+    slots: &[bool],
+    data_type: &DataType,
+) -> Result<ArrayRef, WriteError> {
+    let array: ArrayRef = match (column, data_type) {
+        (PhysicalColumn::Boolean(column), DataType::Boolean) => {
+            Arc::new(BooleanArray::from(spread(column.values, slots)))
+        }
+        (PhysicalColumn::Int32(column), DataType::Decimal128(..)) => {
+            let values = column.values.into_iter().map(i128::from).collect();
+            decimal_array(field, spread(values, slots), data_type)?
+        }
+        (PhysicalColumn::Int32(column), _) => {
+            let array = Arc::new(Int32Array::from(spread(column.values, slots)));
+            cast_integers(array, data_type).map_err(|e| arrow_error(field, e))?
+        }
+        (PhysicalColumn::Int64(column), DataType::Decimal128(..)) => {
+            let values = column.values.into_iter().map(i128::from).collect();
+            decimal_array(field, spread(values, slots), data_type)?
+        }
+        (PhysicalColumn::Int64(column), _) => {
+            let array = Arc::new(Int64Array::from(spread(column.values, slots)));
+            cast_integers(array, data_type).map_err(|e| arrow_error(field, e))?
+        }
+        (PhysicalColumn::Int96(column), DataType::Timestamp(..)) => {
+            let nanos = column.values.iter().map(|v| v.to_nanos()).collect();
+            let array = Arc::new(Int64Array::from(spread(nanos, slots)));
+            cast_integers(array, data_type).map_err(|e| arrow_error(field, e))?
+        }
+        (PhysicalColumn::Float(column), DataType::Float32) => {
+            Arc::new(Float32Array::from(spread(column.values, slots)))
+        }
+        (PhysicalColumn::Double(column), DataType::Float64) => {
+            Arc::new(Float64Array::from(spread(column.values, slots)))
+        }
+        (PhysicalColumn::ByteArray(column), DataType::Utf8) => {
+            let mut strings = Vec::with_capacity(slots.len());
+            for value in spread(column.values, slots) {
+                let string = match value {
+                    Some(bytes) => match String::from_utf8(bytes.data().to_vec()) {
+                        Ok(s) => Some(s),
+                        Err(_) => {
+                            return Err(WriteError::from(format!(
+                                "Invalid UTF-8 in field {}, which Arrow can't hold as a string",
+                                field.name
+                            )))
+                        }
+                    },
+                    None => None,
+                };
+                strings.push(string);
+            }
+            Arc::new(StringArray::from(strings))
+        }
+        (PhysicalColumn::ByteArray(column), DataType::Binary) => {
+            let values = spread(column.values, slots);
+            Arc::new(BinaryArray::from_iter(
+                values.iter().map(|v| v.as_ref().map(|b| b.data())),
+            ))
+        }
+        (PhysicalColumn::ByteArray(column), DataType::Decimal128(..)) => {
+            let values = column.values.iter().map(|b| decimal(b.data())).collect();
+            decimal_array(field, spread(values, slots), data_type)?
+        }
+        (PhysicalColumn::FixedLenByteArray(column), DataType::FixedSizeBinary(size)) => {
+            let values = spread(column.values, slots);
+            let array = FixedSizeBinaryArray::try_from_sparse_iter_with_size(
+                values.iter().map(|v| v.as_ref().map(|b| b.data())),
+                *size,
+            );
+            Arc::new(array.map_err(|e| arrow_error(field, e))?)
+        }
+        (PhysicalColumn::FixedLenByteArray(column), DataType::Decimal128(..)) => {
+            let values = column.values.iter().map(|b| decimal(b.data())).collect();
+            decimal_array(field, spread(values, slots), data_type)?
+        }
+        _ => return Err(unsupported(field, data_type)),
+    };
+    Ok(array)
+}
+
+/// Place each value in the next slot that holds one, leaving the others null
+fn spread<T>(values: Vec<T>, slots: &[bool]) -> Vec<Option<T>> {
+    let mut values = values.into_iter();
+    slots
+        .iter()
+        .map(|&slot| if slot { values.next() } else { None })
+        .collect()
+}
+
+/// Convert an array of INT32 or INT64 values to an Arrow type of the same width by reinterpreting
+/// them, so UINT32 and UINT64 values above the signed maximum keep their bits, or cast them to a
+/// narrower integer type their values were already clamped to
+fn cast_integers(array: ArrayRef, data_type: &DataType) -> Result<ArrayRef, ArrowError> {
+    if array.data_type().primitive_width() == data_type.primitive_width() {
+        let data = array.to_data().into_builder().data_type(data_type.clone());
+        Ok(make_array(data.build()?))
+    } else {
+        arrow_cast::cast(&array, data_type)
+    }
+}
+
+fn decimal_array(
+    field: &ParquetField,
+    values: Vec<Option<i128>>,
+    data_type: &DataType,
+) -> Result<ArrayRef, WriteError> {
+    let DataType::Decimal128(precision, scale) = data_type else {
+        return Err(unsupported(field, data_type));
+    };
+    match Decimal128Array::from(values).with_precision_and_scale(*precision, *scale) {
+        Ok(array) => Ok(Arc::new(array)),
+        Err(e) => Err(arrow_error(field, e)),
+    }
+}
+
+/// The unscaled value of a decimal from its big-endian two's complement bytes, at most 16 of
+/// which are written for the 38 digits a decimal can have
+fn decimal(bytes: &[u8]) -> i128 {
+    let sign = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+        0xff
+    } else {
+        0
+    };
+    let mut unscaled = [sign; 16];
+    let bytes = &bytes[bytes.len().saturating_sub(16)..];
+    unscaled[16 - bytes.len()..].copy_from_slice(bytes);
+    i128::from_be_bytes(unscaled)
+}
+
+fn unsupported(field: &ParquetField, data_type: &DataType) -> WriteError {
+    WriteError::from(format!(
+        "Field {} can't be written as Arrow type {} by the arrow writer",
+        field.name, data_type
+    ))
+}
+
+fn arrow_error(field: &ParquetField, error: ArrowError) -> WriteError {
+    WriteError::from(format!(
+        "Error converting field {} to Arrow: {}",
+        field.name, error
+    ))
+}
+
+#[test]
+fn test_write_arrow() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = CompiledSchema::parse(
+        r#"
+        {
+            "fields": [
+                {
+                    "name": "id",
+                    "type": "INT64",
+                    "source_key": "userId"
+                },
+                {
+                    "name": "name",
+                    "type": "BYTE_ARRAY",
+                    "logical_type": "UTF8",
+                    "repetition_type": "OPTIONAL"
+                },
+                {
+                    "name": "tags",
+                    "type": "BYTE_ARRAY",
+                    "logical_type": "LIST",
+                    "element_logical_type": "UTF8",
+                    "repetition_type": "OPTIONAL"
+                }
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+    let files = vec![
+        r#"{"userId": 1, "name": "a", "tags": ["x", null]}"#.to_string(),
+        r#"{"userId": 2, "tags": []}"#.to_string(),
+        r#"{"userId": 3, "name": "c"}"#.to_string(),
+    ];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = write_arrow(&schema, &records, &options).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert_eq!(reader.metadata().num_row_groups(), 2);
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"id": 1, "name": "a", "tags": ["x", null]}),
+            serde_json::json!({"id": 2, "name": null, "tags": []}),
+            serde_json::json!({"id": 3, "name": "c", "tags": null}),
+        ]
+    );
+
+    let missing = vec![r#"{"name": "d"}"#.to_string()];
+    let records = writer::parse_records(&missing, Default::default()).unwrap();
+    assert!(write_arrow(&schema, &records, &options).is_err());
+}
+
+#[test]
+fn test_write_arrow_types() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = CompiledSchema::parse(
+        r#"
+        {
+            "fields": [
+                {"name": "raw", "type": "BYTE_ARRAY", "encoding": "base64", "repetition_type": "OPTIONAL"},
+                {"name": "hash", "type": "FIXED_LEN_BYTE_ARRAY", "encoding": "hex"},
+                {"name": "price", "type": "INT64", "logical_type": "DECIMAL", "precision": 10, "scale": 2},
+                {"name": "total", "type": "FIXED_LEN_BYTE_ARRAY", "logical_type": "DECIMAL", "precision": 20, "scale": 4},
+                {"name": "balance", "type": "BYTE_ARRAY", "logical_type": "DECIMAL", "precision": 30, "scale": 2, "repetition_type": "OPTIONAL"},
+                {"name": "created", "type": "INT96"},
+                {"name": "count", "type": "INT32", "logical_type": "UINT32"},
+                {"name": "scores", "type": "INT32", "repetition_type": "REPEATED"},
+                {"name": "attrs", "type": "BYTE_ARRAY", "logical_type": "MAP", "element_logical_type": "UTF8", "repetition_type": "OPTIONAL"}
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+    let hash = "ab".repeat(writer::FIXED_LEN_BYTE_ARRAY_LENGTH as usize);
+    let files = vec![
+        r#"{"raw": "AP8=", "price": "123.45", "total": "-9999999999999999.9999", "balance": "-1.5", "created": "2024-01-02T03:04:05Z", "count": 4294967295, "scores": [1, 2], "attrs": {"a": "x", "b": null}}"#,
+        r#"{"price": 7, "total": "0.5", "created": 0, "count": "1", "attrs": {}}"#,
+    ]
+    .into_iter()
+    .map(|file| file.replacen('{', &format!(r#"{{"hash": "{}", "#, hash), 1))
+    .collect::<Vec<_>>();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options =
+        crate::options::parse_options(r#"{"coercion": {"string_to_number": true}}"#).unwrap();
+    let bytes = write_arrow(&schema, &records, &options).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    let hash = "q6ur".repeat(writer::FIXED_LEN_BYTE_ARRAY_LENGTH as usize / 3) + "qw==";
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({
+                "raw": "AP8=",
+                "hash": hash,
+                "price": "123.45",
+                "total": "-9999999999999999.9999",
+                "balance": "-1.50",
+                "created": 1704164645000000000i64,
+                "count": 4294967295u32,
+                "scores": [1, 2],
+                "attrs": {"a": "x", "b": null}
+            }),
+            serde_json::json!({
+                "raw": null,
+                "hash": hash,
+                "price": "7.00",
+                "total": "0.5000",
+                "balance": null,
+                "created": 0,
+                "count": 1,
+                "scores": [],
+                "attrs": {}
+            }),
+        ]
+    );
+
+    // Values are converted with the writer options, as they are for the column writers
+    assert_eq!(
+        write_arrow(&schema, &records, &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 1: missing or invalid value for required field count"
+    );
+}
@@ -0,0 +1,252 @@
+use crate::error::WriteError;
+use crate::options::WriterOptions;
+use crate::writer::Record;
+use crate::{
+    write_records, BinaryEncoding, CompiledSchema, ParquetField, ParquetLogicalType,
+    ParquetPrimitiveType, ParquetRepetition, ParquetSchema,
+};
+use apache_avro::types::Value as AvroValue;
+use apache_avro::{Reader, Schema as AvroSchema};
+use base64::Engine;
+use serde_json::{Map, Number, Value};
+
+/// Write an Avro object container file as parquet, with a schema derived from the Avro schema
+/// embedded in the file
+pub(crate) fn write_avro(avro: &[u8]) -> Result<Vec<u8>, WriteError> {
+    let reader = match Reader::new(avro) {
+        Ok(r) => r,
+        Err(_) => return Err(WriteError::from("Error reading Avro file".to_string())),
+    };
+
+    let schema = CompiledSchema::build(avro_to_parquet_schema(reader.writer_schema())?)?;
+
+    let mut records: Vec<Record> = vec![];
+    for (row, datum) in reader.enumerate() {
+        let datum = match datum {
+            Ok(d) => d,
+            Err(_) => {
+                return Err(WriteError::from(format!(
+                    "Row {}: error reading datum",
+                    row
+                )))
+            }
+        };
+        match avro_to_json(datum) {
+            Ok(Value::Object(record)) => records.push(record),
+            Ok(_) => {
+                return Err(WriteError::from(format!(
+                    "Row {}: datum is not a record",
+                    row
+                )))
+            }
+            Err(e) => return Err(WriteError::from(format!("Row {}: {}", row, e))),
+        }
+    }
+
+    write_records(&schema, &records, &WriterOptions::default())
+}
+
+/// Map the top level Avro record to parquet fields. Nested records are flattened into columns
+/// named `parent_child` that read from the `parent.child` key path
+pub(crate) fn avro_to_parquet_schema(schema: &AvroSchema) -> Result<ParquetSchema, String> {
+    let record = match schema {
+        AvroSchema::Record(record) => record,
+        _ => return Err("Avro schema must be a record".to_string()),
+    };
+    let mut fields = vec![];
+    for field in record.fields.iter() {
+        map_field(
+            &mut fields,
+            &field.name,
+            &field.name,
+            field.aliases.clone(),
+            &field.schema,
+            ParquetRepetition::Required,
+        )?;
+    }
+    Ok(ParquetSchema { fields })
+}
+
+fn map_field(
+    fields: &mut Vec<ParquetField>,
+    name: &str,
+    source_key: &str,
+    aliases: Option<Vec<String>>,
+    schema: &AvroSchema,
+    repetition: ParquetRepetition,
+) -> Result<(), String> {
+    use ParquetLogicalType as L;
+    use ParquetPrimitiveType as P;
+
+    let (primitive_type, logical_type, allowed_values) = match schema {
+        AvroSchema::Union(union) => {
+            let non_null = union
+                .variants()
+                .iter()
+                .filter(|v| **v != AvroSchema::Null)
+                .collect::<Vec<_>>();
+            return match non_null.as_slice() {
+                [variant] if union.is_nullable() => map_field(
+                    fields,
+                    name,
+                    source_key,
+                    aliases,
+                    variant,
+                    ParquetRepetition::Optional,
+                ),
+                _ => Err(format!("Unsupported Avro union for field {}", name)),
+            };
+        }
+        AvroSchema::Record(record) => {
+            for child in record.fields.iter() {
+                map_field(
+                    fields,
+                    &format!("{}_{}", name, child.name),
+                    &format!("{}.{}", source_key, child.name),
+                    None,
+                    &child.schema,
+                    repetition,
+                )?;
+            }
+            return Ok(());
+        }
+        AvroSchema::Array(items) if repetition != ParquetRepetition::Repeated => {
+            return map_field(
+                fields,
+                name,
+                source_key,
+                aliases,
+                items,
+                ParquetRepetition::Repeated,
+            );
+        }
+        AvroSchema::Boolean => (P::Boolean, None, None),
+        AvroSchema::Int => (P::Int32, None, None),
+        AvroSchema::Long => (P::Int64, None, None),
+        AvroSchema::Float | AvroSchema::Double => (P::Double, None, None),
+        AvroSchema::Bytes | AvroSchema::Fixed(_) => (P::ByteArray, None, None),
+        AvroSchema::String | AvroSchema::Uuid => (P::ByteArray, Some(L::Utf8), None),
+        AvroSchema::Enum(e) => (P::ByteArray, Some(L::Enum), Some(e.symbols.clone())),
+        AvroSchema::Date => (P::Int32, Some(L::Date), None),
+        AvroSchema::TimeMillis => (P::Int32, Some(L::TimeMillis), None),
+        AvroSchema::TimeMicros => (P::Int64, Some(L::TimeMicros), None),
+        AvroSchema::TimestampMillis | AvroSchema::LocalTimestampMillis => {
+            (P::Int64, Some(L::TimestampMillis), None)
+        }
+        AvroSchema::TimestampMicros | AvroSchema::LocalTimestampMicros => {
+            (P::Int64, Some(L::TimestampMicros), None)
+        }
+        _ => return Err(format!("Unsupported Avro type for field {}", name)),
+    };
+
+    let mut field = ParquetField::new(name.to_string(), primitive_type);
+    field.logical_type = logical_type;
+    field.repetition_type = Some(repetition);
+    field.source_key = (source_key != name).then(|| source_key.to_string());
+    field.aliases = aliases;
+    field.allowed_values = allowed_values;
+    if matches!(schema, AvroSchema::Bytes | AvroSchema::Fixed(_)) {
+        field.encoding = Some(BinaryEncoding::Base64);
+    }
+    fields.push(field);
+    Ok(())
+}
+
+/// Convert an Avro datum to the JSON value the writer expects for the mapped field, bytes are
+/// base64 encoded to match the field's encoding
+fn avro_to_json(value: AvroValue) -> Result<Value, String> {
+    Ok(match value {
+        AvroValue::Null => Value::Null,
+        AvroValue::Boolean(b) => Value::Bool(b),
+        AvroValue::Int(i) | AvroValue::Date(i) | AvroValue::TimeMillis(i) => Value::from(i),
+        AvroValue::Long(i)
+        | AvroValue::TimeMicros(i)
+        | AvroValue::TimestampMillis(i)
+        | AvroValue::TimestampMicros(i)
+        | AvroValue::LocalTimestampMillis(i)
+        | AvroValue::LocalTimestampMicros(i) => Value::from(i),
+        AvroValue::Float(f) => Number::from_f64(f as f64).map_or(Value::Null, Value::Number),
+        AvroValue::Double(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        AvroValue::Bytes(bytes) | AvroValue::Fixed(_, bytes) => {
+            Value::String(base64::engine::general_purpose::STANDARD.encode(bytes))
+        }
+        AvroValue::String(s) | AvroValue::Enum(_, s) => Value::String(s),
+        AvroValue::Uuid(uuid) => Value::String(uuid.to_string()),
+        AvroValue::Union(_, value) => avro_to_json(*value)?,
+        AvroValue::Array(values) => Value::Array(
+            values
+                .into_iter()
+                .map(avro_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        AvroValue::Map(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((k, avro_to_json(v)?)))
+                .collect::<Result<Map<_, _>, String>>()?,
+        ),
+        AvroValue::Record(entries) => Value::Object(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((k, avro_to_json(v)?)))
+                .collect::<Result<Map<_, _>, String>>()?,
+        ),
+        AvroValue::Decimal(_) | AvroValue::Duration(_) => {
+            return Err("Unsupported Avro decimal or duration value".to_string())
+        }
+    })
+}
+
+#[test]
+fn test_write_avro() {
+    use apache_avro::types::Record as AvroRecord;
+    use apache_avro::Writer;
+
+    let avro_schema = AvroSchema::parse_str(
+        r#"
+        {
+            "type": "record",
+            "name": "event",
+            "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": ["null", "string"]},
+                {"name": "level", "type": {"type": "enum", "name": "level", "symbols": ["INFO", "ERROR"]}},
+                {"name": "user", "type": {
+                    "type": "record",
+                    "name": "user",
+                    "fields": [{"name": "age", "type": "int"}]
+                }},
+                {"name": "tags", "type": {"type": "array", "items": "string"}}
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+
+    let parquet_schema = avro_to_parquet_schema(&avro_schema).unwrap();
+    assert_eq!(
+        crate::schema_text(&parquet_schema),
+        "message schema {\n  REQUIRED INT64 id;\n  OPTIONAL BYTE_ARRAY name (UTF8);\n  REQUIRED BYTE_ARRAY level (ENUM);\n  REQUIRED INT32 user_age;\n  REPEATED BYTE_ARRAY tags (UTF8);\n}\n"
+    );
+
+    let mut writer = Writer::new(&avro_schema, vec![]);
+    let mut record = AvroRecord::new(&avro_schema).unwrap();
+    record.put("id", 1i64);
+    record.put(
+        "name",
+        AvroValue::Union(1, Box::new(AvroValue::String("a".to_string()))),
+    );
+    record.put("level", AvroValue::Enum(1, "ERROR".to_string()));
+    record.put(
+        "user",
+        AvroValue::Record(vec![("age".to_string(), AvroValue::Int(30))]),
+    );
+    record.put(
+        "tags",
+        AvroValue::Array(vec![AvroValue::String("x".to_string())]),
+    );
+    writer.append(record).unwrap();
+    let avro = writer.into_inner().unwrap();
+
+    assert!(write_avro(&avro).is_ok());
+}
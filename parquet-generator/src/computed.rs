@@ -0,0 +1,267 @@
+use crate::writer::{self, Record};
+use crate::{convert, csv, ParquetField};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How a computed field gets its value
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum Computed {
+    /// An expression over the record, e.g. `date(event_ts)` or `concat(first, ' ', last)`
+    Expression(String),
+    /// Computed by the field's JS transform, which is called with the whole record
+    Callback(bool),
+}
+
+/// A parsed computed field expression
+#[derive(Debug, PartialEq)]
+enum Expression {
+    /// The value at a path of keys, written like a `source_key`
+    Key(Vec<String>),
+    /// A string in single quotes, `''` is a literal quote
+    Literal(String),
+    Call(Function, Vec<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    /// The UTC `YYYY-MM-DD` date of an RFC 3339 timestamp or milliseconds since the epoch
+    Date,
+    Year,
+    Month,
+    Day,
+    Hour,
+    Lower,
+    Upper,
+    /// The arguments joined as strings, skipping nulls
+    Concat,
+    /// The first argument that isn't null
+    Coalesce,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Function> {
+        Some(match name {
+            "date" => Function::Date,
+            "year" => Function::Year,
+            "month" => Function::Month,
+            "day" => Function::Day,
+            "hour" => Function::Hour,
+            "lower" => Function::Lower,
+            "upper" => Function::Upper,
+            "concat" => Function::Concat,
+            "coalesce" => Function::Coalesce,
+            _ => return None,
+        })
+    }
+
+    fn takes(self, args: usize) -> bool {
+        match self {
+            Function::Concat | Function::Coalesce => args > 0,
+            _ => args == 1,
+        }
+    }
+}
+
+/// Check the expressions of computed fields parse, and callbacks are only declared as `true`
+pub(crate) fn validate(field: &ParquetField) -> Result<(), String> {
+    match &field.computed {
+        Some(Computed::Expression(expression)) => parse(expression).map(|_| ()).map_err(|e| {
+            format!(
+                "Invalid expression for computed field {}: {}",
+                field.name, e
+            )
+        }),
+        Some(Computed::Callback(false)) => Err(format!(
+            "Computed field {} must have an expression or be true",
+            field.name
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Evaluate the expressions of computed fields for each record, setting the results at the
+/// fields' keys
+pub(crate) fn compute_records(
+    fields: &[ParquetField],
+    records: &[Record],
+    case_insensitive: bool,
+) -> Result<Vec<Record>, String> {
+    let mut computed = vec![];
+    for field in fields {
+        if let Some(Computed::Expression(expression)) = &field.computed {
+            computed.push((writer::key_paths(field)?.remove(0), parse(expression)?));
+        }
+    }
+    Ok(records
+        .iter()
+        .map(|record| {
+            let mut record = record.clone();
+            for (path, expression) in computed.iter() {
+                let value = evaluate(expression, &record, case_insensitive);
+                csv::insert_path(&mut record, path, value);
+            }
+            record
+        })
+        .collect())
+}
+
+/// Whether any field has an expression to evaluate
+pub(crate) fn has_expressions(fields: &[ParquetField]) -> bool {
+    fields
+        .iter()
+        .any(|f| matches!(f.computed, Some(Computed::Expression(_))))
+}
+
+fn parse(expression: &str) -> Result<Expression, String> {
+    let mut parser = Parser {
+        rest: expression.trim(),
+    };
+    let parsed = parser.expression()?;
+    match parser.rest.is_empty() {
+        true => Ok(parsed),
+        false => Err(format!("unexpected {}", parser.rest)),
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn expression(&mut self) -> Result<Expression, String> {
+        if let Some(quoted) = self.rest.strip_prefix('\'') {
+            return self.literal(quoted);
+        }
+        let end = self
+            .rest
+            .find(|c: char| "(),'".contains(c) || c.is_whitespace())
+            .unwrap_or(self.rest.len());
+        let (token, rest) = self.rest.split_at(end);
+        if token.is_empty() {
+            return Err(match rest.chars().next() {
+                Some(c) => format!("unexpected {}", c),
+                None => "unexpected end of expression".to_string(),
+            });
+        }
+        self.rest = rest.trim_start();
+
+        let Some(args) = self.rest.strip_prefix('(') else {
+            return writer::split_key_path(token)
+                .map(Expression::Key)
+                .ok_or_else(|| format!("invalid key {}", token));
+        };
+        let function =
+            Function::parse(token).ok_or_else(|| format!("unknown function {}", token))?;
+        self.rest = args.trim_start();
+        let mut args = vec![];
+        if let Some(rest) = self.rest.strip_prefix(')') {
+            self.rest = rest;
+        } else {
+            loop {
+                args.push(self.expression()?);
+                self.rest = self.rest.trim_start();
+                let (separator, rest) = self.rest.split_at(self.rest.len().min(1));
+                self.rest = rest.trim_start();
+                match separator {
+                    "," => continue,
+                    ")" => break,
+                    _ => return Err(format!("expected , or ) after argument to {}", token)),
+                }
+            }
+        }
+        self.rest = self.rest.trim_start();
+        match function.takes(args.len()) {
+            true => Ok(Expression::Call(function, args)),
+            false => Err(format!("wrong number of arguments to {}", token)),
+        }
+    }
+
+    fn literal(&mut self, quoted: &'a str) -> Result<Expression, String> {
+        let mut literal = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            if c != '\'' {
+                literal.push(c);
+                continue;
+            }
+            if quoted[i + 1..].starts_with('\'') {
+                literal.push('\'');
+                chars.next();
+                continue;
+            }
+            self.rest = quoted[i + 1..].trim_start();
+            return Ok(Expression::Literal(literal));
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+fn evaluate(expression: &Expression, record: &Record, case_insensitive: bool) -> Value {
+    let (function, args) = match expression {
+        Expression::Key(path) => {
+            return writer::lookup_path(record, path, case_insensitive)
+                .cloned()
+                .unwrap_or(Value::Null)
+        }
+        Expression::Literal(literal) => return Value::String(literal.clone()),
+        Expression::Call(function, args) => (function, args),
+    };
+    let mut args = args.iter().map(|a| evaluate(a, record, case_insensitive));
+    match function {
+        Function::Coalesce => args.find(|a| !a.is_null()).unwrap_or(Value::Null),
+        Function::Concat => Value::String(args.filter_map(|a| to_string(&a)).collect()),
+        Function::Lower => to_string_value(args.next(), |s| s.to_lowercase()),
+        Function::Upper => to_string_value(args.next(), |s| s.to_uppercase()),
+        Function::Date => match args.next().as_ref().and_then(to_datetime) {
+            Some(t) => Value::String(t.date().format("%Y-%m-%d").to_string()),
+            None => Value::Null,
+        },
+        Function::Year | Function::Month | Function::Day | Function::Hour => {
+            match args.next().as_ref().and_then(to_datetime) {
+                Some(t) => Value::from(match function {
+                    Function::Year => i64::from(t.year()),
+                    Function::Month => i64::from(t.month()),
+                    Function::Day => i64::from(t.day()),
+                    _ => i64::from(t.hour()),
+                }),
+                None => Value::Null,
+            }
+        }
+    }
+}
+
+fn to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        _ => Some(value.to_string()),
+    }
+}
+
+fn to_string_value(value: Option<Value>, map: impl Fn(&str) -> String) -> Value {
+    match value.as_ref().and_then(to_string) {
+        Some(s) => Value::String(map(&s)),
+        None => Value::Null,
+    }
+}
+
+/// Read a UTC date and time from an RFC 3339 timestamp, a `YYYY-MM-DD` date or milliseconds since
+/// the epoch
+fn to_datetime(value: &Value) -> Option<NaiveDateTime> {
+    match value {
+        Value::String(s) => convert::parse_timestamp(s)
+            .map(|t| t.naive_utc())
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|d| d.and_hms_opt(0, 0, 0))
+            }),
+        Value::Number(n) => n
+            .as_i64()
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|t| t.naive_utc()),
+        _ => None,
+    }
+}
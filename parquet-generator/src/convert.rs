@@ -0,0 +1,363 @@
+use crate::options::CoercionOptions;
+use crate::{BinaryEncoding, ParquetField, ParquetLogicalType, ParquetPrimitiveType};
+use base64::Engine;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use parquet::data_type::Int96;
+use serde_json::Value;
+use std::borrow::Cow;
+
+/// Read an integer for a field, parsing strings in the format of its logical type, e.g. RFC 3339
+/// timestamps for TIMESTAMP fields
+pub(crate) fn to_int(
+    field: &ParquetField,
+    value: &Value,
+    coercion: &CoercionOptions,
+) -> Option<i64> {
+    match (&field.logical_type, value) {
+        // Strings that aren't RFC 3339 may still be epoch numbers, like an INT96 timestamp takes
+        (Some(ParquetLogicalType::TimestampMillis), Value::String(s)) => parse_timestamp(s)
+            .map(|t| t.timestamp_millis())
+            .or_else(|| parse_int64(s, value, coercion)),
+        (Some(ParquetLogicalType::TimestampMicros), Value::String(s)) => parse_timestamp(s)
+            .map(|t| t.timestamp_micros())
+            .or_else(|| parse_int64(s, value, coercion)),
+        (Some(ParquetLogicalType::Date), Value::String(s)) => parse_date(s).map(i64::from),
+        (Some(ParquetLogicalType::Decimal), _) => {
+            to_decimal(field, value).and_then(|d| i64::try_from(d).ok())
+        }
+        (_, Value::String(s)) if matches!(field.primitive_type, ParquetPrimitiveType::Int64) => {
+            parse_int64(s, value, coercion)
+        }
+        _ => to_i64(value, coercion),
+    }
+}
+
+/// Integers past 2^53 can't survive a JSON number, so INT64 always takes them as strings
+fn parse_int64(s: &str, value: &Value, coercion: &CoercionOptions) -> Option<i64> {
+    s.parse::<i64>().ok().or_else(|| to_i64(value, coercion))
+}
+
+/// Read an integer like `to_int`, keeping plain numbers past the range of an i64 so they can be
+/// reported as overflowing rather than invalid
+pub(crate) fn to_wide_int(
+    field: &ParquetField,
+    value: &Value,
+    coercion: &CoercionOptions,
+) -> Option<i128> {
+    if let Some(i) = to_int(field, value, coercion) {
+        return Some(i128::from(i));
+    }
+    if int_logical_range(&field.logical_type).is_none() && field.logical_type.is_some() {
+        return None;
+    }
+    match value {
+        Value::Number(n) => n.as_u64().map(i128::from).or_else(|| {
+            n.as_f64()
+                .filter(|f| f.fract() == 0.0 || coercion.truncate_float_to_int)
+                .map(|f| f.trunc() as i128)
+        }),
+        Value::String(s)
+            if coercion.string_to_number
+                || matches!(field.primitive_type, ParquetPrimitiveType::Int64) =>
+        {
+            s.parse::<i128>().ok()
+        }
+        _ => None,
+    }
+}
+
+/// The smallest and largest values an INT32 or INT64 field can hold, narrowed by integer logical
+/// types. UINT32 and UINT64 values above `i32::MAX` and `i64::MAX` are written with the same bits
+/// as the unsigned value
+pub(crate) fn int_range(field: &ParquetField) -> Option<(i128, i128)> {
+    let (min, max) = match field.primitive_type {
+        ParquetPrimitiveType::Int32 => (i32::MIN.into(), i32::MAX.into()),
+        ParquetPrimitiveType::Int64 => (i64::MIN.into(), i64::MAX.into()),
+        _ => return None,
+    };
+    Some(int_logical_range(&field.logical_type).unwrap_or((min, max)))
+}
+
+fn int_logical_range(logical_type: &Option<ParquetLogicalType>) -> Option<(i128, i128)> {
+    match logical_type {
+        Some(ParquetLogicalType::Int8) => Some((i8::MIN.into(), i8::MAX.into())),
+        Some(ParquetLogicalType::Int16) => Some((i16::MIN.into(), i16::MAX.into())),
+        Some(ParquetLogicalType::Int32) => Some((i32::MIN.into(), i32::MAX.into())),
+        Some(ParquetLogicalType::Int64) => Some((i64::MIN.into(), i64::MAX.into())),
+        Some(ParquetLogicalType::Uint8) => Some((0, u8::MAX.into())),
+        Some(ParquetLogicalType::Uint16) => Some((0, u16::MAX.into())),
+        Some(ParquetLogicalType::Uint32) => Some((0, u32::MAX.into())),
+        Some(ParquetLogicalType::Uint64) => Some((0, u64::MAX.into())),
+        _ => None,
+    }
+}
+
+/// The Julian day number of 1970-01-01
+const JULIAN_DAY_OF_EPOCH: i64 = 2_440_588;
+const MILLIS_PER_DAY: i64 = 86_400_000;
+const NANOS_PER_MILLI: i64 = 1_000_000;
+
+/// Read an INT96 timestamp, the nanoseconds of the day followed by the Julian day, from an RFC 3339
+/// string or a number of milliseconds since the epoch (what JavaScript's `Date.getTime` returns)
+pub(crate) fn to_int96(value: &Value, coercion: &CoercionOptions) -> Option<Int96> {
+    let (days, nanos_of_day) = match value {
+        Value::String(s) => match parse_timestamp(s) {
+            Some(t) => {
+                let seconds = t.timestamp();
+                let nanos = seconds.rem_euclid(86_400) * 1_000_000_000
+                    + i64::from(t.timestamp_subsec_nanos());
+                (seconds.div_euclid(86_400), nanos)
+            }
+            None => millis_to_day_and_nanos(to_i64(value, coercion)?),
+        },
+        _ => millis_to_day_and_nanos(to_i64(value, coercion)?),
+    };
+    let julian_day = u32::try_from(days + JULIAN_DAY_OF_EPOCH).ok()?;
+    let mut int96 = Int96::new();
+    int96.set_data(nanos_of_day as u32, (nanos_of_day >> 32) as u32, julian_day);
+    Some(int96)
+}
+
+fn millis_to_day_and_nanos(millis: i64) -> (i64, i64) {
+    (
+        millis.div_euclid(MILLIS_PER_DAY),
+        millis.rem_euclid(MILLIS_PER_DAY) * NANOS_PER_MILLI,
+    )
+}
+
+/// Parse a `YYYY-MM-DD` calendar date into days since the Unix epoch
+pub(crate) fn parse_date(s: &str) -> Option<i32> {
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    i32::try_from((date - NaiveDate::default()).num_days()).ok()
+}
+
+pub(crate) fn parse_timestamp(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(s).ok()
+}
+
+/// Read an integer, optionally from a numeric string or by truncating a float
+pub(crate) fn to_i64(value: &Value, coercion: &CoercionOptions) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| {
+            n.as_f64()
+                .filter(|_| coercion.truncate_float_to_int)
+                .and_then(truncate)
+        }),
+        Value::String(s) if coercion.string_to_number => s.parse::<i64>().ok().or_else(|| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|_| coercion.truncate_float_to_int)
+                .and_then(truncate)
+        }),
+        _ => None,
+    }
+}
+
+/// Read a boolean, optionally from the strings and numbers CSV-originated JSON uses for them
+pub(crate) fn to_bool(value: &Value, coercion: &CoercionOptions) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) if coercion.lenient_booleans => match s.trim() {
+            "1" => Some(true),
+            "0" => Some(false),
+            s if s.eq_ignore_ascii_case("true") => Some(true),
+            s if s.eq_ignore_ascii_case("false") => Some(false),
+            _ => None,
+        },
+        Value::Number(n) if coercion.lenient_booleans => match n.as_u64() {
+            Some(1) => Some(true),
+            Some(0) => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Read a floating point number like `to_f64`, also accepting the `NaN`, `Infinity` and
+/// `-Infinity` tokens JavaScript writes non-finite numbers as
+pub(crate) fn to_float(value: &Value, coercion: &CoercionOptions) -> Option<f64> {
+    match value.as_str() {
+        Some("NaN") => Some(f64::NAN),
+        Some("Infinity") => Some(f64::INFINITY),
+        Some("-Infinity") => Some(f64::NEG_INFINITY),
+        _ => to_f64(value, coercion),
+    }
+}
+
+/// Read a floating point number, optionally from a numeric string
+pub(crate) fn to_f64(value: &Value, coercion: &CoercionOptions) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) if coercion.string_to_number => s.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// Read a string, optionally from a number written in its JSON form
+pub(crate) fn to_str<'a>(value: &'a Value, coercion: &CoercionOptions) -> Option<Cow<'a, str>> {
+    match value {
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        Value::Number(n) if coercion.number_to_string => Some(Cow::Owned(n.to_string())),
+        _ => None,
+    }
+}
+
+/// The only key of the object `js::to_json` puts in place of a `Uint8Array`, holding the index of
+/// its bytes in `WriterOptions::byte_values`
+pub(crate) const UINT8_ARRAY_KEY: &str = "\u{0}Uint8Array";
+
+/// Read the bytes of a string, decoding them when the field declares an encoding, or of a
+/// `Uint8Array` from the JS record path, kept in `byte_values`. DECIMAL values are written as
+/// big-endian two's complement, sign extended to `length` bytes when given
+pub(crate) fn to_bytes(
+    field: &ParquetField,
+    value: &Value,
+    length: Option<usize>,
+    coercion: &CoercionOptions,
+    byte_values: &[Vec<u8>],
+) -> Option<Vec<u8>> {
+    if field.logical_type == Some(ParquetLogicalType::Decimal) {
+        return to_decimal(field, value).map(|d| decimal_bytes(d, length));
+    }
+    if let Some(bytes) = uint8_array(value, byte_values) {
+        return Some(bytes.to_vec());
+    }
+    let s = to_str(value, coercion)?;
+    match field.encoding {
+        None => Some(s.into_owned().into_bytes()),
+        Some(BinaryEncoding::Base64) => base64::engine::general_purpose::STANDARD
+            .decode(s.as_bytes())
+            .ok(),
+        Some(BinaryEncoding::Hex) => decode_hex(&s),
+    }
+}
+
+/// The bytes of a `Uint8Array` that `js::to_json` put in `byte_values`, None for any other value.
+/// Records parsed from JSON strings have no byte values, so the key can't be forged in them
+pub(crate) fn uint8_array<'a>(value: &Value, byte_values: &'a [Vec<u8>]) -> Option<&'a [u8]> {
+    byte_values
+        .get(uint8_array_index(value)?)
+        .map(Vec::as_slice)
+}
+
+/// The index in `byte_values` of the `Uint8Array` a value stands in for
+pub(crate) fn uint8_array_index(value: &Value) -> Option<usize> {
+    match value {
+        Value::Object(map) if map.len() == 1 => {
+            usize::try_from(map.get(UINT8_ARRAY_KEY)?.as_u64()?).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Read a DECIMAL value from a string or number as its unscaled integer, `None` if it has more
+/// digits than the field's precision or more fractional digits than its scale
+pub(crate) fn to_decimal(field: &ParquetField, value: &Value) -> Option<i128> {
+    let precision = field.precision?;
+    let scale = field.scale.unwrap_or(0);
+    match value {
+        Value::String(s) => parse_decimal(s, precision, scale),
+        Value::Number(n) => parse_decimal(&n.to_string(), precision, scale),
+        _ => None,
+    }
+}
+
+fn parse_decimal(s: &str, precision: i32, scale: i32) -> Option<i128> {
+    let s = s.trim();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    // Numbers like 1e-7 or 1.5e20, as JSON numbers are often printed, move the decimal point
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (digits, 0),
+    };
+    let (int_part, frac_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |p: &str| p.bytes().all(|b| b.is_ascii_digit());
+    if (int_part.is_empty() && frac_part.is_empty())
+        || !all_digits(int_part)
+        || !all_digits(frac_part)
+    {
+        return None;
+    }
+    let (int_part, frac_part) = shift_point(int_part, frac_part, exponent)?;
+    let frac_part = frac_part.trim_end_matches('0');
+    if frac_part.len() > scale as usize {
+        return None;
+    }
+
+    let unscaled = format!("{}{:0<width$}", int_part, frac_part, width = scale as usize);
+    let unscaled = unscaled.trim_start_matches('0');
+    if unscaled.len() > precision as usize {
+        return None;
+    }
+    let unscaled = if unscaled.is_empty() {
+        0
+    } else {
+        unscaled.parse::<i128>().ok()?
+    };
+    Some(if negative { -unscaled } else { unscaled })
+}
+
+/// The integer and fractional digits of a number after moving its decimal point `exponent` places
+/// to the right. Points moved further than the 38 digits a decimal can have are only kept for zeros
+fn shift_point(int_part: &str, frac_part: &str, exponent: i32) -> Option<(String, String)> {
+    let digits = format!("{}{}", int_part, frac_part);
+    if digits.bytes().all(|b| b == b'0') {
+        return Some((String::new(), String::new()));
+    }
+    if exponent.unsigned_abs() as usize > 38 + digits.len() {
+        return None;
+    }
+    let point = int_part.len() as i64 + i64::from(exponent);
+    Some(match usize::try_from(point) {
+        Ok(point) if point >= digits.len() => {
+            (format!("{:0<point$}", digits, point = point), String::new())
+        }
+        Ok(point) => (digits[..point].to_string(), digits[point..].to_string()),
+        Err(_) => {
+            let zeros = point.unsigned_abs() as usize;
+            (String::new(), format!("{}{}", "0".repeat(zeros), digits))
+        }
+    })
+}
+
+/// The fewest bytes of two's complement that can hold any decimal of `precision` digits
+pub(crate) fn decimal_length(precision: i32) -> i32 {
+    let max = 10u128.saturating_pow(precision as u32) - 1;
+    (1..16).find(|n| max < 1u128 << (8 * n - 1)).unwrap_or(16)
+}
+
+/// Big-endian two's complement bytes of a decimal, `length` bytes long or as short as possible
+fn decimal_bytes(unscaled: i128, length: Option<usize>) -> Vec<u8> {
+    let bytes = unscaled.to_be_bytes();
+    let length = length.unwrap_or_else(|| {
+        // Drop leading bytes that are only sign extension
+        let redundant = bytes
+            .windows(2)
+            .take_while(|w| (w[0] == 0 && w[1] < 0x80) || (w[0] == 0xff && w[1] >= 0x80))
+            .count();
+        bytes.len() - redundant
+    });
+    let fill = if unscaled < 0 { 0xff } else { 0 };
+    let mut out = vec![fill; length.saturating_sub(bytes.len())];
+    out.extend_from_slice(&bytes[bytes.len().saturating_sub(length)..]);
+    out
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn truncate(f: f64) -> Option<i64> {
+    let truncated = f.trunc();
+    (truncated.is_finite() && truncated >= i64::MIN as f64 && truncated < i64::MAX as f64)
+        .then_some(truncated as i64)
+}
@@ -0,0 +1,545 @@
+use crate::options::{ReaderOptions, WriterOptions};
+use crate::reader::Rows;
+use crate::writer::{self, Record};
+use crate::{ParquetField, ParquetPrimitiveType};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use std::borrow::Cow;
+
+/// Options for reading CSV, passed to `generate_parquet_from_csv` as a JSON object alongside any
+/// writer options
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct CsvOptions {
+    pub(crate) delimiter: char,
+    pub(crate) quote: char,
+    /// Whether the first row names the columns, otherwise columns are the schema's fields in order
+    pub(crate) header: bool,
+    /// Unquoted cells with this text are written as null
+    pub(crate) null_token: String,
+    /// Ends each row instead of a newline (`\n` or `\r\n`), may be more than one character
+    pub(crate) record_terminator: Option<String>,
+    /// Read rows as fixed-width columns of these many characters instead of splitting on the
+    /// delimiter, cells are trimmed of surrounding spaces and quotes have no special meaning
+    pub(crate) column_widths: Option<Vec<usize>>,
+    #[serde(flatten)]
+    pub(crate) writer: WriterOptions,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            quote: '"',
+            header: true,
+            null_token: String::new(),
+            record_terminator: None,
+            column_widths: None,
+            writer: WriterOptions::default(),
+        }
+    }
+}
+
+pub(crate) fn parse_csv_options(options: &str) -> Result<CsvOptions, String> {
+    match serde_json::from_str::<CsvOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+/// Options for writing a parquet file's rows as CSV, passed to `parquet_to_csv` as a JSON object
+/// alongside any reader options
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct CsvExportOptions {
+    pub(crate) delimiter: char,
+    pub(crate) quote: char,
+    /// Whether to start with a row of the column names
+    pub(crate) header: bool,
+    /// Written for nulls, cells of strings with the same text are quoted to tell them apart
+    pub(crate) null_token: String,
+    /// Ends each row, `\n` by default
+    pub(crate) record_terminator: String,
+    #[serde(flatten)]
+    pub(crate) reader: ReaderOptions,
+}
+
+impl Default for CsvExportOptions {
+    fn default() -> Self {
+        CsvExportOptions {
+            delimiter: ',',
+            quote: '"',
+            header: true,
+            null_token: String::new(),
+            record_terminator: "\n".to_string(),
+            reader: ReaderOptions::default(),
+        }
+    }
+}
+
+pub(crate) fn parse_csv_export_options(options: &str) -> Result<CsvExportOptions, String> {
+    match serde_json::from_str::<CsvExportOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+/// A parquet file's rows as CSV, written a row group at a time. Each row has a cell for every top
+/// level column, with LISTs, MAPs and groups written as JSON
+pub(crate) struct CsvExport {
+    rows: Rows,
+    columns: Vec<String>,
+    options: CsvExportOptions,
+    header_written: bool,
+}
+
+impl CsvExport {
+    pub(crate) fn new(bytes: Vec<u8>, mut options: CsvExportOptions) -> Result<CsvExport, String> {
+        let rows = Rows::new(bytes, std::mem::take(&mut options.reader))?;
+        Ok(CsvExport {
+            columns: rows.columns(),
+            rows,
+            options,
+            header_written: false,
+        })
+    }
+
+    /// The CSV of the next row group holding any rows, beginning with the header, none once every
+    /// row has been written
+    pub(crate) fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let mut csv = String::new();
+        if self.options.header && !self.header_written {
+            let header = self.columns.iter().map(|column| Some(column.as_str()));
+            write_row(&mut csv, header, &self.options);
+        }
+        self.header_written = true;
+
+        if let Some(rows) = self.rows.next_row_group()? {
+            for row in &rows {
+                let cells = self
+                    .columns
+                    .iter()
+                    .map(|column| cell_text(&row[column]))
+                    .collect::<Vec<_>>();
+                write_row(&mut csv, cells.iter().map(|c| c.as_deref()), &self.options);
+            }
+        }
+        match csv.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(csv.into_bytes())),
+        }
+    }
+}
+
+/// The text of a cell, none for null
+fn cell_text(value: &Value) -> Option<Cow<'_, str>> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.into()),
+        _ => Some(value.to_string().into()),
+    }
+}
+
+/// Write a row of cells, quoting those that would otherwise be read differently
+fn write_row<'a>(
+    csv: &mut String,
+    cells: impl Iterator<Item = Option<&'a str>>,
+    options: &CsvExportOptions,
+) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            csv.push(options.delimiter);
+        }
+        let text = match cell {
+            Some(text) => text,
+            None => {
+                csv.push_str(&options.null_token);
+                continue;
+            }
+        };
+        let quoted = text == options.null_token
+            || text.contains(options.delimiter)
+            || text.contains(options.quote)
+            || text.contains(['\n', '\r'])
+            || text.contains(options.record_terminator.as_str());
+        if quoted {
+            let quote = options.quote.to_string();
+            csv.push(options.quote);
+            csv.push_str(&text.replace(options.quote, &quote.repeat(2)));
+            csv.push(options.quote);
+        } else {
+            csv.push_str(text);
+        }
+    }
+    csv.push_str(&options.record_terminator);
+}
+
+struct Cell {
+    text: String,
+    quoted: bool,
+}
+
+/// Parse CSV into records, typing each cell by the field it populates so it's written the same
+/// way as the equivalent JSON value. Cells that don't parse as their field's type are kept as
+/// strings for the writer to report
+pub(crate) fn parse_csv(
+    fields: &[ParquetField],
+    csv: &str,
+    options: &CsvOptions,
+) -> Result<Vec<Record>, String> {
+    let rows = match &options.column_widths {
+        Some(widths) => parse_fixed_width_rows(csv, widths, options),
+        None => parse_rows(csv, options)?,
+    };
+    let mut rows = rows.into_iter();
+
+    let columns: Vec<(Vec<String>, Option<&ParquetField>)> = if options.header {
+        let header = rows.next().unwrap_or_default();
+        header
+            .into_iter()
+            .map(|cell| {
+                let field = column_field(fields, &cell.text, &options.writer);
+                (vec![cell.text], field)
+            })
+            .collect()
+    } else {
+        // Computed fields aren't read from the CSV, so they don't take a column
+        fields
+            .iter()
+            .filter(|field| field.computed.is_none())
+            .map(|field| {
+                let path = writer::key_paths(field)?.swap_remove(0);
+                Ok((path, Some(field)))
+            })
+            .collect::<Result<_, String>>()?
+    };
+
+    rows.enumerate()
+        .map(|(row, cells)| {
+            if cells.len() != columns.len() {
+                return Err(format!(
+                    "Row {}: expected {} columns, found {}",
+                    row,
+                    columns.len(),
+                    cells.len()
+                ));
+            }
+            let mut record = Map::new();
+            for ((path, field), cell) in columns.iter().zip(cells) {
+                let value = if !cell.quoted && cell.text == options.null_token {
+                    Value::Null
+                } else {
+                    parse_cell(*field, cell.text)
+                };
+                insert_path(&mut record, path, value);
+            }
+            Ok(record)
+        })
+        .collect()
+}
+
+/// The field a header column populates, the column name must match one of its keys exactly
+fn column_field<'a>(
+    fields: &'a [ParquetField],
+    column: &str,
+    options: &WriterOptions,
+) -> Option<&'a ParquetField> {
+    fields.iter().find(|field| {
+        writer::key_paths(field).is_ok_and(|paths| {
+            paths.iter().any(|path| match path.as_slice() {
+                [key] if options.case_insensitive_keys => key.eq_ignore_ascii_case(column),
+                [key] => key == column,
+                _ => false,
+            })
+        })
+    })
+}
+
+fn parse_cell(field: Option<&ParquetField>, text: String) -> Value {
+    let primitive_type = match field {
+        Some(field) => field.primitive_type,
+        None => return Value::String(text),
+    };
+    let parsed = match primitive_type {
+        ParquetPrimitiveType::Boolean => match text.to_ascii_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        ParquetPrimitiveType::Int32 | ParquetPrimitiveType::Int64 | ParquetPrimitiveType::Int96 => {
+            text.trim().parse::<i64>().ok().map(Value::from)
+        }
+        ParquetPrimitiveType::Float | ParquetPrimitiveType::Double => text
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number),
+        ParquetPrimitiveType::Binary
+        | ParquetPrimitiveType::ByteArray
+        | ParquetPrimitiveType::FixedLenByteArray => None,
+    };
+    parsed.unwrap_or(Value::String(text))
+}
+
+/// Set a value at a path of keys, creating nested objects along the way
+pub(crate) fn insert_path(record: &mut Record, path: &[String], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut object = record;
+    for key in parents {
+        let entry = object
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        object = match entry {
+            Value::Object(nested) => nested,
+            _ => return,
+        };
+    }
+    object.insert(last.clone(), value);
+}
+
+/// Split CSV into rows of cells. Quoted cells may contain delimiters and newlines, with the quote
+/// character escaped by doubling it, and blank lines are skipped
+fn parse_rows(csv: &str, options: &CsvOptions) -> Result<Vec<Vec<Cell>>, String> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+    let mut line = 1;
+
+    let mut chars = csv.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if in_quotes {
+            if c == options.quote {
+                if chars.peek().map(|(_, next)| *next) == Some(options.quote) {
+                    cell.push(c);
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                if c == '\n' {
+                    line += 1;
+                }
+                cell.push(c);
+            }
+        } else if let Some(len) = terminator_len(csv, i, options) {
+            // Skip the rest of the terminator, all of which is at or after `i`
+            while chars.peek().is_some_and(|(j, _)| *j < i + len) {
+                chars.next();
+            }
+            end_row(&mut rows, &mut row, &mut cell, &mut quoted);
+            line += 1;
+        } else if c == options.quote && cell.is_empty() && !quoted {
+            in_quotes = true;
+            quoted = true;
+        } else if c == options.delimiter {
+            row.push(Cell {
+                text: std::mem::take(&mut cell),
+                quoted,
+            });
+            quoted = false;
+        } else {
+            cell.push(c);
+        }
+    }
+
+    if in_quotes {
+        return Err(format!("Line {}: unterminated quoted cell", line));
+    }
+    end_row(&mut rows, &mut row, &mut cell, &mut quoted);
+    Ok(rows)
+}
+
+/// The length in bytes of the record terminator starting at byte `i`, if there is one
+fn terminator_len(text: &str, i: usize, options: &CsvOptions) -> Option<usize> {
+    let rest = &text[i..];
+    match &options.record_terminator {
+        Some(terminator) if !terminator.is_empty() => rest
+            .starts_with(terminator.as_str())
+            .then_some(terminator.len()),
+        _ if rest.starts_with("\r\n") => Some(2),
+        _ if rest.starts_with('\n') || rest.starts_with('\r') => Some(1),
+        _ => None,
+    }
+}
+
+/// Split fixed-width text into rows of cells, a row shorter than the widths has empty cells at
+/// the end and blank rows are skipped
+fn parse_fixed_width_rows(text: &str, widths: &[usize], options: &CsvOptions) -> Vec<Vec<Cell>> {
+    let records: Vec<&str> = match &options.record_terminator {
+        Some(terminator) if !terminator.is_empty() => text.split(terminator.as_str()).collect(),
+        _ => text.lines().collect(),
+    };
+    records
+        .into_iter()
+        .filter(|record| !record.trim().is_empty())
+        .map(|record| {
+            let mut chars = record.chars();
+            widths
+                .iter()
+                .map(|width| Cell {
+                    text: chars
+                        .by_ref()
+                        .take(*width)
+                        .collect::<String>()
+                        .trim()
+                        .to_string(),
+                    quoted: false,
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn end_row(rows: &mut Vec<Vec<Cell>>, row: &mut Vec<Cell>, cell: &mut String, quoted: &mut bool) {
+    if row.is_empty() && cell.is_empty() && !*quoted {
+        return;
+    }
+    row.push(Cell {
+        text: std::mem::take(cell),
+        quoted: *quoted,
+    });
+    *quoted = false;
+    rows.push(std::mem::take(row));
+}
+
+#[test]
+fn test_parse_csv() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "repetition_type": "OPTIONAL"
+            },
+            {
+                "name": "active",
+                "type": "BOOLEAN"
+            }
+        ]
+    }
+    "#;
+    let fields = serde_json::from_str::<crate::ParquetSchema>(schema)
+        .unwrap()
+        .fields;
+    let csv = "active;id;name\r\nTRUE;1;'a;b'\n\nfalse;2;\nfalse;3;''''\n";
+    let options = parse_csv_options(r#"{"delimiter": ";", "quote": "'"}"#).unwrap();
+    let records = parse_csv(&fields, csv, &options).unwrap();
+    assert_eq!(
+        records.into_iter().map(Value::Object).collect::<Vec<_>>(),
+        vec![
+            serde_json::json!({"active": true, "id": 1, "name": "a;b"}),
+            serde_json::json!({"active": false, "id": 2, "name": null}),
+            serde_json::json!({"active": false, "id": 3, "name": "'"}),
+        ]
+    );
+
+    let options = parse_csv_options(r#"{"header": false, "null_token": "NULL"}"#).unwrap();
+    let records = parse_csv(&fields, "1,NULL,true\n2,\"x\"", &options);
+    assert_eq!(records.unwrap_err(), "Row 1: expected 3 columns, found 2");
+}
+
+#[test]
+fn test_parse_delimited_and_fixed_width() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "city",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let fields = serde_json::from_str::<crate::ParquetSchema>(schema)
+        .unwrap()
+        .fields;
+    let expected = vec![
+        serde_json::json!({"id": 1, "city": "Leeds"}),
+        serde_json::json!({"id": 22, "city": "York"}),
+    ];
+
+    let options = parse_csv_options(r#"{"delimiter": "\t", "record_terminator": "~|~"}"#).unwrap();
+    let records = parse_csv(&fields, "id\tcity~|~1\tLeeds~|~22\tYork", &options).unwrap();
+    assert_eq!(
+        records.into_iter().map(Value::Object).collect::<Vec<_>>(),
+        expected
+    );
+
+    let options = parse_csv_options(r#"{"column_widths": [4, 6]}"#).unwrap();
+    let records = parse_csv(&fields, "id  city\n1   Leeds\n22  York\n", &options).unwrap();
+    assert_eq!(
+        records.into_iter().map(Value::Object).collect::<Vec<_>>(),
+        expected
+    );
+}
+
+#[test]
+fn test_csv_export() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT32"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "name": "a;b", "tags": ["x"]}"#.to_string(),
+        r#"{"id": 2, "name": null, "tags": []}"#.to_string(),
+        r#"{"id": 3, "name": "NULL", "tags": []}"#.to_string(),
+        r#"{"id": 4, "name": "it's", "tags": []}"#.to_string(),
+    ];
+    let writer_options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &writer_options).unwrap();
+
+    let export = |options: &str| {
+        let options = parse_csv_export_options(options).unwrap();
+        let mut export = CsvExport::new(bytes.clone(), options).unwrap();
+        let mut chunks = vec![];
+        while let Some(chunk) = export.next_chunk().unwrap() {
+            chunks.push(String::from_utf8(chunk).unwrap());
+        }
+        chunks
+    };
+    assert_eq!(
+        export(
+            r#"{"delimiter": ";", "quote": "'", "null_token": "NULL", "record_terminator": "\r\n"}"#
+        ),
+        vec![
+            "id;name;tags\r\n1;'a;b';[\"x\"]\r\n2;NULL;[]\r\n",
+            "3;'NULL';[]\r\n4;'it''s';[]\r\n",
+        ]
+    );
+    assert_eq!(
+        export(r#"{"header": false, "offset": 1, "limit": 1}"#),
+        vec!["2,,[]\n"]
+    );
+    assert_eq!(export(r#"{"offset": 10}"#), vec!["id,name,tags\n"]);
+
+    // The CSV reads back into the same records
+    let fields = serde_json::from_str::<crate::ParquetSchema>(schema)
+        .unwrap()
+        .fields;
+    let csv = export(r#"{"null_token": "NULL"}"#).concat();
+    let options = parse_csv_options(r#"{"null_token": "NULL"}"#).unwrap();
+    let records = parse_csv(&fields, &csv, &options).unwrap();
+    assert_eq!(records[1]["name"], Value::Null);
+    assert_eq!(records[2]["name"], "NULL");
+}
@@ -0,0 +1,318 @@
+use crate::error::WriteError;
+use crate::options::{LoneSurrogatePolicy, WriterOptions};
+use crate::{unicode, writer, CompiledSchema, ParquetField};
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+
+/// The fields each top level key of a record is read into, with the key's priority among the
+/// field's `source_key` (or name) and aliases, lowest first
+pub(crate) struct FieldKeys {
+    keys: HashMap<String, Vec<(usize, usize)>>,
+    fields: usize,
+}
+
+impl FieldKeys {
+    /// The keys of a schema whose records can be decoded skipping the keys no field reads, rather
+    /// than parsing every record into a JSON object first. The values of the keys that are read
+    /// are still parsed into a `Value` each. `None` when a field is read from a nested key or
+    /// computed, or the options need whole records, as listed on `WriterOptions`
+    pub(crate) fn new(fields: &[ParquetField], options: &WriterOptions) -> Option<FieldKeys> {
+        let whole_records = options.case_insensitive_keys
+            || options.infer_optional
+            || options.flatten.is_some()
+            || options.filter.is_some()
+            || options.dedupe_on.is_some()
+            || (options.sort_records && options.sorting_columns.is_some())
+            || options.lone_surrogates == LoneSurrogatePolicy::Binary;
+        if whole_records {
+            return None;
+        }
+
+        let mut keys: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        for (i, field) in fields.iter().enumerate() {
+            if field.computed.is_some() {
+                return None;
+            }
+            for (priority, path) in writer::key_paths(field).ok()?.into_iter().enumerate() {
+                match <[String; 1]>::try_from(path) {
+                    Ok([key]) => keys.entry(key).or_default().push((i, priority)),
+                    Err(_) => return None,
+                }
+            }
+        }
+        Some(FieldKeys {
+            keys,
+            fields: fields.len(),
+        })
+    }
+}
+
+/// Write records from their JSON to `sink`, one row group at a time, skipping the keys no field
+/// reads. The values of the schema's fields are kept only for the row group being written, which
+/// is cut once the values decoded for it fill it
+pub(crate) fn write_decoded<W: Write + Send>(
+    sink: &mut writer::FileSink<W>,
+    schema: &CompiledSchema,
+    files: &[String],
+    keys: &FieldKeys,
+    options: &WriterOptions,
+) -> Result<(), WriteError> {
+    let fields = &schema.schema.fields;
+    writer::row_groups(0, options)?;
+    let max_rows = options.max_rows_per_row_group.unwrap_or(usize::MAX);
+    let mut columns = vec![vec![]; keys.fields];
+    let mut slots = vec![None; keys.fields];
+    // The bytes of each row's values, and all of them
+    let mut sizes = vec![];
+    let mut bytes = 0;
+    for (row, file) in files.iter().enumerate() {
+        decode_into(
+            file,
+            keys,
+            options.lone_surrogates,
+            &mut slots,
+            &mut columns,
+        )
+        .map_err(|e| WriteError::from(format!("Row {}: {}", row, e)))?;
+        let size = columns
+            .iter()
+            .filter_map(|column| column.last())
+            .map(slot_bytes)
+            .sum();
+        sizes.push(size);
+        bytes += size;
+
+        let full = sizes.len() >= max_rows;
+        if full || sink.fills(sizes.len(), bytes, options) {
+            let written = sink.write_field_values(fields, &columns, &sizes, options, !full)?;
+            for column in columns.iter_mut() {
+                column.drain(..written);
+            }
+            sizes.drain(..written);
+            bytes = sizes.iter().sum();
+        }
+    }
+    if !sizes.is_empty() {
+        sink.write_field_values(fields, &columns, &sizes, options, false)?;
+    }
+    Ok(())
+}
+
+/// The bytes a decoded value takes in memory, or its empty slot when the key was missing
+fn slot_bytes(slot: &Option<Value>) -> usize {
+    match slot {
+        Some(value) => writer::value_bytes(value),
+        None => std::mem::size_of::<Option<Value>>(),
+    }
+}
+
+/// Deserialize each file's values of the fields, returning a column of values for each field.
+/// A `None` is a missing key, a null is kept so it isn't read from an alias instead
+#[cfg(test)]
+fn decode_records(
+    files: &[String],
+    keys: &FieldKeys,
+    surrogates: LoneSurrogatePolicy,
+) -> Result<Vec<Vec<Option<Value>>>, String> {
+    let mut columns = vec![Vec::with_capacity(files.len()); keys.fields];
+    let mut slots = vec![None; keys.fields];
+    for (row, file) in files.iter().enumerate() {
+        decode_into(file, keys, surrogates, &mut slots, &mut columns)
+            .map_err(|e| format!("Row {}: {}", row, e))?;
+    }
+    Ok(columns)
+}
+
+/// Deserialize a file's values of the fields onto the end of each field's column, through
+/// `slots`, which are left empty
+fn decode_into(
+    file: &str,
+    keys: &FieldKeys,
+    surrogates: LoneSurrogatePolicy,
+    slots: &mut [Option<(usize, Value)>],
+    columns: &mut [Vec<Option<Value>>],
+) -> Result<(), String> {
+    let file = unicode::escape_lone_surrogates(file, surrogates)?;
+    let seed = RecordSeed { keys, slots };
+    match decode_record(&file, seed) {
+        Ok(true) => {}
+        Ok(false) => return Err("record is not a JSON object".to_string()),
+        Err(_) => return Err("error parsing record".to_string()),
+    }
+    for (column, slot) in columns.iter_mut().zip(slots.iter_mut()) {
+        column.push(slot.take().map(|(_, value)| value));
+    }
+    Ok(())
+}
+
+/// Deserialize a record, rejecting anything after it
+fn decode_record(json: &str, seed: RecordSeed) -> Result<bool, String> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    seed.deserialize(&mut deserializer)
+        .and_then(|object| deserializer.end().map(|_| object))
+        .map_err(|e| e.to_string())
+}
+
+/// Deserializes a record into `slots`, the value of each field along with the priority of the
+/// key it was read from. Deserializes to whether the record was an object
+struct RecordSeed<'a> {
+    keys: &'a FieldKeys,
+    slots: &'a mut [Option<(usize, Value)>],
+}
+
+impl<'de> DeserializeSeed<'de> for RecordSeed<'_> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordSeed<'_> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<bool, A::Error> {
+        while let Some(fields) = map.next_key_seed(KeySeed(self.keys))? {
+            let fields = match fields {
+                Some(fields) => fields,
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                    continue;
+                }
+            };
+            let value = map.next_value::<Value>()?;
+            // A later duplicate of a key replaces the earlier value, as it does when parsing
+            for &(field, priority) in fields {
+                if self.slots[field]
+                    .as_ref()
+                    .is_none_or(|(earlier, _)| priority <= *earlier)
+                {
+                    self.slots[field] = Some((priority, value.clone()));
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<bool, A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(false)
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_unit<E>(self) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_none<E>(self) -> Result<bool, E> {
+        Ok(false)
+    }
+}
+
+/// Deserializes a key to the fields read from it, without allocating it unless it's escaped
+struct KeySeed<'a>(&'a FieldKeys);
+
+impl<'de, 'a> DeserializeSeed<'de> for KeySeed<'a> {
+    type Value = Option<&'a [(usize, usize)]>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for KeySeed<'a> {
+    type Value = Option<&'a [(usize, usize)]>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a key")
+    }
+
+    fn visit_str<E>(self, key: &str) -> Result<Self::Value, E> {
+        Ok(self.0.keys.get(key).map(Vec::as_slice))
+    }
+}
+
+#[test]
+fn test_decode_records() {
+    let schema = CompiledSchema::parse(
+        r#"
+        {
+            "fields": [
+                {
+                    "name": "id",
+                    "type": "INT64",
+                    "source_key": "userId",
+                    "aliases": ["user_id"]
+                },
+                {
+                    "name": "name",
+                    "type": "BYTE_ARRAY",
+                    "logical_type": "UTF8",
+                    "repetition_type": "OPTIONAL"
+                }
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+    let options = WriterOptions::default();
+    let keys = FieldKeys::new(&schema.schema.fields, &options).unwrap();
+    let files = vec![
+        r#"{"user_id": 1, "userId": 2, "other": {"a": [1]}, "name": "a"}"#.to_string(),
+        r#"{"user_id": 3, "name": null, "name": "b"}"#.to_string(),
+        r#"{"userId": null, "user_id": 4}"#.to_string(),
+    ];
+    assert_eq!(
+        decode_records(&files, &keys, options.lone_surrogates).unwrap(),
+        vec![
+            vec![Some(2.into()), Some(3.into()), Some(Value::Null)],
+            vec![Some("a".into()), Some("b".into()), None],
+        ]
+    );
+
+    for (file, error) in [
+        ("[1, 2]", "Row 0: record is not a JSON object"),
+        (r#""id""#, "Row 0: record is not a JSON object"),
+        (r#"{"userId": 1"#, "Row 0: error parsing record"),
+        (r#"{"userId": 1} 2"#, "Row 0: error parsing record"),
+    ] {
+        assert_eq!(
+            decode_records(&[file.to_string()], &keys, options.lone_surrogates).unwrap_err(),
+            error
+        );
+    }
+
+    let nested = CompiledSchema::parse(
+        r#"{"fields": [{"name": "id", "type": "INT64", "source_key": "user.id"}]}"#,
+    )
+    .unwrap();
+    assert!(FieldKeys::new(&nested.schema.fields, &options).is_none());
+    let options = crate::options::parse_options(r#"{"case_insensitive_keys": true}"#).unwrap();
+    assert!(FieldKeys::new(&schema.schema.fields, &options).is_none());
+}
@@ -0,0 +1,1034 @@
+use crate::rewrite::Sink;
+use crate::writer::NULL_PARTITION;
+use crate::{reader, writer};
+use arrow_array::builder::{ListBuilder, MapBuilder, MapFieldNames, StringBuilder};
+use arrow_array::{ArrayRef, BooleanArray, Int32Array, Int64Array, StringArray, StructArray};
+use arrow_buffer::NullBuffer;
+use arrow_schema::{DataType, Field, Fields, Schema};
+use chrono::{DateTime, NaiveDate, Utc};
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::basic::{ConvertedType, LogicalType, TimeUnit};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::FileReader;
+use parquet::schema::types::ColumnDescriptor;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The directory of a Delta table's commits and checkpoints
+const LOG_DIRECTORY: &str = "_delta_log";
+
+/// The oldest protocol versions, which readers and writers of every Delta version support
+const MIN_READER_VERSION: i32 = 1;
+const MIN_WRITER_VERSION: i32 = 2;
+
+/// Options for a Delta commit of data files, passed to `delta_commit` as a JSON object
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct DeltaOptions {
+    /// The version of the commit, the first commit of a table is 0 and also creates it
+    pub(crate) version: u64,
+    /// The table's unique id, e.g. a UUID, required to create a table
+    pub(crate) table_id: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) description: Option<String>,
+    /// The columns the table is partitioned by, whose values are read from the `column=value`
+    /// directories of each file's path
+    pub(crate) partition_columns: Vec<String>,
+    /// The table's properties, e.g. `{"delta.appendOnly": "true"}`
+    pub(crate) configuration: BTreeMap<String, String>,
+    /// Milliseconds since the epoch the commit and its files are timestamped with, by default now
+    pub(crate) timestamp: Option<i64>,
+}
+
+pub(crate) fn parse_delta_options(options: &str) -> Result<DeltaOptions, String> {
+    match serde_json::from_str::<DeltaOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+/// An action of a Delta commit, a line of its JSON file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Action {
+    CommitInfo(CommitInfo),
+    Protocol(Protocol),
+    MetaData(Metadata),
+    Add(Add),
+    Remove(Remove),
+    Txn(Txn),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommitInfo {
+    pub(crate) timestamp: i64,
+    pub(crate) operation: String,
+    pub(crate) operation_parameters: BTreeMap<String, String>,
+    pub(crate) is_blind_append: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Protocol {
+    pub(crate) min_reader_version: i32,
+    pub(crate) min_writer_version: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Metadata {
+    pub(crate) id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) description: Option<String>,
+    pub(crate) format: Format,
+    /// The table's schema as a JSON string of a Spark struct type
+    pub(crate) schema_string: String,
+    pub(crate) partition_columns: Vec<String>,
+    pub(crate) configuration: BTreeMap<String, String>,
+    pub(crate) created_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct Format {
+    pub(crate) provider: String,
+    pub(crate) options: BTreeMap<String, String>,
+}
+
+/// A data file added to the table
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Add {
+    /// The file's path relative to the table's root
+    pub(crate) path: String,
+    pub(crate) partition_values: BTreeMap<String, Option<String>>,
+    pub(crate) size: u64,
+    pub(crate) modification_time: i64,
+    pub(crate) data_change: bool,
+    /// A JSON string of the file's row count and its top level columns' min, max and null counts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stats: Option<String>,
+}
+
+/// A data file removed from the table, kept in checkpoints as a tombstone for vacuuming
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Remove {
+    pub(crate) path: String,
+    pub(crate) deletion_timestamp: Option<i64>,
+    pub(crate) data_change: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) extended_file_metadata: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) partition_values: Option<BTreeMap<String, Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) size: Option<u64>,
+}
+
+/// The latest version of an application's transactions committed to the table
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Txn {
+    pub(crate) app_id: String,
+    pub(crate) version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) last_updated: Option<i64>,
+}
+
+/// A commit file to write in the table's directory
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct Commit {
+    /// The file's path relative to the table's root, e.g. `_delta_log/00000000000000000000.json`
+    pub(crate) path: String,
+    /// Its newline-delimited JSON actions
+    pub(crate) contents: String,
+}
+
+/// The Delta commit adding parquet files to a table, given as their paths relative to the table's
+/// root and their bytes. The first commit also creates the table, with a schema mapped from the
+/// files', which must all have the same one
+pub(crate) fn commit(
+    files: &[(String, Vec<u8>)],
+    options: &DeltaOptions,
+) -> Result<Commit, String> {
+    if files.is_empty() {
+        return Err("No files to commit".to_string());
+    }
+    let timestamp = options.timestamp.unwrap_or_else(crate::js::unix_millis);
+
+    let mut schema: Option<(&str, Vec<Value>)> = None;
+    let mut adds = vec![];
+    for (path, bytes) in files {
+        let fields = file_schema(bytes).map_err(|e| format!("File {}: {}", path, e))?;
+        match &schema {
+            Some((first, schema)) if *schema != fields => {
+                return Err(format!(
+                    "File {} has a different schema than {}",
+                    path, first
+                ))
+            }
+            Some(_) => {}
+            None => schema = Some((path, fields)),
+        }
+        adds.push(Action::Add(Add {
+            path: uri_path(path),
+            partition_values: partition_values(path, &options.partition_columns)?,
+            size: bytes.len() as u64,
+            modification_time: timestamp,
+            data_change: true,
+            stats: Some(file_stats(bytes).map_err(|e| format!("File {}: {}", path, e))?),
+        }));
+    }
+
+    let mode = match options.version {
+        0 => "ErrorIfExists",
+        _ => "Append",
+    };
+    let partition_by = serde_json::to_string(&options.partition_columns).unwrap_or_default();
+    let mut actions = vec![Action::CommitInfo(CommitInfo {
+        timestamp,
+        operation: "WRITE".to_string(),
+        operation_parameters: BTreeMap::from([
+            ("mode".to_string(), mode.to_string()),
+            ("partitionBy".to_string(), partition_by),
+        ]),
+        is_blind_append: true,
+    })];
+    if options.version == 0 {
+        let Some(id) = options.table_id.clone() else {
+            return Err("table_id is required to create a table".to_string());
+        };
+        let mut fields = schema.map(|(_, fields)| fields).unwrap_or_default();
+        for column in &options.partition_columns {
+            if !fields.iter().any(|field| field["name"] == column.as_str()) {
+                fields.push(
+                    json!({"name": column, "type": "string", "nullable": true, "metadata": {}}),
+                );
+            }
+        }
+        actions.push(Action::Protocol(Protocol {
+            min_reader_version: MIN_READER_VERSION,
+            min_writer_version: MIN_WRITER_VERSION,
+        }));
+        actions.push(Action::MetaData(Metadata {
+            id,
+            name: options.name.clone(),
+            description: options.description.clone(),
+            format: Format {
+                provider: "parquet".to_string(),
+                options: BTreeMap::new(),
+            },
+            schema_string: json!({"type": "struct", "fields": fields}).to_string(),
+            partition_columns: options.partition_columns.clone(),
+            configuration: options.configuration.clone(),
+            created_time: Some(timestamp),
+        }));
+    }
+    actions.extend(adds);
+
+    let mut contents = String::new();
+    for action in &actions {
+        match serde_json::to_string(action) {
+            Ok(line) => contents.push_str(&line),
+            Err(e) => return Err(format!("Error serializing action: {}", e)),
+        }
+        contents.push('\n');
+    }
+    Ok(Commit {
+        path: format!("{}/{:020}.json", LOG_DIRECTORY, options.version),
+        contents,
+    })
+}
+
+/// A checkpoint of a table's state, to write to its `_delta_log` directory along with
+/// `_last_checkpoint` so readers can start from it rather than replaying every commit
+#[derive(Debug)]
+pub(crate) struct Checkpoint {
+    /// The checkpoint's path relative to the table's root, e.g.
+    /// `_delta_log/00000000000000000010.checkpoint.parquet`
+    pub(crate) path: String,
+    pub(crate) bytes: Vec<u8>,
+    /// The contents of `_delta_log/_last_checkpoint`, pointing readers at the checkpoint
+    pub(crate) last_checkpoint: String,
+}
+
+/// The checkpoint of a table after the commits of versions 0 to N, given as their contents in
+/// order. It holds the latest protocol, metadata and transaction of each application, the files
+/// in the table and the tombstones of those removed from it, a row for each
+pub(crate) fn checkpoint(commits: &[String]) -> Result<Checkpoint, String> {
+    let Some(version) = commits.len().checked_sub(1) else {
+        return Err("No commits to checkpoint".to_string());
+    };
+    let actions = table_state(commits)?;
+    let batch = checkpoint_batch(&actions)?;
+    let mut sink = Sink::new(batch.schema(), WriterProperties::builder().build())?;
+    sink.write(&batch)?;
+    let last_checkpoint = json!({"version": version, "size": actions.len()});
+    Ok(Checkpoint {
+        path: format!("{}/{:020}.checkpoint.parquet", LOG_DIRECTORY, version),
+        bytes: sink.finish()?,
+        last_checkpoint: last_checkpoint.to_string(),
+    })
+}
+
+/// Replay commits into the actions a checkpoint of them holds. Later actions replace earlier ones
+/// of the same kind: the protocol, the metadata, an application's transaction and an add or
+/// remove of a path. Other actions, such as commit info, aren't part of a table's state
+fn table_state(commits: &[String]) -> Result<Vec<Action>, String> {
+    let (mut protocol, mut metadata) = (None, None);
+    let mut txns = BTreeMap::new();
+    let mut files = BTreeMap::new();
+    for (version, commit) in commits.iter().enumerate() {
+        for (line, text) in commit.lines().enumerate() {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let error =
+                |e: serde_json::Error| format!("Commit {} line {}: {}", version, line + 1, e);
+            let value = serde_json::from_str::<Value>(text).map_err(error)?;
+            let kind = value
+                .as_object()
+                .and_then(|action| action.keys().next())
+                .map(String::as_str);
+            if !matches!(
+                kind,
+                Some("protocol" | "metaData" | "add" | "remove" | "txn")
+            ) {
+                continue;
+            }
+            if !value["add"]["deletionVector"].is_null() {
+                return Err(format!(
+                    "Commit {} line {}: deletion vectors aren't supported",
+                    version,
+                    line + 1
+                ));
+            }
+            match serde_json::from_value::<Action>(value).map_err(error)? {
+                Action::Protocol(action) => protocol = Some(action),
+                Action::MetaData(action) => metadata = Some(action),
+                Action::Txn(action) => {
+                    txns.insert(action.app_id.clone(), action);
+                }
+                Action::Add(action) => {
+                    files.insert(action.path.clone(), Action::Add(action));
+                }
+                Action::Remove(action) => {
+                    files.insert(action.path.clone(), Action::Remove(action));
+                }
+                Action::CommitInfo(_) => {}
+            }
+        }
+    }
+    let (Some(protocol), Some(metadata)) = (protocol, metadata) else {
+        return Err("The commits don't create a table, with a protocol and metaData".to_string());
+    };
+    let mut actions = vec![Action::Protocol(protocol), Action::MetaData(metadata)];
+    actions.extend(txns.into_values().map(Action::Txn));
+    // Files in a checkpoint are the table's state rather than changes to it
+    actions.extend(files.into_values().map(|file| match file {
+        Action::Add(add) => Action::Add(Add {
+            data_change: false,
+            ..add
+        }),
+        file => file,
+    }));
+    Ok(actions)
+}
+
+/// A record batch of checkpoint actions, with a struct column for each kind of action that's only
+/// set in the rows of that kind, as Delta's checkpoint schema has it
+fn checkpoint_batch(actions: &[Action]) -> Result<arrow_array::RecordBatch, String> {
+    let protocols = actions
+        .iter()
+        .map(|action| match action {
+            Action::Protocol(protocol) => Some(protocol),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let metadatas = actions
+        .iter()
+        .map(|action| match action {
+            Action::MetaData(metadata) => Some(metadata),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let txns = actions
+        .iter()
+        .map(|action| match action {
+            Action::Txn(txn) => Some(txn),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let adds = actions
+        .iter()
+        .map(|action| match action {
+            Action::Add(add) => Some(add),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let removes = actions
+        .iter()
+        .map(|action| match action {
+            Action::Remove(remove) => Some(remove),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let txn = struct_array(
+        &txns,
+        vec![
+            ("appId", strings(&txns, |t| Some(&t.app_id))),
+            ("version", longs(&txns, |t| Some(t.version))),
+            ("lastUpdated", longs(&txns, |t| t.last_updated)),
+        ],
+    )?;
+    let add = struct_array(
+        &adds,
+        vec![
+            ("path", strings(&adds, |a| Some(&a.path))),
+            (
+                "partitionValues",
+                string_map(&adds, |a| Some(a.partition_values.clone())),
+            ),
+            ("size", longs(&adds, |a| Some(a.size as i64))),
+            (
+                "modificationTime",
+                longs(&adds, |a| Some(a.modification_time)),
+            ),
+            ("dataChange", booleans(&adds, |a| Some(a.data_change))),
+            ("stats", strings(&adds, |a| a.stats.as_ref())),
+        ],
+    )?;
+    let remove = struct_array(
+        &removes,
+        vec![
+            ("path", strings(&removes, |r| Some(&r.path))),
+            (
+                "deletionTimestamp",
+                longs(&removes, |r| r.deletion_timestamp),
+            ),
+            ("dataChange", booleans(&removes, |r| Some(r.data_change))),
+            (
+                "extendedFileMetadata",
+                booleans(&removes, |r| r.extended_file_metadata),
+            ),
+            (
+                "partitionValues",
+                string_map(&removes, |r| r.partition_values.clone()),
+            ),
+            ("size", longs(&removes, |r| r.size.map(|size| size as i64))),
+        ],
+    )?;
+    let mut partition_columns = ListBuilder::new(StringBuilder::new());
+    for metadata in &metadatas {
+        if let Some(metadata) = metadata {
+            for column in &metadata.partition_columns {
+                partition_columns.values().append_value(column);
+            }
+        }
+        partition_columns.append(metadata.is_some());
+    }
+    let format = struct_array(
+        &metadatas,
+        vec![
+            (
+                "provider",
+                strings(&metadatas, |m| Some(&m.format.provider)),
+            ),
+            (
+                "options",
+                string_map(&metadatas, |m| Some(to_optional(&m.format.options))),
+            ),
+        ],
+    )?;
+    let metadata = struct_array(
+        &metadatas,
+        vec![
+            ("id", strings(&metadatas, |m| Some(&m.id))),
+            ("name", strings(&metadatas, |m| m.name.as_ref())),
+            (
+                "description",
+                strings(&metadatas, |m| m.description.as_ref()),
+            ),
+            ("format", format),
+            (
+                "schemaString",
+                strings(&metadatas, |m| Some(&m.schema_string)),
+            ),
+            ("partitionColumns", Arc::new(partition_columns.finish())),
+            (
+                "configuration",
+                string_map(&metadatas, |m| Some(to_optional(&m.configuration))),
+            ),
+            ("createdTime", longs(&metadatas, |m| m.created_time)),
+        ],
+    )?;
+    let protocol = struct_array(
+        &protocols,
+        vec![
+            (
+                "minReaderVersion",
+                Arc::new(Int32Array::from(
+                    protocols
+                        .iter()
+                        .map(|p| p.map(|p| p.min_reader_version))
+                        .collect::<Vec<_>>(),
+                )),
+            ),
+            (
+                "minWriterVersion",
+                Arc::new(Int32Array::from(
+                    protocols
+                        .iter()
+                        .map(|p| p.map(|p| p.min_writer_version))
+                        .collect::<Vec<_>>(),
+                )),
+            ),
+        ],
+    )?;
+
+    let columns = vec![
+        ("txn", txn),
+        ("add", add),
+        ("remove", remove),
+        ("metaData", metadata),
+        ("protocol", protocol),
+    ];
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, column)| Field::new(*name, column.data_type().clone(), true))
+            .collect::<Vec<_>>(),
+    );
+    let columns = columns.into_iter().map(|(_, column)| column).collect();
+    arrow_array::RecordBatch::try_new(Arc::new(schema), columns)
+        .map_err(|e| format!("Error building checkpoint: {}", e))
+}
+
+/// A nullable struct column, null in the rows without a value
+fn struct_array<T>(rows: &[Option<T>], fields: Vec<(&str, ArrayRef)>) -> Result<ArrayRef, String> {
+    let (fields, columns): (Vec<_>, Vec<_>) = fields
+        .into_iter()
+        .map(|(name, column)| (Field::new(name, column.data_type().clone(), true), column))
+        .unzip();
+    let nulls = NullBuffer::from(rows.iter().map(Option::is_some).collect::<Vec<_>>());
+    match StructArray::try_new(Fields::from(fields), columns, Some(nulls)) {
+        Ok(array) => Ok(Arc::new(array)),
+        Err(e) => Err(format!("Error building checkpoint: {}", e)),
+    }
+}
+
+fn strings<'a, T>(rows: &[Option<&'a T>], value: impl Fn(&'a T) -> Option<&'a String>) -> ArrayRef {
+    Arc::new(StringArray::from(
+        rows.iter()
+            .map(|row| row.and_then(&value).map(String::as_str))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+fn longs<T>(rows: &[Option<&T>], value: impl Fn(&T) -> Option<i64>) -> ArrayRef {
+    Arc::new(Int64Array::from(
+        rows.iter()
+            .map(|row| row.and_then(&value))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+fn booleans<T>(rows: &[Option<&T>], value: impl Fn(&T) -> Option<bool>) -> ArrayRef {
+    Arc::new(BooleanArray::from(
+        rows.iter()
+            .map(|row| row.and_then(&value))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// A map of strings to nullable strings, with the field names Spark gives maps
+fn string_map<'a, T>(
+    rows: &[Option<&'a T>],
+    value: impl Fn(&'a T) -> Option<BTreeMap<String, Option<String>>>,
+) -> ArrayRef {
+    let names = MapFieldNames {
+        entry: "key_value".to_string(),
+        key: "key".to_string(),
+        value: "value".to_string(),
+    };
+    let mut builder = MapBuilder::new(Some(names), StringBuilder::new(), StringBuilder::new());
+    for row in rows {
+        let map = row.and_then(&value);
+        for (key, value) in map.iter().flatten() {
+            builder.keys().append_value(key);
+            builder.values().append_option(value.as_ref());
+        }
+        // Only fails when there are fewer values than keys, which can't happen here
+        let _ = builder.append(map.is_some());
+    }
+    Arc::new(builder.finish())
+}
+
+fn to_optional(map: &BTreeMap<String, String>) -> BTreeMap<String, Option<String>> {
+    map.iter()
+        .map(|(key, value)| (key.clone(), Some(value.clone())))
+        .collect()
+}
+
+/// The partition values of a file from the `column=value` directories of its path
+fn partition_values(
+    path: &str,
+    partition_columns: &[String],
+) -> Result<BTreeMap<String, Option<String>>, String> {
+    let directories = path.split('/').rev().skip(1).collect::<Vec<_>>();
+    let mut values = BTreeMap::new();
+    for column in partition_columns {
+        let value = directories.iter().find_map(|directory| {
+            let (name, value) = directory.split_once('=')?;
+            (name == column).then_some(value)
+        });
+        match value {
+            Some(NULL_PARTITION) => values.insert(column.clone(), None),
+            Some(value) => values.insert(
+                column.clone(),
+                Some(writer::unescape_partition_value(value)),
+            ),
+            None => {
+                return Err(format!(
+                    "File {} has no value for partition column {}",
+                    path, column
+                ))
+            }
+        };
+    }
+    Ok(values)
+}
+
+/// A file's path as the relative URI Delta readers decode, percent-encoding the UTF-8 bytes of
+/// everything but the characters RFC 2396 allows in a path, so the `%` of an escaped partition
+/// value is kept
+fn uri_path(path: &str) -> String {
+    let mut uri = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'!'
+            | b'~'
+            | b'*'
+            | b'\''
+            | b'('
+            | b')'
+            | b'/'
+            | b':'
+            | b'@'
+            | b'&'
+            | b'='
+            | b'+'
+            | b'$'
+            | b',' => uri.push(byte as char),
+            byte => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The fields of a parquet file's schema as Spark struct fields, the schema Delta tables declare
+fn file_schema(bytes: &[u8]) -> Result<Vec<Value>, String> {
+    let file = reader::open(bytes.to_vec())?;
+    let metadata = file.metadata().file_metadata();
+    let schema = parquet_to_arrow_schema(metadata.schema_descr(), metadata.key_value_metadata())
+        .map_err(|e| format!("Error reading schema: {}", e))?;
+    schema
+        .fields()
+        .iter()
+        .map(|field| struct_field(field))
+        .collect()
+}
+
+fn struct_field(field: &Field) -> Result<Value, String> {
+    Ok(json!({
+        "name": field.name(),
+        "type": spark_type(field)?,
+        "nullable": field.is_nullable(),
+        "metadata": {},
+    }))
+}
+
+fn struct_type(fields: &Fields) -> Result<Value, String> {
+    let fields = fields
+        .iter()
+        .map(|field| struct_field(field))
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(json!({"type": "struct", "fields": fields}))
+}
+
+/// The Spark type of a field's values
+fn spark_type(field: &Field) -> Result<Value, String> {
+    let name = match field.data_type() {
+        DataType::Boolean => "boolean",
+        DataType::Int8 => "byte",
+        DataType::Int16 | DataType::UInt8 => "short",
+        DataType::Int32 | DataType::UInt16 => "integer",
+        DataType::Int64 | DataType::UInt32 => "long",
+        DataType::UInt64 => "decimal(20,0)",
+        DataType::Float32 => "float",
+        DataType::Float64 => "double",
+        DataType::Utf8 | DataType::LargeUtf8 => "string",
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => "binary",
+        DataType::Date32 | DataType::Date64 => "date",
+        DataType::Timestamp(_, _) => "timestamp",
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            return Ok(Value::String(format!("decimal({},{})", precision, scale)))
+        }
+        DataType::List(element) | DataType::LargeList(element) => {
+            return Ok(json!({
+                "type": "array",
+                "elementType": spark_type(element)?,
+                "containsNull": element.is_nullable(),
+            }))
+        }
+        DataType::Map(entries, _) => {
+            let DataType::Struct(entries) = entries.data_type() else {
+                return Err(format!("Unsupported map type for field {}", field.name()));
+            };
+            let [key, value] = &entries.iter().collect::<Vec<_>>()[..] else {
+                return Err(format!("Unsupported map type for field {}", field.name()));
+            };
+            return Ok(json!({
+                "type": "map",
+                "keyType": spark_type(key)?,
+                "valueType": spark_type(value)?,
+                "valueContainsNull": value.is_nullable(),
+            }));
+        }
+        DataType::Struct(fields) => return struct_type(fields),
+        data_type => {
+            return Err(format!(
+                "Field {} has type {}, which Delta tables don't support",
+                field.name(),
+                data_type
+            ))
+        }
+    };
+    Ok(Value::String(name.to_string()))
+}
+
+/// A parquet file's Delta statistics as a JSON string, the min and max of top level columns whose
+/// statistics can be compared as Delta does, and the null count of every top level column
+fn file_stats(bytes: &[u8]) -> Result<String, String> {
+    let file = reader::open(bytes.to_vec())?;
+    let metadata = file.metadata();
+    let statistics = reader::statistics(bytes.to_vec())?;
+
+    let (mut min_values, mut max_values, mut null_count) = (Map::new(), Map::new(), Map::new());
+    let columns = metadata.file_metadata().schema_descr().columns();
+    for (column, statistics) in columns.iter().zip(statistics.columns) {
+        if column.path().parts().len() > 1 {
+            continue;
+        }
+        let name = column.name().to_string();
+        if let Some(nulls) = statistics.null_count {
+            null_count.insert(name.clone(), Value::from(nulls));
+        }
+        let min = statistics.min.and_then(|min| stats_value(min, column));
+        let max = statistics.max.and_then(|max| stats_value(max, column));
+        if let (Some(min), Some(max)) = (min, max) {
+            min_values.insert(name.clone(), min);
+            max_values.insert(name, max);
+        }
+    }
+    let stats = json!({
+        "numRecords": metadata.file_metadata().num_rows(),
+        "minValues": min_values,
+        "maxValues": max_values,
+        "nullCount": null_count,
+    });
+    Ok(stats.to_string())
+}
+
+/// A statistic as Delta writes it: numbers and strings as they are, dates as `YYYY-MM-DD` and
+/// timestamps in RFC 3339. None for types Delta doesn't keep a min and max of, or ones whose
+/// statistics don't order values the same way, such as decimals and binary
+fn stats_value(value: Value, column: &ColumnDescriptor) -> Option<Value> {
+    let timestamp = |n: i64, unit: &TimeUnit| match unit {
+        TimeUnit::MILLIS(_) => DateTime::from_timestamp_millis(n),
+        TimeUnit::MICROS(_) => DateTime::from_timestamp_micros(n),
+        TimeUnit::NANOS(_) => DateTime::from_timestamp(
+            n.div_euclid(1_000_000_000),
+            n.rem_euclid(1_000_000_000) as u32,
+        ),
+    };
+    let timestamp_value = |timestamp: DateTime<Utc>| {
+        Value::String(timestamp.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+    };
+    match (column.logical_type(), column.converted_type()) {
+        (Some(LogicalType::Date), _) | (None, ConvertedType::DATE) => {
+            let days = chrono::Duration::days(value.as_i64()?);
+            let date = NaiveDate::default().checked_add_signed(days)?;
+            Some(Value::String(date.format("%Y-%m-%d").to_string()))
+        }
+        (Some(LogicalType::Timestamp { unit, .. }), _) => {
+            timestamp(value.as_i64()?, &unit).map(timestamp_value)
+        }
+        (None, ConvertedType::TIMESTAMP_MILLIS) => {
+            DateTime::from_timestamp_millis(value.as_i64()?).map(timestamp_value)
+        }
+        (None, ConvertedType::TIMESTAMP_MICROS) => {
+            DateTime::from_timestamp_micros(value.as_i64()?).map(timestamp_value)
+        }
+        (Some(LogicalType::Decimal { .. }), _) | (None, ConvertedType::DECIMAL) => None,
+        _ if reader::is_text(column) => Some(value),
+        _ => match value {
+            Value::Number(_) => Some(value),
+            _ => None,
+        },
+    }
+}
+
+#[test]
+fn test_commit() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "day", "type": "INT32", "logical_type": "DATE"},
+            {"name": "ts", "type": "INT64", "logical_type": "TIMESTAMP_MILLIS"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let write =
+        |ids: std::ops::Range<i64>| {
+            let files = ids
+            .map(|id| {
+                format!(
+                    r#"{{"id": {}, "name": {}, "day": "2024-01-0{}", "ts": {}, "tags": ["t"]}}"#,
+                    id,
+                    if id % 2 == 0 { "null".to_string() } else { format!("\"n{}\"", id) },
+                    id + 1,
+                    1_700_000_000_000 + id
+                )
+            })
+            .collect();
+            crate::write_parquet(schema.to_string(), files, &Default::default()).unwrap()
+        };
+    let files = vec![
+        ("region=eu/part-0.parquet".to_string(), write(0..3)),
+        (
+            format!("region={}/part-1.parquet", NULL_PARTITION),
+            write(3..5),
+        ),
+    ];
+    let options = parse_delta_options(
+        r#"{"table_id": "5b2f7c1e-0000-4000-8000-000000000000", "partition_columns": ["region"], "timestamp": 1700000000000}"#,
+    )
+    .unwrap();
+    let created = commit(&files, &options).unwrap();
+    assert_eq!(created.path, "_delta_log/00000000000000000000.json");
+    let actions = created
+        .contents
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(actions.len(), 5);
+    assert_eq!(actions[0]["commitInfo"]["operation"], "WRITE");
+    assert_eq!(
+        actions[1],
+        json!({"protocol": {"minReaderVersion": 1, "minWriterVersion": 2}})
+    );
+    let metadata = &actions[2]["metaData"];
+    assert_eq!(metadata["id"], "5b2f7c1e-0000-4000-8000-000000000000");
+    assert_eq!(metadata["partitionColumns"], json!(["region"]));
+    let schema = serde_json::from_str::<Value>(metadata["schemaString"].as_str().unwrap()).unwrap();
+    let types = schema["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|field| (field["name"].as_str().unwrap(), field["type"].clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            ("id", json!("long")),
+            ("name", json!("string")),
+            ("day", json!("date")),
+            ("ts", json!("timestamp")),
+            (
+                "tags",
+                json!({"type": "array", "elementType": "string", "containsNull": true})
+            ),
+            ("region", json!("string")),
+        ]
+    );
+
+    let add = &actions[3]["add"];
+    assert_eq!(add["path"], "region=eu/part-0.parquet");
+    assert_eq!(add["partitionValues"], json!({"region": "eu"}));
+    assert_eq!(add["size"], files[0].1.len());
+    assert_eq!(add["dataChange"], true);
+    let stats = serde_json::from_str::<Value>(add["stats"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        stats,
+        json!({
+            "numRecords": 3,
+            "minValues": {"id": 0, "name": "n1", "day": "2024-01-01", "ts": "2023-11-14T22:13:20.000Z"},
+            "maxValues": {"id": 2, "name": "n1", "day": "2024-01-03", "ts": "2023-11-14T22:13:20.002Z"},
+            "nullCount": {"id": 0, "name": 2, "day": 0, "ts": 0}
+        })
+    );
+    assert_eq!(
+        actions[4]["add"]["partitionValues"],
+        json!({"region": null})
+    );
+
+    let options =
+        parse_delta_options(r#"{"version": 12, "partition_columns": ["region"]}"#).unwrap();
+    let appended = commit(&files[..1], &options).unwrap();
+    assert_eq!(appended.path, "_delta_log/00000000000000000012.json");
+    assert_eq!(appended.contents.lines().count(), 2);
+
+    let options = parse_delta_options(r#"{"partition_columns": ["region"]}"#).unwrap();
+    assert_eq!(
+        commit(&files, &options).unwrap_err(),
+        "table_id is required to create a table"
+    );
+    let options =
+        parse_delta_options(r#"{"version": 1, "partition_columns": ["country"]}"#).unwrap();
+    assert_eq!(
+        commit(&files, &options).unwrap_err(),
+        "File region=eu/part-0.parquet has no value for partition column country"
+    );
+    let other = crate::write_parquet(
+        r#"{"fields": [{"name": "id", "type": "INT32"}]}"#.to_string(),
+        vec![r#"{"id": 1}"#.to_string()],
+        &Default::default(),
+    )
+    .unwrap();
+    let mixed = vec![
+        files[0].clone(),
+        ("region=us/part-2.parquet".to_string(), other),
+    ];
+    let options =
+        parse_delta_options(r#"{"version": 1, "partition_columns": ["region"]}"#).unwrap();
+    assert_eq!(
+        commit(&mixed, &options).unwrap_err(),
+        "File region=us/part-2.parquet has a different schema than region=eu/part-0.parquet"
+    );
+
+    // Escaped partition directories are decoded for partitionValues and kept escaped in the URI
+    let schema = crate::CompiledSchema::parse(
+        r#"{"fields": [{"name": "id", "type": "INT64"}, {"name": "region", "type": "BYTE_ARRAY", "logical_type": "UTF8"}]}"#,
+    )
+    .unwrap();
+    let records = writer::parse_records(
+        &[r#"{"id": 1, "region": "a/b 100%"}"#.to_string()],
+        Default::default(),
+    )
+    .unwrap();
+    let partitioned = crate::write_partitioned(
+        &schema,
+        &records,
+        &["region".to_string()],
+        &Default::default(),
+    )
+    .unwrap();
+    assert_eq!(partitioned[0].0, "region=a%2Fb 100%25/part-0000.parquet");
+    let options =
+        parse_delta_options(r#"{"version": 1, "partition_columns": ["region"]}"#).unwrap();
+    let appended = commit(&partitioned, &options).unwrap();
+    let add = serde_json::from_str::<Value>(appended.contents.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(
+        add["add"]["path"],
+        "region=a%252Fb%20100%2525/part-0000.parquet"
+    );
+    assert_eq!(add["add"]["partitionValues"], json!({"region": "a/b 100%"}));
+}
+
+#[test]
+fn test_checkpoint() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}]}"#;
+    let file = |id: i64| {
+        let records = vec![format!(r#"{{"id": {}}}"#, id)];
+        crate::write_parquet(schema.to_string(), records, &Default::default()).unwrap()
+    };
+    let options = parse_delta_options(
+        r#"{"table_id": "t", "partition_columns": ["region"], "configuration": {"delta.appendOnly": "false"}, "timestamp": 1}"#,
+    )
+    .unwrap();
+    let created = commit(
+        &[
+            ("region=eu/a.parquet".to_string(), file(1)),
+            ("region=us/b.parquet".to_string(), file(2)),
+        ],
+        &options,
+    )
+    .unwrap();
+    let options =
+        parse_delta_options(r#"{"version": 1, "partition_columns": ["region"], "timestamp": 2}"#)
+            .unwrap();
+    let appended = commit(&[("region=eu/c.parquet".to_string(), file(3))], &options).unwrap();
+    let removed = [
+        r#"{"commitInfo": {"timestamp": 3, "operation": "DELETE", "engineInfo": "other"}}"#,
+        r#"{"remove": {"path": "region=us/b.parquet", "deletionTimestamp": 3, "dataChange": true}}"#,
+        r#"{"txn": {"appId": "ingest", "version": 7}}"#,
+    ]
+    .join("\n");
+    let commits = vec![created.contents, appended.contents, removed];
+
+    let written = checkpoint(&commits).unwrap();
+    assert_eq!(
+        written.path,
+        "_delta_log/00000000000000000002.checkpoint.parquet"
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(&written.last_checkpoint).unwrap(),
+        json!({"version": 2, "size": 6})
+    );
+    let rows = crate::reader::read_rows(written.bytes, Default::default()).unwrap();
+    assert_eq!(rows.len(), 6);
+    assert_eq!(
+        rows[0]["protocol"],
+        json!({"minReaderVersion": 1, "minWriterVersion": 2})
+    );
+    assert!(rows[0]["add"].is_null());
+    assert_eq!(rows[1]["metaData"]["id"], "t");
+    assert_eq!(rows[1]["metaData"]["partitionColumns"], json!(["region"]));
+    assert_eq!(
+        rows[1]["metaData"]["configuration"],
+        json!({"delta.appendOnly": "false"})
+    );
+    assert_eq!(rows[1]["metaData"]["format"]["provider"], "parquet");
+    assert_eq!(
+        rows[2]["txn"],
+        json!({"appId": "ingest", "version": 7, "lastUpdated": null})
+    );
+    let paths = rows[3..]
+        .iter()
+        .map(|row| match row["add"].is_null() {
+            true => ("remove", row["remove"]["path"].clone()),
+            false => ("add", row["add"]["path"].clone()),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            ("add", json!("region=eu/a.parquet")),
+            ("add", json!("region=eu/c.parquet")),
+            ("remove", json!("region=us/b.parquet")),
+        ]
+    );
+    assert_eq!(rows[3]["add"]["dataChange"], false);
+    assert_eq!(rows[3]["add"]["partitionValues"], json!({"region": "eu"}));
+
+    assert_eq!(checkpoint(&[]).unwrap_err(), "No commits to checkpoint");
+    assert_eq!(
+        checkpoint(&commits[1..]).unwrap_err(),
+        "The commits don't create a table, with a protocol and metaData"
+    );
+}
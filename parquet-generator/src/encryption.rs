@@ -0,0 +1,612 @@
+use crate::error::WriteError;
+use crate::options::EncryptionOptions;
+use aes_gcm::aead::consts::U12;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::aes::Aes192;
+use aes_gcm::{Aes128Gcm, Aes256Gcm, AesGcm, Nonce};
+use base64::Engine;
+use parquet::column::writer::ColumnCloseResult;
+use parquet::file::metadata::{ColumnChunkMetaData, RowGroupMetaData, RowGroupMetaDataPtr};
+use parquet::file::properties::WriterPropertiesPtr;
+use parquet::format::{
+    AesGcmV1, ColumnCryptoMetaData, ColumnIndex, ColumnMetaData, ColumnOrder, EncryptionAlgorithm,
+    EncryptionWithColumnKey, EncryptionWithFooterKey, FileCryptoMetaData, FileMetaData,
+    OffsetIndex, TypeDefinedOrder,
+};
+use parquet::schema::types::{self, ColumnDescPtr, SchemaDescPtr, SchemaDescriptor, TypePtr};
+use parquet::thrift::TSerializable;
+use std::io::Write;
+use std::sync::Arc;
+use thrift::protocol::TCompactOutputProtocol;
+
+/// The magic bytes at the start and end of a parquet file with a plaintext footer
+const PARQUET_MAGIC: &[u8; 4] = b"PAR1";
+
+/// The magic bytes at the start and end of a parquet file with an encrypted footer
+const PARQUET_MAGIC_ENCRYPTED_FOOTER: &[u8; 4] = b"PARE";
+
+/// The bytes of an AES-GCM nonce, which the spec fixes at 96 bits
+pub(crate) const NONCE_LENGTH: usize = 12;
+
+/// The bytes of an AES-GCM authentication tag
+pub(crate) const TAG_LENGTH: usize = 16;
+
+/// The bytes of the random part of every module's AAD, which ties the module to its file
+const AAD_FILE_UNIQUE_LENGTH: usize = 8;
+
+/// The kinds of module the parquet encryption spec encrypts separately, each with its own AAD
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Module {
+    Footer = 0,
+    ColumnMetaData = 1,
+    DataPage = 2,
+    DictionaryPage = 3,
+    DataPageHeader = 4,
+    DictionaryPageHeader = 5,
+    ColumnIndex = 6,
+    OffsetIndex = 7,
+}
+
+/// The keys a file's modules are encrypted with, decoded from `EncryptionOptions`
+pub(crate) struct FileEncryptor {
+    footer_key: AesKey,
+    footer_key_metadata: Option<Vec<u8>>,
+    encrypted_footer: bool,
+    /// Random bytes at the start of every module's AAD, so modules can't be swapped between files
+    aad_file_unique: Vec<u8>,
+    /// The key of each leaf column, None for columns encrypted with the footer key when the footer
+    /// is, and left in plaintext otherwise
+    columns: Vec<Option<ColumnKey>>,
+}
+
+struct ColumnKey {
+    key: AesKey,
+    key_metadata: Option<Vec<u8>>,
+}
+
+impl FileEncryptor {
+    pub(crate) fn new(
+        options: &EncryptionOptions,
+        columns: &[ColumnDescPtr],
+    ) -> Result<Self, String> {
+        let footer_key = match &options.footer_key {
+            Some(key) => aes_key(key).map_err(|e| format!("Invalid footer_key: {}", e))?,
+            None => return Err("Encryption needs a footer_key to sign the footer with".to_string()),
+        };
+        let field_name = |column: &ColumnDescPtr| column.path().parts()[0].clone();
+        if let Some(name) = options
+            .column_keys
+            .keys()
+            .find(|name| !columns.iter().any(|c| field_name(c) == **name))
+        {
+            return Err(format!("Unknown field {} in column_keys", name));
+        }
+        let columns = columns
+            .iter()
+            .map(|column| {
+                let name = field_name(column);
+                let Some(key) = options.column_keys.get(&name) else {
+                    return Ok(None);
+                };
+                Ok(Some(ColumnKey {
+                    key: aes_key(&key.key)
+                        .map_err(|e| format!("Invalid key for field {}: {}", name, e))?,
+                    key_metadata: key.key_metadata.clone().map(String::into_bytes),
+                }))
+            })
+            .collect::<Result<_, String>>()?;
+        let mut aad_file_unique = vec![0; AAD_FILE_UNIQUE_LENGTH];
+        getrandom::getrandom(&mut aad_file_unique)
+            .map_err(|e| format!("Couldn't generate the file's AAD: {}", e))?;
+        Ok(FileEncryptor {
+            footer_key,
+            footer_key_metadata: options.footer_key_metadata.clone().map(String::into_bytes),
+            encrypted_footer: options.encrypted_footer,
+            aad_file_unique,
+            columns,
+        })
+    }
+
+    /// The encryptor of a column chunk's modules, None when the column is left in plaintext
+    pub(crate) fn column(
+        &self,
+        row_group: usize,
+        column: usize,
+    ) -> Result<Option<ModuleEncryptor<'_>>, WriteError> {
+        let key = match &self.columns[column] {
+            Some(key) => &key.key,
+            None if self.encrypted_footer => &self.footer_key,
+            None => return Ok(None),
+        };
+        let row_group = i16::try_from(row_group)
+            .map_err(|_| WriteError::from("Too many row groups to encrypt".to_string()))?;
+        let column = i16::try_from(column)
+            .map_err(|_| WriteError::from("Too many columns to encrypt".to_string()))?;
+        Ok(Some(ModuleEncryptor {
+            key,
+            aad_file_unique: &self.aad_file_unique,
+            row_group,
+            column,
+        }))
+    }
+
+    fn algorithm(&self) -> EncryptionAlgorithm {
+        EncryptionAlgorithm::AESGCMV1(AesGcmV1 {
+            aad_prefix: None,
+            aad_file_unique: Some(self.aad_file_unique.clone()),
+            supply_aad_prefix: None,
+        })
+    }
+
+    fn footer_aad(&self) -> Vec<u8> {
+        let mut aad = self.aad_file_unique.clone();
+        aad.push(Module::Footer as u8);
+        aad
+    }
+}
+
+/// Encrypts the modules of a single column chunk, which share the key and the row group and column
+/// ordinals of their AADs
+pub(crate) struct ModuleEncryptor<'a> {
+    key: &'a AesKey,
+    aad_file_unique: &'a [u8],
+    row_group: i16,
+    column: i16,
+}
+
+impl ModuleEncryptor<'_> {
+    /// Encrypt one of the column chunk's modules, `page` is the ordinal of the data page among the
+    /// chunk's data pages
+    pub(crate) fn encrypt(
+        &self,
+        module: Module,
+        page: usize,
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let aad = module_aad(
+            self.aad_file_unique,
+            module,
+            self.row_group,
+            self.column,
+            page,
+        )?;
+        encrypt_module(self.key, &aad, plaintext)
+    }
+}
+
+/// The AAD of a column chunk's module, the file's unique bytes followed by the module type and the
+/// ordinals of its row group and column, and its page for data pages and their headers
+pub(crate) fn module_aad(
+    aad_file_unique: &[u8],
+    module: Module,
+    row_group: i16,
+    column: i16,
+    page: usize,
+) -> Result<Vec<u8>, String> {
+    let mut aad = aad_file_unique.to_vec();
+    aad.push(module as u8);
+    aad.extend_from_slice(&row_group.to_le_bytes());
+    aad.extend_from_slice(&column.to_le_bytes());
+    if matches!(module, Module::DataPage | Module::DataPageHeader) {
+        let page = i16::try_from(page)
+            .map_err(|_| "Too many pages in a column chunk to encrypt".to_string())?;
+        aad.extend_from_slice(&page.to_le_bytes());
+    }
+    Ok(aad)
+}
+
+/// Encrypt a module into the length, nonce, ciphertext and tag the spec lays it out as
+fn encrypt_module(key: &AesKey, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    let (nonce, sealed) = seal(key, aad, plaintext)?;
+    let length = (NONCE_LENGTH + sealed.len()) as u32;
+    let mut module = Vec::with_capacity(4 + length as usize);
+    module.extend_from_slice(&length.to_le_bytes());
+    module.extend_from_slice(&nonce);
+    module.extend_from_slice(&sealed);
+    Ok(module)
+}
+
+/// Encrypt with a random nonce, returning the nonce along with the ciphertext and tag
+fn seal(
+    key: &AesKey,
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<([u8; NONCE_LENGTH], Vec<u8>), String> {
+    let mut nonce = [0; NONCE_LENGTH];
+    getrandom::getrandom(&mut nonce).map_err(|e| format!("Couldn't generate a nonce: {}", e))?;
+    let sealed = key.encrypt(&nonce, plaintext, aad)?;
+    Ok((nonce, sealed))
+}
+
+/// Decrypt the module at the start of `bytes`, returning its plaintext and how many bytes it took
+#[cfg(test)]
+pub(crate) fn decrypt_module(key: &AesKey, aad: &[u8], bytes: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let length = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+    let nonce = bytes.get(4..4 + NONCE_LENGTH)?.try_into().ok()?;
+    let plaintext = key.decrypt(&nonce, bytes.get(4 + NONCE_LENGTH..4 + length)?, aad)?;
+    Some((plaintext, 4 + length))
+}
+
+fn aes_key(key: &str) -> Result<AesKey, String> {
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(key)
+        .map_err(|e| e.to_string())?;
+    AesKey::new(&key)
+}
+
+/// An AES-GCM key of any of the sizes the spec allows
+pub(crate) enum AesKey {
+    Aes128(Aes128Gcm),
+    Aes192(AesGcm<Aes192, U12>),
+    Aes256(Aes256Gcm),
+}
+
+impl AesKey {
+    pub(crate) fn new(key: &[u8]) -> Result<Self, String> {
+        match key.len() {
+            16 => Ok(AesKey::Aes128(Aes128Gcm::new(key.into()))),
+            24 => Ok(AesKey::Aes192(AesGcm::new(key.into()))),
+            32 => Ok(AesKey::Aes256(Aes256Gcm::new(key.into()))),
+            length => Err(format!(
+                "AES keys must be 16, 24 or 32 bytes, got {}",
+                length
+            )),
+        }
+    }
+
+    /// Encrypt `plaintext`, returning the ciphertext followed by the tag
+    pub(crate) fn encrypt(
+        &self,
+        nonce: &[u8; NONCE_LENGTH],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let nonce = Nonce::from_slice(nonce);
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        match self {
+            AesKey::Aes128(cipher) => cipher.encrypt(nonce, payload),
+            AesKey::Aes192(cipher) => cipher.encrypt(nonce, payload),
+            AesKey::Aes256(cipher) => cipher.encrypt(nonce, payload),
+        }
+        .map_err(|_| "Module is too large to encrypt".to_string())
+    }
+
+    /// Decrypt the ciphertext and tag of `sealed`, None when the tag doesn't match
+    #[cfg(test)]
+    pub(crate) fn decrypt(
+        &self,
+        nonce: &[u8; NONCE_LENGTH],
+        sealed: &[u8],
+        aad: &[u8],
+    ) -> Option<Vec<u8>> {
+        let nonce = Nonce::from_slice(nonce);
+        let payload = Payload { msg: sealed, aad };
+        match self {
+            AesKey::Aes128(cipher) => cipher.decrypt(nonce, payload),
+            AesKey::Aes192(cipher) => cipher.decrypt(nonce, payload),
+            AesKey::Aes256(cipher) => cipher.decrypt(nonce, payload),
+        }
+        .ok()
+    }
+}
+
+/// Writes an encrypted file in place of parquet's `SerializedFileWriter`, which writes a plaintext
+/// copy of each column chunk's metadata after its pages and can't encrypt the page index or the
+/// column metadata in the footer. Column chunks come already encrypted by `ColumnPageWriter` and
+/// are copied as parquet's own row group writer would. The footer is either encrypted with the
+/// footer key, or left in plaintext for readers without the keys and signed with the footer key
+pub(crate) struct EncryptedFileWriter<W: Write> {
+    sink: W,
+    bytes_written: usize,
+    message_type: TypePtr,
+    schema: SchemaDescPtr,
+    properties: WriterPropertiesPtr,
+    encryptor: Arc<FileEncryptor>,
+    row_groups: Vec<RowGroupMetaDataPtr>,
+    /// The column and offset index of each column chunk of each row group
+    indexes: Vec<Vec<ColumnIndexes>>,
+}
+
+type ColumnIndexes = (Option<ColumnIndex>, Option<OffsetIndex>);
+
+impl<W: Write> EncryptedFileWriter<W> {
+    pub(crate) fn new(
+        sink: W,
+        message_type: TypePtr,
+        properties: WriterPropertiesPtr,
+        encryptor: Arc<FileEncryptor>,
+    ) -> Result<Self, WriteError> {
+        let schema = Arc::new(SchemaDescriptor::new(message_type.clone()));
+        if let Some(column) = schema
+            .columns()
+            .iter()
+            .find(|column| properties.bloom_filter_properties(column.path()).is_some())
+        {
+            return Err(WriteError::from(format!(
+                "Bloom filters can't be written to encrypted files, but column {} has one",
+                column.path().string()
+            )));
+        }
+        let mut writer = EncryptedFileWriter {
+            sink,
+            bytes_written: 0,
+            message_type,
+            schema,
+            properties,
+            encryptor,
+            row_groups: vec![],
+            indexes: vec![],
+        };
+        writer.write(writer.magic())?;
+        Ok(writer)
+    }
+
+    pub(crate) fn schema_descr(&self) -> &SchemaDescriptor {
+        &self.schema
+    }
+
+    pub(crate) fn flushed_row_groups(&self) -> &[RowGroupMetaDataPtr] {
+        &self.row_groups
+    }
+
+    pub(crate) fn next_row_group(&mut self) -> EncryptedRowGroupWriter<'_, W> {
+        EncryptedRowGroupWriter {
+            file_offset: self.bytes_written as i64,
+            file: self,
+            columns: vec![],
+            indexes: vec![],
+            rows: 0,
+        }
+    }
+
+    fn magic(&self) -> &'static [u8; 4] {
+        match self.encryptor.encrypted_footer {
+            true => PARQUET_MAGIC_ENCRYPTED_FOOTER,
+            false => PARQUET_MAGIC,
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), WriteError> {
+        match self.sink.write_all(bytes) {
+            Ok(_) => {
+                self.bytes_written += bytes.len();
+                Ok(())
+            }
+            Err(_) => Err(WriteError::from("Error writing file".to_string())),
+        }
+    }
+
+    /// Write the page indexes and the footer, with the metadata of encrypted columns encrypted,
+    /// returning the sink
+    pub(crate) fn into_inner(mut self) -> Result<W, WriteError> {
+        let mut row_groups = self
+            .row_groups
+            .iter()
+            .map(|row_group| row_group.to_thrift())
+            .collect::<Vec<_>>();
+        let indexes = std::mem::take(&mut self.indexes);
+        // Every column index and then every offset index, as parquet lays them out
+        for (i, row_group) in row_groups.iter_mut().enumerate() {
+            for (j, column) in row_group.columns.iter_mut().enumerate() {
+                if let Some(index) = &indexes[i][j].0 {
+                    let (offset, length) = self.write_index(i, j, Module::ColumnIndex, index)?;
+                    column.column_index_offset = Some(offset);
+                    column.column_index_length = Some(length);
+                }
+            }
+        }
+        for (i, row_group) in row_groups.iter_mut().enumerate() {
+            for (j, column) in row_group.columns.iter_mut().enumerate() {
+                if let Some(index) = &indexes[i][j].1 {
+                    let (offset, length) = self.write_index(i, j, Module::OffsetIndex, index)?;
+                    column.offset_index_offset = Some(offset);
+                    column.offset_index_length = Some(length);
+                }
+            }
+        }
+
+        for (i, row_group) in row_groups.iter_mut().enumerate() {
+            for (j, column) in row_group.columns.iter_mut().enumerate() {
+                let (Some(encryptor), Some(metadata)) =
+                    (self.encryptor.column(i, j)?, column.meta_data.as_mut())
+                else {
+                    continue;
+                };
+                let Some(key) = &self.encryptor.columns[j] else {
+                    // Encrypted with the footer key, so the metadata is hidden in the footer
+                    column.crypto_metadata = Some(ColumnCryptoMetaData::ENCRYPTIONWITHFOOTERKEY(
+                        EncryptionWithFooterKey {},
+                    ));
+                    continue;
+                };
+                column.encrypted_column_metadata = Some(
+                    encryptor
+                        .encrypt(Module::ColumnMetaData, 0, &thrift_bytes(&*metadata)?)
+                        .map_err(WriteError::from)?,
+                );
+                column.crypto_metadata = Some(ColumnCryptoMetaData::ENCRYPTIONWITHCOLUMNKEY(
+                    EncryptionWithColumnKey {
+                        path_in_schema: metadata.path_in_schema.clone(),
+                        key_metadata: key.key_metadata.clone(),
+                    },
+                ));
+                // Readers without the column's key still find its pages, but not its statistics, when
+                // the footer is readable, and nothing of it otherwise
+                if self.encryptor.encrypted_footer {
+                    column.meta_data = None;
+                } else {
+                    *metadata = ColumnMetaData {
+                        statistics: None,
+                        encoding_stats: None,
+                        ..metadata.clone()
+                    };
+                }
+            }
+        }
+
+        let file_metadata = FileMetaData {
+            version: self.properties.writer_version().as_num(),
+            schema: types::to_thrift(&self.message_type)
+                .map_err(|_| WriteError::from("Error writing schema".to_string()))?,
+            num_rows: self.row_groups.iter().map(|r| r.num_rows()).sum(),
+            row_groups,
+            key_value_metadata: self.properties.key_value_metadata().cloned(),
+            created_by: Some(self.properties.created_by().to_owned()),
+            column_orders: Some(
+                (0..self.schema.num_columns())
+                    .map(|_| ColumnOrder::TYPEORDER(TypeDefinedOrder {}))
+                    .collect(),
+            ),
+            encryption_algorithm: None,
+            footer_signing_key_metadata: None,
+        };
+        if self.encryptor.encrypted_footer {
+            // The encrypted footer follows the plaintext crypto metadata readers decrypt it with
+            let crypto_metadata = FileCryptoMetaData {
+                encryption_algorithm: self.encryptor.algorithm(),
+                key_metadata: self.encryptor.footer_key_metadata.clone(),
+            };
+            let mut footer = thrift_bytes(&crypto_metadata)?;
+            footer.extend(
+                encrypt_module(
+                    &self.encryptor.footer_key,
+                    &self.encryptor.footer_aad(),
+                    &thrift_bytes(&file_metadata)?,
+                )
+                .map_err(WriteError::from)?,
+            );
+            self.write(&footer)?;
+            self.write(&(footer.len() as u32).to_le_bytes())?;
+            self.write(PARQUET_MAGIC_ENCRYPTED_FOOTER)?;
+            return Ok(self.sink);
+        }
+
+        let file_metadata = FileMetaData {
+            encryption_algorithm: Some(self.encryptor.algorithm()),
+            footer_signing_key_metadata: self.encryptor.footer_key_metadata.clone(),
+            ..file_metadata
+        };
+        let footer = thrift_bytes(&file_metadata)?;
+        // The footer's signature is the nonce and tag of encrypting it with the footer key
+        let (nonce, sealed) = seal(
+            &self.encryptor.footer_key,
+            &self.encryptor.footer_aad(),
+            &footer,
+        )
+        .map_err(WriteError::from)?;
+        self.write(&footer)?;
+        self.write(&nonce)?;
+        self.write(&sealed[sealed.len() - TAG_LENGTH..])?;
+        let length = (footer.len() + NONCE_LENGTH + TAG_LENGTH) as u32;
+        self.write(&length.to_le_bytes())?;
+        self.write(PARQUET_MAGIC)?;
+        Ok(self.sink)
+    }
+
+    /// Write a column or offset index, encrypted when its column is, returning its offset and length
+    fn write_index(
+        &mut self,
+        row_group: usize,
+        column: usize,
+        module: Module,
+        index: &impl TSerializable,
+    ) -> Result<(i64, i32), WriteError> {
+        let mut bytes = thrift_bytes(index)?;
+        if let Some(encryptor) = self.encryptor.column(row_group, column)? {
+            bytes = encryptor
+                .encrypt(module, 0, &bytes)
+                .map_err(WriteError::from)?;
+        }
+        let offset = self.bytes_written as i64;
+        self.write(&bytes)?;
+        Ok((offset, bytes.len() as i32))
+    }
+}
+
+/// Appends a row group's column chunks to an `EncryptedFileWriter`
+pub(crate) struct EncryptedRowGroupWriter<'a, W: Write> {
+    file: &'a mut EncryptedFileWriter<W>,
+    file_offset: i64,
+    columns: Vec<ColumnChunkMetaData>,
+    indexes: Vec<ColumnIndexes>,
+    rows: i64,
+}
+
+impl<W: Write> EncryptedRowGroupWriter<'_, W> {
+    /// Copy a column chunk's pages out of `chunk`, where they were written, like
+    /// `SerializedRowGroupWriter::append_column`
+    pub(crate) fn append_column(
+        &mut self,
+        chunk: &[u8],
+        close: ColumnCloseResult,
+    ) -> Result<(), WriteError> {
+        let metadata = close.metadata;
+        let src_dictionary_offset = metadata.dictionary_page_offset();
+        let src_data_offset = metadata.data_page_offset();
+        let src_offset = src_dictionary_offset.unwrap_or(src_data_offset);
+        let write_offset = self.file.bytes_written as i64;
+        let Some(pages) =
+            chunk.get(src_offset as usize..(src_offset + metadata.compressed_size()) as usize)
+        else {
+            return Err(WriteError::from("Column chunk is too short".to_string()));
+        };
+        self.file.write(pages)?;
+
+        let map_offset = |offset| offset - src_offset + write_offset;
+        // No copy of the metadata follows the pages, so the chunk's offset is its first page's
+        let mut builder = ColumnChunkMetaData::builder(metadata.column_descr_ptr())
+            .set_compression(metadata.compression())
+            .set_encodings(metadata.encodings().clone())
+            .set_file_offset(write_offset)
+            .set_total_compressed_size(metadata.compressed_size())
+            .set_total_uncompressed_size(metadata.uncompressed_size())
+            .set_num_values(metadata.num_values())
+            .set_data_page_offset(map_offset(src_data_offset))
+            .set_dictionary_page_offset(src_dictionary_offset.map(map_offset));
+        if let Some(statistics) = metadata.statistics() {
+            builder = builder.set_statistics(statistics.clone());
+        }
+        let metadata = builder
+            .build()
+            .map_err(|_| WriteError::from("Error appending column chunk".to_string()))?;
+
+        let mut offset_index = close.offset_index;
+        if let Some(offset_index) = offset_index.as_mut() {
+            for location in &mut offset_index.page_locations {
+                location.offset = map_offset(location.offset);
+            }
+        }
+        self.rows = close.rows_written as i64;
+        self.columns.push(metadata);
+        self.indexes.push((close.column_index, offset_index));
+        Ok(())
+    }
+
+    pub(crate) fn close(self) -> Result<(), WriteError> {
+        let ordinal = i16::try_from(self.file.row_groups.len())
+            .map_err(|_| WriteError::from("Too many row groups to encrypt".to_string()))?;
+        let row_group = RowGroupMetaData::builder(self.file.schema.clone())
+            .set_total_byte_size(self.columns.iter().map(|c| c.uncompressed_size()).sum())
+            .set_column_metadata(self.columns)
+            .set_num_rows(self.rows)
+            .set_sorting_columns(self.file.properties.sorting_columns().cloned())
+            .set_ordinal(ordinal)
+            .set_file_offset(self.file_offset)
+            .build()
+            .map_err(|_| WriteError::from("Error closing row group writer".to_string()))?;
+        self.file.row_groups.push(Arc::new(row_group));
+        self.file.indexes.push(self.indexes);
+        Ok(())
+    }
+}
+
+fn thrift_bytes(value: &impl TSerializable) -> Result<Vec<u8>, WriteError> {
+    let mut bytes = vec![];
+    match value.write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut bytes)) {
+        Ok(_) => Ok(bytes),
+        Err(_) => Err(WriteError::from("Error writing file metadata".to_string())),
+    }
+}
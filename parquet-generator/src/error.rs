@@ -0,0 +1,90 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+/// An error from writing a parquet file, surfaced to JavaScript as the thrown value
+#[derive(Debug, PartialEq)]
+pub(crate) enum WriteError {
+    /// A general failure, thrown as a string
+    Message(String),
+    /// A record value that doesn't match its field, thrown as an object with the same fields
+    Value(Box<ValueError>),
+    /// The write was stopped through a `CancellationToken`, thrown as an `Error` named
+    /// `CancelledError`
+    Cancelled,
+}
+
+/// A value in a record that couldn't be written to its field
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct ValueError {
+    pub(crate) row: usize,
+    pub(crate) field: String,
+    pub(crate) expected: String,
+    /// The JSON value found in the record, null when the field is missing
+    pub(crate) actual: Value,
+}
+
+impl WriteError {
+    /// Shift the row an error reports by `offset`, for errors from writing a slice of the records
+    /// that starts at that row
+    pub(crate) fn offset_row(self, offset: usize) -> Self {
+        match self {
+            WriteError::Value(mut e) => {
+                e.row += offset;
+                WriteError::Value(e)
+            }
+            WriteError::Message(message) => {
+                let row = message
+                    .strip_prefix("Row ")
+                    .and_then(|rest| rest.split_once(": "))
+                    .and_then(|(row, rest)| Some((row.parse::<usize>().ok()?, rest)));
+                match row {
+                    Some((row, rest)) => {
+                        WriteError::Message(format!("Row {}: {}", row + offset, rest))
+                    }
+                    None => WriteError::Message(message),
+                }
+            }
+            WriteError::Cancelled => WriteError::Cancelled,
+        }
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Message(message) => write!(f, "{}", message),
+            WriteError::Value(e) => write!(
+                f,
+                "Row {}: expected {} for field {}, got {}",
+                e.row, e.expected, e.field, e.actual
+            ),
+            WriteError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl From<String> for WriteError {
+    fn from(message: String) -> Self {
+        WriteError::Message(message)
+    }
+}
+
+impl From<WriteError> for JsValue {
+    fn from(error: WriteError) -> Self {
+        match error {
+            WriteError::Message(message) => JsValue::from_str(message.as_str()),
+            WriteError::Value(e) => match serde_json::to_string(&e) {
+                Ok(json) => js_sys::JSON::parse(json.as_str())
+                    .unwrap_or_else(|_| JsValue::from_str(json.as_str())),
+                Err(_) => JsValue::from_str(WriteError::Value(e).to_string().as_str()),
+            },
+            WriteError::Cancelled => {
+                let error = js_sys::Error::new("Cancelled");
+                error.set_name("CancelledError");
+                error.into()
+            }
+        }
+    }
+}
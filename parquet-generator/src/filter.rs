@@ -0,0 +1,217 @@
+use crate::writer::{self, Record};
+use crate::ParquetField;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+
+/// A condition a record must meet to be written, e.g.
+/// `{"field": "level", "op": "==", "value": "ERROR"}`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Condition {
+    /// The name of the schema field compared, read from its keys like any other value
+    pub(crate) field: String,
+    pub(crate) op: Operator,
+    /// The JSON value compared against, an array for `in`
+    pub(crate) value: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub(crate) enum Operator {
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    /// Equal to any element of the condition's array
+    #[serde(rename = "in")]
+    In,
+}
+
+/// A filter on the rows read from a parquet file, a condition on a top level column or conditions
+/// combined with `{"and": [...]}` or `{"or": [...]}`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Predicate {
+    And { and: Vec<Predicate> },
+    Or { or: Vec<Predicate> },
+    Condition(Condition),
+}
+
+/// What a row group's statistics say about a column's values, `bounds` being the min and max
+/// when they're recorded in the form rows are read in
+pub(crate) struct ColumnRange {
+    pub(crate) bounds: Option<(Value, Value)>,
+    pub(crate) null_count: Option<u64>,
+    pub(crate) rows: u64,
+}
+
+impl Predicate {
+    /// Check every condition is on one of `columns`, with an array value for `in`
+    pub(crate) fn validate(&self, columns: &[String]) -> Result<(), String> {
+        match self {
+            Predicate::And { and: predicates } | Predicate::Or { or: predicates } => predicates
+                .iter()
+                .try_for_each(|predicate| predicate.validate(columns)),
+            Predicate::Condition(condition) => {
+                if !columns.contains(&condition.field) {
+                    return Err(format!("Unknown field {} in filter", condition.field));
+                }
+                if condition.op == Operator::In && !condition.value.is_array() {
+                    return Err(format!(
+                        "Filter on field {} with op in needs an array value",
+                        condition.field
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether a row read as a JSON object meets the predicate, comparing values as
+    /// `filter_records` does
+    pub(crate) fn matches(&self, row: &Map<String, Value>) -> bool {
+        match self {
+            Predicate::And { and } => and.iter().all(|predicate| predicate.matches(row)),
+            Predicate::Or { or } => or.iter().any(|predicate| predicate.matches(row)),
+            Predicate::Condition(condition) => matches(
+                row.get(&condition.field).unwrap_or(&Value::Null),
+                condition.op,
+                &condition.value,
+            ),
+        }
+    }
+
+    /// Whether any row of a row group could meet the predicate, given the ranges of its columns.
+    /// False only when the statistics rule every row out, so the row group needn't be decoded
+    pub(crate) fn may_match(&self, range: &dyn Fn(&str) -> Option<ColumnRange>) -> bool {
+        match self {
+            Predicate::And { and } => and.iter().all(|predicate| predicate.may_match(range)),
+            Predicate::Or { or } => or.iter().any(|predicate| predicate.may_match(range)),
+            Predicate::Condition(condition) => match range(&condition.field) {
+                Some(range) => may_match(&range, condition.op, &condition.value),
+                None => true,
+            },
+        }
+    }
+}
+
+fn may_match(range: &ColumnRange, op: Operator, operand: &Value) -> bool {
+    if op == Operator::In {
+        return operand
+            .as_array()
+            .is_some_and(|values| values.iter().any(|v| may_match(range, Operator::Eq, v)));
+    }
+    if operand.is_null() {
+        // Only `==` and `!=` match nulls, and the bounds leave nulls out
+        return match op {
+            Operator::Eq => range.null_count != Some(0),
+            Operator::Ne => range.null_count != Some(range.rows),
+            _ => false,
+        };
+    }
+    if range.null_count == Some(range.rows) {
+        return op == Operator::Ne;
+    }
+    let (min, max) = match &range.bounds {
+        Some(bounds) => bounds,
+        None => return true,
+    };
+    // A comparison the values don't support can't rule anything out
+    let below_min = compare(operand, min);
+    let above_max = compare(operand, max);
+    match op {
+        Operator::Eq => below_min != Some(Ordering::Less) && above_max != Some(Ordering::Greater),
+        Operator::Ne => {
+            !(range.null_count == Some(0) && equals(min, operand) && equals(max, operand))
+        }
+        Operator::Lt => below_min.is_none_or(|o| o == Ordering::Greater),
+        Operator::Le => below_min != Some(Ordering::Less),
+        Operator::Gt => above_max.is_none_or(|o| o == Ordering::Less),
+        Operator::Ge => above_max != Some(Ordering::Greater),
+        // Answered element by element above
+        Operator::In => true,
+    }
+}
+
+/// Keep the records meeting every condition. Missing values compare as null, which is only
+/// equal to null and never less or greater than anything
+pub(crate) fn filter_records(
+    fields: &[ParquetField],
+    records: &[Record],
+    conditions: &[Condition],
+    case_insensitive: bool,
+) -> Result<Vec<Record>, String> {
+    let mut paths = vec![];
+    for condition in conditions {
+        match fields.iter().find(|f| f.name == condition.field) {
+            Some(field) => paths.push(writer::key_paths(field)?),
+            None => return Err(format!("Unknown field {} in filter", condition.field)),
+        }
+        if condition.op == Operator::In && !condition.value.is_array() {
+            return Err(format!(
+                "Filter on field {} with op in needs an array value",
+                condition.field
+            ));
+        }
+    }
+
+    Ok(records
+        .iter()
+        .filter(|record| {
+            conditions
+                .iter()
+                .zip(paths.iter())
+                .all(|(condition, paths)| {
+                    let value =
+                        writer::lookup(record, paths, case_insensitive).unwrap_or(&Value::Null);
+                    matches(value, condition.op, &condition.value)
+                })
+        })
+        .cloned()
+        .collect())
+}
+
+fn matches(value: &Value, op: Operator, operand: &Value) -> bool {
+    match op {
+        Operator::Eq => equals(value, operand),
+        Operator::Ne => !equals(value, operand),
+        Operator::In => operand
+            .as_array()
+            .is_some_and(|values| values.iter().any(|v| equals(value, v))),
+        Operator::Lt => compare(value, operand) == Some(Ordering::Less),
+        Operator::Le => matches!(
+            compare(value, operand),
+            Some(Ordering::Less | Ordering::Equal)
+        ),
+        Operator::Gt => compare(value, operand) == Some(Ordering::Greater),
+        Operator::Ge => matches!(
+            compare(value, operand),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+    }
+}
+
+/// JSON equality, except numbers are equal by value so `1` equals `1.0`
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+/// Numbers compare by value and strings lexicographically, which orders RFC 3339 timestamps
+/// with the same offset
+pub(crate) fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
@@ -0,0 +1,84 @@
+use crate::options::FlattenOptions;
+use crate::writer::{self, Record};
+use crate::{ParquetField, ParquetLogicalType, ParquetPrimitiveType, ParquetRepetition};
+use serde_json::{Map, Value};
+
+/// Expand the nested objects of each record into top level keys joined by the separator
+pub(crate) fn flatten_records(records: &[Record], options: &FlattenOptions) -> Vec<Record> {
+    records
+        .iter()
+        .map(|record| {
+            let mut flattened = Map::new();
+            flatten_into(&mut flattened, None, record, 0, options);
+            flattened
+        })
+        .collect()
+}
+
+fn flatten_into(
+    flattened: &mut Record,
+    prefix: Option<&str>,
+    object: &Record,
+    depth: usize,
+    options: &FlattenOptions,
+) {
+    for (key, value) in object {
+        let key = match prefix {
+            Some(prefix) => format!("{}{}{}", prefix, options.separator, key),
+            None => key.clone(),
+        };
+        match value {
+            Value::Object(nested) if options.max_depth.is_none_or(|max| depth < max) => {
+                flatten_into(flattened, Some(&key), nested, depth + 1, options)
+            }
+            _ => {
+                flattened.insert(key, value.clone());
+            }
+        }
+    }
+}
+
+/// Add an OPTIONAL field for every key in the flattened records that no field reads, typed from the
+/// first non-null value. Keys whose values are all null, arrays or objects are left out
+pub(crate) fn infer_fields(
+    fields: &mut Vec<ParquetField>,
+    records: &[Record],
+) -> Result<(), String> {
+    let mut known = Vec::new();
+    for field in fields.iter() {
+        known.extend(writer::key_paths(field)?);
+    }
+
+    for record in records {
+        for (key, value) in record {
+            if known.iter().any(|path| path.len() == 1 && &path[0] == key) {
+                continue;
+            }
+            if let Some(mut field) = infer_field(key, value) {
+                if key.contains(['.', '\\']) {
+                    field.source_key = Some(key.replace('\\', "\\\\").replace('.', "\\."));
+                }
+                known.push(vec![key.clone()]);
+                fields.push(field);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn infer_field(key: &str, value: &Value) -> Option<ParquetField> {
+    let (primitive_type, logical_type) = match value {
+        Value::Bool(_) => (ParquetPrimitiveType::Boolean, None),
+        Value::Number(n) if n.is_i64() => (ParquetPrimitiveType::Int64, None),
+        Value::Number(_) => (ParquetPrimitiveType::Double, None),
+        Value::String(_) => (
+            ParquetPrimitiveType::ByteArray,
+            Some(ParquetLogicalType::Utf8),
+        ),
+        _ => return None,
+    };
+    let mut field = ParquetField::new(key.to_string(), primitive_type);
+    field.logical_type = logical_type;
+    field.repetition_type = Some(ParquetRepetition::Optional);
+    Some(field)
+}
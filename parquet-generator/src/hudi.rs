@@ -0,0 +1,473 @@
+use crate::iceberg::TableFile;
+use crate::reader;
+use arrow_schema::{DataType, Field, Fields, TimeUnit};
+use chrono::DateTime;
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::file::reader::FileReader;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// The directory of a Hudi table's timeline and properties
+const META_DIRECTORY: &str = ".hoodie";
+
+/// The file in each partition directory marking it as one of the table's partitions
+const PARTITION_METADATA_FILE: &str = ".hoodie_partition_metadata";
+
+/// The table version written to `hoodie.properties`, that of Hudi 0.14
+const TABLE_VERSION: i32 = 6;
+
+/// Options for a Hudi commit of data files, passed to `hudi_commit` as a JSON object
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct HudiOptions {
+    /// The table's name, which also names the record of its Avro schema
+    pub(crate) table_name: String,
+    /// Commit to a table that already exists, leaving its `hoodie.properties` as they are
+    pub(crate) existing_table: bool,
+    /// The fields the table is partitioned by, in the order of the directories in the files' paths
+    pub(crate) partition_fields: Vec<String>,
+    /// The fields identifying a record, for upserts by later writers
+    pub(crate) record_key_fields: Vec<String>,
+    /// Milliseconds since the epoch of the commit's instant, by default now
+    pub(crate) timestamp: Option<i64>,
+}
+
+pub(crate) fn parse_hudi_options(options: &str) -> Result<HudiOptions, String> {
+    match serde_json::from_str::<HudiOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+/// The paths to write data files to as a Hudi table's base files, and the metadata files of the
+/// commit adding them
+#[derive(Debug, PartialEq)]
+pub(crate) struct Commit {
+    /// The path of each data file relative to the table's root, in the partition directory it was
+    /// given in and named `{file id}_{write token}_{instant}.parquet` as Hudi expects
+    pub(crate) data_paths: Vec<String>,
+    /// The commit's timeline files, the table's `hoodie.properties` when it's created and each
+    /// partition's metadata
+    pub(crate) files: Vec<TableFile>,
+}
+
+/// The commit inserting parquet files into a copy-on-write Hudi table, given as their paths
+/// relative to the table's root and their bytes. The table doesn't populate Hudi's meta fields,
+/// so the files are registered as they are, and the schema is mapped from the files', which must
+/// all have the same one
+pub(crate) fn commit(files: &[(String, Vec<u8>)], options: &HudiOptions) -> Result<Commit, String> {
+    if files.is_empty() {
+        return Err("No files to commit".to_string());
+    }
+    if options.table_name.is_empty() {
+        return Err("table_name is required".to_string());
+    }
+    let table_name = options.table_name.as_str();
+    let timestamp = options.timestamp.unwrap_or_else(crate::js::unix_millis);
+    let Some(instant) = DateTime::from_timestamp_millis(timestamp) else {
+        return Err(format!("Invalid timestamp {}", timestamp));
+    };
+    let instant = instant.format("%Y%m%d%H%M%S%3f").to_string();
+
+    let mut schema: Option<(&str, Value)> = None;
+    let mut data_paths = vec![];
+    let mut write_stats = BTreeMap::<String, Vec<Value>>::new();
+    // Whether partition directories are named `field=value`, rather than just the value
+    let mut hive_style = true;
+    for (i, (path, bytes)) in files.iter().enumerate() {
+        let (fields, rows) =
+            file_schema(bytes, table_name).map_err(|e| format!("File {}: {}", path, e))?;
+        match &schema {
+            Some((first, schema)) if *schema != fields => {
+                return Err(format!(
+                    "File {} has a different schema than {}",
+                    path, first
+                ))
+            }
+            Some(_) => {}
+            None => schema = Some((path, fields)),
+        }
+
+        let partition = path.rsplit_once('/').map_or("", |(directory, _)| directory);
+        let depth = partition.split('/').filter(|d| !d.is_empty()).count();
+        if depth != options.partition_fields.len() {
+            return Err(format!(
+                "File {} is in {} partition directories but the table has {} partition fields",
+                path,
+                depth,
+                options.partition_fields.len()
+            ));
+        }
+        hive_style &= partition
+            .split('/')
+            .filter(|d| !d.is_empty())
+            .zip(&options.partition_fields)
+            .all(|(directory, field)| directory.starts_with(&format!("{}=", field)));
+        let file_id = format!("{}-0", crate::js::random_uuid()?);
+        let name = format!("{}_{}-0-0_{}.parquet", file_id, i, instant);
+        let data_path = match partition {
+            "" => name,
+            partition => format!("{}/{}", partition, name),
+        };
+        write_stats
+            .entry(partition.to_string())
+            .or_default()
+            .push(json!({
+                "fileId": file_id,
+                "path": data_path,
+                "prevCommit": "null",
+                "numWrites": rows,
+                "numDeletes": 0,
+                "numUpdateWrites": 0,
+                "numInserts": rows,
+                "totalWriteBytes": bytes.len(),
+                "totalWriteErrors": 0,
+                "tempPath": null,
+                "partitionPath": partition,
+                "totalLogRecords": 0,
+                "totalLogFilesCompacted": 0,
+                "totalLogSizeCompacted": 0,
+                "totalUpdatedRecordsCompacted": 0,
+                "totalLogBlocks": 0,
+                "totalCorruptLogBlock": 0,
+                "totalRollbackBlocks": 0,
+                "fileSizeInBytes": bytes.len(),
+                "minEventTime": null,
+                "maxEventTime": null,
+                "runtimeStats": null
+            }));
+        data_paths.push(data_path);
+    }
+    let schema = schema
+        .map(|(_, schema)| schema.to_string())
+        .unwrap_or_default();
+
+    let mut metadata_files = vec![];
+    if !options.existing_table {
+        metadata_files.push(TableFile {
+            path: format!("{}/hoodie.properties", META_DIRECTORY),
+            bytes: table_properties(&schema, hive_style, options).into_bytes(),
+        });
+    }
+    let commit = json!({
+        "partitionToWriteStats": write_stats,
+        "compacted": false,
+        "extraMetadata": {"schema": schema},
+        "operationType": "INSERT"
+    });
+    let commit = match serde_json::to_vec_pretty(&commit) {
+        Ok(commit) => commit,
+        Err(e) => return Err(format!("Error serializing commit: {}", e)),
+    };
+    // A commit's instant moves from requested to inflight to completed, and readers only see
+    // completed ones
+    metadata_files.extend([
+        TableFile {
+            path: format!("{}/{}.commit.requested", META_DIRECTORY, instant),
+            bytes: vec![],
+        },
+        TableFile {
+            path: format!("{}/{}.inflight", META_DIRECTORY, instant),
+            bytes: vec![],
+        },
+        TableFile {
+            path: format!("{}/{}.commit", META_DIRECTORY, instant),
+            bytes: commit,
+        },
+    ]);
+    for partition in write_stats.keys() {
+        let depth = partition.split('/').filter(|d| !d.is_empty()).count();
+        let properties = properties(&[
+            ("commitTime", instant.clone()),
+            ("partitionDepth", depth.to_string()),
+        ]);
+        let path = match partition.as_str() {
+            "" => PARTITION_METADATA_FILE.to_string(),
+            partition => format!("{}/{}", partition, PARTITION_METADATA_FILE),
+        };
+        metadata_files.push(TableFile {
+            path,
+            bytes: format!("#partition metadata\n{}", properties).into_bytes(),
+        });
+    }
+
+    Ok(Commit {
+        data_paths,
+        files: metadata_files,
+    })
+}
+
+/// The `hoodie.properties` creating a copy-on-write table of parquet files without meta fields
+fn table_properties(schema: &str, hive_style: bool, options: &HudiOptions) -> String {
+    let table_name = options.table_name.as_str();
+    let mut properties = vec![
+        ("hoodie.table.name", table_name.to_string()),
+        ("hoodie.table.type", "COPY_ON_WRITE".to_string()),
+        ("hoodie.table.version", TABLE_VERSION.to_string()),
+        ("hoodie.timeline.layout.version", "1".to_string()),
+        ("hoodie.table.base.file.format", "PARQUET".to_string()),
+        ("hoodie.archivelog.folder", "archived".to_string()),
+        ("hoodie.populate.meta.fields", "false".to_string()),
+        (
+            "hoodie.partition.metafile.use.base.format",
+            "false".to_string(),
+        ),
+        ("hoodie.table.create.schema", schema.to_string()),
+    ];
+    if !options.record_key_fields.is_empty() {
+        let fields = options.record_key_fields.join(",");
+        properties.push(("hoodie.table.recordkey.fields", fields));
+    }
+    if !options.partition_fields.is_empty() {
+        let fields = options.partition_fields.join(",");
+        properties.push(("hoodie.table.partition.fields", fields));
+        properties.push((
+            "hoodie.datasource.write.hive_style_partitioning",
+            hive_style.to_string(),
+        ));
+    }
+    // Hudi checks the table's name against this when it loads the properties
+    let checksum = crc32fast::hash(format!(".{}", table_name).as_bytes());
+    properties.push(("hoodie.table.checksum", checksum.to_string()));
+    format!("#Updated by lakeside\n{}", self::properties(&properties))
+}
+
+/// Lines of Java properties, with keys and values escaped as `Properties.store` does
+fn properties(properties: &[(&str, String)]) -> String {
+    properties
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", escape_property(key), escape_property(value)))
+        .collect()
+}
+
+fn escape_property(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for (i, c) in s.chars().enumerate() {
+        match c {
+            ' ' if i == 0 => escaped.push_str("\\ "),
+            '\\' | '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if (' '..='~').contains(&c) => escaped.push(c),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("\\u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// A parquet file's schema as the Avro record schema Hudi records a table's schema with, and its
+/// number of rows
+fn file_schema(bytes: &[u8], table_name: &str) -> Result<(Value, i64), String> {
+    let file = reader::open(bytes.to_vec())?;
+    let metadata = file.metadata().file_metadata();
+    let schema = parquet_to_arrow_schema(metadata.schema_descr(), metadata.key_value_metadata())
+        .map_err(|e| format!("Error reading schema: {}", e))?;
+    let name = format!("{}_record", table_name);
+    let mut record = record(&name, schema.fields())?;
+    record["namespace"] = Value::String(format!("hoodie.{}", table_name));
+    Ok((record, metadata.num_rows()))
+}
+
+/// An Avro record of fields. Nested records are named after the path to them, as Avro names must
+/// be unique within a schema
+fn record(name: &str, fields: &Fields) -> Result<Value, String> {
+    let fields = fields
+        .iter()
+        .map(|field| {
+            let mut avro_field = Map::new();
+            avro_field.insert("name".to_string(), Value::from(field.name().as_str()));
+            avro_field.insert("type".to_string(), avro_type(name, field)?);
+            if field.is_nullable() {
+                avro_field.insert("default".to_string(), Value::Null);
+            }
+            Ok(Value::Object(avro_field))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(json!({"type": "record", "name": name, "fields": fields}))
+}
+
+/// The Avro type of a field's values, a union with null when they're nullable
+fn avro_type(parent: &str, field: &Field) -> Result<Value, String> {
+    let avro_type = match field.data_type() {
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => json!("int"),
+        DataType::UInt8 | DataType::UInt16 => json!("int"),
+        DataType::Int64 | DataType::UInt32 => json!("long"),
+        DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Utf8 | DataType::LargeUtf8 => json!("string"),
+        DataType::Binary | DataType::LargeBinary => json!("bytes"),
+        DataType::FixedSizeBinary(size) => json!({
+            "type": "fixed",
+            "name": format!("{}_{}", parent, field.name()),
+            "size": size
+        }),
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => json!({
+            "type": "bytes",
+            "logicalType": "decimal",
+            "precision": precision,
+            "scale": scale
+        }),
+        DataType::Date32 | DataType::Date64 => json!({"type": "int", "logicalType": "date"}),
+        DataType::Time32(_) => json!({"type": "int", "logicalType": "time-millis"}),
+        DataType::Time64(_) => json!({"type": "long", "logicalType": "time-micros"}),
+        DataType::Timestamp(TimeUnit::Second | TimeUnit::Millisecond, _) => {
+            json!({"type": "long", "logicalType": "timestamp-millis"})
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            json!({"type": "long", "logicalType": "timestamp-micros"})
+        }
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => json!("long"),
+        DataType::List(element) | DataType::LargeList(element) => {
+            let name = format!("{}_{}", parent, field.name());
+            json!({"type": "array", "items": avro_type(&name, element)?})
+        }
+        DataType::Map(entries, _) => {
+            let DataType::Struct(entries) = entries.data_type() else {
+                return Err(format!("Unsupported map type for field {}", field.name()));
+            };
+            let [key, value] = &entries.iter().collect::<Vec<_>>()[..] else {
+                return Err(format!("Unsupported map type for field {}", field.name()));
+            };
+            if !matches!(key.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                return Err(format!(
+                    "Field {} is a map with {} keys, but Avro map keys are strings",
+                    field.name(),
+                    key.data_type()
+                ));
+            }
+            let name = format!("{}_{}", parent, field.name());
+            json!({"type": "map", "values": avro_type(&name, value)?})
+        }
+        DataType::Struct(fields) => record(&format!("{}_{}", parent, field.name()), fields)?,
+        data_type => {
+            return Err(format!(
+                "Field {} has type {}, which Hudi tables don't support",
+                field.name(),
+                data_type
+            ))
+        }
+    };
+    match field.is_nullable() {
+        true => Ok(json!(["null", avro_type])),
+        false => Ok(avro_type),
+    }
+}
+
+#[test]
+fn test_commit() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "ts", "type": "INT64", "logical_type": "TIMESTAMP_MILLIS"}
+        ]
+    }
+    "#;
+    let write = |ids: std::ops::Range<i64>| {
+        let files = ids
+            .map(|id| format!(r#"{{"id": {}, "name": "n{}", "ts": {}}}"#, id, id, id))
+            .collect();
+        crate::write_parquet(schema.to_string(), files, &Default::default()).unwrap()
+    };
+    let files = vec![
+        ("region=eu/part-0000.parquet".to_string(), write(0..3)),
+        ("region=us/part-0000.parquet".to_string(), write(3..5)),
+    ];
+    let options = parse_hudi_options(
+        r#"{"table_name": "events", "partition_fields": ["region"], "record_key_fields": ["id"], "timestamp": 1700000000123}"#,
+    )
+    .unwrap();
+    let created = commit(&files, &options).unwrap();
+
+    assert_eq!(created.data_paths.len(), 2);
+    let (partition, name) = created.data_paths[1].split_once('/').unwrap();
+    assert_eq!(partition, "region=us");
+    let parts = name.strip_suffix(".parquet").unwrap().split('_');
+    let [file_id, write_token, instant] = parts.collect::<Vec<_>>()[..] else {
+        panic!("unexpected base file name {}", name);
+    };
+    assert_eq!(file_id.len(), 38);
+    assert!(file_id.ends_with("-0"));
+    assert_eq!(write_token, "1-0-0");
+    assert_eq!(instant, "20231114221320123");
+
+    assert_eq!(
+        created
+            .files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            ".hoodie/hoodie.properties",
+            ".hoodie/20231114221320123.commit.requested",
+            ".hoodie/20231114221320123.inflight",
+            ".hoodie/20231114221320123.commit",
+            "region=eu/.hoodie_partition_metadata",
+            "region=us/.hoodie_partition_metadata",
+        ]
+    );
+    let properties = String::from_utf8(created.files[0].bytes.clone()).unwrap();
+    assert!(properties.contains("\nhoodie.table.name=events\n"));
+    assert!(properties.contains("\nhoodie.table.recordkey.fields=id\n"));
+    assert!(properties.contains("\nhoodie.table.partition.fields=region\n"));
+    assert!(properties.contains("\nhoodie.datasource.write.hive_style_partitioning=true\n"));
+    assert!(properties.contains(&format!(
+        "\nhoodie.table.checksum={}\n",
+        crc32fast::hash(b".events")
+    )));
+    assert!(properties
+        .contains(r#"hoodie.table.create.schema={"type"\:"record","name"\:"events_record""#));
+
+    let committed = serde_json::from_slice::<Value>(&created.files[3].bytes).unwrap();
+    let stats = &committed["partitionToWriteStats"]["region=eu"][0];
+    assert_eq!(stats["path"], created.data_paths[0]);
+    assert_eq!(stats["numInserts"], 3);
+    assert_eq!(stats["fileSizeInBytes"], files[0].1.len());
+    assert_eq!(stats["partitionPath"], "region=eu");
+    let schema =
+        serde_json::from_str::<Value>(committed["extraMetadata"]["schema"].as_str().unwrap());
+    assert_eq!(
+        schema.unwrap()["fields"],
+        json!([
+            {"name": "id", "type": "long"},
+            {"name": "name", "type": ["null", "string"], "default": null},
+            {"name": "ts", "type": {"type": "long", "logicalType": "timestamp-millis"}}
+        ])
+    );
+    assert_eq!(
+        String::from_utf8(created.files[4].bytes.clone()).unwrap(),
+        "#partition metadata\ncommitTime=20231114221320123\npartitionDepth=1\n"
+    );
+
+    let options = parse_hudi_options(
+        r#"{"table_name": "events", "existing_table": true, "timestamp": 1700000000123}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        commit(&files, &options).unwrap_err(),
+        "File region=eu/part-0000.parquet is in 1 partition directories but the table has 0 partition fields"
+    );
+    let files = vec![("part-0000.parquet".to_string(), write(0..1))];
+    let appended = commit(&files, &options).unwrap();
+    assert_eq!(appended.files.len(), 4);
+    assert_eq!(appended.files[3].path, ".hoodie_partition_metadata");
+
+    let options = parse_hudi_options("{}").unwrap();
+    assert_eq!(
+        commit(&files, &options).unwrap_err(),
+        "table_name is required"
+    );
+}
@@ -0,0 +1,834 @@
+use crate::reader;
+use apache_avro::types::Value as AvroValue;
+use apache_avro::{to_avro_datum, Schema as AvroSchema};
+use arrow_schema::{DataType, Field, Fields};
+use parquet::arrow::parquet_to_arrow_schema;
+use parquet::basic::{ConvertedType, LogicalType, TimeUnit, Type as PhysicalType};
+use parquet::file::reader::FileReader;
+use parquet::schema::types::ColumnDescriptor;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// The magic bytes an Avro object container file starts with
+const AVRO_MAGIC: &[u8] = b"Obj\x01";
+
+/// Ends each block of an Avro file, any 16 bytes that don't occur in the file's header
+const AVRO_SYNC_MARKER: &[u8; 16] = b"lakeside-iceberg";
+
+/// The id partition fields are numbered from, so an unpartitioned table's last is one less
+const PARTITION_FIELD_ID_START: i32 = 1000;
+
+/// The table property of the mapping from column names to field ids, for data files written
+/// without field ids
+const NAME_MAPPING_PROPERTY: &str = "schema.name-mapping.default";
+
+/// The Avro schema of a version 2 manifest's entries for an unpartitioned table, with Iceberg's
+/// field ids. Maps with int keys are arrays of key-value records, as Iceberg writes them
+const MANIFEST_ENTRY_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "manifest_entry",
+    "fields": [
+        {"name": "status", "type": "int", "field-id": 0},
+        {"name": "snapshot_id", "type": ["null", "long"], "default": null, "field-id": 1},
+        {"name": "sequence_number", "type": ["null", "long"], "default": null, "field-id": 3},
+        {"name": "file_sequence_number", "type": ["null", "long"], "default": null, "field-id": 4},
+        {"name": "data_file", "field-id": 2, "type": {
+            "type": "record",
+            "name": "r2",
+            "fields": [
+                {"name": "content", "type": "int", "field-id": 134},
+                {"name": "file_path", "type": "string", "field-id": 100},
+                {"name": "file_format", "type": "string", "field-id": 101},
+                {"name": "partition", "type": {"type": "record", "name": "r102", "fields": []}, "field-id": 102},
+                {"name": "record_count", "type": "long", "field-id": 103},
+                {"name": "file_size_in_bytes", "type": "long", "field-id": 104},
+                {"name": "column_sizes", "default": null, "field-id": 108, "type": ["null", {
+                    "type": "array",
+                    "logicalType": "map",
+                    "items": {"type": "record", "name": "k117_v118", "fields": [
+                        {"name": "key", "type": "int", "field-id": 117},
+                        {"name": "value", "type": "long", "field-id": 118}
+                    ]}
+                }]},
+                {"name": "value_counts", "default": null, "field-id": 109, "type": ["null", {
+                    "type": "array",
+                    "logicalType": "map",
+                    "items": {"type": "record", "name": "k119_v120", "fields": [
+                        {"name": "key", "type": "int", "field-id": 119},
+                        {"name": "value", "type": "long", "field-id": 120}
+                    ]}
+                }]},
+                {"name": "null_value_counts", "default": null, "field-id": 110, "type": ["null", {
+                    "type": "array",
+                    "logicalType": "map",
+                    "items": {"type": "record", "name": "k121_v122", "fields": [
+                        {"name": "key", "type": "int", "field-id": 121},
+                        {"name": "value", "type": "long", "field-id": 122}
+                    ]}
+                }]},
+                {"name": "lower_bounds", "default": null, "field-id": 125, "type": ["null", {
+                    "type": "array",
+                    "logicalType": "map",
+                    "items": {"type": "record", "name": "k126_v127", "fields": [
+                        {"name": "key", "type": "int", "field-id": 126},
+                        {"name": "value", "type": "bytes", "field-id": 127}
+                    ]}
+                }]},
+                {"name": "upper_bounds", "default": null, "field-id": 128, "type": ["null", {
+                    "type": "array",
+                    "logicalType": "map",
+                    "items": {"type": "record", "name": "k129_v130", "fields": [
+                        {"name": "key", "type": "int", "field-id": 129},
+                        {"name": "value", "type": "bytes", "field-id": 130}
+                    ]}
+                }]},
+                {"name": "sort_order_id", "type": ["null", "int"], "default": null, "field-id": 140}
+            ]
+        }}
+    ]
+}"#;
+
+/// The Avro schema of a version 2 manifest list's entries, with Iceberg's field ids
+const MANIFEST_FILE_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "manifest_file",
+    "fields": [
+        {"name": "manifest_path", "type": "string", "field-id": 500},
+        {"name": "manifest_length", "type": "long", "field-id": 501},
+        {"name": "partition_spec_id", "type": "int", "field-id": 502},
+        {"name": "content", "type": "int", "field-id": 517},
+        {"name": "sequence_number", "type": "long", "field-id": 515},
+        {"name": "min_sequence_number", "type": "long", "field-id": 516},
+        {"name": "added_snapshot_id", "type": "long", "field-id": 503},
+        {"name": "added_files_count", "type": "int", "field-id": 504},
+        {"name": "existing_files_count", "type": "int", "field-id": 505},
+        {"name": "deleted_files_count", "type": "int", "field-id": 506},
+        {"name": "added_rows_count", "type": "long", "field-id": 512},
+        {"name": "existing_rows_count", "type": "long", "field-id": 513},
+        {"name": "deleted_rows_count", "type": "long", "field-id": 514},
+        {"name": "partitions", "default": null, "field-id": 507, "type": ["null", {
+            "type": "array",
+            "element-id": 508,
+            "items": {"type": "record", "name": "r508", "fields": [
+                {"name": "contains_null", "type": "boolean", "field-id": 509},
+                {"name": "contains_nan", "type": ["null", "boolean"], "default": null, "field-id": 518},
+                {"name": "lower_bound", "type": ["null", "bytes"], "default": null, "field-id": 510},
+                {"name": "upper_bound", "type": ["null", "bytes"], "default": null, "field-id": 511}
+            ]}
+        }]}
+    ]
+}"#;
+
+/// Options for creating an Iceberg table of data files, passed to `iceberg_table` as a JSON object
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct IcebergOptions {
+    /// The URI of the table's root, e.g. `s3://bucket/warehouse/events`, which data and metadata
+    /// file paths are relative to
+    pub(crate) location: String,
+    /// The table's unique id, a UUID
+    pub(crate) table_uuid: String,
+    /// The id of the snapshot adding the files, by default the timestamp
+    pub(crate) snapshot_id: Option<i64>,
+    /// Milliseconds since the epoch the table and snapshot are timestamped with, by default now
+    pub(crate) timestamp: Option<i64>,
+    /// The table's properties, e.g. `{"write.format.default": "parquet"}`
+    pub(crate) properties: BTreeMap<String, String>,
+}
+
+pub(crate) fn parse_iceberg_options(options: &str) -> Result<IcebergOptions, String> {
+    match serde_json::from_str::<IcebergOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+/// A file of an Iceberg table's metadata, with its path relative to the table's root
+#[derive(Debug, PartialEq)]
+pub(crate) struct TableFile {
+    pub(crate) path: String,
+    pub(crate) bytes: Vec<u8>,
+}
+
+/// The metadata files creating an unpartitioned Iceberg table with a snapshot appending parquet
+/// files, given as their paths relative to the table's root and their bytes: the table metadata
+/// `metadata/v1.metadata.json` and `metadata/version-hint.text` pointing at it, the manifest
+/// listing the files and the snapshot's manifest list. The schema is mapped from the files',
+/// which must all have the same one. Files written without field ids are read by name through
+/// the table's default name mapping
+pub(crate) fn table(
+    files: &[(String, Vec<u8>)],
+    options: &IcebergOptions,
+) -> Result<Vec<TableFile>, String> {
+    if files.is_empty() {
+        return Err("No files to add".to_string());
+    }
+    if options.location.is_empty() {
+        return Err("location is required to create a table".to_string());
+    }
+    if options.table_uuid.is_empty() {
+        return Err("table_uuid is required to create a table".to_string());
+    }
+    let location = options.location.trim_end_matches('/');
+    let timestamp = options.timestamp.unwrap_or_else(crate::js::unix_millis);
+    let snapshot_id = options.snapshot_id.unwrap_or(timestamp);
+
+    let mut schema: Option<(&str, Schema)> = None;
+    let mut entries = vec![];
+    let (mut records, mut size) = (0, 0);
+    for (path, bytes) in files {
+        let file_schema = file_schema(bytes).map_err(|e| format!("File {}: {}", path, e))?;
+        let schema = match &schema {
+            Some((first, schema)) if schema.schema != file_schema.schema => {
+                return Err(format!(
+                    "File {} has a different schema than {}",
+                    path, first
+                ))
+            }
+            Some((_, schema)) => schema,
+            None => &schema.insert((path, file_schema)).1,
+        };
+        let data_file = data_file(&format!("{}/{}", location, path), bytes, schema)
+            .map_err(|e| format!("File {}: {}", path, e))?;
+        records += data_file.record_count;
+        size += bytes.len() as i64;
+        entries.push(manifest_entry(snapshot_id, data_file));
+    }
+    let Some((_, schema)) = schema else {
+        return Err("No files to add".to_string());
+    };
+
+    let manifest_path = format!("metadata/{}-m0.avro", options.table_uuid);
+    let manifest = write_avro(
+        MANIFEST_ENTRY_SCHEMA,
+        &[
+            ("schema", schema.schema.to_string()),
+            ("schema-id", "0".to_string()),
+            ("partition-spec", "[]".to_string()),
+            ("partition-spec-id", "0".to_string()),
+            ("format-version", "2".to_string()),
+            ("content", "data".to_string()),
+        ],
+        entries,
+    )?;
+    let manifest_list_path = format!(
+        "metadata/snap-{}-1-{}.avro",
+        snapshot_id, options.table_uuid
+    );
+    let manifest_file = record(vec![
+        (
+            "manifest_path",
+            string(format!("{}/{}", location, manifest_path)),
+        ),
+        ("manifest_length", AvroValue::Long(manifest.len() as i64)),
+        ("partition_spec_id", AvroValue::Int(0)),
+        ("content", AvroValue::Int(0)),
+        ("sequence_number", AvroValue::Long(1)),
+        ("min_sequence_number", AvroValue::Long(1)),
+        ("added_snapshot_id", AvroValue::Long(snapshot_id)),
+        ("added_files_count", AvroValue::Int(files.len() as i32)),
+        ("existing_files_count", AvroValue::Int(0)),
+        ("deleted_files_count", AvroValue::Int(0)),
+        ("added_rows_count", AvroValue::Long(records)),
+        ("existing_rows_count", AvroValue::Long(0)),
+        ("deleted_rows_count", AvroValue::Long(0)),
+        ("partitions", null()),
+    ]);
+    let manifest_list = write_avro(
+        MANIFEST_FILE_SCHEMA,
+        &[
+            ("snapshot-id", snapshot_id.to_string()),
+            ("parent-snapshot-id", "null".to_string()),
+            ("sequence-number", "1".to_string()),
+            ("format-version", "2".to_string()),
+        ],
+        vec![manifest_file],
+    )?;
+
+    let mut properties = options.properties.clone();
+    properties.insert(
+        NAME_MAPPING_PROPERTY.to_string(),
+        Value::Array(schema.name_mapping).to_string(),
+    );
+    let (files_count, records, size) = (
+        files.len().to_string(),
+        records.to_string(),
+        size.to_string(),
+    );
+    let metadata = json!({
+        "format-version": 2,
+        "table-uuid": options.table_uuid,
+        "location": location,
+        "last-sequence-number": 1,
+        "last-updated-ms": timestamp,
+        "last-column-id": schema.last_column_id,
+        "current-schema-id": 0,
+        "schemas": [schema.schema],
+        "default-spec-id": 0,
+        "partition-specs": [{"spec-id": 0, "fields": []}],
+        "last-partition-id": PARTITION_FIELD_ID_START - 1,
+        "default-sort-order-id": 0,
+        "sort-orders": [{"order-id": 0, "fields": []}],
+        "properties": properties,
+        "current-snapshot-id": snapshot_id,
+        "refs": {"main": {"snapshot-id": snapshot_id, "type": "branch"}},
+        "snapshots": [{
+            "snapshot-id": snapshot_id,
+            "sequence-number": 1,
+            "timestamp-ms": timestamp,
+            "summary": {
+                "operation": "append",
+                "added-data-files": files_count,
+                "added-records": records,
+                "added-files-size": size,
+                "total-data-files": files_count,
+                "total-records": records,
+                "total-files-size": size,
+                "total-delete-files": "0",
+                "total-position-deletes": "0",
+                "total-equality-deletes": "0"
+            },
+            "manifest-list": format!("{}/{}", location, manifest_list_path),
+            "schema-id": 0
+        }],
+        "snapshot-log": [{"timestamp-ms": timestamp, "snapshot-id": snapshot_id}],
+        "metadata-log": []
+    });
+    let metadata = match serde_json::to_vec_pretty(&metadata) {
+        Ok(metadata) => metadata,
+        Err(e) => return Err(format!("Error serializing table metadata: {}", e)),
+    };
+
+    Ok(vec![
+        TableFile {
+            path: "metadata/v1.metadata.json".to_string(),
+            bytes: metadata,
+        },
+        TableFile {
+            path: "metadata/version-hint.text".to_string(),
+            bytes: b"1".to_vec(),
+        },
+        TableFile {
+            path: manifest_path,
+            bytes: manifest,
+        },
+        TableFile {
+            path: manifest_list_path,
+            bytes: manifest_list,
+        },
+    ])
+}
+
+/// A table schema mapped from a parquet file's
+#[derive(Debug)]
+struct Schema {
+    /// The Iceberg schema, a struct type with a `schema-id`
+    schema: Value,
+    /// The field ids of column names, nested as the columns are
+    name_mapping: Vec<Value>,
+    /// The ids of top level fields by name
+    top_level_ids: HashMap<String, i32>,
+    last_column_id: i32,
+}
+
+fn file_schema(bytes: &[u8]) -> Result<Schema, String> {
+    let file = reader::open(bytes.to_vec())?;
+    let metadata = file.metadata().file_metadata();
+    let arrow_schema =
+        parquet_to_arrow_schema(metadata.schema_descr(), metadata.key_value_metadata())
+            .map_err(|e| format!("Error reading schema: {}", e))?;
+    let mut last_column_id = 0;
+    let (mut schema, name_mapping) = struct_type(arrow_schema.fields(), &mut last_column_id)?;
+    schema["schema-id"] = Value::from(0);
+    let top_level_ids = name_mapping
+        .iter()
+        .filter_map(|mapping| {
+            let name = mapping["names"][0].as_str()?;
+            Some((name.to_string(), mapping["field-id"].as_i64()? as i32))
+        })
+        .collect();
+    Ok(Schema {
+        schema,
+        name_mapping,
+        top_level_ids,
+        last_column_id,
+    })
+}
+
+/// A struct type and the name mapping of its fields. The fields are numbered before any nested
+/// fields, as Iceberg assigns ids
+fn struct_type(fields: &Fields, last_id: &mut i32) -> Result<(Value, Vec<Value>), String> {
+    let ids = fields
+        .iter()
+        .map(|_| {
+            *last_id += 1;
+            *last_id
+        })
+        .collect::<Vec<_>>();
+    let (mut struct_fields, mut name_mapping) = (vec![], vec![]);
+    for (field, id) in fields.iter().zip(ids) {
+        let (field_type, nested) = field_type(field, last_id)?;
+        struct_fields.push(json!({
+            "id": id,
+            "name": field.name(),
+            "required": !field.is_nullable(),
+            "type": field_type,
+        }));
+        name_mapping.push(mapping(id, field.name(), nested));
+    }
+    Ok((
+        json!({"type": "struct", "fields": struct_fields}),
+        name_mapping,
+    ))
+}
+
+/// The Iceberg type of a field's values, and the name mapping of any nested fields
+fn field_type(field: &Field, last_id: &mut i32) -> Result<(Value, Vec<Value>), String> {
+    let name = match field.data_type() {
+        DataType::Boolean => "boolean",
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => "int",
+        DataType::UInt8 | DataType::UInt16 => "int",
+        DataType::Int64 | DataType::UInt32 => "long",
+        DataType::Float32 => "float",
+        DataType::Float64 => "double",
+        DataType::Utf8 | DataType::LargeUtf8 => "string",
+        DataType::Binary | DataType::LargeBinary => "binary",
+        DataType::Date32 | DataType::Date64 => "date",
+        DataType::Time32(_) | DataType::Time64(_) => "time",
+        DataType::Timestamp(_, None) => "timestamp",
+        DataType::Timestamp(_, Some(_)) => "timestamptz",
+        DataType::FixedSizeBinary(length) => {
+            return Ok((Value::String(format!("fixed[{}]", length)), vec![]))
+        }
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            return Ok((
+                Value::String(format!("decimal({}, {})", precision, scale)),
+                vec![],
+            ))
+        }
+        DataType::List(element) | DataType::LargeList(element) => {
+            *last_id += 1;
+            let element_id = *last_id;
+            let (element_type, nested) = field_type(element, last_id)?;
+            let list = json!({
+                "type": "list",
+                "element-id": element_id,
+                "element": element_type,
+                "element-required": !element.is_nullable(),
+            });
+            return Ok((list, vec![mapping(element_id, "element", nested)]));
+        }
+        DataType::Map(entries, _) => {
+            let DataType::Struct(entries) = entries.data_type() else {
+                return Err(format!("Unsupported map type for field {}", field.name()));
+            };
+            let [key, value] = &entries.iter().collect::<Vec<_>>()[..] else {
+                return Err(format!("Unsupported map type for field {}", field.name()));
+            };
+            let (key_id, value_id) = (*last_id + 1, *last_id + 2);
+            *last_id += 2;
+            let (key_type, key_nested) = field_type(key, last_id)?;
+            let (value_type, value_nested) = field_type(value, last_id)?;
+            let map = json!({
+                "type": "map",
+                "key-id": key_id,
+                "key": key_type,
+                "value-id": value_id,
+                "value": value_type,
+                "value-required": !value.is_nullable(),
+            });
+            let nested = vec![
+                mapping(key_id, "key", key_nested),
+                mapping(value_id, "value", value_nested),
+            ];
+            return Ok((map, nested));
+        }
+        DataType::Struct(fields) => return struct_type(fields, last_id),
+        data_type => {
+            return Err(format!(
+                "Field {} has type {}, which Iceberg tables don't support",
+                field.name(),
+                data_type
+            ))
+        }
+    };
+    Ok((Value::String(name.to_string()), vec![]))
+}
+
+fn mapping(id: i32, name: &str, nested: Vec<Value>) -> Value {
+    let mut mapping = json!({"field-id": id, "names": [name]});
+    if !nested.is_empty() {
+        mapping["fields"] = Value::Array(nested);
+    }
+    mapping
+}
+
+/// What a manifest records of a data file
+struct DataFile {
+    path: String,
+    size: i64,
+    record_count: i64,
+    column_sizes: BTreeMap<i32, i64>,
+    value_counts: BTreeMap<i32, i64>,
+    null_value_counts: BTreeMap<i32, i64>,
+    lower_bounds: BTreeMap<i32, Vec<u8>>,
+    upper_bounds: BTreeMap<i32, Vec<u8>>,
+}
+
+/// A data file with the sizes, value and null counts and bounds of its top level columns, keyed by
+/// field id
+fn data_file(path: &str, bytes: &[u8], schema: &Schema) -> Result<DataFile, String> {
+    let file = reader::open(bytes.to_vec())?;
+    let metadata = file.metadata();
+    let statistics = reader::statistics(bytes.to_vec())?;
+    let record_count = metadata.file_metadata().num_rows();
+
+    let mut data_file = DataFile {
+        path: path.to_string(),
+        size: bytes.len() as i64,
+        record_count,
+        column_sizes: BTreeMap::new(),
+        value_counts: BTreeMap::new(),
+        null_value_counts: BTreeMap::new(),
+        lower_bounds: BTreeMap::new(),
+        upper_bounds: BTreeMap::new(),
+    };
+    let columns = metadata.file_metadata().schema_descr().columns();
+    for (i, (column, statistics)) in columns.iter().zip(statistics.columns).enumerate() {
+        if column.path().parts().len() > 1 {
+            continue;
+        }
+        let Some(id) = schema.top_level_ids.get(column.name()).copied() else {
+            continue;
+        };
+        let size = metadata
+            .row_groups()
+            .iter()
+            .map(|row_group| row_group.column(i).compressed_size())
+            .sum();
+        data_file.column_sizes.insert(id, size);
+        data_file.value_counts.insert(id, record_count);
+        if let Some(nulls) = statistics.null_count {
+            data_file.null_value_counts.insert(id, nulls as i64);
+        }
+        let lower = statistics.min.and_then(|min| bound(&min, column));
+        let upper = statistics.max.and_then(|max| bound(&max, column));
+        if let (Some(lower), Some(upper)) = (lower, upper) {
+            data_file.lower_bounds.insert(id, lower);
+            data_file.upper_bounds.insert(id, upper);
+        }
+    }
+    Ok(data_file)
+}
+
+/// A statistic in Iceberg's single-value binary form: little-endian numbers, with timestamps in
+/// microseconds, and UTF-8 strings. None for types whose statistics don't order values as
+/// Iceberg's do, or aren't in its units, such as decimals, binary and times
+fn bound(value: &Value, column: &ColumnDescriptor) -> Option<Vec<u8>> {
+    if reader::is_text(column) {
+        return Some(value.as_str()?.as_bytes().to_vec());
+    }
+    let micros = |n: i64, unit: &TimeUnit| match unit {
+        TimeUnit::MILLIS(_) => n.checked_mul(1_000),
+        TimeUnit::MICROS(_) => Some(n),
+        TimeUnit::NANOS(_) => Some(n.div_euclid(1_000)),
+    };
+    let long = |n: i64| Some(n.to_le_bytes().to_vec());
+    match (column.logical_type(), column.converted_type()) {
+        (Some(LogicalType::Timestamp { unit, .. }), _) => micros(value.as_i64()?, &unit)
+            .map(i64::to_le_bytes)
+            .map(|bytes| bytes.to_vec()),
+        (None, ConvertedType::TIMESTAMP_MILLIS) => long(value.as_i64()?.checked_mul(1_000)?),
+        (None, ConvertedType::TIMESTAMP_MICROS) => long(value.as_i64()?),
+        (Some(LogicalType::Decimal { .. } | LogicalType::Time { .. }), _)
+        | (None, ConvertedType::DECIMAL | ConvertedType::TIME_MILLIS)
+        | (None, ConvertedType::TIME_MICROS) => None,
+        (
+            Some(LogicalType::Integer {
+                bit_width: 32,
+                is_signed: false,
+            }),
+            _,
+        )
+        | (None, ConvertedType::UINT_32) => long(value.as_i64()?),
+        _ => match column.physical_type() {
+            PhysicalType::BOOLEAN => Some(vec![value.as_bool()? as u8]),
+            PhysicalType::INT32 => Some((value.as_i64()? as i32).to_le_bytes().to_vec()),
+            PhysicalType::INT64 => long(value.as_i64()?),
+            PhysicalType::FLOAT => Some((value.as_f64()? as f32).to_le_bytes().to_vec()),
+            PhysicalType::DOUBLE => Some(value.as_f64()?.to_le_bytes().to_vec()),
+            _ => None,
+        },
+    }
+}
+
+/// A manifest entry adding a data file in a snapshot, whose sequence numbers are inherited from
+/// the manifest list
+fn manifest_entry(snapshot_id: i64, data_file: DataFile) -> AvroValue {
+    let counts = |counts: BTreeMap<i32, i64>| {
+        let entries = counts
+            .into_iter()
+            .map(|(id, count)| {
+                record(vec![
+                    ("key", AvroValue::Int(id)),
+                    ("value", AvroValue::Long(count)),
+                ])
+            })
+            .collect();
+        AvroValue::Union(1, Box::new(AvroValue::Array(entries)))
+    };
+    let bounds = |bounds: BTreeMap<i32, Vec<u8>>| {
+        let entries = bounds
+            .into_iter()
+            .map(|(id, bound)| {
+                record(vec![
+                    ("key", AvroValue::Int(id)),
+                    ("value", AvroValue::Bytes(bound)),
+                ])
+            })
+            .collect();
+        AvroValue::Union(1, Box::new(AvroValue::Array(entries)))
+    };
+    record(vec![
+        ("status", AvroValue::Int(1)),
+        (
+            "snapshot_id",
+            AvroValue::Union(1, Box::new(AvroValue::Long(snapshot_id))),
+        ),
+        ("sequence_number", null()),
+        ("file_sequence_number", null()),
+        (
+            "data_file",
+            record(vec![
+                ("content", AvroValue::Int(0)),
+                ("file_path", string(data_file.path)),
+                ("file_format", string("PARQUET".to_string())),
+                ("partition", record(vec![])),
+                ("record_count", AvroValue::Long(data_file.record_count)),
+                ("file_size_in_bytes", AvroValue::Long(data_file.size)),
+                ("column_sizes", counts(data_file.column_sizes)),
+                ("value_counts", counts(data_file.value_counts)),
+                ("null_value_counts", counts(data_file.null_value_counts)),
+                ("lower_bounds", bounds(data_file.lower_bounds)),
+                ("upper_bounds", bounds(data_file.upper_bounds)),
+                ("sort_order_id", null()),
+            ]),
+        ),
+    ])
+}
+
+fn record(fields: Vec<(&str, AvroValue)>) -> AvroValue {
+    AvroValue::Record(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(s: String) -> AvroValue {
+    AvroValue::String(s)
+}
+
+/// The null branch of an optional field's union
+fn null() -> AvroValue {
+    AvroValue::Union(0, Box::new(AvroValue::Null))
+}
+
+/// An Avro object container file of records in a single block. It's written here rather than by
+/// apache-avro's `Writer`, which leaves the field ids Iceberg readers match fields by out of the
+/// schema in the file's header
+fn write_avro(
+    schema: &str,
+    metadata: &[(&str, String)],
+    records: Vec<AvroValue>,
+) -> Result<Vec<u8>, String> {
+    let parsed = match AvroSchema::parse_str(schema) {
+        Ok(parsed) => parsed,
+        Err(e) => return Err(format!("Error parsing Avro schema: {}", e)),
+    };
+    let schema = match serde_json::from_str::<Value>(schema) {
+        Ok(schema) => schema.to_string(),
+        Err(e) => return Err(format!("Error parsing Avro schema: {}", e)),
+    };
+    let mut header = HashMap::from([
+        (
+            "avro.schema".to_string(),
+            AvroValue::Bytes(schema.into_bytes()),
+        ),
+        ("avro.codec".to_string(), AvroValue::Bytes(b"null".to_vec())),
+    ]);
+    for (key, value) in metadata {
+        header.insert(
+            key.to_string(),
+            AvroValue::Bytes(value.clone().into_bytes()),
+        );
+    }
+    let encode = |schema: &AvroSchema, value: AvroValue| {
+        to_avro_datum(schema, value).map_err(|e| format!("Error encoding Avro record: {}", e))
+    };
+
+    let mut bytes = AVRO_MAGIC.to_vec();
+    let header_schema = AvroSchema::Map(Box::new(AvroSchema::Bytes));
+    bytes.extend(encode(&header_schema, AvroValue::Map(header))?);
+    bytes.extend(AVRO_SYNC_MARKER);
+    let count = records.len() as i64;
+    let mut block = vec![];
+    for record in records {
+        block.extend(encode(&parsed, record)?);
+    }
+    bytes.extend(encode(&AvroSchema::Long, AvroValue::Long(count))?);
+    bytes.extend(encode(
+        &AvroSchema::Long,
+        AvroValue::Long(block.len() as i64),
+    )?);
+    bytes.extend(block);
+    bytes.extend(AVRO_SYNC_MARKER);
+    Ok(bytes)
+}
+
+#[test]
+fn test_table() {
+    use apache_avro::Reader;
+
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "ts", "type": "INT64", "logical_type": "TIMESTAMP_MILLIS"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let write = |ids: std::ops::Range<i64>| {
+        let files = ids
+            .map(|id| {
+                format!(
+                    r#"{{"id": {}, "name": "n{}", "ts": {}, "tags": ["t"]}}"#,
+                    id, id, id
+                )
+            })
+            .collect();
+        crate::write_parquet(schema.to_string(), files, &Default::default()).unwrap()
+    };
+    let files = vec![
+        ("data/part-0.parquet".to_string(), write(0..3)),
+        ("data/part-1.parquet".to_string(), write(3..5)),
+    ];
+    let options = parse_iceberg_options(
+        r#"{"location": "s3://bucket/events/", "table_uuid": "9c12d441-03fe-4693-9a96-a0705ddf69c1", "snapshot_id": 42, "timestamp": 1700000000000}"#,
+    )
+    .unwrap();
+    let table = table(&files, &options).unwrap();
+    assert_eq!(
+        table
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "metadata/v1.metadata.json",
+            "metadata/version-hint.text",
+            "metadata/9c12d441-03fe-4693-9a96-a0705ddf69c1-m0.avro",
+            "metadata/snap-42-1-9c12d441-03fe-4693-9a96-a0705ddf69c1.avro",
+        ]
+    );
+
+    let metadata = serde_json::from_slice::<Value>(&table[0].bytes).unwrap();
+    assert_eq!(metadata["location"], "s3://bucket/events");
+    assert_eq!(metadata["last-column-id"], 5);
+    assert_eq!(
+        metadata["schemas"][0]["fields"],
+        json!([
+            {"id": 1, "name": "id", "required": true, "type": "long"},
+            {"id": 2, "name": "name", "required": false, "type": "string"},
+            {"id": 3, "name": "ts", "required": true, "type": "timestamptz"},
+            {"id": 4, "name": "tags", "required": true, "type": {
+                "type": "list", "element-id": 5, "element": "string", "element-required": false
+            }}
+        ])
+    );
+    let name_mapping = metadata["properties"][NAME_MAPPING_PROPERTY]
+        .as_str()
+        .unwrap();
+    assert_eq!(
+        serde_json::from_str::<Value>(name_mapping).unwrap()[3],
+        json!({"field-id": 4, "names": ["tags"], "fields": [{"field-id": 5, "names": ["element"]}]})
+    );
+    let snapshot = &metadata["snapshots"][0];
+    assert_eq!(snapshot["summary"]["added-records"], "5");
+    assert_eq!(
+        snapshot["manifest-list"],
+        "s3://bucket/events/metadata/snap-42-1-9c12d441-03fe-4693-9a96-a0705ddf69c1.avro"
+    );
+    assert_eq!(metadata["current-snapshot-id"], 42);
+
+    let manifest = Reader::new(&table[2].bytes[..]).unwrap();
+    assert_eq!(manifest.user_metadata()["format-version"], b"2".to_vec());
+    let entries = manifest.map(|entry| entry.unwrap()).collect::<Vec<_>>();
+    assert_eq!(entries.len(), 2);
+    let AvroValue::Record(entry) = &entries[0] else {
+        panic!("manifest entry is not a record");
+    };
+    assert_eq!(entry[0], ("status".to_string(), AvroValue::Int(1)));
+    let AvroValue::Record(data_file) = &entry[4].1 else {
+        panic!("data file is not a record");
+    };
+    assert_eq!(
+        data_file[1].1,
+        AvroValue::String("s3://bucket/events/data/part-0.parquet".to_string())
+    );
+    assert_eq!(data_file[4].1, AvroValue::Long(3));
+    assert_eq!(data_file[5].1, AvroValue::Long(files[0].1.len() as i64));
+    let bound = |id: i32, bytes: Vec<u8>| {
+        record(vec![
+            ("key", AvroValue::Int(id)),
+            ("value", AvroValue::Bytes(bytes)),
+        ])
+    };
+    assert_eq!(
+        data_file[9].1,
+        AvroValue::Union(
+            1,
+            Box::new(AvroValue::Array(vec![
+                bound(1, 0i64.to_le_bytes().to_vec()),
+                bound(2, b"n0".to_vec()),
+                bound(3, 0i64.to_le_bytes().to_vec()),
+            ]))
+        )
+    );
+    assert_eq!(
+        data_file[10].1,
+        AvroValue::Union(
+            1,
+            Box::new(AvroValue::Array(vec![
+                bound(1, 2i64.to_le_bytes().to_vec()),
+                bound(2, b"n2".to_vec()),
+                bound(3, 2_000i64.to_le_bytes().to_vec()),
+            ]))
+        )
+    );
+    // The field ids are kept in the schema in the header
+    let header = String::from_utf8_lossy(&table[2].bytes[..2000.min(table[2].bytes.len())]);
+    assert!(header.contains(r#""field-id":134"#));
+
+    let manifest_list = Reader::new(&table[3].bytes[..]).unwrap();
+    let manifests = manifest_list
+        .map(|entry| entry.unwrap())
+        .collect::<Vec<_>>();
+    let AvroValue::Record(manifest_file) = &manifests[0] else {
+        panic!("manifest file is not a record");
+    };
+    assert_eq!(
+        manifest_file[1].1,
+        AvroValue::Long(table[2].bytes.len() as i64)
+    );
+    assert_eq!(manifest_file[10].1, AvroValue::Long(5));
+
+    let options = parse_iceberg_options(r#"{"location": "s3://bucket/events"}"#).unwrap();
+    assert_eq!(
+        super::iceberg::table(&files, &options).unwrap_err(),
+        "table_uuid is required to create a table"
+    );
+}
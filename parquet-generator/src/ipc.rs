@@ -0,0 +1,279 @@
+use crate::{writer, CompiledSchema};
+use arrow_array::{new_null_array, ArrayRef, RecordBatch};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{Schema, SchemaRef};
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::{parquet_to_arrow_schema, ArrowWriter};
+use parquet::schema::types::SchemaDescriptor;
+use std::io::Cursor;
+use std::sync::Arc;
+
+/// The magic bytes an Arrow IPC file starts with, a stream has no magic
+const ARROW_FILE_MAGIC: &[u8] = b"ARROW1";
+
+/// Write an Arrow IPC file or stream as parquet. With a schema, columns are matched to fields by
+/// their name, `source_key` or aliases and cast to the field's type, otherwise the Arrow schema
+/// is written as is
+pub(crate) fn write_ipc(schema: Option<&CompiledSchema>, ipc: &[u8]) -> Result<Vec<u8>, String> {
+    let (source_schema, batches) = read_ipc(ipc)?;
+
+    let target_schema = match schema {
+        Some(schema) => {
+            let descriptor = SchemaDescriptor::new(schema.message_type.clone());
+            match parquet_to_arrow_schema(&descriptor, None) {
+                Ok(s) => Arc::new(s),
+                Err(_) => return Err("Error converting schema to Arrow".to_string()),
+            }
+        }
+        None => source_schema,
+    };
+
+    let mut arrow_writer = match ArrowWriter::try_new(vec![], target_schema.clone(), None) {
+        Ok(w) => w,
+        Err(_) => return Err("Error creating writer".to_string()),
+    };
+    for batch in batches {
+        let batch = match schema {
+            Some(schema) => project_batch(schema, &target_schema, &batch)?,
+            None => batch,
+        };
+        if arrow_writer.write(&batch).is_err() {
+            return Err("Error writing record batch".to_string());
+        }
+    }
+
+    match arrow_writer.into_inner() {
+        Ok(bytes_buffer) => Ok(bytes_buffer),
+        Err(_) => Err("Error closing writer".to_string()),
+    }
+}
+
+/// Read a parquet file into an Arrow IPC stream, a record batch for each row group. The Arrow
+/// schema stored in the file's metadata by Arrow writers is used when there is one, otherwise
+/// it's converted from the parquet schema
+pub(crate) fn parquet_to_ipc(bytes: Vec<u8>) -> Result<Vec<u8>, String> {
+    let builder = match ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes)) {
+        Ok(builder) => builder,
+        Err(e) => return Err(format!("Error reading parquet file: {}", e)),
+    };
+    // Batches are sized to the largest row group so each row group is read as one
+    let batch_size = builder
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|row_group| row_group.num_rows() as usize)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let schema = builder.schema().clone();
+    let reader = match builder.with_batch_size(batch_size).build() {
+        Ok(reader) => reader,
+        Err(e) => return Err(format!("Error reading parquet file: {}", e)),
+    };
+
+    let mut stream_writer = match StreamWriter::try_new(vec![], &schema) {
+        Ok(w) => w,
+        Err(_) => return Err("Error creating Arrow IPC writer".to_string()),
+    };
+    for batch in reader {
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(e) => return Err(format!("Error reading record batch: {}", e)),
+        };
+        if stream_writer.write(&batch).is_err() {
+            return Err("Error writing record batch".to_string());
+        }
+    }
+    match stream_writer.into_inner() {
+        Ok(ipc) => Ok(ipc),
+        Err(_) => Err("Error closing Arrow IPC writer".to_string()),
+    }
+}
+
+fn read_ipc(ipc: &[u8]) -> Result<(SchemaRef, Vec<RecordBatch>), String> {
+    let cursor = Cursor::new(ipc);
+    let (schema, batches): (SchemaRef, Vec<_>) = if ipc.starts_with(ARROW_FILE_MAGIC) {
+        match FileReader::try_new(cursor, None) {
+            Ok(reader) => (reader.schema(), reader.collect()),
+            Err(_) => return Err("Error reading Arrow IPC file".to_string()),
+        }
+    } else {
+        match StreamReader::try_new(cursor, None) {
+            Ok(reader) => (reader.schema(), reader.collect()),
+            Err(_) => return Err("Error reading Arrow IPC stream".to_string()),
+        }
+    };
+    match batches.into_iter().collect::<Result<Vec<_>, _>>() {
+        Ok(batches) => Ok((schema, batches)),
+        Err(_) => Err("Error reading Arrow record batch".to_string()),
+    }
+}
+
+/// Arrange a batch's columns in schema order, cast to the types of the target schema. Missing
+/// columns are written as null when the field is OPTIONAL
+fn project_batch(
+    schema: &CompiledSchema,
+    target_schema: &SchemaRef,
+    batch: &RecordBatch,
+) -> Result<RecordBatch, String> {
+    let source_schema = batch.schema();
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(target_schema.fields().len());
+
+    for (field, target) in schema.schema.fields.iter().zip(target_schema.fields()) {
+        let index = source_column(&source_schema, field)?;
+        let column = match index {
+            Some(i) => match arrow_cast::cast(batch.column(i), target.data_type()) {
+                Ok(c) => c,
+                Err(_) => {
+                    return Err(format!(
+                        "Error casting column {} to {}",
+                        source_schema.field(i).name(),
+                        target.data_type()
+                    ))
+                }
+            },
+            None if target.is_nullable() => new_null_array(target.data_type(), batch.num_rows()),
+            None => return Err(format!("Missing column for required field {}", field.name)),
+        };
+        if !target.is_nullable() && column.null_count() > 0 {
+            return Err(format!("Null values for required field {}", field.name));
+        }
+        columns.push(column);
+    }
+
+    match RecordBatch::try_new(target_schema.clone(), columns) {
+        Ok(b) => Ok(b),
+        Err(_) => Err("Error building record batch".to_string()),
+    }
+}
+
+fn source_column(
+    source_schema: &Schema,
+    field: &crate::ParquetField,
+) -> Result<Option<usize>, String> {
+    let paths = writer::key_paths(field)?;
+    Ok(paths.iter().find_map(|path| match path.as_slice() {
+        [key] => source_schema.index_of(key).ok(),
+        _ => None,
+    }))
+}
+
+#[test]
+fn test_write_ipc_with_schema() {
+    use arrow_array::{Int64Array, StringArray};
+    use arrow_ipc::writer::StreamWriter;
+    use arrow_schema::{DataType, Field};
+    use parquet::file::reader::{FileReader as _, SerializedFileReader};
+
+    let source_schema = Arc::new(Schema::new(vec![
+        Field::new("userId", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, true),
+    ]));
+    let batch = RecordBatch::try_new(
+        source_schema.clone(),
+        vec![
+            Arc::new(Int64Array::from(vec![1, 2])),
+            Arc::new(StringArray::from(vec![Some("a"), None])),
+        ],
+    )
+    .unwrap();
+    let mut stream_writer = StreamWriter::try_new(vec![], &source_schema).unwrap();
+    stream_writer.write(&batch).unwrap();
+    let ipc = stream_writer.into_inner().unwrap();
+
+    let schema = CompiledSchema::parse(
+        r#"
+        {
+            "fields": [
+                {
+                    "name": "user_id",
+                    "type": "INT32",
+                    "source_key": "userId"
+                },
+                {
+                    "name": "name",
+                    "type": "BYTE_ARRAY",
+                    "logical_type": "UTF8",
+                    "repetition_type": "OPTIONAL"
+                },
+                {
+                    "name": "score",
+                    "type": "DOUBLE",
+                    "repetition_type": "OPTIONAL"
+                }
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+    let bytes = write_ipc(Some(&schema), &ipc).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"user_id": 1, "name": "a", "score": null}),
+            serde_json::json!({"user_id": 2, "name": null, "score": null}),
+        ]
+    );
+}
+
+#[test]
+fn test_parquet_to_ipc() {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::Array;
+    use arrow_schema::DataType;
+
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"}
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "name": "a"}"#.to_string(),
+        r#"{"id": 2}"#.to_string(),
+        r#"{"id": 3, "name": "c"}"#.to_string(),
+    ];
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let ipc = parquet_to_ipc(bytes).unwrap();
+    let (schema, batches) = read_ipc(&ipc).unwrap();
+    assert_eq!(schema.field(0).data_type(), &DataType::Int64);
+    assert_eq!(schema.field(1).data_type(), &DataType::Utf8);
+    assert!(schema.field(1).is_nullable());
+    assert_eq!(
+        batches
+            .iter()
+            .map(RecordBatch::num_rows)
+            .collect::<Vec<_>>(),
+        vec![2, 1]
+    );
+    let ids = batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column(0)
+                .as_primitive::<Int64Type>()
+                .values()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert!(batches[0].column(1).is_null(1));
+
+    assert!(parquet_to_ipc(b"not parquet".to_vec())
+        .unwrap_err()
+        .starts_with("Error reading parquet file"));
+}
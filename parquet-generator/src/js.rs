@@ -0,0 +1,701 @@
+use crate::computed::Computed;
+use crate::options::{LoneSurrogatePolicy, WriterOptions};
+use crate::writer::{self, Record};
+use crate::{convert, csv, unicode, ParquetField};
+use js_sys::{Array, BigInt, Date, Function, JsString, Object, Promise, Reflect, Uint8Array};
+use serde_json::{Map, Number, Value};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{ReadableStream, ReadableStreamDefaultController};
+
+/// The largest integer a JS number holds exactly, integral numbers within it become JSON integers
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Convert an array of plain JS objects into records without going through a JSON string, adding
+/// the bytes of their `Uint8Array` values to `byte_values`
+pub(crate) fn parse_js_records(
+    records: &Array,
+    surrogates: LoneSurrogatePolicy,
+    byte_values: &mut Vec<Vec<u8>>,
+) -> Result<Vec<Record>, String> {
+    records
+        .iter()
+        .enumerate()
+        .map(
+            |(row, record)| match to_json(&record, surrogates, byte_values, &mut vec![]) {
+                Ok(Value::Object(record)) => Ok(record),
+                Ok(_) => Err(format!("Row {}: record is not an object", row)),
+                Err(e) => Err(format!("Row {}: {}", row, e)),
+            },
+        )
+        .collect()
+}
+
+/// Replace the values of fields that have a function in `transforms`, keyed by field name, with
+/// what the function returns when called with the value (null when missing), or the record for
+/// computed fields, and the row index. Returned `Uint8Array` values are added to the options'
+/// `byte_values`
+pub(crate) fn apply_transforms(
+    fields: &[ParquetField],
+    records: &mut [Record],
+    transforms: &Object,
+    options: &mut WriterOptions,
+) -> Result<(), String> {
+    for field in fields {
+        let transform = match Reflect::get(transforms, &JsValue::from_str(&field.name)) {
+            Ok(t) if t.is_function() => Function::from(t),
+            Ok(t) if t.is_undefined() => continue,
+            _ => {
+                return Err(format!(
+                    "Transform for field {} is not a function",
+                    field.name
+                ))
+            }
+        };
+        let paths = writer::key_paths(field)?;
+        for (row, record) in records.iter_mut().enumerate() {
+            let value = match field.computed {
+                Some(Computed::Callback(true)) => record_to_js(record, &options.byte_values),
+                _ => writer::lookup(record, &paths, options.case_insensitive_keys)
+                    .map_or(JsValue::NULL, |v| value_to_js(v, &options.byte_values)),
+            };
+            let transformed = match transform.call2(&JsValue::NULL, &value, &JsValue::from(row)) {
+                Ok(transformed) => to_json(
+                    &transformed,
+                    options.lone_surrogates,
+                    &mut options.byte_values,
+                    &mut vec![],
+                )
+                .map_err(|e| format!("Row {}: {}", row, e))?,
+                Err(e) => {
+                    return Err(format!(
+                        "Row {}: transform for field {} threw {}",
+                        row,
+                        field.name,
+                        thrown_message(&e)
+                    ))
+                }
+            };
+            csv::insert_path(record, &paths[0], transformed);
+        }
+    }
+    Ok(())
+}
+
+/// Keep the records a JS predicate returns a truthy value for when called with the record and
+/// its row index
+pub(crate) fn filter_records(
+    records: Vec<Record>,
+    filter: &Function,
+) -> Result<Vec<Record>, String> {
+    let mut accepted = Vec::with_capacity(records.len());
+    for (row, record) in records.into_iter().enumerate() {
+        let js_record = record_to_js(&record, &[]);
+        match filter.call2(&JsValue::NULL, &js_record, &JsValue::from(row)) {
+            Ok(keep) if keep.is_truthy() => accepted.push(record),
+            Ok(_) => {}
+            Err(e) => return Err(format!("Row {}: filter threw {}", row, thrown_message(&e))),
+        }
+    }
+    Ok(accepted)
+}
+
+/// Pass a chunk of a parquet file to a JS sink function as a `Uint8Array`
+pub(crate) fn emit_chunk(sink: &Function, chunk: &[u8]) -> Result<(), String> {
+    match sink.call1(&JsValue::NULL, &Uint8Array::from(chunk)) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Sink threw {}", thrown_message(&e))),
+    }
+}
+
+/// Pass a chunk of a parquet file to a JS sink function as a `Uint8Array`, waiting for the promise
+/// it returns, if any, to resolve
+pub(crate) async fn write_chunk(sink: &Function, chunk: &[u8]) -> Result<(), String> {
+    let returned = match sink.call1(&JsValue::NULL, &Uint8Array::from(chunk)) {
+        Ok(returned) => returned,
+        Err(e) => return Err(format!("Sink threw {}", thrown_message(&e))),
+    };
+    match JsFuture::from(Promise::resolve(&returned)).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Sink rejected {}", thrown_message(&e))),
+    }
+}
+
+/// A `ReadableStream` of `Uint8Array` chunks that calls `next_chunk` each time it's read from,
+/// closing once it returns none and erroring if it fails
+pub(crate) fn readable_stream(
+    mut next_chunk: impl FnMut() -> Result<Option<Vec<u8>>, String> + 'static,
+) -> Result<ReadableStream, JsValue> {
+    let pull = Closure::<dyn FnMut(ReadableStreamDefaultController) -> Result<(), JsValue>>::new(
+        move |controller: ReadableStreamDefaultController| match next_chunk() {
+            Ok(Some(chunk)) => controller.enqueue_with_chunk(&Uint8Array::from(&chunk[..])),
+            Ok(None) => controller.close(),
+            Err(e) => Err(JsValue::from_str(&e)),
+        },
+    );
+    let source = Object::new();
+    Reflect::set(&source, &JsValue::from_str("pull"), &pull.into_js_value())?;
+    // web-sys only binds the constructor behind its unstable APIs, so it's called through the global
+    let constructor: Function =
+        Reflect::get(&js_sys::global(), &JsValue::from_str("ReadableStream"))?.dyn_into()?;
+    Ok(Reflect::construct(&constructor, &Array::of1(&source))?.unchecked_into())
+}
+
+/// Call a JS progress callback with an object of the progress so far
+pub(crate) fn report_progress(
+    callback: &Function,
+    progress: writer::Progress,
+) -> Result<(), String> {
+    let progress = serde_json::to_value(progress).unwrap_or(Value::Null);
+    match callback.call1(&JsValue::NULL, &to_js(&progress)) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Progress callback threw {}", thrown_message(&e))),
+    }
+}
+
+/// Milliseconds from an arbitrary start, for timing parts of a write. `performance.now()` where
+/// there is one, `Date.now()` otherwise, and the system clock outside of wasm where neither exists
+pub(crate) fn now_millis() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let performance = Reflect::get(&js_sys::global(), &JsValue::from_str("performance"))
+            .ok()
+            .filter(|performance| performance.is_object());
+        let now = performance.and_then(|performance| {
+            let now: Function = Reflect::get(&performance, &JsValue::from_str("now"))
+                .ok()?
+                .dyn_into()
+                .ok()?;
+            now.call0(&performance).ok()?.as_f64()
+        });
+        now.unwrap_or_else(Date::now)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64() * 1_000.0)
+    }
+}
+
+/// Milliseconds since the Unix epoch, from `Date.now()` or the system clock outside of wasm
+pub(crate) fn unix_millis() -> i64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Date::now() as i64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as i64)
+    }
+}
+
+/// A random version 4 UUID, with bytes from `crypto.getRandomValues()` or the OS's generator
+/// outside of wasm
+pub(crate) fn random_uuid() -> Result<String, String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Couldn't generate a UUID: {}", e))?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// The message of an `Error`, or the string of any other thrown value
+fn thrown_message(thrown: &JsValue) -> String {
+    match thrown.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => thrown
+            .as_string()
+            .unwrap_or_else(|| format!("{:?}", thrown)),
+    }
+}
+
+/// Convert a JSON value into the JS value `JSON.parse` would give
+pub(crate) fn to_js(value: &Value) -> JsValue {
+    value_to_js(value, &[])
+}
+
+/// Convert a JSON value into a JS value, handing the bytes of a `Uint8Array` in `byte_values` back
+/// to callbacks as one
+fn value_to_js(value: &Value, byte_values: &[Vec<u8>]) -> JsValue {
+    match value {
+        Value::Null => JsValue::NULL,
+        Value::Bool(b) => JsValue::from_bool(*b),
+        Value::Number(n) => JsValue::from_f64(n.as_f64().unwrap_or(f64::NAN)),
+        Value::String(s) => JsValue::from_str(s),
+        Value::Array(elements) => elements
+            .iter()
+            .map(|v| value_to_js(v, byte_values))
+            .collect::<Array>()
+            .into(),
+        Value::Object(entries) => match convert::uint8_array(value, byte_values) {
+            Some(bytes) => Uint8Array::from(bytes).into(),
+            None => record_to_js(entries, byte_values),
+        },
+    }
+}
+
+/// Convert a JSON object into a JS object, without wrapping it in a `Value` first
+pub(crate) fn record_to_js(record: &Record, byte_values: &[Vec<u8>]) -> JsValue {
+    let object = Object::new();
+    for (key, entry) in record {
+        let _ = Reflect::set(
+            &object,
+            &JsValue::from_str(key),
+            &value_to_js(entry, byte_values),
+        );
+    }
+    object.into()
+}
+
+/// What converting a JS value to JSON needs to know about it, so the conversion can run against
+/// values other than a `JsValue` in host tests
+enum JsKind<V> {
+    Null,
+    Undefined,
+    Bool(bool),
+    Number(f64),
+    /// The string, or its UTF-16 code units when it holds lone surrogates, since copying such a
+    /// string out of JS replaces them
+    String(Result<String, Vec<u16>>),
+    /// The decimal digits of a `BigInt`
+    BigInt(String),
+    /// The RFC 3339 string of a `Date`, None when it's an invalid date
+    Date(Option<String>),
+    /// The bytes of a `Uint8Array`
+    Bytes(Vec<u8>),
+    Array(Vec<V>),
+    /// The own enumerable string-keyed properties of an object, in order
+    Object(Vec<(String, V)>),
+    /// A function or symbol
+    Unsupported,
+}
+
+trait JsSource: Clone {
+    fn kind(&self) -> Result<JsKind<Self>, String>;
+
+    /// Whether this is the same object as `other`, for finding cycles
+    fn same(&self, other: &Self) -> bool;
+}
+
+impl JsSource for JsValue {
+    fn kind(&self) -> Result<JsKind<JsValue>, String> {
+        if self.is_null() {
+            return Ok(JsKind::Null);
+        }
+        if self.is_undefined() {
+            return Ok(JsKind::Undefined);
+        }
+        if let Some(b) = self.as_bool() {
+            return Ok(JsKind::Bool(b));
+        }
+        if let Some(n) = self.as_f64() {
+            return Ok(JsKind::Number(n));
+        }
+        if let Some(s) = self.dyn_ref::<JsString>() {
+            return Ok(JsKind::String(match s.is_valid_utf16() {
+                true => Ok(String::from(s)),
+                false => Err(s.iter().collect()),
+            }));
+        }
+        if let Some(bigint) = self.dyn_ref::<BigInt>() {
+            return match bigint.to_string(10) {
+                Ok(digits) => Ok(JsKind::BigInt(String::from(digits))),
+                Err(_) => Err("error converting BigInt".to_string()),
+            };
+        }
+        if let Some(date) = self.dyn_ref::<Date>() {
+            // `toISOString` throws on an invalid date
+            return Ok(JsKind::Date(
+                (!date.get_time().is_nan()).then(|| String::from(date.to_iso_string())),
+            ));
+        }
+        if let Some(bytes) = self.dyn_ref::<Uint8Array>() {
+            return Ok(JsKind::Bytes(bytes.to_vec()));
+        }
+        if Array::is_array(self) {
+            return Ok(JsKind::Array(Array::from(self).iter().collect()));
+        }
+        match self.dyn_ref::<Object>() {
+            Some(object) if !self.is_function() => Ok(JsKind::Object(
+                Object::entries(object)
+                    .iter()
+                    .filter_map(|entry| {
+                        let entry = Array::from(&entry);
+                        Some((entry.get(0).as_string()?, entry.get(1)))
+                    })
+                    .collect(),
+            )),
+            _ => Ok(JsKind::Unsupported),
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        Object::is(self, other)
+    }
+}
+
+/// Convert a JS value into its JSON equivalent, `undefined` becomes null and dates become RFC 3339
+/// strings as `JSON.stringify` would produce. A `BigInt` becomes an exact integer when it fits in
+/// 64 bits and a decimal string otherwise. A `Uint8Array` has its bytes added to `byte_values` and
+/// becomes an object holding their index under `convert::UINT8_ARRAY_KEY`, which BYTE_ARRAY and
+/// FIXED_LEN_BYTE_ARRAY fields write the bytes of while plain arrays of numbers are still rejected,
+/// so objects can't use that key themselves. `ancestors` holds the arrays and objects
+/// `value` is nested in, so a cyclic value is an error rather than endless recursion.
+///
+/// `serde_wasm_bindgen::from_value` can't stand in for this walk: it fails on a `BigInt` past 64
+/// bits, reads a `Date` as an empty object since it has no own properties, and copies strings out
+/// with their lone surrogates already replaced, before `surrogates` can decide what happens to them
+fn to_json<V: JsSource>(
+    value: &V,
+    surrogates: LoneSurrogatePolicy,
+    byte_values: &mut Vec<Vec<u8>>,
+    ancestors: &mut Vec<V>,
+) -> Result<Value, String> {
+    match value.kind()? {
+        JsKind::Null | JsKind::Undefined | JsKind::Date(None) => Ok(Value::Null),
+        JsKind::Bool(b) => Ok(Value::Bool(b)),
+        JsKind::Number(n) => Ok(number_to_json(n)),
+        JsKind::String(Ok(s)) => Ok(Value::String(s)),
+        JsKind::String(Err(units)) => unicode::decode_utf16(&units, surrogates).map(Value::String),
+        JsKind::BigInt(digits) => Ok(bigint_to_json(digits)),
+        JsKind::Date(Some(iso)) => Ok(Value::String(iso)),
+        JsKind::Bytes(bytes) => {
+            byte_values.push(bytes);
+            let mut map = Map::new();
+            let index = Value::from(byte_values.len() - 1);
+            map.insert(convert::UINT8_ARRAY_KEY.to_string(), index);
+            Ok(Value::Object(map))
+        }
+        JsKind::Array(elements) => nested(value, ancestors, |ancestors| {
+            elements
+                .iter()
+                .map(|v| to_json(v, surrogates, byte_values, ancestors))
+                .collect::<Result<_, _>>()
+                .map(Value::Array)
+        }),
+        JsKind::Object(entries) => nested(value, ancestors, |ancestors| {
+            entries
+                .into_iter()
+                .map(|(key, v)| match key.as_str() {
+                    convert::UINT8_ARRAY_KEY => Err(format!("reserved key {:?}", key)),
+                    _ => Ok((key, to_json(&v, surrogates, byte_values, ancestors)?)),
+                })
+                .collect::<Result<_, String>>()
+                .map(Value::Object)
+        }),
+        JsKind::Unsupported => Err("unsupported JS value".to_string()),
+    }
+}
+
+/// Convert the contents of an array or object with it pushed onto `ancestors`, failing if it's
+/// already one of them
+fn nested<V: JsSource>(
+    value: &V,
+    ancestors: &mut Vec<V>,
+    convert: impl FnOnce(&mut Vec<V>) -> Result<Value, String>,
+) -> Result<Value, String> {
+    if ancestors.iter().any(|ancestor| ancestor.same(value)) {
+        return Err("cyclic value".to_string());
+    }
+    ancestors.push(value.clone());
+    let converted = convert(ancestors);
+    ancestors.pop();
+    converted
+}
+
+fn number_to_json(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER {
+        return Value::Number(Number::from(n as i64));
+    }
+    // JSON has no NaN or infinities, so they're passed on as the tokens `String(n)` gives
+    match Number::from_f64(n) {
+        Some(n) => Value::Number(n),
+        None if n.is_nan() => Value::String("NaN".to_string()),
+        None if n > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+fn bigint_to_json(digits: String) -> Value {
+    if let Ok(n) = digits.parse::<i64>() {
+        return Value::Number(Number::from(n));
+    }
+    if let Ok(n) = digits.parse::<u64>() {
+        return Value::Number(Number::from(n));
+    }
+    Value::String(digits)
+}
+
+/// A JS value built in Rust, for running `to_json` on the host. Arrays and objects are shared so
+/// tests can build cycles
+#[cfg(test)]
+#[derive(Clone)]
+enum TestValue {
+    Kind(std::rc::Rc<dyn Fn() -> JsKind<TestValue>>),
+    Shared(std::rc::Rc<std::cell::RefCell<Option<TestValue>>>),
+}
+
+#[cfg(test)]
+impl TestValue {
+    fn new(kind: impl Fn() -> JsKind<TestValue> + 'static) -> Self {
+        TestValue::Kind(std::rc::Rc::new(kind))
+    }
+
+    fn array(elements: Vec<TestValue>) -> Self {
+        TestValue::new(move || JsKind::Array(elements.clone()))
+    }
+
+    fn object(entries: Vec<(&'static str, TestValue)>) -> Self {
+        TestValue::new(move || {
+            JsKind::Object(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+impl JsSource for TestValue {
+    fn kind(&self) -> Result<JsKind<TestValue>, String> {
+        match self {
+            TestValue::Kind(kind) => Ok(kind()),
+            TestValue::Shared(value) => value.borrow().as_ref().unwrap().kind(),
+        }
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TestValue::Shared(a), TestValue::Shared(b)) => std::rc::Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn test_to_json() {
+    let value = |kind: fn() -> JsKind<TestValue>| TestValue::new(kind);
+    let record = TestValue::object(vec![
+        ("id", value(|| JsKind::BigInt("7".to_string()))),
+        (
+            "huge",
+            value(|| JsKind::BigInt("18446744073709551616".to_string())),
+        ),
+        (
+            "negative",
+            value(|| JsKind::BigInt("-9223372036854775808".to_string())),
+        ),
+        (
+            "unsigned",
+            value(|| JsKind::BigInt("18446744073709551615".to_string())),
+        ),
+        ("null", value(|| JsKind::Null)),
+        ("missing", value(|| JsKind::Undefined)),
+        (
+            "tags",
+            TestValue::array(vec![
+                value(|| JsKind::String(Ok("x".to_string()))),
+                value(|| JsKind::Undefined),
+                value(|| JsKind::Null),
+            ]),
+        ),
+        ("count", value(|| JsKind::Number(3.0))),
+        ("ratio", value(|| JsKind::Number(2.5))),
+        ("unsafe", value(|| JsKind::Number(2f64.powi(60)))),
+        ("nan", value(|| JsKind::Number(f64::NAN))),
+        ("infinity", value(|| JsKind::Number(f64::NEG_INFINITY))),
+        (
+            "created_at",
+            value(|| JsKind::Date(Some("1970-01-01T00:00:00.000Z".to_string()))),
+        ),
+        ("invalid_date", value(|| JsKind::Date(None))),
+        ("bytes", value(|| JsKind::Bytes(vec![1, 255]))),
+    ]);
+    let mut byte_values = vec![vec![0]];
+    assert_eq!(
+        to_json(
+            &record,
+            LoneSurrogatePolicy::Reject,
+            &mut byte_values,
+            &mut vec![]
+        )
+        .unwrap(),
+        serde_json::json!({
+            "id": 7,
+            "huge": "18446744073709551616",
+            "negative": -9223372036854775808i64,
+            "unsigned": 18446744073709551615u64,
+            "null": null,
+            "missing": null,
+            "tags": ["x", null, null],
+            "count": 3,
+            "ratio": 2.5,
+            "unsafe": 1152921504606846976.0,
+            "nan": "NaN",
+            "infinity": "-Infinity",
+            "created_at": "1970-01-01T00:00:00.000Z",
+            "invalid_date": null,
+            "bytes": {convert::UINT8_ARRAY_KEY: 1},
+        })
+    );
+    assert_eq!(byte_values, [vec![0], vec![1, 255]]);
+
+    let forged = TestValue::object(vec![(
+        convert::UINT8_ARRAY_KEY,
+        value(|| JsKind::Number(0.0)),
+    )]);
+    assert_eq!(
+        to_json(
+            &forged,
+            LoneSurrogatePolicy::Reject,
+            &mut byte_values,
+            &mut vec![]
+        )
+        .unwrap_err(),
+        "reserved key \"\\0Uint8Array\""
+    );
+
+    let lone = value(|| JsKind::String(Err(vec![0x61, 0xD800])));
+    assert_eq!(
+        to_json(&lone, LoneSurrogatePolicy::Reject, &mut vec![], &mut vec![]).unwrap_err(),
+        "lone surrogate \\uD800 in string"
+    );
+    assert_eq!(
+        to_json(
+            &lone,
+            LoneSurrogatePolicy::Replace,
+            &mut vec![],
+            &mut vec![]
+        )
+        .unwrap(),
+        serde_json::json!("a\u{FFFD}")
+    );
+    let function = value(|| JsKind::Unsupported);
+    assert_eq!(
+        to_json(
+            &function,
+            LoneSurrogatePolicy::Reject,
+            &mut vec![],
+            &mut vec![]
+        )
+        .unwrap_err(),
+        "unsupported JS value"
+    );
+}
+
+#[test]
+fn test_to_json_cycles() {
+    let shared = |value: Option<TestValue>| std::rc::Rc::new(std::cell::RefCell::new(value));
+    // An object that holds itself, as `a.self = a` builds
+    let cell = shared(None);
+    let cyclic = TestValue::Shared(cell.clone());
+    *cell.borrow_mut() = Some(TestValue::object(vec![("self", cyclic.clone())]));
+    assert_eq!(
+        to_json(
+            &cyclic,
+            LoneSurrogatePolicy::Reject,
+            &mut vec![],
+            &mut vec![]
+        )
+        .unwrap_err(),
+        "cyclic value"
+    );
+
+    // The same object twice, not nested in itself, isn't a cycle
+    let point = TestValue::Shared(shared(Some(TestValue::object(vec![(
+        "x",
+        TestValue::new(|| JsKind::Number(1.0)),
+    )]))));
+    let record = TestValue::object(vec![("a", point.clone()), ("b", point)]);
+    assert_eq!(
+        to_json(
+            &record,
+            LoneSurrogatePolicy::Reject,
+            &mut vec![],
+            &mut vec![]
+        )
+        .unwrap(),
+        serde_json::json!({"a": {"x": 1}, "b": {"x": 1}})
+    );
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+#[wasm_bindgen_test::wasm_bindgen_test]
+fn test_to_json_js_values() {
+    let record = js_sys::eval(
+        r#"({
+            id: 7n,
+            huge: 18446744073709551616n,
+            negative: -9223372036854775808n,
+            user: {name: "a", tags: ["x", undefined, null], address: {city: undefined}},
+            matrix: [[1, 2.5], [], [{}]],
+            missing: undefined,
+            created_at: new Date(0),
+            invalid_date: new Date(NaN),
+            bytes: new Uint8Array([1, 255]),
+        })"#,
+    )
+    .unwrap();
+    let mut byte_values = vec![];
+    assert_eq!(
+        to_json(
+            &record,
+            LoneSurrogatePolicy::Reject,
+            &mut byte_values,
+            &mut vec![]
+        )
+        .unwrap(),
+        serde_json::json!({
+            "id": 7,
+            "huge": "18446744073709551616",
+            "negative": -9223372036854775808i64,
+            "user": {"name": "a", "tags": ["x", null, null], "address": {"city": null}},
+            "matrix": [[1, 2.5], [], [{}]],
+            "missing": null,
+            "created_at": "1970-01-01T00:00:00.000Z",
+            "invalid_date": null,
+            "bytes": {convert::UINT8_ARRAY_KEY: 0},
+        })
+    );
+    assert_eq!(byte_values, [vec![1, 255]]);
+
+    let cyclic = js_sys::eval(r#"(() => { const a = {}; a.self = a; return a; })()"#).unwrap();
+    assert_eq!(
+        to_json(
+            &cyclic,
+            LoneSurrogatePolicy::Reject,
+            &mut vec![],
+            &mut vec![]
+        )
+        .unwrap_err(),
+        "cyclic value"
+    );
+
+    let records = js_sys::eval(r#"[{id: 1n}, 5]"#).unwrap();
+    assert_eq!(
+        parse_js_records(
+            &Array::from(&records),
+            LoneSurrogatePolicy::Reject,
+            &mut vec![]
+        )
+        .unwrap_err(),
+        "Row 1: record is not an object"
+    );
+}
@@ -1,24 +1,113 @@
 use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
-use parquet::schema::printer;
+use parquet::errors::ParquetError;
 use parquet::schema::types::Type;
-use parquet::{file::writer::SerializedFileWriter, schema::parser::parse_message_type};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Range;
 use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::Clamped;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[cfg(feature = "arrow-writer")]
+mod arrow;
+mod avro;
+mod computed;
+mod convert;
+mod csv;
+mod decode;
+mod delta;
+mod encryption;
+mod error;
+mod filter;
+mod flatten;
+mod hudi;
+mod iceberg;
+mod ipc;
+mod js;
+mod options;
+mod pages;
+mod reader;
+mod rewrite;
+mod unicode;
+mod validate;
+mod writer;
+
+use error::WriteError;
+use options::{ReaderOptions, WriterOptions};
+
+/// Start the pool of web workers columns are encoded on with the `threads` feature, awaited once
+/// before generating files, e.g. `await initThreadPool(navigator.hardwareConcurrency)`. Needs a
+/// build with shared memory, `-C target-feature=+atomics,+bulk-memory` on nightly with
+/// `-Z build-std=panic_abort,std`, and a cross-origin isolated page for `SharedArrayBuffer`
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct ParquetSchema {
     fields: Vec<ParquetField>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct ParquetField {
     name: String,
     #[serde(rename = "type")]
     primitive_type: ParquetPrimitiveType,
     logical_type: Option<ParquetLogicalType>,
     repetition_type: Option<ParquetRepetition>,
+    /// The JSON key the column is populated from when it differs from `name`. Dots separate
+    /// keys of nested objects, e.g. `user.address.city`, literal dots are escaped as `\.`
+    source_key: Option<String>,
+    /// Other JSON keys the column may be populated from, tried in order after `source_key`
+    aliases: Option<Vec<String>>,
+    allowed_values: Option<Vec<String>>,
+    #[serde(default)]
+    on_invalid: InvalidValuePolicy,
+    /// How string values of a BYTE_ARRAY or FIXED_LEN_BYTE_ARRAY field encode their bytes, by
+    /// default the UTF-8 bytes of the string are written
+    encoding: Option<BinaryEncoding>,
+    /// The number of significant digits of a DECIMAL field
+    precision: Option<i32>,
+    /// The number of those digits after the decimal point, defaults to 0
+    scale: Option<i32>,
+    /// The logical type of the elements of a LIST field or the values of a MAP field, whose `type`
+    /// is the elements' or values' type
+    element_logical_type: Option<ParquetLogicalType>,
+    /// What to do with an integer outside the range of the field's type
+    #[serde(default)]
+    on_overflow: OverflowPolicy,
+    /// What to write when a record is missing the value or has it set to null
+    #[serde(default)]
+    on_null: NullPolicy,
+    /// The value written in place of a null when `on_null` is `default`
+    default: Option<serde_json::Value>,
+    /// An expression deriving the field's value from other keys of the record, or `true` when
+    /// a JS transform computes it from the whole record
+    computed: Option<computed::Computed>,
+}
+
+impl ParquetField {
+    /// A REQUIRED field with no logical type or other attributes
+    fn new(name: String, primitive_type: ParquetPrimitiveType) -> ParquetField {
+        ParquetField {
+            name,
+            primitive_type,
+            logical_type: None,
+            repetition_type: None,
+            source_key: None,
+            aliases: None,
+            allowed_values: None,
+            on_invalid: InvalidValuePolicy::default(),
+            encoding: None,
+            precision: None,
+            scale: None,
+            element_logical_type: None,
+            on_overflow: OverflowPolicy::default(),
+            on_null: NullPolicy::default(),
+            default: None,
+            computed: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
@@ -29,12 +118,13 @@ enum ParquetPrimitiveType {
     Int64,
     Int96,
     Binary,
+    Float,
     Double,
     ByteArray,
     FixedLenByteArray,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 enum ParquetLogicalType {
     Utf8,
@@ -61,7 +151,7 @@ enum ParquetLogicalType {
     Interval,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 enum ParquetRepetition {
     Required,
@@ -69,7 +159,55 @@ enum ParquetRepetition {
     Repeated,
 }
 
-fn logical_type_matcher(parquet_logical_type: ParquetLogicalType) -> ConvertedType {
+/// What to do with a value that fails validation, e.g. an ENUM value that isn't
+/// in the field's `allowed_values`
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum InvalidValuePolicy {
+    /// Fail the whole write, reporting the offending row
+    #[default]
+    Error,
+    /// Write a null in place of the value, only valid for OPTIONAL fields
+    Null,
+}
+
+/// What to do with a number outside the range of its field's type, e.g. 300 for a UINT8 field
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OverflowPolicy {
+    /// Fail the whole write, reporting the offending row
+    #[default]
+    Error,
+    /// Write the nearest value in range instead
+    Clamp,
+    /// Write a null in place of the value, only valid for OPTIONAL fields
+    Null,
+}
+
+/// What to do with a null or missing value
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum NullPolicy {
+    /// Write a null, or fail for REQUIRED fields
+    #[default]
+    Skip,
+    /// Write the field's `default` value instead
+    Default,
+    /// Fail the whole write, reporting the offending row
+    Error,
+}
+
+/// Text encodings of binary data in JSON strings
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BinaryEncoding {
+    /// Standard base64 with padding
+    Base64,
+    /// Hexadecimal, in either case
+    Hex,
+}
+
+fn logical_type_matcher(parquet_logical_type: &ParquetLogicalType) -> ConvertedType {
     match parquet_logical_type {
         ParquetLogicalType::Utf8 => ConvertedType::UTF8,
         ParquetLogicalType::Map => ConvertedType::MAP,
@@ -103,48 +241,197 @@ fn physical_type_matcher(parquet_primitive_type: ParquetPrimitiveType) -> Physic
         ParquetPrimitiveType::Int64 => PhysicalType::INT64,
         ParquetPrimitiveType::Int96 => PhysicalType::INT96,
         ParquetPrimitiveType::Binary => PhysicalType::BYTE_ARRAY,
+        ParquetPrimitiveType::Float => PhysicalType::FLOAT,
         ParquetPrimitiveType::Double => PhysicalType::DOUBLE,
         ParquetPrimitiveType::ByteArray => PhysicalType::BYTE_ARRAY,
         ParquetPrimitiveType::FixedLenByteArray => PhysicalType::FIXED_LEN_BYTE_ARRAY,
     }
 }
 
-fn build_schema(schema: String) -> String {
-    let schema = serde_json::from_str::<ParquetSchema>(schema.as_str()).unwrap();
+/// Build the parquet message type of a schema, failing on fields the parquet writer rejects, e.g.
+/// a logical type that doesn't fit its physical type
+fn build_schema(schema: &ParquetSchema) -> Result<Type, String> {
     let mut type_vec: Vec<Arc<Type>> = vec![];
 
-    for field in schema.fields {
-        let type_builder = Type::primitive_type_builder(
-            field.name.as_str(),
-            physical_type_matcher(field.primitive_type),
-        )
-        .with_repetition(match field.repetition_type {
+    for field in schema.fields.iter() {
+        let invalid = |e: ParquetError| format!("Invalid field {}: {}", field.name, e);
+        let repetition = match field.repetition_type {
             Some(ParquetRepetition::Required) => Repetition::REQUIRED,
             Some(ParquetRepetition::Optional) => Repetition::OPTIONAL,
             Some(ParquetRepetition::Repeated) => Repetition::REPEATED,
             None => Repetition::REQUIRED,
+        };
+        let nested = nested_leaf(field);
+        let leaf = nested.as_ref().unwrap_or(field);
+        let type_builder = Type::primitive_type_builder(
+            leaf.name.as_str(),
+            physical_type_matcher(leaf.primitive_type),
+        )
+        .with_repetition(match nested {
+            Some(_) => Repetition::OPTIONAL,
+            None => repetition,
         })
-        .with_length(match field.primitive_type {
-            ParquetPrimitiveType::FixedLenByteArray => 1024,
+        .with_length(match leaf.primitive_type {
+            ParquetPrimitiveType::FixedLenByteArray => fixed_len(leaf),
             _ => 0,
         })
-        .with_converted_type(match field.logical_type {
-            Some(logical_type) => logical_type_matcher(logical_type),
+        .with_precision(leaf.precision.unwrap_or(-1))
+        .with_scale(leaf.scale.unwrap_or(-1))
+        .with_converted_type(match leaf.logical_type {
+            Some(ref logical_type) => logical_type_matcher(logical_type),
             None => ConvertedType::NONE,
         });
-        let converted_type = type_builder.build().unwrap();
-        type_vec.push(Arc::new(converted_type));
-    }
+        let converted_type = Arc::new(type_builder.build().map_err(invalid)?);
 
-    let mut buf = Vec::new();
+        // LISTs and MAPs are written as the standard three levels,
+        // `<name> (LIST) { repeated group list { optional <type> element } }` and
+        // `<name> (MAP) { repeated group key_value { required binary key (UTF8); optional <type> value } }`
+        let (group_name, converted, leaves) = match field.logical_type {
+            Some(ParquetLogicalType::List) => ("list", ConvertedType::LIST, vec![converted_type]),
+            Some(ParquetLogicalType::Map) => {
+                let key = Type::primitive_type_builder("key", PhysicalType::BYTE_ARRAY)
+                    .with_repetition(Repetition::REQUIRED)
+                    .with_converted_type(ConvertedType::UTF8)
+                    .build()
+                    .map_err(invalid)?;
+                (
+                    "key_value",
+                    ConvertedType::MAP,
+                    vec![Arc::new(key), converted_type],
+                )
+            }
+            _ => {
+                type_vec.push(converted_type);
+                continue;
+            }
+        };
+        let repeated_group = Type::group_type_builder(group_name)
+            .with_repetition(Repetition::REPEATED)
+            .with_fields(leaves)
+            .build()
+            .map_err(invalid)?;
+        let nested_type = Type::group_type_builder(field.name.as_str())
+            .with_repetition(repetition)
+            .with_converted_type(converted)
+            .with_fields(vec![Arc::new(repeated_group)])
+            .build()
+            .map_err(invalid)?;
+        type_vec.push(Arc::new(nested_type));
+    }
 
-    let schema = Type::group_type_builder("schema")
+    Type::group_type_builder("schema")
         .with_fields(type_vec)
         .build()
-        .unwrap();
-    printer::print_schema(&mut buf, &schema);
+        .map_err(|e| format!("Invalid schema: {}", e))
+}
 
-    String::from_utf8(buf).unwrap()
+/// The element of a LIST field or the value of a MAP field, an OPTIONAL field of the field's type
+/// and `element_logical_type`
+fn nested_leaf(field: &ParquetField) -> Option<ParquetField> {
+    let name = match field.logical_type {
+        Some(ParquetLogicalType::List) => "element",
+        Some(ParquetLogicalType::Map) => "value",
+        _ => return None,
+    };
+    let mut leaf = field.clone();
+    leaf.name = name.to_string();
+    leaf.logical_type = field.element_logical_type.clone();
+    leaf.repetition_type = Some(ParquetRepetition::Optional);
+    Some(leaf)
+}
+
+/// The length of a FIXED_LEN_BYTE_ARRAY field, DECIMAL fields use the fewest bytes that can hold
+/// their precision
+fn fixed_len(field: &ParquetField) -> i32 {
+    match (&field.logical_type, field.precision) {
+        (Some(ParquetLogicalType::Decimal), Some(precision)) => convert::decimal_length(precision),
+        _ => writer::FIXED_LEN_BYTE_ARRAY_LENGTH,
+    }
+}
+
+/// Check a DECIMAL field's precision and scale fit its physical type, the largest decimals that
+/// can be written have 38 digits
+fn validate_decimal(field: &ParquetField) -> Result<(), String> {
+    if field.logical_type != Some(ParquetLogicalType::Decimal) {
+        return Ok(());
+    }
+    let max_precision = match field.primitive_type {
+        ParquetPrimitiveType::Int32 => 9,
+        ParquetPrimitiveType::Int64 => 18,
+        ParquetPrimitiveType::Binary
+        | ParquetPrimitiveType::ByteArray
+        | ParquetPrimitiveType::FixedLenByteArray => 38,
+        _ => return Err(format!("Unsupported type for DECIMAL field {}", field.name)),
+    };
+    match (field.precision, field.scale.unwrap_or(0)) {
+        (Some(precision), scale)
+            if (1..=max_precision).contains(&precision) && (0..=precision).contains(&scale) =>
+        {
+            Ok(())
+        }
+        _ => Err(format!(
+            "Invalid precision or scale for DECIMAL field {}, precision must be between 1 and {}",
+            field.name, max_precision
+        )),
+    }
+}
+
+/// A schema that has been parsed and built into a parquet message type once, so it can be
+/// reused across calls to `generate_parquet_compiled` without parsing it again
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct CompiledSchema {
+    schema: ParquetSchema,
+    message_type: Arc<Type>,
+}
+
+impl CompiledSchema {
+    fn parse(schema: &str) -> Result<CompiledSchema, String> {
+        match serde_json::from_str::<ParquetSchema>(schema) {
+            Ok(s) => CompiledSchema::build(s),
+            Err(e) => Err(format!("Error parsing schema: {}", e)),
+        }
+    }
+
+    fn build(schema: ParquetSchema) -> Result<CompiledSchema, String> {
+        for field in schema.fields.iter() {
+            match nested_leaf(field) {
+                Some(_) if field.repetition_type == Some(ParquetRepetition::Repeated) => {
+                    return Err(format!(
+                        "LIST or MAP field {} can't be REPEATED",
+                        field.name
+                    ))
+                }
+                Some(leaf) => validate_decimal(&leaf)?,
+                None => validate_decimal(field)?,
+            }
+            computed::validate(field)?;
+            if field.on_null == NullPolicy::Default && field.default.is_none() {
+                return Err(format!(
+                    "Field {} has on_null set to default but no default value",
+                    field.name
+                ));
+            }
+        }
+        let message_type = Arc::new(build_schema(&schema)?);
+        Ok(CompiledSchema {
+            schema,
+            message_type,
+        })
+    }
+}
+
+/// Parse and build a schema once, for use with `generate_parquet_compiled`
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+#[wasm_bindgen]
+pub fn compile_schema(schema: String) -> Result<CompiledSchema, JsValue> {
+    match CompiledSchema::parse(schema.as_str()) {
+        Ok(compiled) => Ok(compiled),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
 }
 
 /// Generate a parquet file from a schema and a list of files which are JSON strings
@@ -167,70 +454,5347 @@ fn build_schema(schema: String) -> String {
 /// ```
 #[wasm_bindgen]
 pub fn generate_parquet(schema: String, files: Vec<String>) -> Result<Clamped<Vec<u8>>, JsValue> {
-    let message_type = build_schema(schema);
-    let parsed_schema = parse_message_type(message_type.as_str());
+    match write_parquet(schema, files, &WriterOptions::default()) {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    let schema = match parsed_schema {
-        Ok(s) => Arc::new(s),
-        Err(_) => return Err(JsValue::from_str("Error parsing schema")),
-    };
+/// Generate a parquet file like `generate_parquet`, with writer options
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"case_insensitive_keys": true}`
+#[wasm_bindgen]
+pub fn generate_parquet_with_options(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = options::parse_options(options.as_str())
+        .map_err(WriteError::from)
+        .and_then(|options| write_parquet(schema, files, &options));
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file like `generate_parquet_with_options`, converting the records to Arrow
+/// record batches and writing them with arrow-rs. Values are converted as they are for
+/// `generate_parquet_with_options`, but arrow-rs picks the physical types the file is written
+/// with. Only available with the `arrow-writer` feature
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"case_insensitive_keys": true}`
+#[cfg(feature = "arrow-writer")]
+#[wasm_bindgen]
+pub fn generate_parquet_arrow(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = options::parse_options(options.as_str())
+        .map_err(WriteError::from)
+        .and_then(|options| {
+            let schema = CompiledSchema::parse(schema.as_str())?;
+            let records = writer::parse_records(&files, options.lone_surrogates)?;
+            arrow::write_arrow(&schema, &records, &options)
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    let buffer = vec![];
+/// Generate a parquet file like `generate_parquet_with_options`, using a schema returned by
+/// `compile_schema`
+///
+/// # Arguments
+///
+/// * `schema`: A schema returned by `compile_schema`
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options
+#[wasm_bindgen]
+pub fn generate_parquet_compiled(
+    schema: &CompiledSchema,
+    files: Vec<String>,
+    options: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = options::parse_options(options.as_str())
+        .map_err(WriteError::from)
+        .and_then(|options| write_compiled(schema, files, &options));
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
 
-    let mut writer = match SerializedFileWriter::new(buffer, schema, Default::default()) {
-        Ok(w) => w,
-        Err(_) => return Err(JsValue::from_str("Error creating writer")),
-    };
+/// Writes parquet files with one schema and set of options over and over, e.g. to flush telemetry
+/// every few seconds, keeping the output and column chunk buffers allocated between files rather
+/// than allocating them for each file and growing wasm memory
+#[wasm_bindgen]
+pub struct WriteContext {
+    schema: CompiledSchema,
+    options: WriterOptions,
+    /// The keys records are deserialized from, when they don't need to be parsed whole
+    keys: Option<decode::FieldKeys>,
+    buffers: writer::BufferPool,
+}
 
-    let mut row_group_writer = match writer.next_row_group() {
-        Ok(rgw) => rgw,
-        Err(_) => return Err(JsValue::from_str("Error creating row group writer")),
-    };
+#[wasm_bindgen]
+impl WriteContext {
+    /// Create a context to write files with
+    ///
+    /// # Arguments
+    ///
+    /// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+    /// * `options`: A JSON object of writer options
+    #[wasm_bindgen(constructor)]
+    pub fn new(schema: String, options: String) -> Result<WriteContext, JsValue> {
+        WriteContext::create(schema.as_str(), options.as_str()).map_err(JsValue::from)
+    }
 
-    while let Ok(Some(col_writer)) = row_group_writer.next_column() {
-        if col_writer.close().is_err() {
-            return Err(JsValue::from_str("Error closing column writer"));
+    /// Write a parquet file, returning a copy of its bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `files`: A list of strings of JSON objects that match the schema
+    pub fn write(&mut self, files: Vec<String>) -> Result<js_sys::Uint8Array, JsValue> {
+        match self.write_files(&files) {
+            Ok(bytes) => Ok(js_sys::Uint8Array::from(bytes)),
+            Err(e) => Err(e.into()),
         }
     }
+}
 
-    if row_group_writer.close().is_err() {
-        return Err(JsValue::from_str("Error closing row group writer"));
+impl WriteContext {
+    fn create(schema: &str, options: &str) -> Result<WriteContext, WriteError> {
+        let schema = CompiledSchema::parse(schema)?;
+        let options = options::parse_options(options)?;
+        Ok(WriteContext {
+            keys: decode::FieldKeys::new(&schema.schema.fields, &options),
+            schema,
+            options,
+            buffers: writer::BufferPool::default(),
+        })
     }
 
-    return match writer.into_inner() {
-        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
-        Err(_) => Err(JsValue::from_str("Error closing writer")),
+    fn write_files(&mut self, files: &[String]) -> Result<&[u8], WriteError> {
+        let options = &self.options;
+        if let Some(keys) = &self.keys {
+            let mut sink = self.buffers.sink(&self.schema.message_type, options)?;
+            decode::write_decoded(&mut sink, &self.schema, files, keys, options)?;
+            return self.buffers.finish(sink);
+        }
+
+        let records = writer::parse_records(files, options.lone_surrogates)?;
+        let prepared = prepare_records(&self.schema, &records, options, &mut HashSet::new())?;
+        let records = &prepared.records[..];
+        let schema = resolve_schema(&self.schema, records, options)?;
+        let mut sink = self.buffers.sink(&schema.message_type, options)?;
+        for row_group in writer::row_groups(records.len(), options)? {
+            sink.write_row_group(&schema.schema.fields, &records[row_group], options)?;
+        }
+        self.buffers.finish(sink)
+    }
+}
+
+/// A parquet file along with what happened to the records while writing it
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct WriteReport {
+    bytes: Vec<u8>,
+    filtered_rows: usize,
+    dropped_rows: usize,
+    peak_memory_bytes: usize,
+    columns: Vec<pages::ColumnReport>,
+    timings: writer::Timings,
+}
+
+#[wasm_bindgen]
+impl WriteReport {
+    /// The parquet file
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Clamped<Vec<u8>> {
+        Clamped(self.bytes.clone())
+    }
+
+    /// The number of records left out by the `filter` option or callback
+    #[wasm_bindgen(getter)]
+    pub fn filtered_rows(&self) -> usize {
+        self.filtered_rows
+    }
+
+    /// The number of records dropped as duplicates by `dedupe_on`
+    #[wasm_bindgen(getter)]
+    pub fn dropped_rows(&self) -> usize {
+        self.dropped_rows
+    }
+
+    /// The most memory a row group needed while writing, its parsed records plus its largest
+    /// column chunk, or every column chunk with the `threads` feature, for tuning
+    /// `max_memory_bytes`
+    #[wasm_bindgen(getter)]
+    pub fn peak_memory_bytes(&self) -> usize {
+        self.peak_memory_bytes
+    }
+
+    /// How each column's data pages were encoded, as an array of
+    /// `{column, encodings, dictionary_fallback}` in schema order
+    #[wasm_bindgen(getter)]
+    pub fn columns(&self) -> JsValue {
+        match serde_json::to_string(&self.columns) {
+            Ok(json) => js_sys::JSON::parse(json.as_str()).unwrap_or(JsValue::NULL),
+            Err(_) => JsValue::NULL,
+        }
+    }
+
+    /// Where the time writing the file went, as
+    /// `{parse_millis, prepare_millis, lookup_millis, columns, total_millis}` with `columns` an
+    /// array of `{column, encode_millis}` in schema order
+    #[wasm_bindgen(getter)]
+    pub fn timings(&self) -> JsValue {
+        match serde_json::to_string(&self.timings) {
+            Ok(json) => js_sys::JSON::parse(json.as_str()).unwrap_or(JsValue::NULL),
+            Err(_) => JsValue::NULL,
+        }
+    }
+}
+
+impl WriteReport {
+    /// Add the time taken to parse the records, before they were written
+    fn parsed_in(mut self, parse_millis: f64) -> Self {
+        self.timings.parse_millis += parse_millis;
+        self.timings.total_millis += parse_millis;
+        self
+    }
+}
+
+/// Parse each file into a record like `writer::parse_records`, along with the milliseconds it took
+fn parse_records_timed(
+    files: &[String],
+    surrogates: options::LoneSurrogatePolicy,
+) -> Result<(Vec<writer::Record>, f64), String> {
+    let start = js::now_millis();
+    let records = writer::parse_records(files, surrogates)?;
+    Ok((records, js::now_millis() - start))
+}
+
+/// Generate a parquet file like `generate_parquet_with_options`, returning a report of the
+/// write along with the file
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"dedupe_on": ["id"]}`
+#[wasm_bindgen]
+pub fn generate_parquet_with_report(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+) -> Result<WriteReport, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            write_files_report(&schema, &files, &options, None, None)
+        });
+    result.map_err(JsValue::from)
+}
+
+/// Parquet files rows were split across, by `max_file_bytes`, `max_rows_per_file` or
+/// `rows_per_file`, in order
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct SplitFiles {
+    files: Vec<Vec<u8>>,
+    rows: Vec<usize>,
+}
+
+#[wasm_bindgen]
+impl SplitFiles {
+    /// The parquet files, as an array of `Uint8Array`s
+    #[wasm_bindgen(getter)]
+    pub fn files(&self) -> js_sys::Array {
+        self.files
+            .iter()
+            .map(|file| js_sys::Uint8Array::from(file.as_slice()))
+            .collect()
+    }
+
+    /// The number of rows written to each file
+    #[wasm_bindgen(getter)]
+    pub fn rows(&self) -> Vec<usize> {
+        self.rows.clone()
+    }
+}
+
+/// Generate parquet files like `generate_parquet_with_options`, starting a new file whenever the
+/// next row group would take the current one past `max_file_bytes`, or once it has
+/// `max_rows_per_file` records. Row groups are cut at `max_file_bytes` too, as a file can't end
+/// in the middle of one
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"max_file_bytes": 1500000000}`
+#[wasm_bindgen]
+pub fn generate_parquet_split(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+) -> Result<SplitFiles, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let records = writer::parse_records(&files, options.lone_surrogates)?;
+            write_split(&schema, &records, &options)
+        });
+    result.map_err(JsValue::from)
+}
+
+fn write_split(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+) -> Result<SplitFiles, WriteError> {
+    let prepared = prepare_records(schema, records, options, &mut HashSet::new())?;
+    let records = &prepared.records[..];
+    let schema = resolve_schema(schema, records, options)?;
+    write_files(&schema, records, options)
+}
+
+/// Write prepared records to as many files as `max_file_bytes` needs, or a single empty file when
+/// there are none
+fn write_files(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+) -> Result<SplitFiles, WriteError> {
+    if options.max_file_bytes == Some(0) {
+        return Err(WriteError::from(
+            "max_file_bytes must be greater than 0".to_string(),
+        ));
+    }
+    let max_rows_per_file = match options.max_rows_per_file {
+        Some(0) => {
+            return Err(WriteError::from(
+                "max_rows_per_file must be greater than 0".to_string(),
+            ))
+        }
+        Some(max_rows_per_file) => max_rows_per_file,
+        None => usize::MAX,
     };
+
+    let mut split = SplitFiles {
+        files: vec![],
+        rows: vec![],
+    };
+    let mut sink = None;
+    // The records written to earlier files, which errors' rows are offset by
+    let mut rows_before = 0;
+    let mut rows = 0;
+    // Row groups never span files, so they're made from each file's worth of records
+    for start in (0..records.len()).step_by(max_rows_per_file) {
+        let end = start.saturating_add(max_rows_per_file).min(records.len());
+        for row_group in writer::row_groups(end - start, options)? {
+            let mut row_group = start + row_group.start..start + row_group.end;
+            // The sink leaves the records that don't fit in a full file for the next one
+            while !row_group.is_empty() {
+                let file = match &mut sink {
+                    Some(file) => file,
+                    None => {
+                        let output = writer::output_buffer(&schema.schema.fields, 0, options);
+                        let file = sink.insert(writer::FileSink::new(
+                            output,
+                            &schema.message_type,
+                            options,
+                        )?);
+                        if let Some(max_file_bytes) = options.max_file_bytes {
+                            file.split_at(max_file_bytes);
+                        }
+                        file
+                    }
+                };
+                let written = file
+                    .write_row_group(&schema.schema.fields, &records[row_group.clone()], options)
+                    .map_err(|e| e.offset_row(rows_before))?;
+                row_group.start += written;
+                rows += written;
+                if row_group.start == end || file.full() {
+                    if let Some(file) = sink.take() {
+                        split.files.push(file.finish()?.0);
+                        split.rows.push(rows);
+                        rows_before += rows;
+                        rows = 0;
+                    }
+                }
+            }
+        }
+    }
+    // The last file, or a single empty file when there are no records
+    if sink.is_some() || split.files.is_empty() {
+        let file = match sink {
+            Some(file) => file,
+            None => writer::FileSink::new(vec![], &schema.message_type, options)?,
+        };
+        split.files.push(file.finish()?.0);
+        split.rows.push(rows);
+    }
+    Ok(split)
 }
 
-#[test]
-fn test_build_schema_basic() {
-    let schema = r#"
-    {
-        "fields": [
-            {
-                "name": "id",
-                "type": "INT32"
-            },
-            {
-                "name": "name",
-                "type": "BYTE_ARRAY",
-                "logical_type": "UTF8"
-            },
-            {
-                "name": "age",
-                "type": "INT32"
-            },
-            {
-                "name": "is_active",
-                "type": "BOOLEAN"
+/// Generate parquet files like `generate_parquet_split`, partitioned hive-style by the values of
+/// some fields. Returns a `Map` of each file's path, e.g.
+/// `date=2024-01-01/region=eu/part-0000.parquet`, to its bytes. Null or missing values are put in
+/// the `__HIVE_DEFAULT_PARTITION__` directory, and `drop_partition_columns` leaves the fields out
+/// of the files. `partition_template` and `file_template` name the directories and files to match
+/// an existing layout, e.g. `part-{seq:05}-{uuid}.parquet`
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `partition_by`: The top level fields to partition by, in directory order
+/// * `options`: A JSON object of writer options, e.g. `{"drop_partition_columns": true}`
+#[wasm_bindgen]
+pub fn generate_partitioned(
+    schema: String,
+    files: Vec<String>,
+    partition_by: Vec<String>,
+    options: String,
+) -> Result<js_sys::Map, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let records = writer::parse_records(&files, options.lone_surrogates)?;
+            write_partitioned(&schema, &records, &partition_by, &options)
+        });
+    let files = js_sys::Map::new();
+    for (path, file) in result.map_err(JsValue::from)? {
+        let file = js_sys::Uint8Array::from(file.as_slice());
+        files.set(&JsValue::from_str(path.as_str()), &file);
+    }
+    Ok(files)
+}
+
+/// The path and bytes of each file of the partitioned records. Records are prepared before they're
+/// partitioned, so they can be partitioned by computed fields, and the schema is resolved from
+/// every record so the files' schemas match
+fn write_partitioned(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    partition_by: &[String],
+    options: &WriterOptions,
+) -> Result<Vec<(String, Vec<u8>)>, WriteError> {
+    let prepared = prepare_records(schema, records, options, &mut HashSet::new())?;
+    let records = &prepared.records[..];
+    let mut schema = resolve_schema(schema, records, options)?;
+    let partitions = writer::partition(&schema.schema.fields, records, partition_by, options)?;
+    if options.drop_partition_columns {
+        let mut parquet_schema = schema.schema.clone();
+        parquet_schema
+            .fields
+            .retain(|field| !partition_by.contains(&field.name));
+        if parquet_schema.fields.is_empty() {
+            return Err(WriteError::from(
+                "Can't drop every field as a partition column".to_string(),
+            ));
+        }
+        schema = Cow::Owned(CompiledSchema::build(parquet_schema)?);
+    }
+
+    let template = options
+        .file_template
+        .as_deref()
+        .unwrap_or(writer::DEFAULT_FILE_TEMPLATE);
+    let template = writer::NameTemplate::parse(template, "file_template", &["seq", "uuid"])?;
+    let uuid = js::random_uuid()?;
+
+    let mut files = vec![];
+    let mut paths = HashSet::new();
+    for (directory, records) in partitions {
+        let split = write_files(&schema, &records, options)?;
+        for (seq, file) in split.files.into_iter().enumerate() {
+            let name = template.render(|placeholder| match placeholder {
+                "seq" => seq.to_string(),
+                _ => uuid.clone(),
+            });
+            let path = format!("{}/{}", directory, name);
+            if !paths.insert(path.clone()) {
+                return Err(WriteError::from(format!(
+                    "file_template names more than one file {}, use {{seq}} to number them",
+                    path
+                )));
             }
-        ]
+            files.push((path, file));
+        }
     }
-    "#;
-    let schema = build_schema(schema.to_string());
-    assert_eq!(
-        schema,
-        "message schema {\n  REQUIRED INT32 id;\n  REQUIRED BYTE_ARRAY name (UTF8);\n  REQUIRED INT32 age;\n  REQUIRED BOOLEAN is_active;\n}\n"
+    Ok(files)
+}
+
+/// Generate a parquet file like `generate_parquet_with_report`, only writing the records a JS
+/// predicate accepts
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, any `filter` is applied after the predicate
+/// * `filter`: A function called with each record and its row index, records it returns a falsy
+///   value for are left out
+#[wasm_bindgen]
+pub fn generate_parquet_with_filter(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+    filter: js_sys::Function,
+) -> Result<WriteReport, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let (records, parse_millis) = parse_records_timed(&files, options.lone_surrogates)?;
+            let total = records.len();
+            let accepted = js::filter_records(records, &filter)?;
+            let mut report = write_report(&schema, &accepted, &options)?.parsed_in(parse_millis);
+            report.filtered_rows += total - accepted.len();
+            Ok(report)
+        });
+    result.map_err(JsValue::from)
+}
+
+/// Generate a parquet file like `generate_parquet_with_options`, handing it to a JS function in
+/// chunks as row groups are written so the whole file is never held in memory. Set
+/// `max_rows_per_row_group` or `max_row_group_bytes` to keep the chunks small
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"max_rows_per_row_group": 10000}`
+/// * `sink`: A function called with each chunk of the file as a `Uint8Array`, in order. The last
+///   call ends the file with its footer, and throwing stops the write
+#[wasm_bindgen]
+pub fn generate_parquet_to_sink(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+    sink: js_sys::Function,
+) -> Result<(), JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let records = writer::parse_records(&files, options.lone_surrogates)?;
+            ChunkedFile::new(&schema, records, options)?
+                .write_to(|chunk| Ok(js::emit_chunk(&sink, &chunk)?))
+        });
+    result.map_err(JsValue::from)
+}
+
+/// Generate a parquet file like `generate_parquet_to_sink`, waiting for the promise the sink
+/// returns for each chunk before writing the next row group, so a slow sink such as an upload or
+/// an OPFS file isn't outpaced by the writer
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"max_rows_per_row_group": 10000}`
+/// * `sink`: A function called with each chunk of the file as a `Uint8Array`, in order. No more
+///   row groups are written until the promise it returns resolves, and rejecting stops the write
+#[wasm_bindgen]
+pub async fn generate_parquet_to_async_sink(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+    sink: js_sys::Function,
+) -> Result<(), JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let records = writer::parse_records(&files, options.lone_surrogates)?;
+            ChunkedFile::new(&schema, records, options)
+        });
+    let mut file = result?;
+    while let Some(chunk) = file.next_chunk()? {
+        js::write_chunk(&sink, &chunk)
+            .await
+            .map_err(WriteError::from)?;
+    }
+    Ok(())
+}
+
+/// Generate a parquet file like `generate_parquet_with_options` as a `ReadableStream` of
+/// `Uint8Array` chunks, which can be passed straight to a `Response` or piped to a
+/// `WritableStream`. A row group is written each time the stream is read from, so set
+/// `max_rows_per_row_group` or `max_row_group_bytes` to keep the chunks small
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"max_rows_per_row_group": 10000}`
+#[wasm_bindgen]
+pub fn generate_parquet_stream(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+) -> Result<web_sys::ReadableStream, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let records = writer::parse_records(&files, options.lone_surrogates)?;
+            ChunkedFile::new(&schema, records, options)
+        });
+    match result {
+        Ok(mut file) => js::readable_stream(move || file.next_chunk().map_err(|e| e.to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file like `generate_parquet_with_report`, calling a JS function as it's
+/// written so UIs can show progress through long conversions
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"max_rows_per_row_group": 10000}`
+/// * `progress`: A function called with `{records_processed, columns_written, bytes_written}`
+///   after every `interval` column chunks and once the file is complete, throwing stops the write
+/// * `interval`: The number of column chunks written between calls
+#[wasm_bindgen]
+pub fn generate_parquet_with_progress(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+    progress: js_sys::Function,
+    interval: usize,
+) -> Result<WriteReport, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let callback: writer::ProgressCallback =
+                Box::new(move |p| Ok(js::report_progress(&progress, p)?));
+            write_files_report(&schema, &files, &options, Some((interval, callback)), None)
+        });
+    result.map_err(JsValue::from)
+}
+
+/// A handle for cancelling a long conversion from the UI. Writes given the token check it before
+/// each row group and after each column chunk, and throw an `Error` named `CancelledError` once
+/// `cancel` has been called, e.g. from a progress callback or between appended batches
+#[wasm_bindgen]
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    cancelled: writer::CancelFlag,
+}
+
+#[wasm_bindgen]
+impl CancellationToken {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Stop any write given the token at its next check
+    pub fn cancel(&self) {
+        self.cancelled.set(true);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.get()
+    }
+}
+
+/// Generate a parquet file like `generate_parquet_with_progress`, stopping once a token is
+/// cancelled
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"max_rows_per_row_group": 10000}`
+/// * `token`: A `CancellationToken`, the write throws a `CancelledError` once it's cancelled
+/// * `progress`: An optional function called with `{records_processed, columns_written,
+///   bytes_written}` after every `interval` column chunks, where the UI can cancel the token
+/// * `interval`: The number of column chunks written between calls of `progress`
+#[wasm_bindgen]
+pub fn generate_parquet_cancellable(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+    token: &CancellationToken,
+    progress: Option<js_sys::Function>,
+    interval: usize,
+) -> Result<WriteReport, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let options = options::parse_options(options.as_str())?;
+            let progress = progress.map(|progress| {
+                let callback: writer::ProgressCallback =
+                    Box::new(move |p| Ok(js::report_progress(&progress, p)?));
+                (interval, callback)
+            });
+            write_files_report(
+                &schema,
+                &files,
+                &options,
+                progress,
+                Some(token.cancelled.clone()),
+            )
+        });
+    result.map_err(JsValue::from)
+}
+
+/// Generate a parquet file like `generate_parquet_with_options`, passing the values of fields
+/// through JS functions first, e.g. to parse currency strings or locale dates
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `files`: A list of strings of JSON objects that match the schema
+/// * `options`: A JSON object of writer options, e.g. `{"case_insensitive_keys": true}`
+/// * `transforms`: An object of functions keyed by field name, each called with the field's value
+///   (null when missing) and the row index, whose result is written instead. Returning null
+///   writes a null and throwing fails the write. Fields with `computed` set to `true` have their
+///   function called with the whole record instead
+#[wasm_bindgen]
+pub fn generate_parquet_with_transforms(
+    schema: String,
+    files: Vec<String>,
+    options: String,
+    transforms: js_sys::Object,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let mut options = options::parse_options(options.as_str())?;
+            let mut records = writer::parse_records(&files, options.lone_surrogates)?;
+            js::apply_transforms(
+                &schema.schema.fields,
+                &mut records,
+                &transforms,
+                &mut options,
+            )?;
+            write_records(&schema, &records, &options)
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file from a schema and newline-delimited JSON, one record per line
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `ndjson`: JSON objects that match the schema separated by newlines, blank lines are skipped
+#[wasm_bindgen]
+pub fn generate_parquet_ndjson(
+    schema: String,
+    ndjson: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let records = writer::parse_ndjson(ndjson.as_str(), 0, Default::default())?;
+            write_records(&schema, &records, &WriterOptions::default())
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file from a schema and a single JSON document holding an array of records
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `json_array`: A JSON array of objects that match the schema
+#[wasm_bindgen]
+pub fn generate_parquet_from_array(
+    schema: String,
+    json_array: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let records = writer::parse_json_array(json_array.as_str(), Default::default())?;
+            write_records(&schema, &records, &WriterOptions::default())
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file from CSV, with values parsed as the types declared in the schema
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `csv`: The CSV text, with a header row naming the columns unless `header` is false
+/// * `options`: A JSON object of CSV options (`delimiter`, `quote`, `header`, `null_token`,
+///   `record_terminator`, `column_widths`), which may also include any writer options
+#[wasm_bindgen]
+pub fn generate_parquet_from_csv(
+    schema: String,
+    csv: String,
+    options: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .and_then(|schema| Ok((schema, csv::parse_csv_options(options.as_str())?)))
+        .map_err(WriteError::from)
+        .and_then(|(schema, options)| {
+            let records = csv::parse_csv(&schema.schema.fields, csv.as_str(), &options)?;
+            write_records(&schema, &records, &options.writer)
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file from Arrow IPC data, e.g. the output of arrow-js `tableToIPC`
+///
+/// # Arguments
+///
+/// * `schema`: An optional JSON representation of the schema, columns are matched to fields by
+///   name and cast to their type. Without one the Arrow schema is used
+/// * `ipc`: An Arrow IPC stream or file
+#[wasm_bindgen]
+pub fn generate_parquet_from_arrow(
+    schema: Option<String>,
+    ipc: Vec<u8>,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = match schema {
+        Some(schema) => CompiledSchema::parse(schema.as_str())
+            .and_then(|schema| ipc::write_ipc(Some(&schema), &ipc)),
+        None => ipc::write_ipc(None, &ipc),
+    };
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Generate a parquet file from a schema and an array of plain JS objects, skipping the
+/// stringify-then-parse round trip of the JSON string entry points
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `records`: An array of JS objects that match the schema, `Date` values are written as
+///   RFC 3339 strings and `Uint8Array` values as the bytes of BYTE_ARRAY and FIXED_LEN_BYTE_ARRAY
+///   fields
+/// * `options`: A JSON object of writer options, see `generate_parquet_with_options`
+#[wasm_bindgen]
+pub fn generate_parquet_from_js(
+    schema: String,
+    records: js_sys::Array,
+    options: String,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let mut options = options::parse_options(options.as_str())?;
+            let records =
+                js::parse_js_records(&records, options.lone_surrogates, &mut options.byte_values)?;
+            write_records(&schema, &records, &options)
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file from a schema and a list of MessagePack encoded records
+///
+/// # Arguments
+///
+/// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+/// * `records`: A list of MessagePack maps that match the schema
+#[wasm_bindgen]
+pub fn generate_parquet_msgpack(
+    schema: String,
+    records: Vec<js_sys::Uint8Array>,
+) -> Result<Clamped<Vec<u8>>, JsValue> {
+    let records = records.iter().map(|r| r.to_vec()).collect::<Vec<_>>();
+    let result = CompiledSchema::parse(schema.as_str())
+        .map_err(WriteError::from)
+        .and_then(|schema| {
+            let records = writer::parse_msgpack_records(&records)?;
+            write_records(&schema, &records, &WriterOptions::default())
+        });
+    match result {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Generate a parquet file from an Avro object container file, with the parquet schema mapped
+/// from the Avro schema embedded in the file
+///
+/// # Arguments
+///
+/// * `avro`: The bytes of an Avro object container file
+#[wasm_bindgen]
+pub fn generate_parquet_from_avro(avro: Vec<u8>) -> Result<Clamped<Vec<u8>>, JsValue> {
+    match avro::write_avro(&avro) {
+        Ok(bytes_buffer) => Ok(Clamped(bytes_buffer)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_parquet(
+    schema: String,
+    files: Vec<String>,
+    options: &WriterOptions,
+) -> Result<Vec<u8>, WriteError> {
+    write_compiled(&CompiledSchema::parse(schema.as_str())?, files, options)
+}
+
+fn write_compiled(
+    schema: &CompiledSchema,
+    files: Vec<String>,
+    options: &WriterOptions,
+) -> Result<Vec<u8>, WriteError> {
+    if let Some(keys) = decode::FieldKeys::new(&schema.schema.fields, options) {
+        let output = writer::output_buffer(&schema.schema.fields, files.len(), options);
+        let mut sink = writer::FileSink::new(output, &schema.message_type, options)?;
+        decode::write_decoded(&mut sink, schema, &files, &keys, options)?;
+        return Ok(sink.finish()?.0);
+    }
+    write_files_report(schema, &files, options, None, None).map(|report| report.bytes)
+}
+
+fn write_records(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+) -> Result<Vec<u8>, WriteError> {
+    write_report(schema, records, options).map(|report| report.bytes)
+}
+
+fn write_report(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+) -> Result<WriteReport, WriteError> {
+    write_report_with_progress(schema, records, options, None, None)
+}
+
+/// Write the records like `write_report`, reporting progress after every `interval` column
+/// chunks when a callback is given and stopping once `cancelled` is set
+fn write_report_with_progress(
+    schema: &CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+    progress: Option<(usize, writer::ProgressCallback)>,
+    cancelled: Option<writer::CancelFlag>,
+) -> Result<WriteReport, WriteError> {
+    let start = js::now_millis();
+    let prepared = prepare_records(schema, records, options, &mut HashSet::new())?;
+    let records = &prepared.records[..];
+    let schema = resolve_schema(schema, records, options)?;
+    let prepare_millis = js::now_millis() - start;
+
+    let output = writer::output_buffer(&schema.schema.fields, records.len(), options);
+    let mut sink = writer::FileSink::new(output, &schema.message_type, options)?;
+    if let Some((interval, callback)) = progress {
+        sink.on_progress(interval, callback)?;
+    }
+    if let Some(cancelled) = cancelled {
+        sink.cancel_on(cancelled);
+    }
+    for row_group in writer::row_groups(records.len(), options)? {
+        sink.write_row_group(&schema.schema.fields, &records[row_group], options)?;
+    }
+    let peak_memory_bytes = sink.peak_memory_bytes();
+    let mut timings = sink.timings();
+    let (bytes, columns) = sink.finish()?;
+    timings.prepare_millis = prepare_millis;
+    timings.total_millis = js::now_millis() - start;
+    Ok(WriteReport {
+        bytes,
+        filtered_rows: prepared.filtered_rows,
+        dropped_rows: prepared.dropped_rows,
+        peak_memory_bytes,
+        columns,
+        timings,
+    })
+}
+
+/// Write a file like `write_report_with_progress` from the records' JSON, parsing and preparing
+/// them a row group at a time so only the records of the row group being written are held.
+/// Options that need every record up front parse them all first
+fn write_files_report(
+    schema: &CompiledSchema,
+    files: &[String],
+    options: &WriterOptions,
+    progress: Option<(usize, writer::ProgressCallback)>,
+    cancelled: Option<writer::CancelFlag>,
+) -> Result<WriteReport, WriteError> {
+    if whole_input_option(options).is_some() {
+        let (records, parse_millis) = parse_records_timed(files, options.lone_surrogates)?;
+        let report = write_report_with_progress(schema, &records, options, progress, cancelled)?;
+        return Ok(report.parsed_in(parse_millis));
+    }
+
+    let start = js::now_millis();
+    let schema = resolve_schema(schema, &[], options)?;
+    let fields = &schema.schema.fields;
+    let output = writer::output_buffer(fields, files.len(), options);
+    let mut sink = writer::FileSink::new(output, &schema.message_type, options)?;
+    if let Some((interval, callback)) = progress {
+        sink.on_progress(interval, callback)?;
+    }
+    if let Some(cancelled) = cancelled {
+        sink.cancel_on(cancelled);
+    }
+    let (mut parse_millis, mut prepare_millis) = (0.0, 0.0);
+    let (mut filtered_rows, mut dropped_rows) = (0, 0);
+    let mut seen = HashSet::new();
+    let mut pending = writer::PendingRecords::default();
+    for (row, file) in files.iter().enumerate() {
+        let parse_start = js::now_millis();
+        let records = writer::parse_records(std::slice::from_ref(file), options.lone_surrogates)
+            .map_err(|e| WriteError::from(e).offset_row(row))?;
+        let prepare_start = js::now_millis();
+        parse_millis += prepare_start - parse_start;
+        let prepared = prepare_records(&schema, &records, options, &mut seen)
+            .map_err(|e| e.offset_row(row))?;
+        filtered_rows += prepared.filtered_rows;
+        dropped_rows += prepared.dropped_rows;
+        match prepared.records {
+            Cow::Owned(prepared) => pending.extend(prepared),
+            Cow::Borrowed(_) => pending.extend(records),
+        }
+        prepare_millis += js::now_millis() - prepare_start;
+        pending.write_filled(&mut sink, fields, options)?;
+    }
+    pending.write_all(&mut sink, fields, options)?;
+
+    let peak_memory_bytes = sink.peak_memory_bytes();
+    let mut timings = sink.timings();
+    let (bytes, columns) = sink.finish()?;
+    timings.parse_millis = parse_millis;
+    timings.prepare_millis = prepare_millis;
+    timings.total_millis = js::now_millis() - start;
+    Ok(WriteReport {
+        bytes,
+        filtered_rows,
+        dropped_rows,
+        peak_memory_bytes,
+        columns,
+        timings,
+    })
+}
+
+/// The name of an option that needs every record before any can be written, if one is on
+fn whole_input_option(options: &WriterOptions) -> Option<&'static str> {
+    let whole_input = [
+        ("infer_optional", options.infer_optional),
+        (
+            "flatten.infer_fields",
+            options.flatten.as_ref().is_some_and(|f| f.infer_fields),
+        ),
+        ("sort_records", options.sort_records),
+    ];
+    whole_input
+        .into_iter()
+        .find(|(_, on)| *on)
+        .map(|(name, _)| name)
+}
+
+/// A parquet file written a row group at a time as its chunks are asked for, so only the row
+/// group being written is held in memory. A chunk is whatever the parquet writer has flushed once
+/// a row group or the footer is written, so chunks don't line up exactly with row groups
+struct ChunkedFile {
+    schema: CompiledSchema,
+    records: Vec<writer::Record>,
+    options: WriterOptions,
+    /// The row groups left to write
+    row_groups: std::vec::IntoIter<Range<usize>>,
+    buffer: writer::ChunkBuffer,
+    /// The file being written, taken once the footer is written
+    sink: Option<writer::FileSink<writer::ChunkBuffer>>,
+}
+
+impl ChunkedFile {
+    fn new(
+        schema: &CompiledSchema,
+        records: Vec<writer::Record>,
+        options: WriterOptions,
+    ) -> Result<Self, WriteError> {
+        let prepared = prepare_records(schema, &records, &options, &mut HashSet::new())?;
+        let records = match prepared.records {
+            Cow::Owned(prepared) => prepared,
+            Cow::Borrowed(_) => records,
+        };
+        let schema = resolve_schema(schema, &records, &options)?.into_owned();
+        let row_groups = writer::row_groups(records.len(), &options)?.into_iter();
+        let buffer = writer::ChunkBuffer::default();
+        let sink = writer::FileSink::new(buffer.clone(), &schema.message_type, &options)?;
+        Ok(ChunkedFile {
+            schema,
+            records,
+            options,
+            row_groups,
+            buffer,
+            sink: Some(sink),
+        })
+    }
+
+    /// The next chunk of the file, none once the footer has been returned
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, WriteError> {
+        let Some(sink) = self.sink.as_mut() else {
+            return Ok(None);
+        };
+        for row_group in self.row_groups.by_ref() {
+            sink.write_row_group(
+                &self.schema.schema.fields,
+                &self.records[row_group],
+                &self.options,
+            )?;
+            let chunk = self.buffer.take();
+            if !chunk.is_empty() {
+                return Ok(Some(chunk));
+            }
+        }
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+        Ok(Some(self.buffer.take()))
+    }
+
+    /// Hand each chunk of the file to `emit` in order, stopping at the first error it returns
+    fn write_to(
+        mut self,
+        mut emit: impl FnMut(Vec<u8>) -> Result<(), WriteError>,
+    ) -> Result<(), WriteError> {
+        while let Some(chunk) = self.next_chunk()? {
+            emit(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// The records left to write once computed fields, flattening, the filter, deduplication and
+/// sorting have been applied, in that order
+struct PreparedRecords<'a> {
+    records: Cow<'a, [writer::Record]>,
+    filtered_rows: usize,
+    dropped_rows: usize,
+}
+
+/// Prepare records for writing, `seen` holds the `dedupe_on` keys of records already written
+fn prepare_records<'a>(
+    schema: &CompiledSchema,
+    records: &'a [writer::Record],
+    options: &WriterOptions,
+    seen: &mut HashSet<String>,
+) -> Result<PreparedRecords<'a>, WriteError> {
+    let fields = &schema.schema.fields;
+    let mut records = Cow::Borrowed(records);
+    if computed::has_expressions(fields) {
+        records = Cow::Owned(computed::compute_records(
+            fields,
+            &records,
+            options.case_insensitive_keys,
+        )?);
+    }
+    if let Some(flatten) = &options.flatten {
+        records = Cow::Owned(flatten::flatten_records(&records, flatten));
+    }
+
+    let mut filtered_rows = 0;
+    if let Some(conditions) = &options.filter {
+        let filtered =
+            filter::filter_records(fields, &records, conditions, options.case_insensitive_keys)?;
+        filtered_rows = records.len() - filtered.len();
+        records = Cow::Owned(filtered);
+    }
+
+    let mut dropped_rows = 0;
+    if let Some(keys) = &options.dedupe_on {
+        let deduped = writer::dedupe(fields, &records, keys, options, seen)?;
+        dropped_rows = records.len() - deduped.len();
+        records = Cow::Owned(deduped);
+    }
+
+    if let Some(sorting_columns) = options
+        .sorting_columns
+        .as_ref()
+        .filter(|_| options.sort_records)
+    {
+        records = Cow::Owned(writer::sort_records(
+            fields,
+            &records,
+            sorting_columns,
+            options,
+        )?);
+    }
+
+    Ok(PreparedRecords {
+        records,
+        filtered_rows,
+        dropped_rows,
+    })
+}
+
+/// The schema the records are written with. Inference can add fields or change their repetition,
+/// and raw strings drop the UTF8 annotation, so the message type is rebuilt from a copy when any
+/// of them are on
+fn resolve_schema<'a>(
+    schema: &'a CompiledSchema,
+    records: &[writer::Record],
+    options: &WriterOptions,
+) -> Result<Cow<'a, CompiledSchema>, WriteError> {
+    let infer_fields = options.flatten.as_ref().is_some_and(|f| f.infer_fields);
+    let raw_strings = options.lone_surrogates == options::LoneSurrogatePolicy::Binary;
+    if !(options.infer_optional || infer_fields || raw_strings) {
+        return Ok(Cow::Borrowed(schema));
+    }
+
+    let mut parquet_schema = schema.schema.clone();
+    if raw_strings {
+        unicode::utf8_to_binary(&mut parquet_schema.fields);
+    }
+    if infer_fields {
+        flatten::infer_fields(&mut parquet_schema.fields, records)?;
+    }
+    if options.infer_optional {
+        writer::infer_optional(&mut parquet_schema.fields, records, options)?;
+    }
+    Ok(Cow::Owned(CompiledSchema::build(parquet_schema)?))
+}
+
+/// Writes a parquet file from batches of records as they arrive, e.g. while paging through an
+/// API, instead of from every record at once. Row groups are written as soon as a batch fills
+/// them, so set `max_rows_per_row_group`, `max_row_group_bytes` or `max_memory_bytes` to bound the
+/// records held
+#[wasm_bindgen]
+pub struct ParquetStreamWriter {
+    schema: CompiledSchema,
+    options: WriterOptions,
+    /// The file being written, taken by `finish`
+    sink: Option<writer::FileSink<Vec<u8>>>,
+    /// Prepared records of the row group still being filled
+    buffered: writer::PendingRecords,
+    /// The number of records appended so far, which parse errors' rows are offset by
+    rows_appended: usize,
+    /// The `dedupe_on` keys of every record so far, so duplicates are dropped across batches
+    seen: HashSet<String>,
+}
+
+#[wasm_bindgen]
+impl ParquetStreamWriter {
+    /// Start writing a parquet file
+    ///
+    /// # Arguments
+    ///
+    /// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+    /// * `options`: A JSON object of writer options, except those that need every record up front
+    #[wasm_bindgen(constructor)]
+    pub fn new(schema: String, options: String) -> Result<ParquetStreamWriter, JsValue> {
+        ParquetStreamWriter::create(schema.as_str(), options.as_str()).map_err(JsValue::from)
+    }
+
+    /// Append a batch of records, writing any row groups it fills
+    ///
+    /// # Arguments
+    ///
+    /// * `records`: A list of strings of JSON objects that match the schema
+    pub fn append(&mut self, records: Vec<String>) -> Result<(), JsValue> {
+        self.append_records(&records).map_err(JsValue::from)
+    }
+
+    /// Stop writing once `token` is cancelled, later calls throw a `CancelledError`
+    pub fn cancel_with(&mut self, token: &CancellationToken) {
+        if let Some(sink) = self.sink.as_mut() {
+            sink.cancel_on(token.cancelled.clone());
+        }
+    }
+
+    /// Write the remaining records and the footer, returning the parquet file
+    pub fn finish(&mut self) -> Result<Clamped<Vec<u8>>, JsValue> {
+        match self.finish_file() {
+            Ok(bytes) => Ok(Clamped(bytes)),
+            Err(e) => Err(JsValue::from(e)),
+        }
+    }
+}
+
+impl ParquetStreamWriter {
+    fn create(schema: &str, options: &str) -> Result<ParquetStreamWriter, WriteError> {
+        let schema = CompiledSchema::parse(schema)?;
+        let options = options::parse_options(options)?;
+        if let Some(name) = whole_input_option(&options) {
+            return Err(WriteError::from(format!(
+                "{} needs every record up front, so it can't be used when streaming",
+                name
+            )));
+        }
+        let schema = resolve_schema(&schema, &[], &options)?.into_owned();
+        let output = writer::output_buffer(&schema.schema.fields, 0, &options);
+        let sink = writer::FileSink::new(output, &schema.message_type, &options)?;
+        Ok(ParquetStreamWriter {
+            schema,
+            options,
+            sink: Some(sink),
+            buffered: writer::PendingRecords::default(),
+            rows_appended: 0,
+            seen: HashSet::new(),
+        })
+    }
+
+    fn append_records(&mut self, files: &[String]) -> Result<(), WriteError> {
+        let records = writer::parse_records(files, self.options.lone_surrogates)
+            .map_err(|e| WriteError::from(e).offset_row(self.rows_appended))?;
+        self.append_parsed(records)
+    }
+
+    /// Append records that have already been parsed, whose errors' rows are offset by the records
+    /// appended before them
+    fn append_parsed(&mut self, records: Vec<writer::Record>) -> Result<(), WriteError> {
+        let Some(sink) = self.sink.as_mut() else {
+            return Err(WriteError::from(
+                "The stream has already finished".to_string(),
+            ));
+        };
+        sink.check_cancelled()?;
+        let rows_before = self.rows_appended;
+        self.rows_appended += records.len();
+        let prepared = prepare_records(&self.schema, &records, &self.options, &mut self.seen)
+            .map_err(|e| e.offset_row(rows_before))?;
+        self.buffered.extend(prepared.records.into_owned());
+        let buffered = self.buffered.len();
+        self.buffered
+            .write_filled(sink, &self.schema.schema.fields, &self.options)?;
+        if self.buffered.len() < buffered && !self.options.byte_values.is_empty() {
+            self.buffered
+                .retain_byte_values(&mut self.options.byte_values);
+        }
+        Ok(())
+    }
+
+    fn finish_file(&mut self) -> Result<Vec<u8>, WriteError> {
+        let Some(mut sink) = self.sink.take() else {
+            return Err(WriteError::from(
+                "The stream has already finished".to_string(),
+            ));
+        };
+        self.buffered
+            .write_all(&mut sink, &self.schema.schema.fields, &self.options)?;
+        sink.finish().map(|(bytes, _)| bytes)
+    }
+}
+
+/// A sink for a `WritableStream` that writes a parquet file from the chunks piped to it, e.g.
+/// `response.body.pipeTo(new WritableStream(sink))` for a response of newline-delimited JSON.
+/// Bytes and strings are read as JSON lines, which may be split across chunks, and arrays as
+/// plain JS objects
+#[wasm_bindgen]
+pub struct ParquetSink {
+    writer: ParquetStreamWriter,
+    /// Bytes after the last newline, the start of a line the next chunk finishes
+    pending: Vec<u8>,
+    /// The number of lines read so far, which line numbers in errors are offset by
+    lines: usize,
+    /// The parquet file, once the sink is closed
+    file: Option<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl ParquetSink {
+    /// Start writing a parquet file, takes the same arguments as `ParquetStreamWriter`
+    ///
+    /// # Arguments
+    ///
+    /// * `schema`: A JSON representation of the schema, which is then parsed into a parquet schema
+    /// * `options`: A JSON object of writer options, except those that need every record up front
+    #[wasm_bindgen(constructor)]
+    pub fn new(schema: String, options: String) -> Result<ParquetSink, JsValue> {
+        match ParquetStreamWriter::create(schema.as_str(), options.as_str()) {
+            Ok(writer) => Ok(ParquetSink {
+                writer,
+                pending: vec![],
+                lines: 0,
+                file: None,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write a chunk, a `Uint8Array` or string of JSON lines or an array of records
+    pub fn write(&mut self, chunk: JsValue) -> Result<(), JsValue> {
+        let result = if let Some(lines) = chunk.as_string() {
+            self.write_lines(lines.as_bytes())
+        } else if let Some(bytes) = chunk.dyn_ref::<js_sys::Uint8Array>() {
+            self.write_lines(&bytes.to_vec())
+        } else if let Some(records) = chunk.dyn_ref::<js_sys::Array>() {
+            let options = &mut self.writer.options;
+            js::parse_js_records(records, options.lone_surrogates, &mut options.byte_values)
+                .map_err(|e| WriteError::from(e).offset_row(self.writer.rows_appended))
+                .and_then(|records| self.writer.append_parsed(records))
+        } else {
+            Err(WriteError::from(
+                "Chunks must be a Uint8Array, a string or an array of records".to_string(),
+            ))
+        };
+        result.map_err(JsValue::from)
+    }
+
+    /// Write the last line and the footer, returning the parquet file
+    pub fn close(&mut self) -> Result<Clamped<Vec<u8>>, JsValue> {
+        match self.close_file() {
+            Ok(bytes) => Ok(Clamped(bytes)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The parquet file once the sink is closed, for when `close` is called by a `WritableStream`
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Result<Clamped<Vec<u8>>, JsValue> {
+        match &self.file {
+            Some(bytes) => Ok(Clamped(bytes.clone())),
+            None => Err(JsValue::from_str("The sink hasn't been closed")),
+        }
+    }
+}
+
+impl ParquetSink {
+    fn write_lines(&mut self, chunk: &[u8]) -> Result<(), WriteError> {
+        self.pending.extend_from_slice(chunk);
+        let Some(end) = self.pending.iter().rposition(|b| *b == b'\n') else {
+            return Ok(());
+        };
+        let lines: Vec<u8> = self.pending.drain(..=end).collect();
+        self.parse_lines(lines)
+    }
+
+    fn parse_lines(&mut self, lines: Vec<u8>) -> Result<(), WriteError> {
+        let lines = match String::from_utf8(lines) {
+            Ok(lines) => lines,
+            Err(_) => {
+                return Err(WriteError::from(format!(
+                    "Line {}: records are not valid UTF-8",
+                    self.lines + 1
+                )))
+            }
+        };
+        let records =
+            writer::parse_ndjson(&lines, self.lines, self.writer.options.lone_surrogates)?;
+        self.lines += lines.lines().count();
+        self.writer.append_parsed(records)
+    }
+
+    fn close_file(&mut self) -> Result<Vec<u8>, WriteError> {
+        if !self.pending.is_empty() {
+            let lines = std::mem::take(&mut self.pending);
+            self.parse_lines(lines)?;
+        }
+        let bytes = self.writer.finish_file()?;
+        self.file = Some(bytes.clone());
+        Ok(bytes)
+    }
+}
+
+/// Read a parquet file back into an array of records, e.g. to preview a file that was just
+/// generated. LISTs are read as arrays and MAPs as objects, byte arrays as base64 strings and
+/// dates, timestamps and decimals as strings
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn read_parquet(bytes: Vec<u8>) -> Result<JsValue, JsValue> {
+    match reader::read_rows(bytes, ReaderOptions::default()) {
+        Ok(rows) => Ok(js::to_js(&serde_json::Value::Array(rows))),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Read the first `n` rows of a parquet file like `read_parquet`, for previews. Rows are decoded
+/// lazily, so only the row groups and pages holding them are read
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `n`: The number of rows to read
+#[wasm_bindgen]
+pub fn parquet_head(bytes: Vec<u8>, n: usize) -> Result<JsValue, JsValue> {
+    let options = ReaderOptions {
+        limit: Some(n),
+        ..Default::default()
+    };
+    match reader::read_rows(bytes, options) {
+        Ok(rows) => Ok(js::to_js(&serde_json::Value::Array(rows))),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Read a parquet file back into an array of records like `read_parquet`, with reader options,
+/// e.g. `{"offset": 100, "limit": 50}` to page through a large file without decoding all of it, or
+/// `{"filter": {"field": "status", "op": "==", "value": "failed"}}` to only read matching rows, or
+/// `{"row_group": 3}` to read a single row group, up to the `num_row_groups` of `inspect_parquet`
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `options`: A JSON object of reader options
+#[wasm_bindgen]
+pub fn read_parquet_with_options(bytes: Vec<u8>, options: String) -> Result<JsValue, JsValue> {
+    match options::parse_reader_options(options.as_str())
+        .and_then(|options| reader::read_rows(bytes, options))
+    {
+        Ok(rows) => Ok(js::to_js(&serde_json::Value::Array(rows))),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Read what a parquet file holds from its footer, as
+/// `{schema, num_rows, num_row_groups, row_groups, codecs, created_by, version}` with `schema` in
+/// parquet's text format and `row_groups` an array of
+/// `{num_rows, total_byte_size, compressed_size}`
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn inspect_parquet(bytes: Vec<u8>) -> Result<JsValue, JsValue> {
+    match reader::inspect(bytes).and_then(|info| {
+        serde_json::to_value(info).map_err(|e| format!("Error serializing metadata: {}", e))
+    }) {
+        Ok(info) => Ok(js::to_js(&info)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Read the min, max, null count and distinct count of each column from a parquet file's footer,
+/// as `{columns, row_groups}` with `columns` the statistics for the whole file and `row_groups` an
+/// array of each row group's. A column's statistics are `{column, min, max, null_count,
+/// distinct_count}`, null where the file doesn't record them
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn read_statistics(bytes: Vec<u8>) -> Result<JsValue, JsValue> {
+    match reader::statistics(bytes).and_then(|statistics| {
+        serde_json::to_value(statistics).map_err(|e| format!("Error serializing statistics: {}", e))
+    }) {
+        Ok(statistics) => Ok(js::to_js(&statistics)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Convert a parquet file to CSV as a `ReadableStream` of `Uint8Array` chunks, a row group at a
+/// time, with a cell for every top level column. LISTs, MAPs and groups are written as JSON
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `options`: A JSON object of CSV options, `delimiter`, `quote`, `header`, `null_token` and
+///   `record_terminator`, alongside any reader options, e.g. `{"delimiter": ";", "limit": 1000}`
+#[wasm_bindgen]
+pub fn parquet_to_csv(bytes: Vec<u8>, options: String) -> Result<web_sys::ReadableStream, JsValue> {
+    match csv::parse_csv_export_options(options.as_str())
+        .and_then(|options| csv::CsvExport::new(bytes, options))
+    {
+        Ok(mut export) => js::readable_stream(move || export.next_chunk()),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Convert a parquet file to an Arrow IPC stream, which arrow-js `tableFromIPC` or DuckDB-WASM can
+/// read without converting each value, with a record batch for each row group
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn parquet_to_arrow_ipc(bytes: Vec<u8>) -> Result<Vec<u8>, JsValue> {
+    ipc::parquet_to_ipc(bytes).map_err(|e| JsValue::from_str(e.as_str()))
+}
+
+/// Convert a parquet file to newline-delimited JSON as a `ReadableStream` of `Uint8Array` chunks,
+/// decoding a row group each time the stream is read from. Rows are written as `read_parquet`
+/// reads them
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `options`: A JSON object of reader options, e.g. `{"limit": 1000}`
+#[wasm_bindgen]
+pub fn parquet_to_ndjson(
+    bytes: Vec<u8>,
+    options: String,
+) -> Result<web_sys::ReadableStream, JsValue> {
+    match options::parse_reader_options(options.as_str())
+        .and_then(|options| reader::Rows::new(bytes, options))
+    {
+        Ok(mut rows) => js::readable_stream(move || rows.next_ndjson()),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Check a parquet file is intact before it's uploaded or stored, as
+/// `{valid, error, location, pages_checked, checksums_verified}`. Checks the magic bytes, that the
+/// footer parses, page CRC32 checksums where pages have them, and that every column decodes.
+/// `location` is where the first problem was found, `{row_group, column, page, offset}` with
+/// whichever are known
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn validate_parquet(bytes: Vec<u8>) -> Result<JsValue, JsValue> {
+    match serde_json::to_value(validate::validate(bytes)) {
+        Ok(report) => Ok(js::to_js(&report)),
+        Err(e) => Err(JsValue::from_str(&format!(
+            "Error serializing report: {}",
+            e
+        ))),
+    }
+}
+
+/// The schema of a parquet file in parquet's text format, e.g.
+/// `message schema { REQUIRED INT64 id; OPTIONAL BYTE_ARRAY name (UTF8); }`
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn parquet_schema_string(bytes: Vec<u8>) -> Result<String, JsValue> {
+    reader::schema_text(bytes).map_err(|e| JsValue::from_str(e.as_str()))
+}
+
+/// The schema of a parquet file as a JSON schema that can be passed to `generate_parquet`, so a
+/// third party file's schema can be reused. Groups are flattened into `parent_child` columns read
+/// from `parent.child`
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn parquet_schema_json(bytes: Vec<u8>) -> Result<String, JsValue> {
+    reader::schema_json(bytes).map_err(|e| JsValue::from_str(e.as_str()))
+}
+
+/// Read the key-value metadata of a parquet file, as `{file, columns}` with `file` the footer's
+/// entries as an object and `columns` an array of `{row_group, column, metadata}` for the column
+/// chunks with entries of their own
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn read_key_value_metadata(bytes: Vec<u8>) -> Result<JsValue, JsValue> {
+    match reader::key_value_metadata(&bytes).and_then(|metadata| {
+        serde_json::to_value(metadata).map_err(|e| format!("Error serializing metadata: {}", e))
+    }) {
+        Ok(metadata) => Ok(js::to_js(&metadata)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// List the pages of a column, for debugging why a file is larger than expected, as an array of
+/// `{row_group, page, offset, page_type, encoding, compressed_size, uncompressed_size, num_values,
+/// num_nulls, num_rows, has_checksum}`. `num_nulls` and `num_rows` are only recorded by v2 data
+/// pages
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `column`: The column's path, e.g. `tags.list.element` for the elements of a LIST field `tags`
+#[wasm_bindgen]
+pub fn dump_pages(bytes: Vec<u8>, column: String) -> Result<JsValue, JsValue> {
+    match reader::dump_pages(bytes, column.as_str()).and_then(|pages| {
+        serde_json::to_value(pages).map_err(|e| format!("Error serializing pages: {}", e))
+    }) {
+        Ok(pages) => Ok(js::to_js(&pages)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Measure how much space each column of a parquet file takes, to decide which need different
+/// options, as an array of `{column, encodings, dictionary_fallback, dictionary, compressed_bytes,
+/// uncompressed_bytes, compression_ratio}` summed across row groups
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+#[wasm_bindgen]
+pub fn storage_report(bytes: Vec<u8>) -> Result<JsValue, JsValue> {
+    match reader::storage_report(bytes).and_then(|report| {
+        serde_json::to_value(report).map_err(|e| format!("Error serializing report: {}", e))
+    }) {
+        Ok(report) => Ok(js::to_js(&report)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Split a parquet file into files of `rows_per_file` rows each, the last holding the rest, e.g. to
+/// fit a per-object upload limit. Rows are copied as Arrow arrays rather than through JSON, and
+/// each file keeps the source's schema, key-value metadata, codecs and row group size
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `rows_per_file`: The number of rows in each file
+#[wasm_bindgen]
+pub fn split_parquet(bytes: Vec<u8>, rows_per_file: usize) -> Result<SplitFiles, JsValue> {
+    match rewrite::split(bytes, rows_per_file) {
+        Ok(split) => Ok(SplitFiles {
+            rows: split.iter().map(|(_, rows)| *rows).collect(),
+            files: split.into_iter().map(|(file, _)| file).collect(),
+        }),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Rewrite a parquet file with another codec, other encodings or another row group size, copying
+/// its rows as Arrow arrays rather than through JSON. The schema, key-value metadata and anything
+/// the options don't change are kept
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `options`: A JSON object of transcode options, e.g.
+///   `{"compression": "GZIP", "max_rows_per_row_group": 100000, "columns": {"id": {"encoding": "DELTA_BINARY_PACKED"}}}`
+#[wasm_bindgen]
+pub fn transcode_parquet(bytes: Vec<u8>, options: String) -> Result<Vec<u8>, JsValue> {
+    match options::parse_transcode_options(options.as_str())
+        .and_then(|options| rewrite::transcode(bytes, &options))
+    {
+        Ok(bytes) => Ok(bytes),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// Rewrite a parquet file without some of its fields, e.g. to strip sensitive fields before
+/// uploading it. The dropped fields' columns aren't decoded, and the rest of the file is kept as it
+/// was written
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `columns`: The names of the top level fields to drop, e.g. `["ssn", "email"]`
+#[wasm_bindgen]
+pub fn drop_columns(bytes: Vec<u8>, columns: Vec<String>) -> Result<Vec<u8>, JsValue> {
+    rewrite::drop_columns(bytes, &columns).map_err(|e| JsValue::from_str(e.as_str()))
+}
+
+/// Rewrite a parquet file with fields renamed and new fields appended, e.g. an `ingest_batch_id`.
+/// The file's column chunks are copied without decoding them, only the appended fields' columns
+/// are encoded
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `options`: A JSON object of the top level fields to rename and the fields to append, each
+///   with a `computed` expression or a `default` written in every row, e.g.
+///   `{"rename": {"ts": "event_ts"}, "append": [{"name": "ingest_batch_id", "type": "INT64", "default": 42}]}`
+#[wasm_bindgen]
+pub fn rewrite_columns(bytes: Vec<u8>, options: String) -> Result<Vec<u8>, JsValue> {
+    options::parse_rewrite_options(options.as_str())
+        .map_err(WriteError::from)
+        .and_then(|options| rewrite::rewrite_columns(bytes, &options))
+        .map_err(JsValue::from)
+}
+
+/// Rewrite a parquet file with its rows sorted, setting the sorting columns in its metadata for
+/// query engines to prune row groups by range. Rows are copied as Arrow arrays rather than through
+/// JSON, and the file keeps its schema, key-value metadata, codecs and row group size
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `keys`: A JSON array of the columns to sort by, earlier columns taking precedence, e.g.
+///   `[{"column": "region"}, {"column": "ts", "descending": true, "nulls_first": true}]`
+#[wasm_bindgen]
+pub fn sort_parquet(bytes: Vec<u8>, keys: String) -> Result<Vec<u8>, JsValue> {
+    match options::parse_sorting_columns(keys.as_str()).and_then(|keys| rewrite::sort(bytes, &keys))
+    {
+        Ok(bytes) => Ok(bytes),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// A parquet file with its duplicate rows removed, and how many were
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct DedupedFile {
+    bytes: Vec<u8>,
+    removed_rows: usize,
+}
+
+#[wasm_bindgen]
+impl DedupedFile {
+    /// The parquet file
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    /// The number of duplicate rows removed
+    #[wasm_bindgen(getter)]
+    pub fn removed_rows(&self) -> usize {
+        self.removed_rows
+    }
+}
+
+/// Rewrite a parquet file without duplicate rows, those whose values for the key columns match
+/// another row's. Rows are copied as Arrow arrays rather than through JSON, and the file keeps its
+/// schema, key-value metadata, codecs and row group size
+///
+/// # Arguments
+///
+/// * `bytes`: A parquet file
+/// * `key_columns`: The names of the top level fields that identify a row
+/// * `options`: A JSON object of dedupe options, `{"keep": "last"}` keeps the last of each set
+///   of duplicates rather than the first
+#[wasm_bindgen]
+pub fn dedupe_parquet(
+    bytes: Vec<u8>,
+    key_columns: Vec<String>,
+    options: String,
+) -> Result<DedupedFile, JsValue> {
+    match options::parse_dedupe_options(options.as_str())
+        .and_then(|options| rewrite::dedupe(bytes, &key_columns, &options))
+    {
+        Ok((bytes, removed_rows)) => Ok(DedupedFile {
+            bytes,
+            removed_rows,
+        }),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// The Delta Lake commit adding parquet files to a table, to write to the table's directory
+/// alongside the files so it can be read as a Delta table. Returns an object of the commit's
+/// `path`, e.g. `_delta_log/00000000000000000000.json`, and its `contents`. The first commit
+/// creates the table, with its protocol and a schema mapped from the files', which must all match.
+/// Each file is added with its size, partition values and statistics
+///
+/// # Arguments
+///
+/// * `paths`: The paths of the files relative to the table's root, e.g.
+///   `region=eu/part-00000.parquet` with hive-style partition directories, escaped as
+///   `generate_partitioned` escapes them
+/// * `files`: The parquet files, in the same order
+/// * `options`: A JSON object of Delta options, e.g.
+///   `{"version": 0, "table_id": "<uuid>", "partition_columns": ["region"]}`
+#[wasm_bindgen]
+pub fn delta_commit(
+    paths: Vec<String>,
+    files: Vec<js_sys::Uint8Array>,
+    options: String,
+) -> Result<JsValue, JsValue> {
+    if paths.len() != files.len() {
+        let error = format!("Got {} paths for {} files", paths.len(), files.len());
+        return Err(JsValue::from_str(error.as_str()));
+    }
+    let files = paths
+        .into_iter()
+        .zip(files.iter().map(|file| file.to_vec()))
+        .collect::<Vec<_>>();
+    match delta::parse_delta_options(options.as_str())
+        .and_then(|options| delta::commit(&files, &options))
+        .and_then(|commit| {
+            serde_json::to_value(commit).map_err(|e| format!("Error serializing commit: {}", e))
+        }) {
+        Ok(commit) => Ok(js::to_js(&commit)),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// A Delta Lake checkpoint and the `_last_checkpoint` file pointing to it
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct DeltaCheckpoint {
+    checkpoint: delta::Checkpoint,
+}
+
+#[wasm_bindgen]
+impl DeltaCheckpoint {
+    /// The checkpoint's path relative to the table's root, e.g.
+    /// `_delta_log/00000000000000000010.checkpoint.parquet`
+    #[wasm_bindgen(getter)]
+    pub fn path(&self) -> String {
+        self.checkpoint.path.clone()
+    }
+
+    /// The checkpoint, a parquet file
+    #[wasm_bindgen(getter)]
+    pub fn bytes(&self) -> Vec<u8> {
+        self.checkpoint.bytes.clone()
+    }
+
+    /// The contents of `_delta_log/_last_checkpoint`
+    #[wasm_bindgen(getter)]
+    pub fn last_checkpoint(&self) -> String {
+        self.checkpoint.last_checkpoint.clone()
+    }
+}
+
+/// A Delta Lake checkpoint of a table's state after a series of commits, so readers, and writers
+/// keeping a table's log, don't need to replay every commit. It holds the latest protocol,
+/// metadata and transaction of each application, the files in the table and tombstones of those
+/// removed from it
+///
+/// # Arguments
+///
+/// * `commits`: The contents of the table's commits from version 0, in order, as `delta_commit`
+///   returns them or read from `_delta_log`. The checkpoint is of the last one's version
+#[wasm_bindgen]
+pub fn delta_checkpoint(commits: Vec<String>) -> Result<DeltaCheckpoint, JsValue> {
+    match delta::checkpoint(&commits) {
+        Ok(checkpoint) => Ok(DeltaCheckpoint { checkpoint }),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// The metadata files of an Iceberg table, to write to the table's directory
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct TableFiles {
+    files: Vec<iceberg::TableFile>,
+}
+
+#[wasm_bindgen]
+impl TableFiles {
+    /// The files' paths relative to the table's root, e.g. `metadata/v1.metadata.json`
+    #[wasm_bindgen(getter)]
+    pub fn paths(&self) -> Vec<String> {
+        self.files.iter().map(|file| file.path.clone()).collect()
+    }
+
+    /// The files, as an array of `Uint8Array`s in the same order as `paths`
+    #[wasm_bindgen(getter)]
+    pub fn files(&self) -> js_sys::Array {
+        self.files
+            .iter()
+            .map(|file| js_sys::Uint8Array::from(file.bytes.as_slice()))
+            .collect()
+    }
+}
+
+/// The metadata creating an unpartitioned Iceberg table of parquet files, to write to the table's
+/// directory alongside them so it can be read as an Iceberg table: the table metadata JSON, a
+/// manifest of the files with their sizes and column statistics and the manifest list of the
+/// table's first snapshot. The schema is mapped from the files', which must all match, and
+/// columns are matched to it by name through the table's default name mapping
+///
+/// # Arguments
+///
+/// * `paths`: The paths of the files relative to the table's root, e.g. `data/part-00000.parquet`
+/// * `files`: The parquet files, in the same order
+/// * `options`: A JSON object of Iceberg options, e.g.
+///   `{"location": "s3://bucket/warehouse/events", "table_uuid": "<uuid>"}`
+#[wasm_bindgen]
+pub fn iceberg_table(
+    paths: Vec<String>,
+    files: Vec<js_sys::Uint8Array>,
+    options: String,
+) -> Result<TableFiles, JsValue> {
+    if paths.len() != files.len() {
+        let error = format!("Got {} paths for {} files", paths.len(), files.len());
+        return Err(JsValue::from_str(error.as_str()));
+    }
+    let files = paths
+        .into_iter()
+        .zip(files.iter().map(|file| file.to_vec()))
+        .collect::<Vec<_>>();
+    match iceberg::parse_iceberg_options(options.as_str())
+        .and_then(|options| iceberg::table(&files, &options))
+    {
+        Ok(files) => Ok(TableFiles { files }),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// A Hudi commit of data files, with the paths to write them to
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct HudiCommit {
+    commit: hudi::Commit,
+}
+
+#[wasm_bindgen]
+impl HudiCommit {
+    /// The paths to write each data file to relative to the table's root, in the order they were
+    /// given, renamed as Hudi base files e.g. `region=eu/<file id>_0-0-0_20240101000000000.parquet`
+    #[wasm_bindgen(getter)]
+    pub fn data_paths(&self) -> Vec<String> {
+        self.commit.data_paths.clone()
+    }
+
+    /// The paths of the commit's metadata files relative to the table's root, e.g.
+    /// `.hoodie/20240101000000000.commit`
+    #[wasm_bindgen(getter)]
+    pub fn paths(&self) -> Vec<String> {
+        self.commit
+            .files
+            .iter()
+            .map(|file| file.path.clone())
+            .collect()
+    }
+
+    /// The metadata files, as an array of `Uint8Array`s in the same order as `paths`
+    #[wasm_bindgen(getter)]
+    pub fn files(&self) -> js_sys::Array {
+        self.commit
+            .files
+            .iter()
+            .map(|file| js_sys::Uint8Array::from(file.bytes.as_slice()))
+            .collect()
+    }
+}
+
+/// The Hudi commit inserting parquet files into a copy-on-write table, so they can be registered
+/// as a Hudi table: a completed instant on the `.hoodie` timeline, the table's
+/// `hoodie.properties` when it's created, and each partition's metadata. The files keep their
+/// bytes, as the table doesn't populate Hudi's meta fields, but are renamed as Hudi base files.
+/// The schema is mapped from the files', which must all match
+///
+/// # Arguments
+///
+/// * `paths`: The paths of the files relative to the table's root, e.g.
+///   `region=eu/part-00000.parquet` with a directory for each partition field
+/// * `files`: The parquet files, in the same order
+/// * `options`: A JSON object of Hudi options, e.g.
+///   `{"table_name": "events", "partition_fields": ["region"], "record_key_fields": ["id"]}`
+#[wasm_bindgen]
+pub fn hudi_commit(
+    paths: Vec<String>,
+    files: Vec<js_sys::Uint8Array>,
+    options: String,
+) -> Result<HudiCommit, JsValue> {
+    if paths.len() != files.len() {
+        let error = format!("Got {} paths for {} files", paths.len(), files.len());
+        return Err(JsValue::from_str(error.as_str()));
+    }
+    let files = paths
+        .into_iter()
+        .zip(files.iter().map(|file| file.to_vec()))
+        .collect::<Vec<_>>();
+    match hudi::parse_hudi_options(options.as_str())
+        .and_then(|options| hudi::commit(&files, &options))
+    {
+        Ok(commit) => Ok(HudiCommit { commit }),
+        Err(e) => Err(JsValue::from_str(e.as_str())),
+    }
+}
+
+/// The message type of a schema in parquet's text format
+#[cfg(test)]
+fn schema_text(schema: &ParquetSchema) -> String {
+    let mut buf = Vec::new();
+    parquet::schema::printer::print_schema(&mut buf, &build_schema(schema).unwrap());
+    String::from_utf8(buf).unwrap()
+}
+
+#[test]
+fn test_build_schema_basic() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            },
+            {
+                "name": "age",
+                "type": "INT32"
+            },
+            {
+                "name": "is_active",
+                "type": "BOOLEAN"
+            }
+        ]
+    }
+    "#;
+    let schema = schema_text(&serde_json::from_str(schema).unwrap());
+    assert_eq!(
+        schema,
+        "message schema {\n  REQUIRED INT32 id;\n  REQUIRED BYTE_ARRAY name (UTF8);\n  REQUIRED INT32 age;\n  REQUIRED BOOLEAN is_active;\n}\n"
+    );
+}
+
+#[test]
+fn test_build_schema_errors() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT32", "logical_type": "UTF8"}]}"#;
+    assert_eq!(
+        CompiledSchema::parse(schema).err().unwrap(),
+        "Invalid field id: Parquet error: UTF8 cannot annotate field 'id' because it is not a BYTE_ARRAY field"
+    );
+    assert!(CompiledSchema::parse(r#"{"fields": 1}"#)
+        .err()
+        .unwrap()
+        .starts_with("Error parsing schema: "));
+}
+
+#[test]
+fn test_write_parquet_enum_allowed_values() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "status",
+                "type": "BYTE_ARRAY",
+                "logical_type": "ENUM",
+                "allowed_values": ["active", "inactive"]
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"status": "active"}"#.to_string(),
+        r#"{"status": "deleted"}"#.to_string(),
+    ];
+    let err = write_parquet(schema.to_string(), files.clone(), &WriterOptions::default())
+        .unwrap_err()
+        .to_string();
+    assert_eq!(
+        err,
+        "Row 1: value \"deleted\" for field status is not one of the allowed values"
+    );
+
+    let nullable_schema = schema.replace(
+        r#""allowed_values""#,
+        r#""repetition_type": "OPTIONAL", "on_invalid": "null", "allowed_values""#,
+    );
+    assert!(write_parquet(nullable_schema, files, &WriterOptions::default()).is_ok());
+}
+
+#[test]
+fn test_write_parquet_reports_first_invalid_row() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "status",
+                "type": "BYTE_ARRAY",
+                "logical_type": "ENUM",
+                "allowed_values": ["active"]
+            },
+            {
+                "name": "level",
+                "type": "BYTE_ARRAY",
+                "logical_type": "ENUM",
+                "allowed_values": ["low"]
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"status": "active", "level": "high"}"#.to_string(),
+        r#"{"status": "deleted", "level": "low"}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files, &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 0: value \"high\" for field level is not one of the allowed values"
+    );
+}
+
+#[test]
+fn test_write_parquet_source_key() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "user_id",
+                "type": "INT64",
+                "source_key": "userId"
+            },
+            {
+                "name": "version",
+                "type": "INT32",
+                "source_key": "app\\.version"
+            }
+        ]
+    }
+    "#;
+    let files = vec![r#"{"userId": 42, "app.version": 3}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files.clone(), &WriterOptions::default()).is_ok());
+
+    let nested_schema = schema.replace(r#"app\\.version"#, "app.version");
+    let nested_files = vec![r#"{"userId": 42, "app": {"version": 3}}"#.to_string()];
+    assert!(write_parquet(nested_schema, nested_files, &WriterOptions::default()).is_ok());
+}
+
+#[test]
+fn test_write_parquet_aliases_case_insensitive() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64",
+                "aliases": ["identifier"]
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1}"#.to_string(),
+        r#"{"ID": 2}"#.to_string(),
+        r#"{"Identifier": 3}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files.clone(), &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 1: missing or invalid value for required field id"
+    );
+
+    let options = options::parse_options(r#"{"case_insensitive_keys": true}"#).unwrap();
+    assert!(write_parquet(schema.to_string(), files, &options).is_ok());
+}
+
+#[test]
+fn test_write_parquet_infer_optional() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "name": "a"}"#.to_string(),
+        r#"{"id": 2, "name": null}"#.to_string(),
+        r#"{"id": 3}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files.clone(), &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 1: missing or invalid value for required field name"
+    );
+
+    let mut parquet_schema = serde_json::from_str::<ParquetSchema>(schema).unwrap();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    writer::infer_optional(
+        &mut parquet_schema.fields,
+        &records,
+        &WriterOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        schema_text(&parquet_schema),
+        "message schema {\n  REQUIRED INT32 id;\n  OPTIONAL BYTE_ARRAY name (UTF8);\n}\n"
+    );
+
+    let options = options::parse_options(r#"{"infer_optional": true}"#).unwrap();
+    assert!(write_parquet(schema.to_string(), files, &options).is_ok());
+}
+
+#[test]
+fn test_write_context_reuses_buffers() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let files = (0..100)
+        .map(|i| format!(r#"{{"id": {}, "name": "user {}"}}"#, i, i))
+        .collect::<Vec<_>>();
+    for options in [
+        r#"{"max_rows_per_row_group": 10}"#,
+        r#"{"infer_optional": true}"#,
+    ] {
+        let mut context = WriteContext::create(schema, options).unwrap();
+        let expected = write_parquet(
+            schema.to_string(),
+            files.clone(),
+            &options::parse_options(options).unwrap(),
+        )
+        .unwrap();
+        let first = context.write_files(&files).unwrap();
+        assert_eq!(first, expected);
+        let output = first.as_ptr();
+        let second = context.write_files(&files).unwrap();
+        assert_eq!(second, expected);
+        // Written into the same allocation as the first file
+        assert_eq!(second.as_ptr(), output);
+        assert!(!context.buffers.columns.is_empty());
+        assert_eq!(
+            context.write_files(&files[..1]).unwrap(),
+            write_parquet(
+                schema.to_string(),
+                files[..1].to_vec(),
+                &options::parse_options(options).unwrap()
+            )
+            .unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_output_buffer_capacity() {
+    let schema = CompiledSchema::parse(
+        r#"
+        {
+            "fields": [
+                {"name": "id", "type": "INT64"},
+                {"name": "score", "type": "DOUBLE", "repetition_type": "OPTIONAL"},
+                {"name": "ok", "type": "BOOLEAN"},
+                {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8"},
+                {"name": "counts", "type": "INT32", "logical_type": "LIST"}
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+    let fields = &schema.schema.fields;
+    assert!(writer::output_buffer(fields, 1000, &WriterOptions::default()).capacity() >= 17_000);
+
+    let options = options::parse_options(r#"{"estimated_output_bytes": 1000000}"#).unwrap();
+    assert!(writer::output_buffer(fields, 10, &options).capacity() >= 1_000_000);
+    let files = vec![r#"{"id": 1, "ok": true, "name": "a", "counts": [1]}"#.to_string()];
+    assert!(write_compiled(&schema, files, &options).is_ok());
+}
+
+#[test]
+fn test_write_split() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let schema = CompiledSchema::parse(
+        r#"{"fields": [{"name": "id", "type": "INT64"}, {"name": "name", "type": "BYTE_ARRAY"}]}"#,
+    )
+    .unwrap();
+    let files = (0..1000)
+        .map(|i| format!(r#"{{"id": {}, "name": "{}"}}"#, i, "x".repeat(i % 50)))
+        .collect::<Vec<_>>();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(
+        r#"{"max_file_bytes": 20000, "max_rows_per_row_group": 100, "dictionary_enabled": false}"#,
+    )
+    .unwrap();
+    let split = write_split(&schema, &records, &options).unwrap();
+    assert!(split.files.len() > 1);
+    assert_eq!(split.rows.iter().sum::<usize>(), 1000);
+
+    let mut first_id = 0;
+    for (file, rows) in split.files.iter().zip(split.rows.iter()) {
+        let reader = SerializedFileReader::new(bytes::Bytes::from(file.clone())).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows() as usize, *rows);
+        let row_groups = reader.metadata().row_groups().iter();
+        assert!(row_groups.map(|rg| rg.compressed_size()).sum::<i64>() + 4 <= 20000);
+        let first = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+        assert_eq!(first.get_long(0).unwrap(), first_id);
+        first_id += *rows as i64;
+    }
+
+    // Without row group options the row groups are cut at max_file_bytes
+    let options =
+        options::parse_options(r#"{"max_file_bytes": 5000, "dictionary_enabled": false}"#).unwrap();
+    let split = write_split(&schema, &records, &options).unwrap();
+    assert!(split.files.len() > 1);
+    assert_eq!(split.rows.iter().sum::<usize>(), 1000);
+    for file in &split.files {
+        let reader = SerializedFileReader::new(bytes::Bytes::from(file.clone())).unwrap();
+        let row_groups = reader.metadata().row_groups().iter();
+        assert!(row_groups.map(|rg| rg.compressed_size()).sum::<i64>() + 4 <= 5000);
+    }
+
+    // Row groups end at the end of each file's rows
+    let options =
+        options::parse_options(r#"{"max_rows_per_file": 300, "max_rows_per_row_group": 200}"#)
+            .unwrap();
+    let split = write_split(&schema, &records, &options).unwrap();
+    assert_eq!(split.rows, vec![300, 300, 300, 100]);
+    let reader = SerializedFileReader::new(bytes::Bytes::from(split.files[1].clone())).unwrap();
+    let row_groups = reader.metadata().row_groups().iter();
+    assert_eq!(
+        row_groups.map(|rg| rg.num_rows()).collect::<Vec<_>>(),
+        vec![200, 100]
+    );
+    let first = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+    assert_eq!(first.get_long(0).unwrap(), 300);
+
+    let unsplit = write_split(&schema, &records, &WriterOptions::default()).unwrap();
+    assert_eq!(unsplit.rows, vec![1000]);
+    let empty = write_split(&schema, &[], &options).unwrap();
+    assert_eq!(empty.rows, vec![0]);
+
+    let options = options::parse_options(r#"{"max_file_bytes": 0}"#).unwrap();
+    assert_eq!(
+        write_split(&schema, &records, &options)
+            .unwrap_err()
+            .to_string(),
+        "max_file_bytes must be greater than 0"
+    );
+    let options = options::parse_options(r#"{"max_rows_per_file": 0}"#).unwrap();
+    assert_eq!(
+        write_split(&schema, &records, &options)
+            .unwrap_err()
+            .to_string(),
+        "max_rows_per_file must be greater than 0"
+    );
+
+    let invalid = [
+        files[0].clone(),
+        files[1].clone(),
+        r#"{"id": "a", "name": "b"}"#.to_string(),
+    ];
+    let records = writer::parse_records(&invalid, Default::default()).unwrap();
+    let options =
+        options::parse_options(r#"{"max_file_bytes": 1, "max_rows_per_row_group": 1}"#).unwrap();
+    assert_eq!(
+        write_split(&schema, &records, &options)
+            .unwrap_err()
+            .to_string(),
+        "Row 2: missing or invalid value for required field id"
+    );
+}
+
+#[test]
+fn test_write_partitioned() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = CompiledSchema::parse(
+        r#"
+        {
+            "fields": [
+                {"name": "id", "type": "INT64"},
+                {"name": "date", "type": "BYTE_ARRAY", "logical_type": "UTF8"},
+                {"name": "region", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"}
+            ]
+        }
+        "#,
+    )
+    .unwrap();
+    let files = [
+        r#"{"id": 0, "date": "2024-01-01", "region": "eu"}"#,
+        r#"{"id": 1, "date": "2024-01-02", "region": "eu"}"#,
+        r#"{"id": 2, "date": "2024-01-01", "region": "eu"}"#,
+        r#"{"id": 3, "date": "2024-01-01", "region": "us/east"}"#,
+        r#"{"id": 4, "date": "2024-01-01"}"#,
+    ]
+    .map(String::from);
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let partition_by = ["date".to_string(), "region".to_string()];
+    let columns = |file: &[u8]| {
+        let reader = SerializedFileReader::new(bytes::Bytes::from(file.to_vec())).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        let columns = metadata.schema_descr().columns().iter();
+        (
+            columns.map(|c| c.name().to_string()).collect::<Vec<_>>(),
+            metadata.num_rows(),
+        )
+    };
+
+    let partitioned =
+        write_partitioned(&schema, &records, &partition_by, &WriterOptions::default()).unwrap();
+    let paths = partitioned.iter().map(|(path, _)| path.as_str());
+    assert_eq!(
+        paths.collect::<Vec<_>>(),
+        vec![
+            "date=2024-01-01/region=__HIVE_DEFAULT_PARTITION__/part-0000.parquet",
+            "date=2024-01-01/region=eu/part-0000.parquet",
+            "date=2024-01-01/region=us%2Feast/part-0000.parquet",
+            "date=2024-01-02/region=eu/part-0000.parquet",
+        ]
+    );
+    assert_eq!(
+        columns(&partitioned[1].1),
+        (
+            vec!["id".to_string(), "date".to_string(), "region".to_string()],
+            2
+        )
+    );
+
+    let options = options::parse_options(r#"{"drop_partition_columns": true}"#).unwrap();
+    let partitioned = write_partitioned(&schema, &records, &partition_by, &options).unwrap();
+    assert_eq!(columns(&partitioned[1].1), (vec!["id".to_string()], 2));
+
+    let options = options::parse_options(
+        r#"{"max_file_bytes": 1, "max_rows_per_row_group": 1, "drop_partition_columns": true}"#,
+    )
+    .unwrap();
+    let partitioned =
+        write_partitioned(&schema, &records, &["date".to_string()], &options).unwrap();
+    let paths = partitioned.iter().map(|(path, _)| path.as_str());
+    assert_eq!(
+        paths.collect::<Vec<_>>()[..2],
+        [
+            "date=2024-01-01/part-0000.parquet",
+            "date=2024-01-01/part-0001.parquet"
+        ]
+    );
+    assert_eq!(partitioned.len(), 5);
+
+    let options = options::parse_options(
+        r#"{"partition_template": "{col}_{value}", "file_template": "data-{seq:3}-{uuid}.parquet"}"#,
+    )
+    .unwrap();
+    let partitioned = write_partitioned(&schema, &records, &partition_by, &options).unwrap();
+    let (directory, name) = partitioned[3].0.rsplit_once('/').unwrap();
+    assert_eq!(directory, "date_2024-01-02/region_eu");
+    assert!(name.starts_with("data-000-") && name.ends_with(".parquet"));
+    assert_eq!(name.len(), "data-000-.parquet".len() + 36);
+    let uuid = |path: &str| path[path.len() - 44..path.len() - 8].to_string();
+    assert_eq!(uuid(&partitioned[0].0), uuid(&partitioned[3].0));
+
+    for (options, error) in [
+        (
+            r#"{"file_template": "part-{n}.parquet"}"#,
+            "Unknown placeholder {n} in file_template",
+        ),
+        (
+            r#"{"file_template": "part-{seq:x}.parquet"}"#,
+            "Invalid width x in file_template",
+        ),
+        (
+            r#"{"partition_template": "{col}"}"#,
+            "partition_template must include {value}",
+        ),
+        (
+            r#"{"file_template": "{{{uuid}}}.parquet", "max_file_bytes": 1, "max_rows_per_row_group": 1}"#,
+            "file_template names more than one file date=2024-01-01/region=eu/{",
+        ),
+    ] {
+        let options = options::parse_options(options).unwrap();
+        let error_message = write_partitioned(&schema, &records, &partition_by, &options)
+            .unwrap_err()
+            .to_string();
+        assert!(error_message.starts_with(error), "{}", error_message);
+    }
+
+    assert_eq!(
+        write_partitioned(&schema, &records, &["day".to_string()], &options)
+            .unwrap_err()
+            .to_string(),
+        "Unknown field day in partition_by"
+    );
+    assert_eq!(
+        write_partitioned(&schema, &records, &[], &options)
+            .unwrap_err()
+            .to_string(),
+        "No fields to partition by"
+    );
+}
+
+#[test]
+fn test_write_compiled_reuses_schema() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let options = WriterOptions::default();
+    let first = write_compiled(&compiled, vec![r#"{"id": 1}"#.to_string()], &options).unwrap();
+    let second = write_compiled(&compiled, vec![r#"{"id": 1}"#.to_string()], &options).unwrap();
+    assert_eq!(first, second);
+    assert_eq!(
+        first,
+        write_parquet(
+            schema.to_string(),
+            vec![r#"{"id": 1}"#.to_string()],
+            &options
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn test_write_parquet_optional_nulls_stay_aligned() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "repetition_type": "OPTIONAL"
+            },
+            {
+                "name": "score",
+                "type": "DOUBLE",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "name": "a"}"#.to_string(),
+        r#"{"id": 2, "name": null, "score": 2.5}"#.to_string(),
+        r#"{"id": 3, "name": "c", "score": 3.5}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"id": 1, "name": "a", "score": null}),
+            serde_json::json!({"id": 2, "name": null, "score": 2.5}),
+            serde_json::json!({"id": 3, "name": "c", "score": 3.5}),
+        ]
+    );
+}
+
+#[test]
+fn test_write_parquet_repeated() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "tags",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "repetition_type": "REPEATED"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "tags": ["a", "b"]}"#.to_string(),
+        r#"{"id": 2, "tags": []}"#.to_string(),
+        r#"{"id": 3}"#.to_string(),
+        r#"{"id": 4, "tags": ["c"]}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    // The record reader doesn't support bare REPEATED primitives, check the levels written
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 4);
+    let tags = reader.metadata().row_group(0).column(1);
+    assert_eq!(tags.num_values(), 5);
+}
+
+#[test]
+fn test_write_parquet_strict() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "score",
+                "type": "DOUBLE",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "score": 1.5}"#.to_string(),
+        r#"{"id": 2, "score": "high"}"#.to_string(),
+    ];
+    assert!(write_parquet(schema.to_string(), files.clone(), &WriterOptions::default()).is_ok());
+
+    let options = options::parse_options(r#"{"strict": true}"#).unwrap();
+    assert_eq!(
+        write_parquet(schema.to_string(), files, &options).unwrap_err(),
+        WriteError::Value(Box::new(error::ValueError {
+            row: 1,
+            field: "score".to_string(),
+            expected: "DOUBLE".to_string(),
+            actual: serde_json::json!("high"),
+        }))
+    );
+
+    let missing = vec![r#"{"score": 1.5}"#.to_string()];
+    assert_eq!(
+        write_parquet(schema.to_string(), missing, &options)
+            .unwrap_err()
+            .to_string(),
+        "Row 0: expected INT32 for field id, got null"
+    );
+}
+
+#[test]
+fn test_write_parquet_coercion() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "count",
+                "type": "INT32"
+            },
+            {
+                "name": "label",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let files = vec![r#"{"count": "42", "label": 7}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files.clone(), &WriterOptions::default()).is_err());
+
+    let options = options::parse_options(
+        r#"{"coercion": {"string_to_number": true, "number_to_string": true}}"#,
+    )
+    .unwrap();
+    assert!(write_parquet(schema.to_string(), files, &options).is_ok());
+
+    let float_files = vec![r#"{"count": 4.5, "label": "a"}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), float_files.clone(), &options).is_err());
+    let options =
+        options::parse_options(r#"{"coercion": {"truncate_float_to_int": true}}"#).unwrap();
+    assert!(write_parquet(schema.to_string(), float_files, &options).is_ok());
+}
+
+#[test]
+fn test_parse_ndjson() {
+    let ndjson = "{\"id\": 1}\r\n\n{\"id\": 2}\n";
+    let records = writer::parse_ndjson(ndjson, 0, Default::default()).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].get("id"), Some(&serde_json::json!(2)));
+
+    assert_eq!(
+        writer::parse_ndjson("{\"id\": 1}\n\n{\"id\": ", 0, Default::default()).unwrap_err(),
+        "Line 3: error parsing record"
+    );
+}
+
+#[test]
+fn test_parse_json_array() {
+    let records =
+        writer::parse_json_array(r#"[{"id": 1}, {"id": 2}]"#, Default::default()).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].get("id"), Some(&serde_json::json!(2)));
+
+    assert_eq!(
+        writer::parse_json_array(r#"[{"id": 1}, 2]"#, Default::default()).unwrap_err(),
+        "Row 1: record is not a JSON object"
+    );
+    assert_eq!(
+        writer::parse_json_array(r#"{"id": 1}"#, Default::default()).unwrap_err(),
+        "Records are not a JSON array"
+    );
+}
+
+#[test]
+fn test_parse_msgpack_records() {
+    let record = serde_json::json!({"id": 1, "user": {"name": "a"}, "score": 2.5});
+    let encoded = vec![
+        rmp_serde::to_vec_named(&record).unwrap(),
+        rmp_serde::to_vec(&[1, 2]).unwrap(),
+    ];
+    let err = writer::parse_msgpack_records(&encoded).unwrap_err();
+    assert_eq!(err, "Row 1: record is not a MessagePack map");
+
+    let records = writer::parse_msgpack_records(&encoded[..1]).unwrap();
+    assert_eq!(serde_json::Value::Object(records[0].clone()), record);
+}
+
+#[test]
+fn test_write_parquet_binary_encoding() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "payload",
+                "type": "BYTE_ARRAY",
+                "encoding": "base64"
+            },
+            {
+                "name": "digest",
+                "type": "BYTE_ARRAY",
+                "encoding": "hex"
+            }
+        ]
+    }
+    "#;
+    let files = vec![r#"{"payload": "AP8Q", "digest": "00ff10"}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files, &WriterOptions::default()).is_ok());
+
+    let invalid = vec![r#"{"payload": "AP8Q", "digest": "0g"}"#.to_string()];
+    assert_eq!(
+        write_parquet(schema.to_string(), invalid, &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 0: missing or invalid value for required field digest"
+    );
+}
+
+#[test]
+fn test_write_parquet_byte_values() {
+    use crate::convert::UINT8_ARRAY_KEY;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "payload",
+                "type": "BYTE_ARRAY",
+                "encoding": "base64"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    // A Uint8Array from generate_parquet_from_js is written without decoding
+    let options = WriterOptions {
+        byte_values: vec![vec![0, 255, 16]],
+        ..Default::default()
+    };
+    let mut record = writer::Record::new();
+    record.insert(
+        "payload".to_string(),
+        serde_json::json!({UINT8_ARRAY_KEY: 0}),
+    );
+    let bytes = write_records(&compiled, &[record], &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let row = reader.get_row_iter(None).unwrap().next().unwrap().unwrap();
+    assert_eq!(row.get_bytes(0).unwrap().data(), &[0, 255, 16]);
+
+    let mut invalid = writer::Record::new();
+    invalid.insert(
+        "payload".to_string(),
+        serde_json::json!({UINT8_ARRAY_KEY: 1}),
+    );
+    assert_eq!(
+        write_records(&compiled, &[invalid], &options)
+            .unwrap_err()
+            .to_string(),
+        "Row 0: missing or invalid value for required field payload"
+    );
+
+    // Records parsed from JSON strings have no bytes for the key to refer to
+    let forged = vec![r#"{"payload": {"\u0000Uint8Array": 0}}"#.to_string()];
+    assert_eq!(
+        write_parquet(schema.to_string(), forged, &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 0: missing or invalid value for required field payload"
+    );
+
+    // A JSON array of numbers isn't a Uint8Array, so it isn't taken as bytes
+    for field in [
+        r#"{"name": "payload", "type": "BYTE_ARRAY"}"#,
+        r#"{"name": "payload", "type": "BYTE_ARRAY", "logical_type": "UTF8"}"#,
+    ] {
+        let schema = format!(r#"{{"fields": [{}]}}"#, field);
+        let files = vec![r#"{"payload": [1, 2, 3]}"#.to_string()];
+        assert_eq!(
+            write_parquet(schema, files, &WriterOptions::default())
+                .unwrap_err()
+                .to_string(),
+            "Row 0: missing or invalid value for required field payload"
+        );
+    }
+}
+
+#[test]
+fn test_write_parquet_rfc3339_timestamps() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "created_at",
+                "type": "INT64",
+                "logical_type": "TIMESTAMP_MILLIS"
+            },
+            {
+                "name": "updated_at",
+                "type": "INT64",
+                "logical_type": "TIMESTAMP_MICROS"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"created_at": "2024-01-02T03:04:05.678Z", "updated_at": "2024-01-02T04:04:05+01:00"}"#
+            .to_string(),
+        r#"{"created_at": 1704164645678, "updated_at": 1704164645000000}"#.to_string(),
+        r#"{"created_at": "1704164645678", "updated_at": "1704164645000000"}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(rows[0], rows[1]);
+    assert_eq!(rows[0], rows[2]);
+
+    let invalid = vec![r#"{"created_at": "yesterday", "updated_at": 0}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), invalid, &WriterOptions::default()).is_err());
+}
+
+#[test]
+fn test_write_parquet_dates() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "day",
+                "type": "INT32",
+                "logical_type": "DATE",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    assert_eq!(convert::parse_date("1970-01-02"), Some(1));
+    assert_eq!(convert::parse_date("1969-12-31"), Some(-1));
+
+    let files = vec![
+        r#"{"day": "2024-02-29"}"#.to_string(),
+        r#"{"day": 19782}"#.to_string(),
+    ];
+    assert!(write_parquet(schema.to_string(), files, &WriterOptions::default()).is_ok());
+
+    let invalid = vec![
+        r#"{"day": "2024-02-29"}"#.to_string(),
+        r#"{"day": "2023-02-29"}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), invalid, &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 1: invalid date \"2023-02-29\" for field day, expected YYYY-MM-DD"
+    );
+}
+
+#[test]
+fn test_write_parquet_decimals() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "price",
+                "type": "INT64",
+                "logical_type": "DECIMAL",
+                "precision": 10,
+                "scale": 2
+            },
+            {
+                "name": "total",
+                "type": "FIXED_LEN_BYTE_ARRAY",
+                "logical_type": "DECIMAL",
+                "precision": 20,
+                "scale": 4
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    assert_eq!(
+        schema_text(&compiled.schema),
+        "message schema {\n  REQUIRED INT64 price (DECIMAL(10,2));\n  REQUIRED FIXED_LEN_BYTE_ARRAY (9) total (DECIMAL(20,4));\n}\n"
+    );
+
+    let files = vec![
+        r#"{"price": "123.45", "total": "-9999999999999999.9999"}"#.to_string(),
+        r#"{"price": 7, "total": "0.5"}"#.to_string(),
+    ];
+    assert!(write_compiled(&compiled, files, &WriterOptions::default()).is_ok());
+
+    let overflow = vec![r#"{"price": "123456789.5", "total": "1.00001"}"#.to_string()];
+    assert_eq!(
+        write_compiled(&compiled, overflow, &WriterOptions::default())
+            .unwrap_err()
+            .to_string(),
+        "Row 0: decimal \"123456789.5\" for field price doesn't fit DECIMAL(10, 2)"
+    );
+
+    assert!(
+        CompiledSchema::parse(&schema.replace("\"precision\": 10", "\"precision\": 19")).is_err()
+    );
+
+    // Exponent-form numbers move the decimal point before checking precision and scale
+    let schema = r#"{"fields": [{"name": "amount", "type": "FIXED_LEN_BYTE_ARRAY", "logical_type": "DECIMAL", "precision": 38, "scale": 10}]}"#;
+    let files = vec![
+        r#"{"amount": 1e-7}"#.to_string(),
+        r#"{"amount": 1.5e20}"#.to_string(),
+        r#"{"amount": "-2.5E-3"}"#.to_string(),
+        r#"{"amount": "0e400"}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"amount": "0.0000001000"}),
+            serde_json::json!({"amount": "150000000000000000000.0000000000"}),
+            serde_json::json!({"amount": "-0.0025000000"}),
+            serde_json::json!({"amount": "0.0000000000"}),
+        ]
+    );
+
+    for amount in ["1e-11", "1e28", "1e999999"] {
+        let files = vec![format!(r#"{{"amount": "{}"}}"#, amount)];
+        assert_eq!(
+            write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap_err(),
+            WriteError::from(format!(
+                "Row 0: decimal \"{}\" for field amount doesn't fit DECIMAL(38, 10)",
+                amount
+            ))
+        );
+    }
+}
+
+#[test]
+fn test_write_parquet_int96() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "ts",
+                "type": "INT96"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"ts": "2024-01-02T03:04:05.678Z"}"#.to_string(),
+        r#"{"ts": 1704164645678}"#.to_string(),
+        r#"{"ts": "1969-12-31T23:59:59Z"}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let millis = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| match row.unwrap().get_column_iter().next() {
+            Some((_, Field::TimestampMillis(millis))) => *millis,
+            other => panic!("unexpected field {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(millis, vec![1704164645678, 1704164645678, -1000]);
+}
+
+#[test]
+fn test_write_parquet_int64_strings() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": "9007199254740993"}"#.to_string(),
+        r#"{"id": 9223372036854775807}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let ids = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().get_long(0).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, vec![9007199254740993, i64::MAX]);
+
+    let files = vec![r#"{"id": "12a"}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files, &WriterOptions::default()).is_err());
+}
+
+#[test]
+fn test_write_parquet_flatten() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "user_name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"user": {"name": "a", "address": {"city": "b"}}, "score": 1}"#.to_string(),
+        r#"{"user": {"name": "c", "address": null}, "score": 2}"#.to_string(),
+    ];
+    let options = options::parse_options(r#"{"flatten": {"infer_fields": true}}"#).unwrap();
+    let bytes = write_parquet(schema.to_string(), files.clone(), &options).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows[0],
+        serde_json::json!({"user_name": "a", "user_address_city": "b", "score": 1})
+    );
+    assert_eq!(
+        rows[1],
+        serde_json::json!({"user_name": "c", "user_address_city": null, "score": 2})
+    );
+
+    let options =
+        options::parse_options(r#"{"flatten": {"separator": ".", "max_depth": 1}}"#).unwrap();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let flattened = flatten::flatten_records(&records, options.flatten.as_ref().unwrap());
+    assert_eq!(
+        serde_json::Value::Object(flattened[0].clone()),
+        serde_json::json!({"user.name": "a", "user.address": {"city": "b"}, "score": 1})
+    );
+}
+
+#[test]
+fn test_write_parquet_list() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "tags",
+                "type": "BYTE_ARRAY",
+                "logical_type": "LIST",
+                "element_logical_type": "UTF8",
+                "repetition_type": "OPTIONAL"
+            },
+            {
+                "name": "scores",
+                "type": "INT32",
+                "logical_type": "LIST"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"tags": ["a", "b"], "scores": [1, null, 3]}"#.to_string(),
+        r#"{"tags": [], "scores": []}"#.to_string(),
+        r#"{"tags": null, "scores": [4]}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"tags": ["a", "b"], "scores": [1, null, 3]}),
+            serde_json::json!({"tags": [], "scores": []}),
+            serde_json::json!({"tags": null, "scores": [4]}),
+        ]
+    );
+
+    let files = vec![r#"{"tags": ["a"]}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files, &WriterOptions::default()).is_err());
+}
+
+#[test]
+fn test_write_parquet_map() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "attributes",
+                "type": "INT64",
+                "logical_type": "MAP",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"attributes": {"z": 1, "a": null, "m": 3}}"#.to_string(),
+        r#"{"attributes": {}}"#.to_string(),
+        r#"{"attributes": null}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let maps = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| match row.unwrap().get_column_iter().next() {
+            Some((_, Field::MapInternal(map))) => Some(
+                map.entries()
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<Vec<_>>(),
+            ),
+            Some((_, Field::Null)) => None,
+            other => panic!("unexpected field {:?}", other),
+        })
+        .collect::<Vec<_>>();
+    let entry = |k: &str, v: &str| (format!("\"{}\"", k), v.to_string());
+    assert_eq!(
+        maps,
+        vec![
+            Some(vec![entry("z", "1"), entry("a", "null"), entry("m", "3")]),
+            Some(vec![]),
+            None,
+        ]
+    );
+
+    let files = vec![r#"{"attributes": {"a": "b"}}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files, &WriterOptions::default()).is_err());
+}
+
+#[test]
+fn test_write_parquet_on_null() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64",
+                "on_null": "error",
+                "repetition_type": "OPTIONAL"
+            },
+            {
+                "name": "status",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "on_null": "default",
+                "default": "unknown"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 1, "status": "active"}"#.to_string(),
+        r#"{"id": 2, "status": null}"#.to_string(),
+        r#"{"id": 3}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(rows[1], serde_json::json!({"id": 2, "status": "unknown"}));
+    assert_eq!(rows[2], serde_json::json!({"id": 3, "status": "unknown"}));
+
+    let files = vec![
+        r#"{"id": 1, "status": "active"}"#.to_string(),
+        r#"{"status": "active"}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap_err(),
+        WriteError::from("Row 1: null value for field id".to_string())
+    );
+
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64", "on_null": "default"}]}"#;
+    assert!(CompiledSchema::parse(schema).is_err());
+}
+
+#[test]
+fn test_write_parquet_lone_surrogates() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "text",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"text": "a\ud800b"}"#.to_string(),
+        r#"{"text": "\\ud800 \ud83d\ude00"}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files.clone(), &WriterOptions::default()).unwrap_err(),
+        WriteError::from("Row 0: lone surrogate \\ud800 in string".to_string())
+    );
+
+    let read_text = |bytes: Vec<u8>| {
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| match row.unwrap().get_column_iter().next() {
+                Some((_, Field::Str(s))) => s.as_bytes().to_vec(),
+                Some((_, Field::Bytes(b))) => b.data().to_vec(),
+                other => panic!("unexpected field {:?}", other),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let options = options::parse_options(r#"{"lone_surrogates": "replace"}"#).unwrap();
+    let bytes = write_parquet(schema.to_string(), files.clone(), &options).unwrap();
+    assert_eq!(
+        read_text(bytes),
+        vec![
+            "a\u{FFFD}b".as_bytes().to_vec(),
+            "\\ud800 \u{1F600}".as_bytes().to_vec()
+        ]
+    );
+
+    let options = options::parse_options(r#"{"lone_surrogates": "binary"}"#).unwrap();
+    let bytes = write_parquet(schema.to_string(), files, &options).unwrap();
+    assert_eq!(read_text(bytes)[0], b"a\xED\xA0\x80b".to_vec());
+}
+
+#[test]
+fn test_write_parquet_overflow() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "small",
+                "type": "INT32",
+                "logical_type": "UINT8",
+                "on_overflow": "clamp"
+            },
+            {
+                "name": "count",
+                "type": "INT32",
+                "repetition_type": "OPTIONAL",
+                "on_overflow": "null"
+            },
+            {
+                "name": "big",
+                "type": "INT64"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"small": 300, "count": 3000000000, "big": 1}"#.to_string(),
+        r#"{"small": -5, "count": 7, "big": 2}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"small": 255, "count": null, "big": 1}),
+            serde_json::json!({"small": 0, "count": 7, "big": 2}),
+        ]
+    );
+
+    let files = vec![
+        r#"{"small": 1, "count": 1, "big": 1}"#.to_string(),
+        r#"{"small": 1, "count": 1, "big": 18446744073709551615}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap_err(),
+        WriteError::from(
+            "Row 1: value 18446744073709551615 for field big is out of range \
+             [-9223372036854775808, 9223372036854775807]"
+                .to_string()
+        )
+    );
+
+    // UINT64 takes the whole unsigned range, written with the same bits
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64", "logical_type": "UINT64"}]}"#;
+    let files = vec![
+        r#"{"id": 18446744073709551615}"#.to_string(),
+        r#"{"id": "9223372036854775808"}"#.to_string(),
+        r#"{"id": 7}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"id": 18446744073709551615u64}),
+            serde_json::json!({"id": 9223372036854775808u64}),
+            serde_json::json!({"id": 7}),
+        ]
+    );
+    let files = vec![r#"{"id": -1}"#.to_string()];
+    assert_eq!(
+        write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap_err(),
+        WriteError::from(
+            "Row 0: value -1 for field id is out of range [0, 18446744073709551615]".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_write_parquet_non_finite() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "ratio",
+                "type": "FLOAT",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"ratio": 0.5}"#.to_string(),
+        r#"{"ratio": "NaN"}"#.to_string(),
+        r#"{"ratio": "-Infinity"}"#.to_string(),
+        r#"{"ratio": 1e39}"#.to_string(),
+    ];
+    assert_eq!(
+        write_parquet(schema.to_string(), files.clone(), &WriterOptions::default()).unwrap_err(),
+        WriteError::from("Row 1: non-finite value \"NaN\" for field ratio".to_string())
+    );
+
+    let read_floats = |bytes: Vec<u8>| {
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| match row.unwrap().get_column_iter().next() {
+                Some((_, Field::Float(f))) => Some(*f),
+                Some((_, Field::Null)) => None,
+                other => panic!("unexpected field {:?}", other),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let options = options::parse_options(r#"{"non_finite": "null"}"#).unwrap();
+    let bytes = write_parquet(schema.to_string(), files[..3].to_vec(), &options).unwrap();
+    assert_eq!(read_floats(bytes), vec![Some(0.5), None, None]);
+
+    let options = options::parse_options(r#"{"non_finite": "write"}"#).unwrap();
+    let bytes = write_parquet(schema.to_string(), files[..3].to_vec(), &options).unwrap();
+    let floats = read_floats(bytes);
+    assert!(floats[1].unwrap().is_nan());
+    assert_eq!(floats[2], Some(f32::NEG_INFINITY));
+
+    assert_eq!(
+        write_parquet(schema.to_string(), files[3..].to_vec(), &options).unwrap_err(),
+        WriteError::from(
+            "Row 0: value 1e39 for field ratio is out of range [-3.4028235e38, 3.4028235e38]"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_write_parquet_lenient_booleans() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let schema = r#"{"fields": [{"name": "active", "type": "BOOLEAN"}]}"#;
+    let files = vec![
+        r#"{"active": "TRUE"}"#.to_string(),
+        r#"{"active": "0"}"#.to_string(),
+        r#"{"active": 1}"#.to_string(),
+        r#"{"active": false}"#.to_string(),
+    ];
+    assert!(write_parquet(schema.to_string(), files.clone(), &WriterOptions::default()).is_err());
+
+    let options = options::parse_options(r#"{"coercion": {"lenient_booleans": true}}"#).unwrap();
+    let bytes = write_parquet(schema.to_string(), files, &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let values = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().get_bool(0).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![true, false, true, false]);
+
+    let files = vec![r#"{"active": 2}"#.to_string()];
+    assert!(write_parquet(schema.to_string(), files, &options).is_err());
+}
+
+#[test]
+fn test_write_parquet_computed() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "event_ts",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            },
+            {
+                "name": "event_date",
+                "type": "INT32",
+                "logical_type": "DATE",
+                "computed": "date(event_ts)"
+            },
+            {
+                "name": "hour",
+                "type": "INT32",
+                "computed": "hour(event_ts)"
+            },
+            {
+                "name": "label",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "computed": "concat(upper(user.name), ' ''', coalesce(user.nick, 'none'), '''')"
+            }
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"event_ts": "2024-01-01T23:30:00-02:00", "user": {"name": "ann"}}"#.to_string(),
+        r#"{"event_ts": "2024-03-04T05:06:07Z", "user": {"name": "bo", "nick": "b"}}"#.to_string(),
+    ];
+    let bytes = write_parquet(schema.to_string(), files, &WriterOptions::default()).unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let rows = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value())
+        .collect::<Vec<_>>();
+    assert_eq!(rows[0]["event_date"], serde_json::json!("2024-01-02"));
+    assert_eq!(rows[0]["hour"], serde_json::json!(1));
+    assert_eq!(rows[0]["label"], serde_json::json!("ANN 'none'"));
+    assert_eq!(rows[1]["event_date"], serde_json::json!("2024-03-04"));
+    assert_eq!(rows[1]["label"], serde_json::json!("BO 'b'"));
+
+    for expression in ["date(a, b)", "nope(a)", "concat(a", "lower('a)", "a..b"] {
+        let schema = format!(
+            r#"{{"fields": [{{"name": "x", "type": "INT32", "computed": "{}"}}]}}"#,
+            expression
+        );
+        assert!(CompiledSchema::parse(&schema).is_err(), "{}", expression);
+    }
+}
+
+#[test]
+fn test_write_parquet_dedupe_on() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "event",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8",
+                "source_key": "e.name"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![
+        r#"{"id": 1, "e": {"name": "click"}}"#.to_string(),
+        r#"{"id": 1, "e": {"name": "view"}}"#.to_string(),
+        r#"{"id": 1, "e": {"name": "click"}, "extra": true}"#.to_string(),
+        r#"{"id": 2, "e": {"name": "click"}}"#.to_string(),
+    ];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let options = options::parse_options(r#"{"dedupe_on": ["id", "event"]}"#).unwrap();
+    let report = write_report(&compiled, &records, &options).unwrap();
+    assert_eq!(report.dropped_rows, 1);
+
+    let options = options::parse_options(r#"{"dedupe_on": ["id"]}"#).unwrap();
+    assert_eq!(
+        write_report(&compiled, &records, &options)
+            .unwrap()
+            .dropped_rows,
+        2
+    );
+
+    let options = options::parse_options(r#"{"dedupe_on": ["missing"]}"#).unwrap();
+    assert_eq!(
+        write_report(&compiled, &records, &options).unwrap_err(),
+        WriteError::from("Unknown field missing in dedupe_on".to_string())
+    );
+}
+
+#[test]
+fn test_write_parquet_filter() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "level",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            },
+            {
+                "name": "code",
+                "type": "INT32",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![
+        r#"{"level": "ERROR", "code": 500}"#.to_string(),
+        r#"{"level": "INFO", "code": 200}"#.to_string(),
+        r#"{"level": "ERROR"}"#.to_string(),
+        r#"{"level": "WARN", "code": 404}"#.to_string(),
+    ];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let filtered_rows = |filter: &str| {
+        let options = options::parse_options(&format!(r#"{{"filter": {}}}"#, filter)).unwrap();
+        write_report(&compiled, &records, &options).map(|r| r.filtered_rows)
+    };
+    assert_eq!(
+        filtered_rows(r#"[{"field": "level", "op": "==", "value": "ERROR"}]"#),
+        Ok(2)
+    );
+    assert_eq!(
+        filtered_rows(
+            r#"[{"field": "level", "op": "in", "value": ["ERROR", "WARN"]},
+                {"field": "code", "op": ">=", "value": 400.0}]"#
+        ),
+        Ok(2)
+    );
+    assert_eq!(
+        filtered_rows(r#"[{"field": "code", "op": "!=", "value": null}]"#),
+        Ok(1)
+    );
+    assert!(filtered_rows(r#"[{"field": "code", "op": "in", "value": 1}]"#).is_err());
+    assert!(filtered_rows(r#"[{"field": "nope", "op": "==", "value": 1}]"#).is_err());
+}
+
+#[test]
+fn test_write_parquet_max_rows_per_row_group() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..5).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let row_counts: Vec<i64> = reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|rg| rg.num_rows())
+        .collect();
+    assert_eq!(row_counts, vec![2, 2, 1]);
+    assert_eq!(reader.get_row_iter(None).unwrap().count(), 5);
+
+    let mut files = files;
+    files[3] = r#"{"id": "x"}"#.to_string();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    assert_eq!(
+        write_records(&compiled, &records, &options).unwrap_err(),
+        WriteError::from("Row 3: missing or invalid value for required field id".to_string())
+    );
+
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 0}"#).unwrap();
+    assert!(write_records(&compiled, &records, &options).is_err());
+}
+
+#[test]
+fn test_write_parquet_page_limits() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..10).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let data_pages = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        let mut pages = reader
+            .get_row_group(0)
+            .unwrap()
+            .get_column_page_reader(0)
+            .unwrap();
+        let mut count = 0;
+        while let Some(page) = pages.get_next_page().unwrap() {
+            if page.page_type() != parquet::basic::PageType::DICTIONARY_PAGE {
+                count += 1;
+            }
+        }
+        count
+    };
+    assert_eq!(data_pages("{}"), 1);
+    assert_eq!(
+        data_pages(r#"{"write_batch_size": 1, "data_page_row_count_limit": 3}"#),
+        4
+    );
+
+    let options = options::parse_options(r#"{"write_batch_size": 0}"#).unwrap();
+    assert!(write_records(&compiled, &records, &options).is_err());
+}
+
+#[test]
+fn test_write_parquet_dictionary_enabled() {
+    use parquet::basic::Encoding;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "tags",
+                "type": "BYTE_ARRAY",
+                "logical_type": "LIST",
+                "element_logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![r#"{"id": 1, "tags": ["a", "b"]}"#.to_string()];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let dictionary_columns = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        reader
+            .metadata()
+            .row_group(0)
+            .columns()
+            .iter()
+            .map(|c| c.encodings().contains(&Encoding::RLE_DICTIONARY))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(dictionary_columns("{}"), vec![true, true]);
+    assert_eq!(
+        dictionary_columns(r#"{"dictionary_enabled": false}"#),
+        vec![false, false]
+    );
+    assert_eq!(
+        dictionary_columns(r#"{"columns": {"tags": {"dictionary_enabled": false}}}"#),
+        vec![true, false]
+    );
+    assert_eq!(
+        dictionary_columns(
+            r#"{"dictionary_enabled": false, "columns": {"id": {"dictionary_enabled": true}}}"#
+        ),
+        vec![true, false]
+    );
+
+    let options = options::parse_options(r#"{"columns": {"nope": {}}}"#).unwrap();
+    assert!(write_records(&compiled, &records, &options).is_err());
+}
+
+#[test]
+fn test_write_parquet_compression() {
+    use parquet::basic::{Compression, GzipLevel};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "message",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = (0..1000)
+        .map(|i| format!(r#"{{"id": {}, "message": "request {} served"}}"#, i, i))
+        .collect::<Vec<_>>();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let write = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes.clone())).unwrap();
+        assert_eq!(reader.get_row_iter(None).unwrap().count(), 1000);
+        let codecs = reader
+            .metadata()
+            .row_group(0)
+            .columns()
+            .iter()
+            .map(|c| c.compression())
+            .collect::<Vec<_>>();
+        (bytes.len(), codecs)
+    };
+    let gzip = Compression::GZIP(GzipLevel::default());
+    let (uncompressed_len, codecs) = write("{}");
+    assert_eq!(codecs, vec![Compression::UNCOMPRESSED; 2]);
+    let (gzip_len, codecs) = write(r#"{"compression": "GZIP"}"#);
+    assert_eq!(codecs, vec![gzip; 2]);
+    assert!(gzip_len < uncompressed_len);
+    let (_, codecs) = write(
+        r#"{"compression": "GZIP", "columns": {"message": {"compression": "UNCOMPRESSED"}}}"#,
+    );
+    assert_eq!(codecs, vec![gzip, Compression::UNCOMPRESSED]);
+
+    assert!(options::parse_options(r#"{"compression": "BROTLI"}"#).is_err());
+}
+
+#[test]
+fn test_write_parquet_column_encoding() {
+    use parquet::basic::Encoding;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..3)
+        .map(|id| format!(r#"{{"id": {}, "name": "n{}"}}"#, id, id))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let options = options::parse_options(
+        r#"{
+            "dictionary_enabled": false,
+            "columns": {
+                "id": {"encoding": "DELTA_BINARY_PACKED"},
+                "name": {"encoding": "DELTA_BYTE_ARRAY"}
+            }
+        }"#,
+    )
+    .unwrap();
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let columns = reader.metadata().row_group(0).columns();
+    assert!(columns[0]
+        .encodings()
+        .contains(&Encoding::DELTA_BINARY_PACKED));
+    assert!(columns[1].encodings().contains(&Encoding::DELTA_BYTE_ARRAY));
+    let ids: Vec<i64> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().get_long(0).unwrap())
+        .collect();
+    assert_eq!(ids, vec![0, 1, 2]);
+
+    let options =
+        options::parse_options(r#"{"columns": {"name": {"encoding": "DELTA_BINARY_PACKED"}}}"#)
+            .unwrap();
+    assert_eq!(
+        write_records(&compiled, &records, &options).unwrap_err(),
+        WriteError::from(
+            "Invalid encoding for field name: DELTA_BINARY_PACKED encoding can't be used for \
+             BYTE_ARRAY columns"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_write_parquet_statistics() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "email",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![
+        r#"{"id": 1, "email": "someone@example.com"}"#.to_string(),
+        r#"{"id": 2, "email": "another@example.com"}"#.to_string(),
+    ];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let metadata = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        reader.metadata().row_group(0).clone()
+    };
+
+    let row_group = metadata("{}");
+    let email = row_group.column(1).statistics().unwrap();
+    assert_eq!(email.min_bytes(), b"another@example.com");
+
+    let row_group = metadata(r#"{"statistics_truncate_length": 4}"#);
+    let email = row_group.column(1).statistics().unwrap();
+    assert_eq!(email.min_bytes(), b"anot");
+    assert_eq!(email.max_bytes(), b"somf");
+
+    let row_group = metadata(r#"{"columns": {"email": {"statistics_enabled": "none"}}}"#);
+    assert!(row_group.column(0).statistics().is_some());
+    assert!(row_group.column(1).statistics().is_none());
+
+    let row_group = metadata(r#"{"statistics_enabled": "none"}"#);
+    assert!(row_group.column(0).statistics().is_none());
+}
+
+#[test]
+fn test_write_parquet_bloom_filter() {
+    use parquet::file::properties::ReaderProperties;
+    use parquet::file::reader::FileReader;
+    use parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..100)
+        .map(|id| format!(r#"{{"id": {}, "name": "n{}"}}"#, id, id))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(
+        r#"{"columns": {"id": {"bloom_filter": {"fpp": 0.01, "ndv": 100}}}}"#,
+    )
+    .unwrap();
+
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let read_options = ReadOptionsBuilder::new()
+        .with_reader_properties(
+            ReaderProperties::builder()
+                .set_read_bloom_filter(true)
+                .build(),
+        )
+        .build();
+    let reader =
+        SerializedFileReader::new_with_options(bytes::Bytes::from(bytes), read_options).unwrap();
+    let row_group = reader.get_row_group(0).unwrap();
+    let ids = row_group.get_column_bloom_filter(0).unwrap();
+    assert!((0..100i64).all(|id| ids.check(&id)));
+    assert!(!ids.check(&1000i64));
+    assert!(row_group.get_column_bloom_filter(1).is_none());
+
+    let options =
+        options::parse_options(r#"{"columns": {"id": {"bloom_filter": {"fpp": 1.5}}}}"#).unwrap();
+    assert!(write_records(&compiled, &records, &options).is_err());
+}
+
+#[test]
+fn test_write_parquet_page_index() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![r#"{"id": 1}"#.to_string()];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let column_index = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        let column = reader.metadata().row_group(0).column(0).clone();
+        assert!(column.offset_index_offset().is_some());
+        (
+            column.column_index_offset().is_some(),
+            column.statistics().is_some(),
+        )
+    };
+    assert_eq!(column_index("{}"), (true, true));
+    assert_eq!(column_index(r#"{"page_index": false}"#), (false, true));
+    assert_eq!(
+        column_index(r#"{"page_index": false, "columns": {"id": {"statistics_enabled": "page"}}}"#),
+        (false, true)
+    );
+}
+
+#[test]
+fn test_write_parquet_sorting_columns() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::format::SortingColumn;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            },
+            {
+                "name": "score",
+                "type": "INT32",
+                "repetition_type": "OPTIONAL"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![
+        r#"{"name": "a", "score": 2}"#.to_string(),
+        r#"{"name": "b"}"#.to_string(),
+        r#"{"name": "c", "score": 3}"#.to_string(),
+        r#"{"name": "d", "score": 2}"#.to_string(),
+    ];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(
+        r#"{
+            "sorting_columns": [
+                {"column": "score", "descending": true, "nulls_first": true},
+                {"column": "name", "descending": true}
+            ],
+            "sort_records": true
+        }"#,
+    )
+    .unwrap();
+
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert_eq!(
+        reader.metadata().row_group(0).sorting_columns(),
+        Some(&vec![
+            SortingColumn::new(1, true, true),
+            SortingColumn::new(0, true, false)
+        ])
+    );
+    let names: Vec<serde_json::Value> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value()["name"].clone())
+        .collect();
+    assert_eq!(names, vec!["b", "c", "d", "a"]);
+
+    let options = options::parse_options(r#"{"sorting_columns": [{"column": "nope"}]}"#).unwrap();
+    assert!(write_records(&compiled, &records, &options).is_err());
+}
+
+#[test]
+fn test_write_parquet_footer_metadata() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::format::KeyValue;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![r#"{"id": 1}"#.to_string()];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(
+        r#"{
+            "key_value_metadata": {"source": "billing", "batch_id": "42", "note": null},
+            "created_by": "lakeside 0.3.0 (myapp)"
+        }"#,
+    )
+    .unwrap();
+
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert_eq!(
+        reader.metadata().file_metadata().key_value_metadata(),
+        Some(&vec![
+            KeyValue::new("batch_id".to_string(), "42".to_string()),
+            KeyValue::new("note".to_string(), None),
+            KeyValue::new("source".to_string(), "billing".to_string()),
+        ])
+    );
+    assert_eq!(
+        reader.metadata().file_metadata().created_by(),
+        Some("lakeside 0.3.0 (myapp)")
+    );
+}
+
+#[test]
+fn test_write_parquet_writer_version() {
+    use parquet::basic::PageType;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "active",
+                "type": "BOOLEAN"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = vec![
+        r#"{"id": 1, "active": true}"#.to_string(),
+        r#"{"id": 2, "active": false}"#.to_string(),
+    ];
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let read = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        let mut pages = reader
+            .get_row_group(0)
+            .unwrap()
+            .get_column_page_reader(1)
+            .unwrap();
+        let page_type = pages.get_next_page().unwrap().unwrap().page_type();
+        let rows: Vec<serde_json::Value> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_json_value())
+            .collect();
+        (page_type, rows)
+    };
+    let (page_type, v1_rows) = read("{}");
+    assert_eq!(page_type, PageType::DATA_PAGE);
+    let (page_type, v2_rows) = read(r#"{"writer_version": "2.0"}"#);
+    assert_eq!(page_type, PageType::DATA_PAGE_V2);
+    assert_eq!(v1_rows, v2_rows);
+
+    assert!(options::parse_options(r#"{"writer_version": "3.0"}"#).is_err());
+}
+
+#[test]
+fn test_write_parquet_max_row_group_bytes() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "text",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..500)
+        .map(|i| format!(r#"{{"text": "{:0>40}"}}"#, i))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    // The rows and bytes of each row group
+    let row_groups = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+        reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|rg| (rg.num_rows() as usize, rg.compressed_size() as usize))
+            .collect::<Vec<_>>()
+    };
+    // Row groups are cut by their encoded size, each as close to the limit as its rows allow
+    let sized = row_groups(r#"{"max_row_group_bytes": 4000}"#);
+    assert!(sized.len() > 1);
+    assert_eq!(sized.iter().map(|(rows, _)| rows).sum::<usize>(), 500);
+    assert!(sized.iter().all(|(_, bytes)| *bytes <= 4000));
+    let (_, full) = sized.split_last().unwrap();
+    assert!(full.iter().all(|(_, bytes)| *bytes > 3000));
+
+    let single = row_groups(r#"{"max_row_group_bytes": 1}"#);
+    assert!(single.iter().all(|(rows, _)| *rows == 1));
+    let capped = row_groups(r#"{"max_row_group_bytes": 4000, "max_rows_per_row_group": 20}"#);
+    assert!(capped.iter().all(|(rows, _)| *rows == 20));
+    assert_eq!(
+        row_groups(r#"{"max_row_group_bytes": 1000000}"#),
+        vec![(500, row_groups("{}")[0].1)]
+    );
+
+    // A streamed file's row groups wait until their batches fill them
+    let mut stream =
+        ParquetStreamWriter::create(schema, r#"{"max_row_group_bytes": 4000}"#).unwrap();
+    for batch in files.chunks(30) {
+        stream.append_records(batch).unwrap();
+    }
+    let bytes = stream.finish_file().unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let streamed = reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|rg| (rg.num_rows() as usize, rg.compressed_size() as usize))
+        .collect::<Vec<_>>();
+    assert_eq!(streamed.iter().map(|(rows, _)| rows).sum::<usize>(), 500);
+    assert!(streamed.iter().all(|(_, bytes)| *bytes <= 4000));
+    let (_, full) = streamed.split_last().unwrap();
+    assert!(full.iter().all(|(_, bytes)| *bytes > 3000));
+}
+
+#[test]
+fn test_write_parquet_max_memory_bytes() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "text",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..300)
+        .map(|i| format!(r#"{{"text": "{}"}}"#, "x".repeat(i % 50)))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let sizes: Vec<usize> = records.iter().map(writer::record_bytes).collect();
+
+    // Row groups are cut before their records plus their column chunk pass the limit, whether the
+    // records are parsed up front or a row group at a time, and the peak is what was measured
+    let options = options::parse_options(r#"{"max_memory_bytes": 20000}"#).unwrap();
+    let reports = [
+        write_report(&compiled, &records, &options).unwrap(),
+        write_files_report(&compiled, &files, &options, None, None).unwrap(),
+    ];
+    for report in reports {
+        let reader = SerializedFileReader::new(bytes::Bytes::from(report.bytes.clone())).unwrap();
+        let mut start = 0;
+        let mut memory = vec![];
+        for row_group in reader.metadata().row_groups() {
+            let rows = row_group.num_rows() as usize;
+            let chunk = row_group.column(0).compressed_size() as usize;
+            memory.push(sizes[start..start + rows].iter().sum::<usize>() + chunk);
+            start += rows;
+        }
+        assert_eq!(start, 300);
+        assert!(memory.len() > 1);
+        assert!(memory.iter().all(|memory| *memory <= 20000));
+        assert_eq!(report.peak_memory_bytes, memory.into_iter().max().unwrap());
+    }
+    // Records decoded straight into their fields' values are cut the same way
+    let bytes = write_compiled(&compiled, files.clone(), &options).unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert!(reader.metadata().num_row_groups() > 1);
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 300);
+
+    let options = options::parse_options(r#"{"max_memory_bytes": 0}"#).unwrap();
+    assert_eq!(
+        write_report(&compiled, &records, &options)
+            .unwrap_err()
+            .to_string(),
+        "max_memory_bytes must be greater than 0"
+    );
+}
+
+#[test]
+fn test_write_parquet_encryption() {
+    use crate::encryption::{decrypt_module, module_aad, AesKey, Module, NONCE_LENGTH, TAG_LENGTH};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::format::{
+        ColumnCryptoMetaData, ColumnIndex, ColumnMetaData, EncryptionAlgorithm,
+        EncryptionWithColumnKey, FileMetaData, OffsetIndex, PageHeader, PageType,
+    };
+    use parquet::record::RowAccessor;
+    use parquet::thrift::TSerializable;
+    use thrift::protocol::TCompactInputProtocol;
+
+    fn thrift<T: TSerializable>(mut bytes: &[u8]) -> T {
+        T::read_from_in_protocol(&mut TCompactInputProtocol::new(&mut bytes)).unwrap()
+    }
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "ssn",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = (0..10)
+        .map(|i| format!(r#"{{"id": {}, "ssn": "123-45-678{}"}}"#, i, i))
+        .collect::<Vec<_>>();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(
+        r#"
+        {
+            "encryption": {
+                "footer_key": "AAECAwQFBgcICQoLDA0ODw==",
+                "footer_key_metadata": "footer",
+                "column_keys": {
+                    "ssn": {
+                        "key": "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+                        "key_metadata": "ssn"
+                    }
+                }
+            }
+        }
+        "#,
+    )
+    .unwrap();
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let footer_key = AesKey::new(&(0..16).collect::<Vec<u8>>()).unwrap();
+    let ssn_key = AesKey::new(&(0..32).collect::<Vec<u8>>()).unwrap();
+    assert!(!bytes.windows(11).any(|w| w == b"123-45-6780"));
+
+    // The footer is plaintext, followed by its signature, the nonce and tag of encrypting it
+    assert_eq!(&bytes[..4], b"PAR1");
+    assert_eq!(&bytes[bytes.len() - 4..], b"PAR1");
+    let end = bytes.len() - 8;
+    let length = u32::from_le_bytes(bytes[end..end + 4].try_into().unwrap()) as usize;
+    let (footer, signature) = bytes[end - length..end].split_at(length - NONCE_LENGTH - TAG_LENGTH);
+    let metadata: FileMetaData = thrift(footer);
+    let Some(EncryptionAlgorithm::AESGCMV1(algorithm)) = &metadata.encryption_algorithm else {
+        panic!("Footer has no encryption algorithm");
+    };
+    let file_unique = algorithm.aad_file_unique.clone().unwrap();
+    assert_eq!(
+        metadata.footer_signing_key_metadata,
+        Some(b"footer".to_vec())
+    );
+    let (nonce, tag) = signature.split_at(NONCE_LENGTH);
+    let mut footer_aad = file_unique.clone();
+    footer_aad.push(Module::Footer as u8);
+    let sealed = footer_key
+        .encrypt(nonce.try_into().unwrap(), footer, &footer_aad)
+        .unwrap();
+    assert_eq!(&sealed[footer.len()..], tag);
+
+    // The id column is plaintext, and the ssn column's metadata is encrypted with a copy left
+    // without its statistics
+    let id = &metadata.row_groups[0].columns[0];
+    assert!(id.crypto_metadata.is_none() && id.encrypted_column_metadata.is_none());
+    let ssn = &metadata.row_groups[0].columns[1];
+    assert_eq!(
+        ssn.crypto_metadata,
+        Some(ColumnCryptoMetaData::ENCRYPTIONWITHCOLUMNKEY(
+            EncryptionWithColumnKey {
+                path_in_schema: vec!["ssn".to_string()],
+                key_metadata: Some(b"ssn".to_vec()),
+            }
+        ))
+    );
+    let aad = |module, page| module_aad(&file_unique, module, 0, 1, page).unwrap();
+    let (column_metadata, _) = decrypt_module(
+        &ssn_key,
+        &aad(Module::ColumnMetaData, 0),
+        ssn.encrypted_column_metadata.as_ref().unwrap(),
+    )
+    .unwrap();
+    let column_metadata: ColumnMetaData = thrift(&column_metadata);
+    assert!(column_metadata.statistics.is_some());
+    let redacted = ssn.meta_data.clone().unwrap();
+    assert!(redacted.statistics.is_none());
+    assert_eq!(
+        redacted,
+        ColumnMetaData {
+            statistics: None,
+            encoding_stats: None,
+            ..column_metadata.clone()
+        }
+    );
+
+    // Each page header and page is a module of its own, with the dictionary page first
+    let mut offset = column_metadata.dictionary_page_offset.unwrap() as usize;
+    let mut pages = vec![];
+    for (header_module, page_module) in [
+        (Module::DictionaryPageHeader, Module::DictionaryPage),
+        (Module::DataPageHeader, Module::DataPage),
+    ] {
+        let (header, length) =
+            decrypt_module(&ssn_key, &aad(header_module, 0), &bytes[offset..]).unwrap();
+        let header: PageHeader = thrift(&header);
+        offset += length;
+        let page = &bytes[offset..offset + header.compressed_page_size as usize];
+        assert_eq!(header.crc, Some(crc32fast::hash(page) as i32));
+        let (page, length) = decrypt_module(&ssn_key, &aad(page_module, 0), page).unwrap();
+        assert_eq!(length, header.compressed_page_size as usize);
+        assert_eq!(page.len(), header.uncompressed_page_size as usize);
+        offset += length;
+        pages.push((header.type_, page));
+    }
+    assert_eq!(
+        offset as i64,
+        column_metadata.dictionary_page_offset.unwrap() + column_metadata.total_compressed_size
+    );
+    // The plain encoded dictionary, each value's length followed by its bytes
+    let (page_type, dictionary) = &pages[0];
+    assert_eq!(*page_type, PageType::DICTIONARY_PAGE);
+    let mut values = vec![];
+    let mut rest = &dictionary[..];
+    while !rest.is_empty() {
+        let length = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+        values.push(String::from_utf8(rest[4..4 + length].to_vec()).unwrap());
+        rest = &rest[4 + length..];
+    }
+    assert_eq!(
+        values,
+        (0..10)
+            .map(|i| format!("123-45-678{}", i))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(pages[1].0, PageType::DATA_PAGE);
+
+    // So is the column's page index
+    let (index, _) = decrypt_module(
+        &ssn_key,
+        &aad(Module::ColumnIndex, 0),
+        &bytes[ssn.column_index_offset.unwrap() as usize..],
+    )
+    .unwrap();
+    let index: ColumnIndex = thrift(&index);
+    assert_eq!(index.min_values, vec![b"123-45-6780".to_vec()]);
+    let (index, _) = decrypt_module(
+        &ssn_key,
+        &aad(Module::OffsetIndex, 0),
+        &bytes[ssn.offset_index_offset.unwrap() as usize..],
+    )
+    .unwrap();
+    let index: OffsetIndex = thrift(&index);
+    assert_eq!(
+        index.page_locations[0].offset,
+        column_metadata.data_page_offset
+    );
+
+    // Readers without the keys can still read the plaintext column
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let projection =
+        parquet::schema::parser::parse_message_type("message schema { REQUIRED INT64 id; }")
+            .unwrap();
+    let ids = reader
+        .get_row_iter(Some(projection))
+        .unwrap()
+        .map(|row| row.unwrap().get_long(0).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(ids, (0..10).collect::<Vec<_>>());
+
+    for (options, error) in [
+        (
+            r#"{"encryption": {"column_keys": {"ssn": {"key": "AAECAwQFBgcICQoLDA0ODw=="}}}}"#,
+            "Encryption needs a footer_key to sign the footer with",
+        ),
+        (
+            r#"{"encryption": {"footer_key": "AAECAwQFBgcICQoLDA0ODw==", "column_keys": {"name": {"key": "AAECAwQFBgcICQoLDA0ODw=="}}}}"#,
+            "Unknown field name in column_keys",
+        ),
+        (
+            r#"{"encryption": {"footer_key": "AAECAwQFBgcICQoLDA0ODw==", "column_keys": {"ssn": {"key": "AAECAw=="}}}}"#,
+            "Invalid key for field ssn: AES keys must be 16, 24 or 32 bytes, got 4",
+        ),
+        (
+            r#"{"encryption": {"footer_key": "AAECAwQFBgcICQoLDA0ODw=="}, "columns": {"ssn": {"bloom_filter": {}}}}"#,
+            "Bloom filters can't be written to encrypted files, but column ssn has one",
+        ),
+    ] {
+        let options = options::parse_options(options).unwrap();
+        assert_eq!(
+            write_records(&compiled, &records, &options)
+                .unwrap_err()
+                .to_string(),
+            error
+        );
+    }
+}
+
+#[test]
+fn test_write_parquet_encrypted_footer() {
+    use crate::encryption::{decrypt_module, module_aad, AesKey, Module};
+    use parquet::file::reader::SerializedFileReader;
+    use parquet::format::{
+        ColumnCryptoMetaData, ColumnMetaData, EncryptionAlgorithm, EncryptionWithColumnKey,
+        EncryptionWithFooterKey, FileCryptoMetaData, FileMetaData, PageHeader,
+    };
+    use parquet::thrift::TSerializable;
+    use thrift::protocol::TCompactInputProtocol;
+
+    fn thrift<T: TSerializable>(bytes: &mut &[u8]) -> T {
+        T::read_from_in_protocol(&mut TCompactInputProtocol::new(bytes)).unwrap()
+    }
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "ssn",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = (0..10)
+        .map(|i| format!(r#"{{"id": {}, "ssn": "123-45-678{}"}}"#, i, i))
+        .collect::<Vec<_>>();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(
+        r#"
+        {
+            "encryption": {
+                "footer_key": "AAECAwQFBgcICQoLDA0ODw==",
+                "footer_key_metadata": "footer",
+                "encrypted_footer": true,
+                "column_keys": {
+                    "ssn": {
+                        "key": "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+                        "key_metadata": "ssn"
+                    }
+                }
+            }
+        }
+        "#,
+    )
+    .unwrap();
+    let bytes = write_records(&compiled, &records, &options).unwrap();
+    let footer_key = AesKey::new(&(0..16).collect::<Vec<u8>>()).unwrap();
+    let ssn_key = AesKey::new(&(0..32).collect::<Vec<u8>>()).unwrap();
+    assert!(!bytes.windows(11).any(|w| w == b"123-45-6780"));
+    assert!(SerializedFileReader::new(bytes::Bytes::from(bytes.clone())).is_err());
+
+    // The plaintext crypto metadata is followed by the footer, encrypted with the footer key
+    assert_eq!(&bytes[..4], b"PARE");
+    assert_eq!(&bytes[bytes.len() - 4..], b"PARE");
+    let end = bytes.len() - 8;
+    let length = u32::from_le_bytes(bytes[end..end + 4].try_into().unwrap()) as usize;
+    let mut footer = &bytes[end - length..end];
+    let crypto_metadata: FileCryptoMetaData = thrift(&mut footer);
+    assert_eq!(crypto_metadata.key_metadata, Some(b"footer".to_vec()));
+    let EncryptionAlgorithm::AESGCMV1(algorithm) = crypto_metadata.encryption_algorithm else {
+        panic!("Footer has no AES-GCM encryption algorithm");
+    };
+    let file_unique = algorithm.aad_file_unique.unwrap();
+    let mut footer_aad = file_unique.clone();
+    footer_aad.push(Module::Footer as u8);
+    let (metadata, length) = decrypt_module(&footer_key, &footer_aad, footer).unwrap();
+    assert_eq!(length, footer.len());
+    let metadata: FileMetaData = thrift(&mut &metadata[..]);
+    assert_eq!(metadata.num_rows, 10);
+    assert!(metadata.encryption_algorithm.is_none());
+
+    // The id column has no key of its own, so it's encrypted with the footer key and its metadata
+    // is only in the encrypted footer
+    let id = &metadata.row_groups[0].columns[0];
+    assert_eq!(
+        id.crypto_metadata,
+        Some(ColumnCryptoMetaData::ENCRYPTIONWITHFOOTERKEY(
+            EncryptionWithFooterKey {}
+        ))
+    );
+    assert!(id.encrypted_column_metadata.is_none());
+    let id_metadata = id.meta_data.as_ref().unwrap();
+    assert!(id_metadata.statistics.is_some());
+    let mut offset = id_metadata.dictionary_page_offset.unwrap() as usize;
+    let aad = |module, column| module_aad(&file_unique, module, 0, column, 0).unwrap();
+    let (header, length) = decrypt_module(
+        &footer_key,
+        &aad(Module::DictionaryPageHeader, 0),
+        &bytes[offset..],
+    )
+    .unwrap();
+    let header: PageHeader = thrift(&mut &header[..]);
+    offset += length;
+    let (page, _) = decrypt_module(
+        &footer_key,
+        &aad(Module::DictionaryPage, 0),
+        &bytes[offset..],
+    )
+    .unwrap();
+    assert_eq!(page.len(), header.uncompressed_page_size as usize);
+    assert_eq!(page[..8], 0i64.to_le_bytes());
+
+    // The ssn column has its own key, and its metadata is only in its encrypted metadata
+    let ssn = &metadata.row_groups[0].columns[1];
+    assert_eq!(
+        ssn.crypto_metadata,
+        Some(ColumnCryptoMetaData::ENCRYPTIONWITHCOLUMNKEY(
+            EncryptionWithColumnKey {
+                path_in_schema: vec!["ssn".to_string()],
+                key_metadata: Some(b"ssn".to_vec()),
+            }
+        ))
+    );
+    assert!(ssn.meta_data.is_none());
+    let (ssn_metadata, _) = decrypt_module(
+        &ssn_key,
+        &aad(Module::ColumnMetaData, 1),
+        ssn.encrypted_column_metadata.as_ref().unwrap(),
+    )
+    .unwrap();
+    let ssn_metadata: ColumnMetaData = thrift(&mut &ssn_metadata[..]);
+    assert_eq!(ssn_metadata.num_values, 10);
+    let offset = ssn_metadata.dictionary_page_offset.unwrap() as usize;
+    assert!(decrypt_module(
+        &ssn_key,
+        &aad(Module::DictionaryPageHeader, 1),
+        &bytes[offset..]
+    )
+    .is_some());
+}
+
+#[test]
+fn test_write_parquet_encryption_ordinals() {
+    use crate::encryption::FileEncryptor;
+    use parquet::schema::parser::parse_message_type;
+    use parquet::schema::types::SchemaDescriptor;
+
+    // Column ordinals past i16::MAX can't be written into module AADs, rather than wrapping
+    let columns = (0..=i16::MAX as usize + 1)
+        .map(|i| format!("required int32 c{};", i))
+        .collect::<String>();
+    let schema = parse_message_type(&format!("message schema {{ {} }}", columns)).unwrap();
+    let schema = SchemaDescriptor::new(Arc::new(schema));
+    let options = options::parse_options(
+        r#"{"encryption": {"footer_key": "AAECAwQFBgcICQoLDA0ODw==", "encrypted_footer": true}}"#,
+    )
+    .unwrap();
+    let encryptor =
+        FileEncryptor::new(options.encryption.as_ref().unwrap(), schema.columns()).unwrap();
+    assert!(encryptor.column(0, i16::MAX as usize).unwrap().is_some());
+    let Err(error) = encryptor.column(0, i16::MAX as usize + 1) else {
+        panic!("Column ordinal past i16::MAX was encrypted");
+    };
+    assert_eq!(error.to_string(), "Too many columns to encrypt");
+    let Err(error) = encryptor.column(i16::MAX as usize + 1, 0) else {
+        panic!("Row group ordinal past i16::MAX was encrypted");
+    };
+    assert_eq!(error.to_string(), "Too many row groups to encrypt");
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_write_parquet_encryption_read_by_parquet() {
+    use arrow_array_decrypt::cast::AsArray;
+    use arrow_array_decrypt::types::Int64Type;
+    use parquet_decrypt::arrow::arrow_reader::{
+        ArrowReaderOptions, ParquetRecordBatchReaderBuilder,
+    };
+    use parquet_decrypt::encryption::decrypt::FileDecryptionProperties;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "ssn",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files = (0..10)
+        .map(|i| format!(r#"{{"id": {}, "ssn": "123-45-678{}"}}"#, i, i))
+        .collect::<Vec<_>>();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    // Parquet's own reader decrypts the file with either footer mode
+    for encrypted_footer in [false, true] {
+        let options = options::parse_options(&format!(
+            r#"
+            {{
+                "encryption": {{
+                    "footer_key": "AAECAwQFBgcICQoLDA0ODw==",
+                    "encrypted_footer": {},
+                    "column_keys": {{
+                        "ssn": {{
+                            "key": "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8="
+                        }}
+                    }}
+                }}
+            }}
+            "#,
+            encrypted_footer
+        ))
+        .unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let decryption = FileDecryptionProperties::builder((0..16).collect())
+            .with_column_key("ssn", (0..32).collect())
+            .build()
+            .unwrap();
+        let options = ArrowReaderOptions::new().with_file_decryption_properties(decryption);
+        let batches = ParquetRecordBatchReaderBuilder::try_new_with_options(
+            bytes::Bytes::from(bytes),
+            options,
+        )
+        .unwrap()
+        .build()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(batches.len(), 1);
+        let ids = batches[0].column(0).as_primitive::<Int64Type>();
+        assert_eq!(ids.values().to_vec(), (0..10).collect::<Vec<i64>>());
+        let ssns = batches[0].column(1).as_string::<i32>();
+        assert_eq!(ssns.value(7), "123-45-6787");
+    }
+}
+
+#[test]
+fn test_write_parquet_page_checksums() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::format::PageHeader;
+    use parquet::thrift::TSerializable;
+    use thrift::protocol::TCompactInputProtocol;
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "tags",
+                "type": "BYTE_ARRAY",
+                "logical_type": "LIST",
+                "element_logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..5)
+        .map(|id| format!(r#"{{"id": {}, "tags": ["t{}", "x"]}}"#, id, id))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    // The CRC of the first page of each column, checked against the page's data
+    let first_page_crcs = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        let bytes = write_records(&compiled, &records, &options).unwrap();
+        let reader = SerializedFileReader::new(bytes::Bytes::from(bytes.clone())).unwrap();
+        let row_group = reader.metadata().row_group(0).clone();
+        let crcs = row_group
+            .columns()
+            .iter()
+            .map(|column| {
+                let (start, _) = column.byte_range();
+                let mut page = &bytes[start as usize..];
+                let header =
+                    PageHeader::read_from_in_protocol(&mut TCompactInputProtocol::new(&mut page))
+                        .unwrap();
+                let data = &page[..header.compressed_page_size as usize];
+                if let Some(crc) = header.crc {
+                    assert_eq!(crc, crc32fast::hash(data) as i32);
+                }
+                header.crc
+            })
+            .collect::<Vec<_>>();
+        let rows: Vec<serde_json::Value> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| row.unwrap().to_json_value())
+            .collect();
+        (crcs, rows)
+    };
+
+    let (crcs, with_checksums) = first_page_crcs(r#"{"max_rows_per_row_group": 2}"#);
+    assert!(crcs.iter().all(Option::is_some));
+    let (crcs, without_checksums) =
+        first_page_crcs(r#"{"max_rows_per_row_group": 2, "page_checksums": false}"#);
+    assert!(crcs.iter().all(Option::is_none));
+    assert_eq!(with_checksums, without_checksums);
+    assert_eq!(with_checksums.len(), 5);
+}
+
+#[test]
+fn test_write_parquet_report_timings() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "attributes",
+                "type": "BYTE_ARRAY",
+                "logical_type": "MAP",
+                "element_logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..1000)
+        .map(|id| format!(r#"{{"id": {}, "attributes": {{"a": "{}"}}}}"#, id, id))
+        .collect();
+    let (records, parse_millis) = parse_records_timed(&files, Default::default()).unwrap();
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 100}"#).unwrap();
+    let report = write_report(&compiled, &records, &options)
+        .unwrap()
+        .parsed_in(parse_millis);
+
+    let timings = &report.timings;
+    assert_eq!(
+        timings
+            .columns
+            .iter()
+            .map(|c| c.column.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "id",
+            "attributes.key_value.key",
+            "attributes.key_value.value"
+        ]
+    );
+    assert_eq!(timings.parse_millis, parse_millis);
+    let encode_millis: f64 = timings.columns.iter().map(|c| c.encode_millis).sum();
+    let parts = timings.parse_millis + timings.prepare_millis + timings.lookup_millis;
+    assert!(timings.columns.iter().all(|c| c.encode_millis >= 0.0));
+    // Columns are encoded at the same time with the `threads` feature
+    let encode_millis = if cfg!(feature = "threads") {
+        0.0
+    } else {
+        encode_millis
+    };
+    assert!(parts + encode_millis <= timings.total_millis);
+}
+
+#[test]
+fn test_write_parquet_report_dictionary_fallback() {
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT64"
+            },
+            {
+                "name": "level",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#;
+    let compiled = CompiledSchema::parse(schema).unwrap();
+    let files: Vec<String> = (0..100)
+        .map(|id| {
+            format!(
+                r#"{{"id": {}, "level": "{}"}}"#,
+                id,
+                ["INFO", "WARN"][id % 2]
+            )
+        })
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+
+    let columns = |options: &str| {
+        let options = options::parse_options(options).unwrap();
+        write_report(&compiled, &records, &options).unwrap().columns
+    };
+    let report =
+        |column: &str, encodings: &[&str], dictionary_fallback: bool| pages::ColumnReport {
+            column: column.to_string(),
+            encodings: encodings.iter().map(|e| e.to_string()).collect(),
+            dictionary_fallback,
+        };
+
+    assert_eq!(
+        columns("{}"),
+        vec![
+            report("id", &["RLE_DICTIONARY"], false),
+            report("level", &["RLE_DICTIONARY"], false),
+        ]
+    );
+    // A dictionary of 20 bytes fills after a few ids, but both levels fit in 16
+    assert_eq!(
+        columns(r#"{"dictionary_page_size_limit": 20, "write_batch_size": 1}"#),
+        vec![
+            report("id", &["RLE_DICTIONARY", "PLAIN"], true),
+            report("level", &["RLE_DICTIONARY"], false),
+        ]
+    );
+    assert_eq!(
+        columns(r#"{"dictionary_enabled": false, "page_checksums": false}"#),
+        vec![
+            report("id", &["PLAIN"], false),
+            report("level", &["PLAIN"], false)
+        ]
+    );
+}
+
+#[test]
+fn test_parquet_stream_writer() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            }
+        ]
+    }
+    "#;
+    let batch = |ids: std::ops::Range<i32>| {
+        ids.map(|id| format!(r#"{{"id": {}}}"#, id % 7))
+            .collect::<Vec<_>>()
+    };
+    let mut stream = ParquetStreamWriter::new(
+        schema.to_string(),
+        r#"{"max_rows_per_row_group": 3, "dedupe_on": ["id"]}"#.to_string(),
+    )
+    .unwrap();
+    stream.append_records(&batch(0..4)).unwrap();
+    assert_eq!(stream.buffered.len(), 1);
+    stream.append_records(&batch(4..10)).unwrap();
+    assert_eq!(stream.buffered.len(), 1);
+    let bytes = stream.finish_file().unwrap();
+    assert!(stream.append_records(&batch(0..1)).is_err());
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let row_counts: Vec<i64> = reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|rg| rg.num_rows())
+        .collect();
+    assert_eq!(row_counts, vec![3, 3, 1]);
+    let ids: Vec<serde_json::Value> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value()["id"].clone())
+        .collect();
+    assert_eq!(ids, (0..7).collect::<Vec<i32>>());
+
+    let mut stream = ParquetStreamWriter::new(schema.to_string(), "{}".to_string()).unwrap();
+    let bytes = stream.finish_file().unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert_eq!(reader.metadata().num_row_groups(), 1);
+    assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+}
+
+#[test]
+fn test_parquet_stream_writer_byte_values() {
+    use crate::convert::UINT8_ARRAY_KEY;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    let schema = r#"{"fields": [{"name": "payload", "type": "BYTE_ARRAY"}]}"#;
+    let mut stream = ParquetStreamWriter::new(
+        schema.to_string(),
+        r#"{"max_rows_per_row_group": 2}"#.to_string(),
+    )
+    .unwrap();
+    // As ParquetSink::write appends arrays of records holding Uint8Arrays
+    let append = |stream: &mut ParquetStreamWriter, payloads: std::ops::Range<u8>| {
+        let records = payloads
+            .map(|payload| {
+                stream.options.byte_values.push(vec![payload; 3]);
+                let index = stream.options.byte_values.len() - 1;
+                let mut record = writer::Record::new();
+                record.insert(
+                    "payload".to_string(),
+                    serde_json::json!({UINT8_ARRAY_KEY: index}),
+                );
+                record
+            })
+            .collect();
+        stream.append_parsed(records).unwrap();
+    };
+    append(&mut stream, 0..3);
+    // Only the bytes of the record still buffered are kept
+    assert_eq!(stream.options.byte_values, [vec![2; 3]]);
+    append(&mut stream, 3..4);
+    assert_eq!(stream.options.byte_values, [vec![2; 3], vec![3; 3]]);
+    append(&mut stream, 4..5);
+    assert_eq!(stream.options.byte_values, [vec![4; 3]]);
+    let bytes = stream.finish_file().unwrap();
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    let payloads: Vec<Vec<u8>> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().get_bytes(0).unwrap().data().to_vec())
+        .collect();
+    assert_eq!(payloads, (0..5).map(|p| vec![p; 3]).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_chunked_file() {
+    let schema = CompiledSchema::parse(
+        r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "payload",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#,
+    )
+    .unwrap();
+    let files: Vec<String> = (0..10)
+        .map(|id| format!(r#"{{"id": {}, "payload": "{:0>4000}"}}"#, id, id))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 4}"#).unwrap();
+
+    let mut file = ChunkedFile::new(&schema, records.clone(), options).unwrap();
+    let mut chunks = vec![];
+    while let Some(chunk) = file.next_chunk().unwrap() {
+        chunks.push(chunk);
+    }
+    // Row groups of 16KB are bigger than the writer's buffer, so each is flushed as it's written
+    assert_eq!(chunks.len(), 4);
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 4}"#).unwrap();
+    assert_eq!(
+        chunks.concat(),
+        write_records(&schema, &records, &options).unwrap()
+    );
+
+    let file = ChunkedFile::new(&schema, records, options).unwrap();
+    let mut calls = 0;
+    let result = file.write_to(|_| {
+        calls += 1;
+        Err(WriteError::from("sink closed".to_string()))
+    });
+    assert_eq!(result.unwrap_err().to_string(), "sink closed");
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn test_parquet_sink() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let schema = r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            }
+        ]
+    }
+    "#;
+    let mut sink = ParquetSink::new(
+        schema.to_string(),
+        r#"{"max_rows_per_row_group": 2}"#.to_string(),
+    )
+    .unwrap();
+    sink.write_lines(b"{\"id\": 1}\n{\"i").unwrap();
+    sink.write_lines(b"d\": 2}\n\n{\"id\"").unwrap();
+    sink.write_lines(b": 3}").unwrap();
+    let bytes = sink.close_file().unwrap();
+    assert_eq!(sink.file, Some(bytes.clone()));
+
+    let reader = SerializedFileReader::new(bytes::Bytes::from(bytes)).unwrap();
+    assert_eq!(reader.metadata().num_row_groups(), 2);
+    let ids: Vec<serde_json::Value> = reader
+        .get_row_iter(None)
+        .unwrap()
+        .map(|row| row.unwrap().to_json_value()["id"].clone())
+        .collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    let mut sink = ParquetSink::new(schema.to_string(), "{}".to_string()).unwrap();
+    sink.write_lines(b"{\"id\": 1}\n\n").unwrap();
+    assert_eq!(
+        sink.write_lines(b"{\"id\": \n").unwrap_err().to_string(),
+        "Line 3: error parsing record"
+    );
+}
+
+#[test]
+fn test_write_parquet_progress() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let schema = CompiledSchema::parse(
+        r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            },
+            {
+                "name": "name",
+                "type": "BYTE_ARRAY",
+                "logical_type": "UTF8"
+            }
+        ]
+    }
+    "#,
+    )
+    .unwrap();
+    let files: Vec<String> = (0..5)
+        .map(|id| format!(r#"{{"id": {}, "name": "n{}"}}"#, id, id))
+        .collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 3}"#).unwrap();
+
+    let calls = Rc::new(RefCell::new(vec![]));
+    let recorded = calls.clone();
+    let callback: writer::ProgressCallback = Box::new(move |progress| {
+        recorded.borrow_mut().push(progress);
+        Ok(())
+    });
+    let report =
+        write_report_with_progress(&schema, &records, &options, Some((2, callback)), None).unwrap();
+    let calls = calls.borrow();
+    let counts: Vec<(usize, usize)> = calls
+        .iter()
+        .map(|p| (p.records_processed, p.columns_written))
+        .collect();
+    // A row group's records count once all of its columns are written
+    assert_eq!(counts, vec![(0, 2), (3, 4), (5, 4)]);
+    assert_eq!(calls.last().unwrap().bytes_written, report.bytes.len());
+
+    let callback: writer::ProgressCallback =
+        Box::new(|_| Err(WriteError::from("cancelled".to_string())));
+    assert_eq!(
+        write_report_with_progress(&schema, &records, &options, Some((1, callback)), None)
+            .unwrap_err()
+            .to_string(),
+        "cancelled"
+    );
+    let callback: writer::ProgressCallback = Box::new(|_| Ok(()));
+    assert!(
+        write_report_with_progress(&schema, &records, &options, Some((0, callback)), None).is_err()
+    );
+}
+
+#[test]
+fn test_write_parquet_cancelled() {
+    let schema = CompiledSchema::parse(
+        r#"
+    {
+        "fields": [
+            {
+                "name": "id",
+                "type": "INT32"
+            }
+        ]
+    }
+    "#,
+    )
+    .unwrap();
+    let files: Vec<String> = (0..5).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let records = writer::parse_records(&files, Default::default()).unwrap();
+    let options = options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+
+    // Cancelling from the progress callback stops the write before the next row group
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    let columns_written = std::rc::Rc::new(std::cell::Cell::new(0));
+    let written = columns_written.clone();
+    let callback: writer::ProgressCallback = Box::new(move |progress| {
+        written.set(progress.columns_written);
+        cancel.cancel();
+        Ok(())
+    });
+    let result = write_report_with_progress(
+        &schema,
+        &records,
+        &options,
+        Some((1, callback)),
+        Some(token.cancelled.clone()),
+    );
+    assert_eq!(result.unwrap_err(), WriteError::Cancelled);
+    assert_eq!(columns_written.get(), 1);
+    assert!(token.cancelled());
+
+    let mut stream = ParquetStreamWriter::new(
+        r#"{"fields": [{"name": "id", "type": "INT32"}]}"#.to_string(),
+        "{}".to_string(),
+    )
+    .unwrap();
+    stream.cancel_with(&token);
+    assert_eq!(
+        stream.append_records(&files).unwrap_err(),
+        WriteError::Cancelled
     );
 }
@@ -0,0 +1,705 @@
+use crate::filter::{Condition, Predicate};
+use crate::ParquetField;
+use parquet::basic::{Compression, Encoding, GzipLevel, Type as PhysicalType};
+use parquet::file::properties::{
+    EnabledStatistics, WriterProperties, WriterPropertiesBuilder, WriterVersion,
+};
+use parquet::format::{KeyValue, SortingColumn};
+use parquet::schema::types::{ColumnDescPtr, ColumnPath, SchemaDescriptor, TypePtr};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Options controlling how records are written, passed to `generate_parquet_with_options` as a
+/// JSON object. Every option is optional and defaults to the behaviour of `generate_parquet`.
+///
+/// Records given as JSON strings are decoded skipping the keys no field reads, unless
+/// `case_insensitive_keys`, `infer_optional`, `flatten`, `filter`, `dedupe_on`, `sort_records`
+/// with `sorting_columns` or a `lone_surrogates` of `binary` is set, or a field is computed or
+/// read from a nested `source_key` or alias. Those parse each record into a whole JSON object
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct WriterOptions {
+    /// Match JSON keys to fields (and their aliases) ignoring ASCII case
+    pub(crate) case_insensitive_keys: bool,
+    /// Scan the records before writing and make REQUIRED fields OPTIONAL when any record is
+    /// missing the value or has it set to null
+    pub(crate) infer_optional: bool,
+    /// Fail with a structured error on any value that can't be written as its field's type,
+    /// instead of writing a null to OPTIONAL fields
+    pub(crate) strict: bool,
+    /// Which values may be converted to a field's type when they're written as another
+    pub(crate) coercion: CoercionOptions,
+    /// Expand nested objects into top level columns before writing
+    pub(crate) flatten: Option<FlattenOptions>,
+    /// What to do with unpaired UTF-16 surrogates in strings, which aren't valid UTF-8
+    pub(crate) lone_surrogates: LoneSurrogatePolicy,
+    /// What to do with NaN and infinite values of FLOAT and DOUBLE fields, given as the strings
+    /// `"NaN"`, `"Infinity"` and `"-Infinity"` or as numbers through `generate_parquet_from_js`
+    pub(crate) non_finite: NonFinitePolicy,
+    /// Drop records whose values for these fields match an earlier record's, keeping the first
+    pub(crate) dedupe_on: Option<Vec<String>>,
+    /// Only write the records meeting all of these conditions
+    pub(crate) filter: Option<Vec<Condition>>,
+    /// Start a new row group after this many records, by default every record is written to a
+    /// single row group
+    pub(crate) max_rows_per_row_group: Option<usize>,
+    /// Start a new row group before its encoded column chunks pass this many bytes, bounding the
+    /// column data buffered at once. A row group's records are predicted from the bytes per row
+    /// of the chunks encoded so far, and encoded again with fewer records when they come out too
+    /// big. A single record bigger than this gets a row group of its own. Applies alongside
+    /// `max_rows_per_row_group`
+    pub(crate) max_row_group_bytes: Option<usize>,
+    /// Start a new row group before the memory it needs, its parsed records plus their encoded
+    /// column chunks, passes this many bytes. Records are counted from their parsed values and
+    /// chunks from the bytes per row of those encoded so far, encoding again with fewer records
+    /// when they come out bigger. Records are parsed a row group at a time, unless
+    /// `sort_records`, `infer_optional` or `flatten.infer_fields` need them all up front, and the
+    /// report gives the peak measured
+    pub(crate) max_memory_bytes: Option<usize>,
+    /// The size in bytes the output is expected to reach, allocated up front so a large file isn't
+    /// copied each time its buffer grows. Without it the fixed width columns' plain encoded size is
+    /// allocated
+    pub(crate) estimated_output_bytes: Option<usize>,
+    /// Start a new file once the next row group is expected to take the current one past this many
+    /// bytes, e.g. to stay under ArrayBuffer size limits. Row groups are cut at this size as with
+    /// `max_row_group_bytes`, a row group is expected to be the size of the one before it and is
+    /// never split across files, and the page index and footer aren't counted so leave room for
+    /// them. Used by `generate_parquet_split` and `generate_partitioned`
+    pub(crate) max_file_bytes: Option<usize>,
+    /// Start a new file once the current one has this many records, ending its last row group
+    /// early when needed. Used by `generate_parquet_split` and `generate_partitioned`
+    pub(crate) max_rows_per_file: Option<usize>,
+    /// Leave the `partition_by` fields out of the files, as their values are in the files' paths.
+    /// Only used by `generate_partitioned`
+    pub(crate) drop_partition_columns: bool,
+    /// How each partition field's directory is named by `generate_partitioned`, with `{col}` for
+    /// the field's name and `{value}` for its escaped value, by default `{col}={value}`
+    pub(crate) partition_template: Option<String>,
+    /// How the files in each partition are named by `generate_partitioned`, with `{seq}` for the
+    /// file's index in the partition and `{uuid}` for a UUID shared by every file of the write. A
+    /// width after a colon zero-pads the value, e.g. the default `part-{seq:04}.parquet`
+    pub(crate) file_template: Option<String>,
+    /// The size in bytes a data page is closed at, checked after every `write_batch_size` values
+    pub(crate) data_page_size_limit: Option<usize>,
+    /// How many values are written to a column at a time, and so how often page limits are checked
+    pub(crate) write_batch_size: Option<usize>,
+    /// The number of rows a data page is closed at, checked after every `write_batch_size` values
+    pub(crate) data_page_row_count_limit: Option<usize>,
+    /// Whether columns are dictionary encoded, on by default
+    pub(crate) dictionary_enabled: Option<bool>,
+    /// The codec every column's pages are compressed with, uncompressed by default
+    pub(crate) compression: Option<Codec>,
+    /// The size in bytes a column's dictionary can grow to before the column falls back to its
+    /// `encoding`, PLAIN by default
+    pub(crate) dictionary_page_size_limit: Option<usize>,
+    /// Which min/max statistics are written, for pages and column chunks by default
+    pub(crate) statistics_enabled: Option<StatisticsLevel>,
+    /// Truncate min/max values of binary and string columns, in statistics and the page index, to
+    /// this many bytes
+    pub(crate) statistics_truncate_length: Option<usize>,
+    /// Whether the column index of page min/max values is written for columns with page
+    /// statistics, on by default. Off caps statistics at the column chunk. The offset index of page
+    /// locations is always written
+    pub(crate) page_index: Option<bool>,
+    /// The columns the records of each row group are sorted by, recorded in the row group metadata
+    pub(crate) sorting_columns: Option<Vec<SortingColumnOptions>>,
+    /// Sort the records by `sorting_columns` before writing, rather than trusting they already are
+    pub(crate) sort_records: bool,
+    /// Entries written to the file footer, e.g. a source system or batch ID, in key order
+    pub(crate) key_value_metadata: Option<BTreeMap<String, Option<String>>>,
+    /// The application written to the footer as the file's producer, parquet-rs's version string
+    /// by default
+    pub(crate) created_by: Option<String>,
+    /// Whether each page header has a CRC32 checksum of the page for readers to detect corruption
+    /// with, on by default
+    pub(crate) page_checksums: Option<bool>,
+    /// The version of the data page format written
+    pub(crate) writer_version: ParquetVersion,
+    /// Encrypt the pages, page index and metadata of columns with parquet modular encryption,
+    /// AES-GCM. Unless `encrypted_footer` is set the footer stays readable, so columns without keys
+    /// can still be read, but it's signed with the footer key. Encrypted files can't have bloom
+    /// filters
+    pub(crate) encryption: Option<EncryptionOptions>,
+    /// Options for the columns of individual fields, by field name, which take precedence over the
+    /// file level options
+    pub(crate) columns: HashMap<String, ColumnOptions>,
+    /// The bytes of the `Uint8Array` values `generate_parquet_from_js` and `ParquetSink` read,
+    /// which records refer to by index under `convert::UINT8_ARRAY_KEY` rather than holding them
+    /// as JSON. Always empty for records parsed from JSON strings
+    #[serde(skip)]
+    pub(crate) byte_values: Vec<Vec<u8>>,
+}
+
+/// Writer options for the columns of a single field, a LIST field's elements or a MAP field's keys
+/// and values
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ColumnOptions {
+    pub(crate) dictionary_enabled: Option<bool>,
+    pub(crate) compression: Option<Codec>,
+    /// How values are encoded in data pages, or once the dictionary is full when dictionary
+    /// encoding is on
+    pub(crate) encoding: Option<ColumnEncoding>,
+    pub(crate) statistics_enabled: Option<StatisticsLevel>,
+    /// Write a bloom filter of the column's values, for engines to skip row groups on point lookups
+    pub(crate) bloom_filter: Option<BloomFilterOptions>,
+}
+
+/// Versions of the parquet format data pages can be written in
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub(crate) enum ParquetVersion {
+    /// V1 data pages, which every reader supports
+    #[default]
+    #[serde(rename = "1.0")]
+    V1,
+    /// V2 data pages, with newer default encodings such as RLE for booleans and delta encodings
+    /// when dictionary encoding falls back
+    #[serde(rename = "2.0")]
+    V2,
+}
+
+/// Parquet modular encryption keys, each a base64 AES key of 16, 24 or 32 bytes
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct EncryptionOptions {
+    /// The key the footer is signed, or encrypted, with, required
+    pub(crate) footer_key: Option<String>,
+    /// Written to the footer, as UTF-8, for readers to find the footer key with
+    pub(crate) footer_key_metadata: Option<String>,
+    /// Encrypt the footer with the footer key, so neither the schema nor any column can be read
+    /// without it, and encrypt columns without a key of their own with the footer key. The file
+    /// starts and ends with `PARE` in place of `PAR1`, which readers without encryption support
+    /// can't open
+    pub(crate) encrypted_footer: bool,
+    /// Keys for the columns of fields, by field name. Every column of a LIST or MAP field is
+    /// encrypted with its field's key, and columns without a key are left in plaintext
+    pub(crate) column_keys: HashMap<String, ColumnKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ColumnKey {
+    pub(crate) key: String,
+    /// Written to the column's metadata, as UTF-8, for readers to find the key with
+    #[serde(default)]
+    pub(crate) key_metadata: Option<String>,
+}
+
+/// A column the records are sorted by, earlier columns take precedence
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SortingColumnOptions {
+    /// The name of a field written as a single, non-repeated column
+    pub(crate) column: String,
+    #[serde(default)]
+    pub(crate) descending: bool,
+    #[serde(default)]
+    pub(crate) nulls_first: bool,
+}
+
+/// How a column's bloom filter is sized, parquet's defaults are used for anything left out
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct BloomFilterOptions {
+    /// On when the options are given, so `{}` writes a filter with the default sizing
+    pub(crate) enabled: bool,
+    /// The false positive probability, between 0 and 1 exclusive
+    pub(crate) fpp: Option<f64>,
+    /// The number of distinct values expected in a row group
+    pub(crate) ndv: Option<u64>,
+}
+
+impl Default for BloomFilterOptions {
+    fn default() -> Self {
+        BloomFilterOptions {
+            enabled: true,
+            fpp: None,
+            ndv: None,
+        }
+    }
+}
+
+impl BloomFilterOptions {
+    fn apply(
+        &self,
+        builder: WriterPropertiesBuilder,
+        path: ColumnPath,
+    ) -> Result<WriterPropertiesBuilder, String> {
+        if !self.enabled {
+            return Ok(builder.set_column_bloom_filter_enabled(path, false));
+        }
+        let mut builder = builder.set_column_bloom_filter_enabled(path.clone(), true);
+        if let Some(fpp) = self.fpp {
+            if !(fpp > 0.0 && fpp < 1.0) {
+                return Err(format!(
+                    "fpp must be between 0 and 1 exclusive, got {}",
+                    fpp
+                ));
+            }
+            builder = builder.set_column_bloom_filter_fpp(path.clone(), fpp);
+        }
+        if let Some(ndv) = self.ndv {
+            if ndv == 0 {
+                return Err("ndv must be greater than 0".to_string());
+            }
+            builder = builder.set_column_bloom_filter_ndv(path, ndv);
+        }
+        Ok(builder)
+    }
+}
+
+/// Where min/max statistics are computed and written
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StatisticsLevel {
+    /// No statistics at all
+    None,
+    /// Statistics for each column chunk in the footer
+    Chunk,
+    /// Statistics for each column chunk and each page
+    Page,
+}
+
+impl From<StatisticsLevel> for EnabledStatistics {
+    fn from(level: StatisticsLevel) -> Self {
+        match level {
+            StatisticsLevel::None => EnabledStatistics::None,
+            StatisticsLevel::Chunk => EnabledStatistics::Chunk,
+            StatisticsLevel::Page => EnabledStatistics::Page,
+        }
+    }
+}
+
+/// The encodings a column's values can be written with
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum ColumnEncoding {
+    /// Values back to back, for any type
+    Plain,
+    /// Run length and bit packed, for BOOLEAN columns
+    Rle,
+    /// Deltas between values, for INT32 and INT64 columns
+    DeltaBinaryPacked,
+    /// Prefix lengths and suffixes, for BYTE_ARRAY and FIXED_LEN_BYTE_ARRAY columns
+    DeltaByteArray,
+    /// The bytes of values split into streams, for FLOAT and DOUBLE columns
+    ByteStreamSplit,
+}
+
+impl ColumnEncoding {
+    /// The parquet encoding, if this writer can write `physical_type` with it
+    fn encoding(self, physical_type: PhysicalType) -> Result<Encoding, String> {
+        let (encoding, supported) = match self {
+            ColumnEncoding::Plain => (Encoding::PLAIN, true),
+            ColumnEncoding::Rle => (Encoding::RLE, physical_type == PhysicalType::BOOLEAN),
+            ColumnEncoding::DeltaBinaryPacked => (
+                Encoding::DELTA_BINARY_PACKED,
+                matches!(physical_type, PhysicalType::INT32 | PhysicalType::INT64),
+            ),
+            ColumnEncoding::DeltaByteArray => (
+                Encoding::DELTA_BYTE_ARRAY,
+                matches!(
+                    physical_type,
+                    PhysicalType::BYTE_ARRAY | PhysicalType::FIXED_LEN_BYTE_ARRAY
+                ),
+            ),
+            // Valid for FLOAT and DOUBLE, but parquet 50 has no encoder for it
+            ColumnEncoding::ByteStreamSplit => {
+                return Err("BYTE_STREAM_SPLIT encoding is not supported by this writer".to_string())
+            }
+        };
+        match supported {
+            true => Ok(encoding),
+            false => Err(format!(
+                "{} encoding can't be used for {} columns",
+                encoding, physical_type
+            )),
+        }
+    }
+}
+
+impl WriterOptions {
+    /// The parquet writer properties set by these options for a file of `message_type`, the rest
+    /// are parquet's defaults
+    pub(crate) fn writer_properties(
+        &self,
+        message_type: &TypePtr,
+    ) -> Result<WriterProperties, String> {
+        let mut builder = WriterProperties::builder();
+        if let Some(limit) = positive("data_page_size_limit", self.data_page_size_limit)? {
+            builder = builder.set_data_page_size_limit(limit);
+        }
+        if let Some(size) = positive("write_batch_size", self.write_batch_size)? {
+            builder = builder.set_write_batch_size(size);
+        }
+        if let Some(limit) = positive("data_page_row_count_limit", self.data_page_row_count_limit)?
+        {
+            builder = builder.set_data_page_row_count_limit(limit);
+        }
+        if let Some(enabled) = self.dictionary_enabled {
+            builder = builder.set_dictionary_enabled(enabled);
+        }
+        if let Some(codec) = self.compression {
+            builder = builder.set_compression(codec.into());
+        }
+        if let Some(limit) = positive(
+            "dictionary_page_size_limit",
+            self.dictionary_page_size_limit,
+        )? {
+            builder = builder.set_dictionary_page_size_limit(limit);
+        }
+        builder = builder.set_writer_version(match self.writer_version {
+            ParquetVersion::V1 => WriterVersion::PARQUET_1_0,
+            ParquetVersion::V2 => WriterVersion::PARQUET_2_0,
+        });
+        if let Some(created_by) = &self.created_by {
+            builder = builder.set_created_by(created_by.clone());
+        }
+        if let Some(metadata) = &self.key_value_metadata {
+            builder = builder.set_key_value_metadata(Some(
+                metadata
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+                    .collect(),
+            ));
+        }
+        let page_index = self.page_index.unwrap_or(true);
+        let statistics = |level: StatisticsLevel| match level {
+            StatisticsLevel::Page if !page_index => StatisticsLevel::Chunk,
+            _ => level,
+        };
+        if let Some(level) = self
+            .statistics_enabled
+            .or((!page_index).then_some(StatisticsLevel::Page))
+        {
+            builder = builder.set_statistics_enabled(statistics(level).into());
+        }
+        if let Some(length) = positive(
+            "statistics_truncate_length",
+            self.statistics_truncate_length,
+        )? {
+            builder = builder
+                .set_statistics_truncate_length(Some(length))
+                .set_column_index_truncate_length(Some(length));
+        }
+
+        let descriptor = SchemaDescriptor::new(message_type.clone());
+        let field_name = |column: &ColumnDescPtr| column.path().parts()[0].clone();
+        if let Some(name) = self
+            .columns
+            .keys()
+            .find(|name| !descriptor.columns().iter().any(|c| field_name(c) == **name))
+        {
+            return Err(format!("Unknown field {} in columns", name));
+        }
+        if let Some(sorting_columns) = &self.sorting_columns {
+            let sorting_columns = sorting_columns
+                .iter()
+                .map(|sorting| {
+                    let index = descriptor.columns().iter().position(|c| {
+                        c.path().parts() == [sorting.column.clone()] && c.max_rep_level() == 0
+                    });
+                    match index {
+                        Some(index) => Ok(SortingColumn::new(
+                            index as i32,
+                            sorting.descending,
+                            sorting.nulls_first,
+                        )),
+                        None => Err(format!(
+                            "Sorting column {} must be a field with a single, non-repeated column",
+                            sorting.column
+                        )),
+                    }
+                })
+                .collect::<Result<_, String>>()?;
+            builder = builder.set_sorting_columns(Some(sorting_columns));
+        }
+        for column in descriptor.columns() {
+            let name = field_name(column);
+            let Some(options) = self.columns.get(&name) else {
+                continue;
+            };
+            let path = column.path().clone();
+            if let Some(enabled) = options.dictionary_enabled {
+                builder = builder.set_column_dictionary_enabled(path.clone(), enabled);
+            }
+            if let Some(codec) = options.compression {
+                builder = builder.set_column_compression(path.clone(), codec.into());
+            }
+            if let Some(level) = options.statistics_enabled {
+                builder =
+                    builder.set_column_statistics_enabled(path.clone(), statistics(level).into());
+            }
+            if let Some(bloom_filter) = options.bloom_filter.as_ref() {
+                builder = bloom_filter
+                    .apply(builder, path.clone())
+                    .map_err(|e| format!("Invalid bloom filter for field {}: {}", name, e))?;
+            }
+            if let Some(encoding) = options.encoding {
+                let encoding = encoding
+                    .encoding(column.physical_type())
+                    .map_err(|e| format!("Invalid encoding for field {}: {}", name, e))?;
+                builder = builder.set_column_encoding(path, encoding);
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+fn positive(name: &str, value: Option<usize>) -> Result<Option<usize>, String> {
+    match value {
+        Some(0) => Err(format!("{} must be greater than 0", name)),
+        _ => Ok(value),
+    }
+}
+
+/// How NaN and infinite floating point values are written
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum NonFinitePolicy {
+    /// Fail the whole write, reporting the offending row
+    #[default]
+    Error,
+    /// Write a null in place of the value, only valid for OPTIONAL fields
+    Null,
+    /// Write the value as it is
+    Write,
+}
+
+/// How strings containing unpaired UTF-16 surrogates, e.g. `"\ud800"`, are written
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LoneSurrogatePolicy {
+    /// Fail the whole write, reporting the offending row
+    #[default]
+    Reject,
+    /// Replace each lone surrogate with U+FFFD
+    Replace,
+    /// Keep the string's raw WTF-8 bytes, UTF8 columns are written as BINARY
+    Binary,
+}
+
+/// How nested objects are expanded, `{"user": {"name": "a"}}` becomes `{"user_name": "a"}` with the
+/// default separator
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct FlattenOptions {
+    /// Joins the keys of a nested value to its parents'
+    pub(crate) separator: String,
+    /// How many levels of nesting to expand, objects below it are kept as they are
+    pub(crate) max_depth: Option<usize>,
+    /// Add an OPTIONAL field to the schema for every flattened key it doesn't already have, typed
+    /// from the first non-null value
+    pub(crate) infer_fields: bool,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        FlattenOptions {
+            separator: "_".to_string(),
+            max_depth: None,
+            infer_fields: false,
+        }
+    }
+}
+
+/// Conversions between JSON types applied when writing, all off by default
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct CoercionOptions {
+    /// Accept numeric strings like `"42"` for integer and floating point fields
+    pub(crate) string_to_number: bool,
+    /// Accept numbers for string fields, written as they appear in JSON
+    pub(crate) number_to_string: bool,
+    /// Accept floats for integer fields, dropping the fractional part
+    pub(crate) truncate_float_to_int: bool,
+    /// Accept `"true"`/`"false"` in any case, `"1"`/`"0"` and the numbers 1 and 0 for boolean
+    /// fields
+    pub(crate) lenient_booleans: bool,
+}
+
+/// Options controlling how a parquet file is read, passed to `read_parquet_with_options` as a JSON
+/// object. Every option is optional and defaults to the behaviour of `read_parquet`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ReaderOptions {
+    /// Skip this many rows from the start of the file, or of the rows meeting `filter`. Row groups
+    /// that end before it aren't decoded at all
+    pub(crate) offset: usize,
+    /// Read at most this many rows, by default every row after the offset
+    pub(crate) limit: Option<usize>,
+    /// Only read the rows meeting this predicate, e.g.
+    /// `{"or": [{"field": "status", "op": "==", "value": "failed"}, ...]}`. Row groups whose
+    /// statistics rule out every row aren't decoded
+    pub(crate) filter: Option<Predicate>,
+    /// Only read the row group at this index, for processing a large file a row group at a time.
+    /// The offset, limit and filter apply within it
+    pub(crate) row_group: Option<usize>,
+}
+
+/// Options for rewriting a parquet file with `transcode_parquet`, passed as a JSON object. Every
+/// option is optional and defaults to what the file was written with
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct TranscodeOptions {
+    /// The codec every column is compressed with
+    pub(crate) compression: Option<Codec>,
+    pub(crate) dictionary_enabled: Option<bool>,
+    pub(crate) max_rows_per_row_group: Option<usize>,
+    pub(crate) data_page_size_limit: Option<usize>,
+    pub(crate) writer_version: Option<ParquetVersion>,
+    /// Options for the columns of a field, keyed by the field's name, which take precedence over
+    /// the options for the whole file
+    pub(crate) columns: HashMap<String, TranscodeColumnOptions>,
+}
+
+/// Options for rewriting the columns of a single field
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct TranscodeColumnOptions {
+    pub(crate) compression: Option<Codec>,
+    pub(crate) dictionary_enabled: Option<bool>,
+    /// How values are encoded in data pages, or once the dictionary is full when dictionary
+    /// encoding is on
+    pub(crate) encoding: Option<ColumnEncoding>,
+}
+
+/// The codecs this build can compress pages with
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub(crate) enum Codec {
+    Uncompressed,
+    /// At the default level
+    Gzip,
+}
+
+impl From<Codec> for Compression {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::Uncompressed => Compression::UNCOMPRESSED,
+            Codec::Gzip => Compression::GZIP(GzipLevel::default()),
+        }
+    }
+}
+
+impl TranscodeOptions {
+    /// Apply these options over `builder`, the properties a file of `descriptor` was written with
+    pub(crate) fn writer_properties(
+        &self,
+        mut builder: WriterPropertiesBuilder,
+        descriptor: &SchemaDescriptor,
+    ) -> Result<WriterProperties, String> {
+        if let Some(size) = positive("max_rows_per_row_group", self.max_rows_per_row_group)? {
+            builder = builder.set_max_row_group_size(size);
+        }
+        if let Some(limit) = positive("data_page_size_limit", self.data_page_size_limit)? {
+            builder = builder.set_data_page_size_limit(limit);
+        }
+        if let Some(version) = self.writer_version {
+            builder = builder.set_writer_version(match version {
+                ParquetVersion::V1 => WriterVersion::PARQUET_1_0,
+                ParquetVersion::V2 => WriterVersion::PARQUET_2_0,
+            });
+        }
+
+        let field_name = |column: &ColumnDescPtr| column.path().parts()[0].clone();
+        if let Some(name) = self
+            .columns
+            .keys()
+            .find(|name| !descriptor.columns().iter().any(|c| field_name(c) == **name))
+        {
+            return Err(format!("Unknown field {} in columns", name));
+        }
+        // The source's codec and dictionary encoding are set for each column, so the options for
+        // the whole file are too, to take precedence over them
+        let default_options = TranscodeColumnOptions::default();
+        for column in descriptor.columns() {
+            let name = field_name(column);
+            let options = self.columns.get(&name).unwrap_or(&default_options);
+            let path = column.path().clone();
+            if let Some(codec) = options.compression.or(self.compression) {
+                builder = builder.set_column_compression(path.clone(), codec.into());
+            }
+            if let Some(enabled) = options.dictionary_enabled.or(self.dictionary_enabled) {
+                builder = builder.set_column_dictionary_enabled(path.clone(), enabled);
+            }
+            if let Some(encoding) = options.encoding {
+                let encoding = encoding
+                    .encoding(column.physical_type())
+                    .map_err(|e| format!("Invalid encoding for field {}: {}", name, e))?;
+                builder = builder.set_column_encoding(path, encoding);
+            }
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Options for rewriting a parquet file's fields with `rewrite_columns`, passed as a JSON object
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RewriteOptions {
+    /// New names for top level fields, keyed by their current name
+    pub(crate) rename: BTreeMap<String, String>,
+    /// Fields added after the file's fields, each written from its `computed` expression over the
+    /// file's rows, with fields already renamed, or its `default` in every row otherwise
+    pub(crate) append: Vec<ParquetField>,
+}
+
+/// Options for removing duplicate rows with `dedupe_parquet`, passed as a JSON object
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct DedupeOptions {
+    /// Which of the rows with the same key is kept
+    pub(crate) keep: Keep,
+}
+
+/// Which of a set of duplicate rows is kept
+#[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Keep {
+    /// The first in the file
+    #[default]
+    First,
+    /// The last in the file, e.g. the latest version of a record appended over time
+    Last,
+}
+
+pub(crate) fn parse_options(options: &str) -> Result<WriterOptions, String> {
+    match serde_json::from_str::<WriterOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+pub(crate) fn parse_reader_options(options: &str) -> Result<ReaderOptions, String> {
+    match serde_json::from_str::<ReaderOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+pub(crate) fn parse_transcode_options(options: &str) -> Result<TranscodeOptions, String> {
+    match serde_json::from_str::<TranscodeOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+pub(crate) fn parse_rewrite_options(options: &str) -> Result<RewriteOptions, String> {
+    match serde_json::from_str::<RewriteOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
+
+pub(crate) fn parse_sorting_columns(keys: &str) -> Result<Vec<SortingColumnOptions>, String> {
+    match serde_json::from_str::<Vec<SortingColumnOptions>>(keys) {
+        Ok(keys) => Ok(keys),
+        Err(e) => Err(format!("Error parsing sort keys: {}", e)),
+    }
+}
+
+pub(crate) fn parse_dedupe_options(options: &str) -> Result<DedupeOptions, String> {
+    match serde_json::from_str::<DedupeOptions>(options) {
+        Ok(o) => Ok(o),
+        Err(e) => Err(format!("Error parsing options: {}", e)),
+    }
+}
@@ -0,0 +1,192 @@
+use crate::encryption::{Module, ModuleEncryptor};
+use crate::error::WriteError;
+use parquet::basic::{Encoding, PageType};
+use parquet::column::page::{CompressedPage, PageWriteSpec, PageWriter};
+use parquet::column::writer::{get_column_writer, ColumnCloseResult};
+use parquet::errors::{ParquetError, Result};
+use parquet::file::metadata::ColumnChunkMetaData;
+use parquet::file::properties::WriterPropertiesPtr;
+use parquet::file::writer::{SerializedColumnWriter, SerializedPageWriter, TrackedWrite};
+use parquet::format::PageHeader;
+use parquet::schema::types::ColumnDescPtr;
+use parquet::thrift::TSerializable;
+use serde::Serialize;
+use std::borrow::Cow;
+use thrift::protocol::{TCompactInputProtocol, TCompactOutputProtocol};
+
+/// The pages written for a column chunk
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct ColumnPages {
+    /// Whether the chunk starts with a dictionary page
+    pub(crate) dictionary: bool,
+    /// The encodings of the chunk's data pages, in the order they were first used
+    pub(crate) encodings: Vec<Encoding>,
+}
+
+/// How a column's data pages were encoded across every row group of a file
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct ColumnReport {
+    /// The column's path in the schema, e.g. `tags.list.element`
+    pub(crate) column: String,
+    pub(crate) encodings: Vec<String>,
+    /// Whether dictionary encoding fell back to the column's fallback encoding, which happens once
+    /// the dictionary outgrows `dictionary_page_size_limit`
+    pub(crate) dictionary_fallback: bool,
+}
+
+/// Writes pages like parquet's own page writer, noting what's written and optionally adding the
+/// CRC32 of each page's data to its header and encrypting each page and header. parquet 50 does
+/// neither, so each page is serialized as usual and its header rewritten
+pub(crate) struct ColumnPageWriter<'a> {
+    sink: &'a mut Vec<u8>,
+    pages: &'a mut ColumnPages,
+    checksums: bool,
+    encryption: Option<ModuleEncryptor<'a>>,
+    /// The data pages written so far, the ordinal of the next one in its AAD
+    data_pages: usize,
+}
+
+impl PageWriter for ColumnPageWriter<'_> {
+    fn write_page(&mut self, page: CompressedPage) -> Result<PageWriteSpec> {
+        let dictionary = page.page_type() == PageType::DICTIONARY_PAGE;
+        match page.page_type() {
+            PageType::DICTIONARY_PAGE => self.pages.dictionary = true,
+            _ if !self.pages.encodings.contains(&page.encoding()) => {
+                self.pages.encodings.push(page.encoding())
+            }
+            _ => {}
+        }
+
+        let mut serialized = TrackedWrite::new(vec![]);
+        let mut spec = SerializedPageWriter::new(&mut serialized).write_page(page)?;
+        let serialized = serialized.into_inner()?;
+        let offset = self.sink.len();
+        if self.checksums || self.encryption.is_some() {
+            let mut data = &serialized[..];
+            let mut header =
+                PageHeader::read_from_in_protocol(&mut TCompactInputProtocol::new(&mut data))
+                    .map_err(thrift_error)?;
+            let (page_module, header_module) = match dictionary {
+                true => (Module::DictionaryPage, Module::DictionaryPageHeader),
+                false => (Module::DataPage, Module::DataPageHeader),
+            };
+            let data = match &self.encryption {
+                Some(encryption) => Cow::Owned(
+                    encryption
+                        .encrypt(page_module, self.data_pages, data)
+                        .map_err(ParquetError::General)?,
+                ),
+                None => Cow::Borrowed(data),
+            };
+            header.compressed_page_size = data.len() as i32;
+            if self.checksums {
+                // The checksum covers everything after the header, compressed and encrypted, as
+                // the spec requires
+                header.crc = Some(crc32fast::hash(&data) as i32);
+            }
+            let mut header_bytes = vec![];
+            header
+                .write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut header_bytes))
+                .map_err(thrift_error)?;
+            if let Some(encryption) = &self.encryption {
+                header_bytes = encryption
+                    .encrypt(header_module, self.data_pages, &header_bytes)
+                    .map_err(ParquetError::General)?;
+            }
+            self.sink.extend_from_slice(&header_bytes);
+            self.sink.extend_from_slice(&data);
+            let added = self.sink.len() - offset - serialized.len();
+            spec.uncompressed_size += added;
+            spec.compressed_size += added;
+        } else {
+            self.sink.extend_from_slice(&serialized);
+        }
+        if !dictionary {
+            self.data_pages += 1;
+        }
+        spec.offset = offset as u64;
+        spec.bytes_written = (self.sink.len() - offset) as u64;
+        Ok(spec)
+    }
+
+    fn write_metadata(&mut self, _metadata: &ColumnChunkMetaData) -> Result<()> {
+        // The row group writer writes the metadata when the column chunk is appended
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Write a column chunk into `buffer` through a `ColumnPageWriter`, returning what the row group
+/// writer needs to append it and the pages written
+pub(crate) fn write_column_chunk(
+    buffer: &mut Vec<u8>,
+    column: ColumnDescPtr,
+    properties: WriterPropertiesPtr,
+    checksums: bool,
+    encryption: Option<ModuleEncryptor<'_>>,
+    write: impl FnOnce(&mut SerializedColumnWriter<'_>) -> std::result::Result<(), WriteError>,
+) -> std::result::Result<(ColumnCloseResult, ColumnPages), WriteError> {
+    let mut pages = ColumnPages::default();
+    let mut result = None;
+    {
+        let page_writer = Box::new(ColumnPageWriter {
+            sink: buffer,
+            pages: &mut pages,
+            checksums,
+            encryption,
+            data_pages: 0,
+        });
+        let column_writer = get_column_writer(column, properties, page_writer);
+        let on_close = Box::new(|close| {
+            result = Some(close);
+            Ok(())
+        });
+        let mut col_writer = SerializedColumnWriter::new(column_writer, Some(on_close));
+        write(&mut col_writer)?;
+        if col_writer.close().is_err() {
+            return Err(WriteError::from("Error closing column writer".to_string()));
+        }
+    }
+    match result {
+        Some(result) => Ok((result, pages)),
+        None => Err(WriteError::from("Error closing column writer".to_string())),
+    }
+}
+
+/// Combine the pages of each row group's column chunks into a report per column
+pub(crate) fn column_reports(
+    columns: &[ColumnDescPtr],
+    row_groups: &[Vec<ColumnPages>],
+) -> Vec<ColumnReport> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let mut encodings: Vec<Encoding> = vec![];
+            let mut dictionary_fallback = false;
+            for pages in row_groups.iter().filter_map(|row_group| row_group.get(i)) {
+                for encoding in pages.encodings.iter() {
+                    if !encodings.contains(encoding) {
+                        encodings.push(*encoding);
+                    }
+                }
+                dictionary_fallback |= pages.dictionary
+                    && pages.encodings.iter().any(|e| {
+                        !matches!(e, Encoding::RLE_DICTIONARY | Encoding::PLAIN_DICTIONARY)
+                    });
+            }
+            ColumnReport {
+                column: column.path().string(),
+                encodings: encodings.iter().map(|e| e.to_string()).collect(),
+                dictionary_fallback,
+            }
+        })
+        .collect()
+}
+
+fn thrift_error(e: thrift::Error) -> ParquetError {
+    ParquetError::General(format!("Error serializing page header: {}", e))
+}
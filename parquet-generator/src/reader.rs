@@ -0,0 +1,1393 @@
+use crate::filter::ColumnRange;
+use crate::options::ReaderOptions;
+use crate::pages::{self, ColumnPages, ColumnReport};
+use crate::{
+    ParquetField, ParquetLogicalType, ParquetPrimitiveType, ParquetRepetition, ParquetSchema,
+};
+use base64::Engine;
+use bytes::Bytes;
+use parquet::basic::{
+    ConvertedType, Encoding, LogicalType, PageType, Repetition, Type as PhysicalType,
+};
+use parquet::file::metadata::{ColumnChunkMetaData, RowGroupMetaData};
+use parquet::file::reader::FileReader;
+use parquet::file::serialized_reader::SerializedFileReader;
+use parquet::file::statistics::Statistics;
+use parquet::format::{FileMetaData, KeyValue, PageHeader};
+use parquet::schema::printer;
+use parquet::schema::types::{ColumnDescriptor, Type};
+use parquet::thrift::TSerializable;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thrift::protocol::TCompactInputProtocol;
+
+/// What a parquet file holds, read from its footer
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct FileInfo {
+    /// The schema in parquet's text format, e.g. `message schema { REQUIRED INT64 id; }`
+    pub(crate) schema: String,
+    pub(crate) num_rows: i64,
+    pub(crate) num_row_groups: usize,
+    pub(crate) row_groups: Vec<RowGroupInfo>,
+    /// The codecs column chunks are compressed with, in the order they're first used
+    pub(crate) codecs: Vec<String>,
+    /// The application that wrote the file
+    pub(crate) created_by: Option<String>,
+    pub(crate) version: i32,
+}
+
+/// The size of a row group
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct RowGroupInfo {
+    pub(crate) num_rows: i64,
+    /// The bytes of the row group's column chunks once decompressed
+    pub(crate) total_byte_size: i64,
+    /// The bytes of the row group's column chunks in the file
+    pub(crate) compressed_size: i64,
+}
+
+/// The statistics of each column of a parquet file, for the whole file and for each row group
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct FileStatistics {
+    pub(crate) columns: Vec<ColumnStatistics>,
+    pub(crate) row_groups: Vec<Vec<ColumnStatistics>>,
+}
+
+/// The statistics of a column, `None` where the file doesn't record them. Strings are read as
+/// strings and other byte arrays as base64, INT96 timestamps as nanoseconds
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct ColumnStatistics {
+    /// The column's path, e.g. `address.city`
+    pub(crate) column: String,
+    pub(crate) min: Option<Value>,
+    pub(crate) max: Option<Value>,
+    pub(crate) null_count: Option<u64>,
+    pub(crate) distinct_count: Option<u64>,
+}
+
+/// A min or max, ordered the way the writer ordered the column
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Bound {
+    Boolean(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bytes(Vec<u8>),
+}
+
+/// The key-value metadata of a parquet file's footer and of its column chunks
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct KeyValueMetadata {
+    pub(crate) file: BTreeMap<String, Option<String>>,
+    /// The column chunks with any metadata of their own
+    pub(crate) columns: Vec<ColumnMetadata>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct ColumnMetadata {
+    pub(crate) row_group: usize,
+    /// The column's path, e.g. `address.city`
+    pub(crate) column: String,
+    pub(crate) metadata: BTreeMap<String, Option<String>>,
+}
+
+/// A page of a column chunk as it's stored, for seeing how a column was written
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct PageInfo {
+    pub(crate) row_group: usize,
+    /// The index of the page in its column chunk
+    pub(crate) page: usize,
+    /// The offset of the page's header in the file
+    pub(crate) offset: u64,
+    pub(crate) page_type: String,
+    pub(crate) encoding: Option<String>,
+    /// The bytes of the page's data as it's stored, not counting the header
+    pub(crate) compressed_size: i32,
+    /// The bytes of the page's data once decompressed
+    pub(crate) uncompressed_size: i32,
+    /// The values in the page, counting nulls, or the entries of a dictionary page
+    pub(crate) num_values: Option<i32>,
+    /// The nulls and rows in the page, only recorded by v2 data pages
+    pub(crate) num_nulls: Option<i32>,
+    pub(crate) num_rows: Option<i32>,
+    pub(crate) has_checksum: bool,
+}
+
+/// How much space a column takes across every row group of a file, and how it was encoded
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct ColumnStorage {
+    #[serde(flatten)]
+    pub(crate) pages: ColumnReport,
+    /// Whether any of the column's chunks has a dictionary page
+    pub(crate) dictionary: bool,
+    /// The bytes of the column's chunks in the file, counting page headers
+    pub(crate) compressed_bytes: i64,
+    /// The bytes of the column's chunks once decompressed
+    pub(crate) uncompressed_bytes: i64,
+    /// How many times smaller the column is compressed, none for an empty column
+    pub(crate) compression_ratio: Option<f64>,
+}
+
+/// A page of a column chunk, its header and the page's data as it's stored
+pub(crate) struct Page<'a> {
+    /// The offset of the page's header in the file
+    pub(crate) offset: u64,
+    pub(crate) header: PageHeader,
+    pub(crate) data: &'a [u8],
+}
+
+/// Open a parquet file held in memory
+pub(crate) fn open(bytes: impl Into<Bytes>) -> Result<SerializedFileReader<Bytes>, String> {
+    match SerializedFileReader::new(bytes.into()) {
+        Ok(reader) => Ok(reader),
+        Err(e) => Err(format!("Error reading parquet file: {}", e)),
+    }
+}
+
+/// Read a parquet file's footer as it's stored. parquet's own metadata leaves out what readers
+/// don't need, such as the key-value metadata of column chunks
+pub(crate) fn footer(bytes: &[u8]) -> Result<FileMetaData, String> {
+    let invalid = || "Error reading parquet file: invalid footer".to_string();
+    let tail = bytes
+        .len()
+        .checked_sub(8)
+        .filter(|_| bytes.ends_with(b"PAR1"))
+        .ok_or_else(invalid)?;
+    let length = u32::from_le_bytes(bytes[tail..tail + 4].try_into().map_err(|_| invalid())?);
+    let start = tail.checked_sub(length as usize).ok_or_else(invalid)?;
+    let mut footer = &bytes[start..tail];
+    FileMetaData::read_from_in_protocol(&mut TCompactInputProtocol::new(&mut footer))
+        .map_err(|e| format!("Error reading parquet file: {}", e))
+}
+
+/// Read the key-value metadata of a parquet file's footer and of each of its column chunks. A
+/// key written more than once keeps its last value
+pub(crate) fn key_value_metadata(bytes: &[u8]) -> Result<KeyValueMetadata, String> {
+    let footer = footer(bytes)?;
+    let entries = |metadata: Option<Vec<KeyValue>>| {
+        metadata
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| (entry.key, entry.value))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let mut columns = vec![];
+    for (row_group, group) in footer.row_groups.into_iter().enumerate() {
+        for chunk in group.columns {
+            let Some(meta_data) = chunk.meta_data else {
+                continue;
+            };
+            let metadata = entries(meta_data.key_value_metadata);
+            if !metadata.is_empty() {
+                columns.push(ColumnMetadata {
+                    row_group,
+                    column: meta_data.path_in_schema.join("."),
+                    metadata,
+                });
+            }
+        }
+    }
+    Ok(KeyValueMetadata {
+        file: entries(footer.key_value_metadata),
+        columns,
+    })
+}
+
+/// The pages of a column chunk, read from the headers in the file rather than decoded, so each page
+/// can be checked or measured as it's stored
+pub(crate) fn chunk_pages<'a>(
+    bytes: &'a [u8],
+    chunk: &ColumnChunkMetaData,
+) -> Result<Vec<Page<'a>>, String> {
+    let (start, length) = chunk.byte_range();
+    let end = start.saturating_add(length);
+    if end > bytes.len() as u64 {
+        return Err(format!(
+            "Column chunk at offset {} runs past the end of the file",
+            start
+        ));
+    }
+
+    let mut pages = vec![];
+    let mut offset = start;
+    while offset < end {
+        let mut remaining = &bytes[offset as usize..end as usize];
+        let header =
+            PageHeader::read_from_in_protocol(&mut TCompactInputProtocol::new(&mut remaining))
+                .map_err(|e| format!("Error reading page header at offset {}: {}", offset, e))?;
+        let size = usize::try_from(header.compressed_page_size).unwrap_or(usize::MAX);
+        if size > remaining.len() {
+            return Err(format!(
+                "Page at offset {} runs past the end of its column chunk",
+                offset
+            ));
+        }
+        let data_offset = end - remaining.len() as u64;
+        pages.push(Page {
+            offset,
+            header,
+            data: &remaining[..size],
+        });
+        offset = data_offset + size as u64;
+    }
+    Ok(pages)
+}
+
+/// The schema of a parquet file in parquet's text format, e.g.
+/// `message schema { REQUIRED INT64 id; }`
+pub(crate) fn schema_text(bytes: Vec<u8>) -> Result<String, String> {
+    let reader = open(bytes)?;
+    let mut schema = vec![];
+    printer::print_schema(&mut schema, reader.metadata().file_metadata().schema());
+    Ok(String::from_utf8_lossy(&schema).into_owned())
+}
+
+/// The schema of a parquet file as a schema records can be written with, as JSON. Keys a field
+/// leaves at their defaults are left out
+pub(crate) fn schema_json(bytes: Vec<u8>) -> Result<String, String> {
+    let reader = open(bytes)?;
+    let schema = to_parquet_schema(reader.metadata().file_metadata().schema())?;
+    let fields = schema
+        .fields
+        .iter()
+        .map(|field| {
+            let defaults =
+                serde_json::to_value(ParquetField::new(field.name.clone(), field.primitive_type));
+            match (serde_json::to_value(field), defaults) {
+                (Ok(Value::Object(mut keys)), Ok(Value::Object(defaults))) => {
+                    keys.retain(|key, value| {
+                        key == "name" || key == "type" || defaults.get(key) != Some(value)
+                    });
+                    Ok(Value::Object(keys))
+                }
+                _ => Err("Error serializing schema".to_string()),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match serde_json::to_string_pretty(&serde_json::json!({ "fields": fields })) {
+        Ok(json) => Ok(json),
+        Err(_) => Err("Error serializing schema".to_string()),
+    }
+}
+
+/// Map a parquet message type to fields that write the same columns. LISTs and MAPs in the
+/// standard three level layout become LIST and MAP fields, and groups are flattened into columns
+/// named `parent_child` that read from the `parent.child` key path, as Avro records are. The
+/// length of a FIXED_LEN_BYTE_ARRAY isn't kept, as fields always have the writer's length
+pub(crate) fn to_parquet_schema(message: &Type) -> Result<ParquetSchema, String> {
+    let mut fields = vec![];
+    for field in message.get_fields() {
+        let key = field.name().replace('.', "\\.");
+        map_type(&mut fields, field, field.name(), &key, false)?;
+    }
+    Ok(ParquetSchema { fields })
+}
+
+fn map_type(
+    fields: &mut Vec<ParquetField>,
+    ty: &Type,
+    name: &str,
+    source_key: &str,
+    optional_parent: bool,
+) -> Result<(), String> {
+    let unsupported = || format!("Unsupported parquet type for field {}", name);
+    let info = ty.get_basic_info();
+    let repetition = match info.repetition() {
+        Repetition::REQUIRED if optional_parent => ParquetRepetition::Optional,
+        Repetition::REQUIRED => ParquetRepetition::Required,
+        Repetition::OPTIONAL => ParquetRepetition::Optional,
+        Repetition::REPEATED => ParquetRepetition::Repeated,
+    };
+
+    if ty.is_group() {
+        let nested = match (info.converted_type(), ty.get_fields()) {
+            (ConvertedType::LIST, [repeated]) if repeated.is_primitive() => {
+                Some((ParquetLogicalType::List, repeated.as_ref()))
+            }
+            (ConvertedType::LIST, [repeated]) => match repeated.get_fields() {
+                [element] if element.is_primitive() => {
+                    Some((ParquetLogicalType::List, element.as_ref()))
+                }
+                _ => return Err(unsupported()),
+            },
+            (ConvertedType::MAP | ConvertedType::MAP_KEY_VALUE, [repeated]) => {
+                match repeated.get_fields() {
+                    [key, value]
+                        if key.is_primitive()
+                            && key.get_physical_type() == PhysicalType::BYTE_ARRAY
+                            && value.is_primitive() =>
+                    {
+                        Some((ParquetLogicalType::Map, value.as_ref()))
+                    }
+                    _ => return Err(unsupported()),
+                }
+            }
+            _ if info.repetition() == Repetition::REPEATED => return Err(unsupported()),
+            _ => None,
+        };
+        let (logical_type, leaf) = match nested {
+            Some(nested) => nested,
+            None => {
+                for child in ty.get_fields() {
+                    let key = child.name().replace('.', "\\.");
+                    map_type(
+                        fields,
+                        child,
+                        &format!("{}_{}", name, child.name()),
+                        &format!("{}.{}", source_key, key),
+                        repetition == ParquetRepetition::Optional,
+                    )?;
+                }
+                return Ok(());
+            }
+        };
+        let mut field = primitive_field(name, leaf).ok_or_else(unsupported)?;
+        field.element_logical_type = field.logical_type.take();
+        field.logical_type = Some(logical_type);
+        field.repetition_type = Some(repetition);
+        field.source_key = (source_key != name).then(|| source_key.to_string());
+        fields.push(field);
+        return Ok(());
+    }
+
+    let mut field = primitive_field(name, ty).ok_or_else(unsupported)?;
+    field.repetition_type = Some(repetition);
+    field.source_key = (source_key != name).then(|| source_key.to_string());
+    fields.push(field);
+    Ok(())
+}
+
+/// A field of a primitive type's physical and logical type, with its precision and scale when
+/// it's a DECIMAL
+fn primitive_field(name: &str, ty: &Type) -> Option<ParquetField> {
+    use ParquetLogicalType as L;
+    use ParquetPrimitiveType as P;
+
+    let primitive_type = match ty.get_physical_type() {
+        PhysicalType::BOOLEAN => P::Boolean,
+        PhysicalType::INT32 => P::Int32,
+        PhysicalType::INT64 => P::Int64,
+        PhysicalType::INT96 => P::Int96,
+        PhysicalType::FLOAT => P::Float,
+        PhysicalType::DOUBLE => P::Double,
+        PhysicalType::BYTE_ARRAY => P::ByteArray,
+        PhysicalType::FIXED_LEN_BYTE_ARRAY => P::FixedLenByteArray,
+    };
+    let logical_type = match ty.get_basic_info().converted_type() {
+        ConvertedType::NONE => None,
+        ConvertedType::UTF8 => Some(L::Utf8),
+        ConvertedType::ENUM => Some(L::Enum),
+        ConvertedType::DECIMAL => Some(L::Decimal),
+        ConvertedType::DATE => Some(L::Date),
+        ConvertedType::TIME_MILLIS => Some(L::TimeMillis),
+        ConvertedType::TIME_MICROS => Some(L::TimeMicros),
+        ConvertedType::TIMESTAMP_MILLIS => Some(L::TimestampMillis),
+        ConvertedType::TIMESTAMP_MICROS => Some(L::TimestampMicros),
+        ConvertedType::UINT_8 => Some(L::Uint8),
+        ConvertedType::UINT_16 => Some(L::Uint16),
+        ConvertedType::UINT_32 => Some(L::Uint32),
+        ConvertedType::UINT_64 => Some(L::Uint64),
+        ConvertedType::INT_8 => Some(L::Int8),
+        ConvertedType::INT_16 => Some(L::Int16),
+        ConvertedType::INT_32 => Some(L::Int32),
+        ConvertedType::INT_64 => Some(L::Int64),
+        ConvertedType::JSON => Some(L::Json),
+        ConvertedType::BSON => Some(L::Bson),
+        ConvertedType::INTERVAL => Some(L::Interval),
+        ConvertedType::MAP | ConvertedType::MAP_KEY_VALUE | ConvertedType::LIST => return None,
+    };
+    let mut field = ParquetField::new(name.to_string(), primitive_type);
+    if logical_type == Some(L::Decimal) {
+        field.precision = Some(ty.get_precision());
+        field.scale = Some(ty.get_scale());
+    }
+    field.logical_type = logical_type;
+    Some(field)
+}
+
+/// List the pages of a column in every row group, from their headers. `column` is the column's
+/// path, e.g. `tags.list.element` for the elements of a LIST field `tags`
+pub(crate) fn dump_pages(bytes: Vec<u8>, column: &str) -> Result<Vec<PageInfo>, String> {
+    let bytes = Bytes::from(bytes);
+    let reader = open(bytes.clone())?;
+    let descriptor = reader.metadata().file_metadata().schema_descr();
+    let index = descriptor
+        .columns()
+        .iter()
+        .position(|c| c.path().string() == column)
+        .ok_or_else(|| format!("Unknown column {}", column))?;
+
+    let mut pages = vec![];
+    for (row_group, metadata) in reader.metadata().row_groups().iter().enumerate() {
+        for (i, page) in chunk_pages(&bytes, metadata.column(index))?
+            .iter()
+            .enumerate()
+        {
+            let header = &page.header;
+            let (encoding, num_values, num_nulls, num_rows) = match (
+                &header.data_page_header,
+                &header.data_page_header_v2,
+                &header.dictionary_page_header,
+            ) {
+                (Some(h), _, _) => (Some(h.encoding), Some(h.num_values), None, None),
+                (_, Some(h), _) => (
+                    Some(h.encoding),
+                    Some(h.num_values),
+                    Some(h.num_nulls),
+                    Some(h.num_rows),
+                ),
+                (_, _, Some(h)) => (Some(h.encoding), Some(h.num_values), None, None),
+                _ => (None, None, None, None),
+            };
+            pages.push(PageInfo {
+                row_group,
+                page: i,
+                offset: page.offset,
+                page_type: PageType::try_from(header.type_)
+                    .map_or_else(|_| format!("{:?}", header.type_), |t| t.to_string()),
+                encoding: encoding.map(|e| {
+                    Encoding::try_from(e).map_or_else(|_| format!("{:?}", e), |e| e.to_string())
+                }),
+                compressed_size: header.compressed_page_size,
+                uncompressed_size: header.uncompressed_page_size,
+                num_values,
+                num_nulls,
+                num_rows,
+                has_checksum: header.crc.is_some(),
+            });
+        }
+    }
+    Ok(pages)
+}
+
+/// Measure each column of a file across its row groups, reading the encodings of its data pages
+/// and whether it has a dictionary from the page headers
+pub(crate) fn storage_report(bytes: Vec<u8>) -> Result<Vec<ColumnStorage>, String> {
+    let bytes = Bytes::from(bytes);
+    let reader = open(bytes.clone())?;
+    let metadata = reader.metadata();
+
+    let mut row_groups = vec![];
+    for row_group in metadata.row_groups() {
+        let mut chunks = vec![];
+        for chunk in row_group.columns() {
+            let mut column_pages = ColumnPages::default();
+            for page in chunk_pages(&bytes, chunk)? {
+                let header = &page.header;
+                let encoding = match (&header.data_page_header, &header.data_page_header_v2) {
+                    (Some(h), _) => h.encoding,
+                    (_, Some(h)) => h.encoding,
+                    _ => {
+                        column_pages.dictionary |= header.dictionary_page_header.is_some();
+                        continue;
+                    }
+                };
+                let encoding = Encoding::try_from(encoding).map_err(|e| e.to_string())?;
+                if !column_pages.encodings.contains(&encoding) {
+                    column_pages.encodings.push(encoding);
+                }
+            }
+            chunks.push(column_pages);
+        }
+        row_groups.push(chunks);
+    }
+
+    let columns = metadata.file_metadata().schema_descr().columns();
+    let reports = pages::column_reports(columns, &row_groups);
+    Ok(reports
+        .into_iter()
+        .enumerate()
+        .map(|(i, report)| {
+            let chunks = metadata
+                .row_groups()
+                .iter()
+                .map(|row_group| row_group.column(i));
+            let compressed_bytes = chunks.clone().map(|c| c.compressed_size()).sum::<i64>();
+            let uncompressed_bytes = chunks.map(|c| c.uncompressed_size()).sum::<i64>();
+            ColumnStorage {
+                pages: report,
+                dictionary: row_groups.iter().any(|chunks| chunks[i].dictionary),
+                compressed_bytes,
+                uncompressed_bytes,
+                compression_ratio: (compressed_bytes > 0)
+                    .then(|| uncompressed_bytes as f64 / compressed_bytes as f64),
+            }
+        })
+        .collect())
+}
+
+/// Read what a parquet file holds from its footer, without decoding any rows
+pub(crate) fn inspect(bytes: Vec<u8>) -> Result<FileInfo, String> {
+    let reader = open(bytes)?;
+    let metadata = reader.metadata();
+    let file_metadata = metadata.file_metadata();
+
+    let mut schema = vec![];
+    printer::print_schema(&mut schema, file_metadata.schema());
+    let mut codecs = vec![];
+    for row_group in metadata.row_groups() {
+        for column in row_group.columns() {
+            let codec = column.compression().to_string();
+            if !codecs.contains(&codec) {
+                codecs.push(codec);
+            }
+        }
+    }
+    Ok(FileInfo {
+        schema: String::from_utf8_lossy(&schema).into_owned(),
+        num_rows: file_metadata.num_rows(),
+        num_row_groups: metadata.num_row_groups(),
+        row_groups: metadata
+            .row_groups()
+            .iter()
+            .map(|row_group| RowGroupInfo {
+                num_rows: row_group.num_rows(),
+                total_byte_size: row_group.total_byte_size(),
+                compressed_size: row_group.compressed_size(),
+            })
+            .collect(),
+        codecs,
+        created_by: file_metadata.created_by().map(str::to_string),
+        version: file_metadata.version(),
+    })
+}
+
+/// Read the statistics of each column from a parquet file's footer. A column's statistics for the
+/// whole file combine its row groups': a min, max or null count is only given when every row group
+/// has one, and a distinct count only when there's a single row group, as they can't be added
+pub(crate) fn statistics(bytes: Vec<u8>) -> Result<FileStatistics, String> {
+    let reader = open(bytes)?;
+    let metadata = reader.metadata();
+    let descriptor = metadata.file_metadata().schema_descr();
+
+    let mut columns = vec![];
+    for (i, column) in descriptor.columns().iter().enumerate() {
+        let chunks = metadata
+            .row_groups()
+            .iter()
+            .map(|row_group| row_group.column(i))
+            .collect::<Vec<_>>();
+        let bounds = chunks
+            .iter()
+            .map(|chunk| chunk_bounds(chunk))
+            .collect::<Option<Vec<_>>>();
+        let (min, max) = match bounds {
+            Some(bounds) => {
+                let min = bounds
+                    .iter()
+                    .map(|(min, _)| min)
+                    .min_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                let max = bounds
+                    .iter()
+                    .map(|(_, max)| max)
+                    .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                (min.cloned(), max.cloned())
+            }
+            None => (None, None),
+        };
+        let null_count = chunks
+            .iter()
+            .map(|chunk| chunk.statistics().map(Statistics::null_count))
+            .sum();
+        let distinct_count = match &chunks[..] {
+            [chunk] => chunk.statistics().and_then(Statistics::distinct_count),
+            _ => None,
+        };
+        columns.push(ColumnStatistics {
+            column: column.path().string(),
+            min: min.map(|min| bound_value(min, column)),
+            max: max.map(|max| bound_value(max, column)),
+            null_count,
+            distinct_count,
+        });
+    }
+
+    let row_groups = metadata
+        .row_groups()
+        .iter()
+        .map(|row_group| {
+            row_group
+                .columns()
+                .iter()
+                .map(|chunk| {
+                    let bounds = chunk_bounds(chunk);
+                    ColumnStatistics {
+                        column: chunk.column_path().string(),
+                        min: bounds
+                            .clone()
+                            .map(|(min, _)| bound_value(min, chunk.column_descr())),
+                        max: bounds.map(|(_, max)| bound_value(max, chunk.column_descr())),
+                        null_count: chunk.statistics().map(Statistics::null_count),
+                        distinct_count: chunk.statistics().and_then(Statistics::distinct_count),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Ok(FileStatistics {
+        columns,
+        row_groups,
+    })
+}
+
+/// The min and max of a column chunk, when it has them
+fn chunk_bounds(chunk: &ColumnChunkMetaData) -> Option<(Bound, Bound)> {
+    let statistics = chunk.statistics().filter(|s| s.has_min_max_set())?;
+    let unsigned = is_unsigned(chunk.column_descr());
+    Some(match statistics {
+        Statistics::Boolean(s) => (Bound::Boolean(*s.min()), Bound::Boolean(*s.max())),
+        Statistics::Int32(s) if unsigned => (
+            Bound::UInt(*s.min() as u32 as u64),
+            Bound::UInt(*s.max() as u32 as u64),
+        ),
+        Statistics::Int32(s) => (Bound::Int(*s.min() as i64), Bound::Int(*s.max() as i64)),
+        Statistics::Int64(s) if unsigned => {
+            (Bound::UInt(*s.min() as u64), Bound::UInt(*s.max() as u64))
+        }
+        Statistics::Int64(s) => (Bound::Int(*s.min()), Bound::Int(*s.max())),
+        Statistics::Int96(s) => (
+            Bound::Int(s.min().to_nanos()),
+            Bound::Int(s.max().to_nanos()),
+        ),
+        Statistics::Float(s) => (Bound::Float(*s.min() as f64), Bound::Float(*s.max() as f64)),
+        Statistics::Double(s) => (Bound::Float(*s.min()), Bound::Float(*s.max())),
+        Statistics::ByteArray(s) => (
+            Bound::Bytes(s.min().data().to_vec()),
+            Bound::Bytes(s.max().data().to_vec()),
+        ),
+        Statistics::FixedLenByteArray(s) => (
+            Bound::Bytes(s.min().data().to_vec()),
+            Bound::Bytes(s.max().data().to_vec()),
+        ),
+    })
+}
+
+fn is_unsigned(column: &ColumnDescriptor) -> bool {
+    match column.logical_type() {
+        Some(LogicalType::Integer { is_signed, .. }) => !is_signed,
+        _ => matches!(
+            column.converted_type(),
+            ConvertedType::UINT_8
+                | ConvertedType::UINT_16
+                | ConvertedType::UINT_32
+                | ConvertedType::UINT_64
+        ),
+    }
+}
+
+pub(crate) fn is_text(column: &ColumnDescriptor) -> bool {
+    matches!(
+        column.logical_type(),
+        Some(LogicalType::String | LogicalType::Enum | LogicalType::Json)
+    ) || matches!(
+        column.converted_type(),
+        ConvertedType::UTF8 | ConvertedType::ENUM | ConvertedType::JSON
+    )
+}
+
+fn bound_value(bound: Bound, column: &ColumnDescriptor) -> Value {
+    let base64 =
+        |bytes: Vec<u8>| Value::String(base64::engine::general_purpose::STANDARD.encode(bytes));
+    match bound {
+        Bound::Boolean(b) => Value::Bool(b),
+        Bound::Int(n) => Value::from(n),
+        Bound::UInt(n) => Value::from(n),
+        Bound::Float(n) => Value::from(n),
+        Bound::Bytes(bytes) if is_text(column) => match String::from_utf8(bytes) {
+            Ok(s) => Value::String(s),
+            Err(e) => base64(e.into_bytes()),
+        },
+        Bound::Bytes(bytes) => base64(bytes),
+    }
+}
+
+/// Read the rows of a parquet file meeting `options`' filter, within its offset and limit, each as
+/// a JSON object keyed by field name, the shape records are written from. LISTs are read as arrays
+/// and MAPs as objects, while byte arrays are read as base64 strings and dates, timestamps and
+/// decimals as strings. Only the row groups that may hold those rows are decoded
+pub(crate) fn read_rows(bytes: Vec<u8>, options: ReaderOptions) -> Result<Vec<Value>, String> {
+    let mut rows = Rows::new(bytes, options)?;
+    let mut read = vec![];
+    while let Some(row_group) = rows.next_row_group()? {
+        read.extend(row_group);
+    }
+    Ok(read)
+}
+
+/// The rows of a parquet file `read_rows` reads, decoded a row group at a time so they can be
+/// streamed
+pub(crate) struct Rows {
+    reader: SerializedFileReader<Bytes>,
+    options: ReaderOptions,
+    /// The next row group to read
+    row_group: usize,
+    /// The index of the next row group's first row in the file
+    start: usize,
+    /// The rows of the offset still to skip
+    skip: usize,
+    /// The number of rows read so far
+    read: usize,
+}
+
+impl Rows {
+    pub(crate) fn new(bytes: Vec<u8>, options: ReaderOptions) -> Result<Rows, String> {
+        let reader = open(bytes)?;
+        let rows = Rows {
+            reader,
+            skip: options.offset,
+            options,
+            row_group: 0,
+            start: 0,
+            read: 0,
+        };
+        if let Some(filter) = &rows.options.filter {
+            filter.validate(&rows.columns())?;
+        }
+        let row_groups = rows.reader.metadata().num_row_groups();
+        match rows.options.row_group {
+            Some(i) if i >= row_groups => Err(format!(
+                "Row group {} out of range, the file has {}",
+                i, row_groups
+            )),
+            _ => Ok(rows),
+        }
+    }
+
+    /// The names of the file's top level columns, the keys of each row
+    pub(crate) fn columns(&self) -> Vec<String> {
+        self.reader
+            .metadata()
+            .file_metadata()
+            .schema()
+            .get_fields()
+            .iter()
+            .map(|field| field.name().to_string())
+            .collect()
+    }
+
+    /// The rows of the next row group holding any, none once every row has been read
+    pub(crate) fn next_row_group(&mut self) -> Result<Option<Vec<Value>>, String> {
+        let limit = self.options.limit.unwrap_or(usize::MAX);
+        let metadata = self.reader.metadata();
+        while self.read < limit && self.row_group < metadata.num_row_groups() {
+            let i = self.row_group;
+            let row_group = metadata.row_group(i);
+            let row_group_rows = row_group.num_rows() as usize;
+            let start = self.start;
+            self.row_group += 1;
+            self.start += row_group_rows;
+            if self.options.row_group.is_some_and(|only| only != i) {
+                continue;
+            }
+
+            let skipped = match &self.options.filter {
+                Some(filter) => !filter.may_match(&|column| column_range(row_group, column)),
+                // Without a filter every row matches, so whole row groups can be counted off the
+                // offset
+                None if self.skip >= row_group_rows => {
+                    self.skip -= row_group_rows;
+                    true
+                }
+                None => false,
+            };
+            if skipped {
+                continue;
+            }
+
+            let row_group_reader = match self.reader.get_row_group(i) {
+                Ok(row_group) => row_group,
+                Err(e) => return Err(format!("Error reading row group {}: {}", i, e)),
+            };
+            let row_iter = match row_group_reader.get_row_iter(None) {
+                Ok(row_iter) => row_iter,
+                Err(e) => return Err(format!("Error reading rows: {}", e)),
+            };
+            let mut rows = vec![];
+            for (row, result) in (start..).zip(row_iter) {
+                let value = match result {
+                    Ok(r) => r.to_json_value(),
+                    Err(e) => return Err(format!("Row {}: error reading row: {}", row, e)),
+                };
+                let matched = match (&self.options.filter, &value) {
+                    (Some(filter), Value::Object(row)) => filter.matches(row),
+                    _ => true,
+                };
+                if !matched {
+                    continue;
+                }
+                if self.skip > 0 {
+                    self.skip -= 1;
+                    continue;
+                }
+                rows.push(value);
+                self.read += 1;
+                if self.read >= limit {
+                    break;
+                }
+            }
+            if !rows.is_empty() {
+                return Ok(Some(rows));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The rows of the next row group holding any as newline-delimited JSON, a line for each row
+    pub(crate) fn next_ndjson(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let rows = match self.next_row_group()? {
+            Some(rows) => rows,
+            None => return Ok(None),
+        };
+        let mut ndjson = vec![];
+        for row in rows {
+            if serde_json::to_writer(&mut ndjson, &row).is_err() {
+                return Err("Error serializing row".to_string());
+            }
+            ndjson.push(b'\n');
+        }
+        Ok(Some(ndjson))
+    }
+}
+
+/// The range of a top level column's values in a row group, when its statistics hold its min and
+/// max in the form its rows are read in. A date's statistics are the days since the epoch, for
+/// example, which can't be compared with the date strings rows are read as
+fn column_range(row_group: &RowGroupMetaData, column: &str) -> Option<ColumnRange> {
+    let chunk = row_group.columns().iter().find(|chunk| {
+        let descr = chunk.column_descr();
+        descr.path().parts() == [column] && descr.max_rep_level() == 0
+    })?;
+    let statistics = chunk.statistics()?;
+    let descr = chunk.column_descr();
+    let comparable = match (statistics, descr.logical_type()) {
+        (Statistics::ByteArray(_) | Statistics::FixedLenByteArray(_), _) => is_text(descr),
+        (Statistics::Int32(_) | Statistics::Int64(_), Some(LogicalType::Integer { .. })) => true,
+        (Statistics::Int32(_) | Statistics::Int64(_), Some(_)) => false,
+        (Statistics::Int32(_) | Statistics::Int64(_), None) => matches!(
+            descr.converted_type(),
+            ConvertedType::NONE
+                | ConvertedType::INT_8
+                | ConvertedType::INT_16
+                | ConvertedType::INT_32
+                | ConvertedType::INT_64
+                | ConvertedType::UINT_8
+                | ConvertedType::UINT_16
+                | ConvertedType::UINT_32
+                | ConvertedType::UINT_64
+        ),
+        (Statistics::Int96(_), _) => false,
+        (Statistics::Boolean(_) | Statistics::Float(_) | Statistics::Double(_), _) => true,
+    };
+    let bounds = chunk_bounds(chunk)
+        .filter(|_| comparable)
+        .map(|(min, max)| (bound_value(min, descr), bound_value(max, descr)));
+    Some(ColumnRange {
+        bounds,
+        null_count: Some(statistics.null_count()),
+        rows: row_group.num_rows() as u64,
+    })
+}
+
+#[test]
+fn test_read_rows() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "day", "type": "INT32", "logical_type": "DATE"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"},
+            {"name": "counts", "type": "INT32", "logical_type": "MAP"}
+        ]
+    }
+    "#;
+    let records = vec![
+        serde_json::json!({"id": 1, "name": "a", "day": "2024-01-02", "tags": ["x"], "counts": {"k": 1}}),
+        serde_json::json!({"id": 2, "name": null, "day": "2024-03-04", "tags": [], "counts": {}}),
+    ];
+    let files = records.iter().map(Value::to_string).collect();
+    let bytes = crate::write_parquet(schema.to_string(), files, &Default::default()).unwrap();
+    assert_eq!(read_rows(bytes, Default::default()).unwrap(), records);
+
+    assert!(read_rows(b"not parquet".to_vec(), Default::default())
+        .unwrap_err()
+        .starts_with("Error reading parquet file"));
+}
+
+#[test]
+fn test_inspect() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}]}"#;
+    let files = (0..5).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let options =
+        crate::options::parse_options(r#"{"max_rows_per_row_group": 2, "created_by": "test"}"#)
+            .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let info = inspect(bytes).unwrap();
+    assert_eq!(info.schema, "message schema {\n  REQUIRED INT64 id;\n}\n");
+    assert_eq!(info.num_rows, 5);
+    assert_eq!(info.num_row_groups, 3);
+    assert_eq!(
+        info.row_groups
+            .iter()
+            .map(|row_group| row_group.num_rows)
+            .collect::<Vec<_>>(),
+        vec![2, 2, 1]
+    );
+    assert!(info
+        .row_groups
+        .iter()
+        .all(|row_group| row_group.compressed_size > 0));
+    assert_eq!(info.codecs, vec!["UNCOMPRESSED"]);
+    assert_eq!(info.created_by.as_deref(), Some("test"));
+    assert_eq!(info.version, 1);
+}
+
+#[test]
+fn test_statistics() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "count", "type": "INT32", "logical_type": "UINT32"},
+            {"name": "data", "type": "BYTE_ARRAY", "encoding": "base64", "repetition_type": "OPTIONAL"}
+        ]
+    }
+    "#;
+    let files = vec![
+        r#"{"id": 3, "name": "b", "count": 4294967295, "data": "/w=="}"#.to_string(),
+        r#"{"id": -1, "name": null, "count": 1}"#.to_string(),
+        r#"{"id": 7, "name": "a", "count": 2, "data": "AQI="}"#.to_string(),
+    ];
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let statistics = statistics(bytes).unwrap();
+    let json = |c: &ColumnStatistics| serde_json::to_value(c).unwrap();
+    assert_eq!(
+        statistics.columns.iter().map(json).collect::<Vec<_>>(),
+        vec![
+            serde_json::json!({"column": "id", "min": -1, "max": 7, "null_count": 0, "distinct_count": null}),
+            serde_json::json!({"column": "name", "min": "a", "max": "b", "null_count": 1, "distinct_count": null}),
+            serde_json::json!({"column": "count", "min": 1, "max": 4294967295u32, "null_count": 0, "distinct_count": null}),
+            serde_json::json!({"column": "data", "min": "AQI=", "max": "/w==", "null_count": 1, "distinct_count": null}),
+        ]
+    );
+    assert_eq!(statistics.row_groups.len(), 2);
+    assert_eq!(
+        json(&statistics.row_groups[0][0]),
+        serde_json::json!({"column": "id", "min": -1, "max": 3, "null_count": 0, "distinct_count": null})
+    );
+    assert_eq!(
+        json(&statistics.row_groups[1][1]),
+        serde_json::json!({"column": "name", "min": "a", "max": "a", "null_count": 0, "distinct_count": null})
+    );
+}
+
+#[test]
+fn test_read_rows_offset_limit() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}]}"#;
+    let files = (0..7).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 3}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let ids = |options: &str| {
+        let options = crate::options::parse_reader_options(options).unwrap();
+        read_rows(bytes.clone(), options)
+            .unwrap()
+            .iter()
+            .map(|row| row["id"].as_i64().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ids("{}"), (0..7).collect::<Vec<_>>());
+    assert_eq!(ids(r#"{"offset": 2, "limit": 3}"#), vec![2, 3, 4]);
+    assert_eq!(ids(r#"{"offset": 3, "limit": 3}"#), vec![3, 4, 5]);
+    assert_eq!(ids(r#"{"offset": 5}"#), vec![5, 6]);
+    assert_eq!(ids(r#"{"limit": 1}"#), vec![0]);
+    assert_eq!(ids(r#"{"offset": 6, "limit": 10}"#), vec![6]);
+    assert_eq!(ids(r#"{"offset": 7}"#), Vec::<i64>::new());
+    assert_eq!(ids(r#"{"limit": 0}"#), Vec::<i64>::new());
+    assert!(crate::options::parse_reader_options(r#"{"offset": -1}"#).is_err());
+}
+
+#[test]
+fn test_read_rows_filter() {
+    use crate::filter::Predicate;
+
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "status", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "day", "type": "INT32", "logical_type": "DATE"}
+        ]
+    }
+    "#;
+    let statuses = ["ok", "ok", "failed", "ok", "ok", "ok", "failed", "ok"];
+    let files = statuses
+        .iter()
+        .enumerate()
+        .map(|(id, status)| {
+            format!(
+                r#"{{"id": {}, "status": "{}", "day": "2024-01-0{}"}}"#,
+                id,
+                status,
+                id + 1
+            )
+        })
+        .collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let ids = |options: &str| {
+        let options = crate::options::parse_reader_options(options).unwrap();
+        read_rows(bytes.clone(), options)
+            .unwrap()
+            .iter()
+            .map(|row| row["id"].as_i64().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        ids(r#"{"filter": {"field": "status", "op": "==", "value": "failed"}}"#),
+        vec![2, 6]
+    );
+    assert_eq!(
+        ids(r#"{"filter": {"field": "status", "op": "==", "value": "failed"}, "offset": 1}"#),
+        vec![6]
+    );
+    assert_eq!(
+        ids(
+            r#"{"filter": {"or": [{"field": "id", "op": "<", "value": 2}, {"field": "id", "op": ">=", "value": 7}]}, "limit": 2}"#
+        ),
+        vec![0, 1]
+    );
+    assert_eq!(
+        ids(
+            r#"{"filter": {"and": [{"field": "id", "op": ">", "value": 2}, {"field": "status", "op": "in", "value": ["failed"]}]}}"#
+        ),
+        vec![6]
+    );
+    assert_eq!(
+        ids(r#"{"filter": {"field": "day", "op": ">=", "value": "2024-01-07"}}"#),
+        vec![6, 7]
+    );
+
+    let options = crate::options::parse_reader_options(
+        r#"{"filter": {"field": "missing", "op": "==", "value": 1}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        read_rows(bytes.clone(), options).unwrap_err(),
+        "Unknown field missing in filter"
+    );
+
+    // Only the row groups the statistics can't rule out may match
+    let reader = open(bytes).unwrap();
+    let filter: Predicate =
+        serde_json::from_str(r#"{"field": "status", "op": "==", "value": "failed"}"#).unwrap();
+    let candidates = reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|row_group| filter.may_match(&|column| column_range(row_group, column)))
+        .collect::<Vec<_>>();
+    assert_eq!(candidates, vec![false, true, false, true]);
+    let filter: Predicate =
+        serde_json::from_str(r#"{"field": "day", "op": "==", "value": "2024-01-01"}"#).unwrap();
+    assert!(reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .all(|row_group| filter.may_match(&|column| column_range(row_group, column))));
+}
+
+#[test]
+fn test_next_ndjson() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}, {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}]}"#;
+    let files = (0..3)
+        .map(|id| format!(r#"{{"id": {}, "tags": ["t{}"]}}"#, id, id))
+        .collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let mut rows = Rows::new(bytes, Default::default()).unwrap();
+    let mut chunks = vec![];
+    while let Some(chunk) = rows.next_ndjson().unwrap() {
+        chunks.push(String::from_utf8(chunk).unwrap());
+    }
+    assert_eq!(
+        chunks,
+        vec![
+            "{\"id\":0,\"tags\":[\"t0\"]}\n{\"id\":1,\"tags\":[\"t1\"]}\n",
+            "{\"id\":2,\"tags\":[\"t2\"]}\n",
+        ]
+    );
+}
+
+#[test]
+fn test_schema_text_and_json() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "price", "type": "INT64", "logical_type": "DECIMAL", "precision": 10, "scale": 2},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"},
+            {"name": "counts", "type": "INT32", "logical_type": "MAP", "repetition_type": "OPTIONAL"}
+        ]
+    }
+    "#;
+    let bytes = crate::write_parquet(schema.to_string(), vec![], &Default::default()).unwrap();
+    assert!(schema_text(bytes.clone())
+        .unwrap()
+        .starts_with("message schema {\n  REQUIRED INT64 id;\n  OPTIONAL BYTE_ARRAY name (UTF8);"));
+
+    let json = schema_json(bytes).unwrap();
+    let expected: Value = serde_json::json!({
+        "fields": [
+            {"name": "id", "type": "INT64", "repetition_type": "REQUIRED"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "price", "type": "INT64", "logical_type": "DECIMAL", "repetition_type": "REQUIRED", "precision": 10, "scale": 2},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "repetition_type": "REQUIRED", "element_logical_type": "UTF8"},
+            {"name": "counts", "type": "INT32", "logical_type": "MAP", "repetition_type": "OPTIONAL"}
+        ]
+    });
+    assert_eq!(serde_json::from_str::<Value>(&json).unwrap(), expected);
+    // The JSON writes a file with the same schema
+    assert!(crate::CompiledSchema::parse(&json).is_ok());
+
+    let message = parquet::schema::parser::parse_message_type(
+        "message m { required int32 id; optional group address { required binary city (UTF8); optional int32 zip; } }",
+    )
+    .unwrap();
+    let fields = to_parquet_schema(&message).unwrap().fields;
+    assert_eq!(
+        fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.source_key.as_deref(), f.repetition_type))
+            .collect::<Vec<_>>(),
+        vec![
+            ("id", None, Some(ParquetRepetition::Required)),
+            (
+                "address_city",
+                Some("address.city"),
+                Some(ParquetRepetition::Optional)
+            ),
+            (
+                "address_zip",
+                Some("address.zip"),
+                Some(ParquetRepetition::Optional)
+            ),
+        ]
+    );
+    let message = parquet::schema::parser::parse_message_type(
+        "message m { repeated group points { required int32 x; } }",
+    )
+    .unwrap();
+    assert_eq!(
+        to_parquet_schema(&message).unwrap_err(),
+        "Unsupported parquet type for field points"
+    );
+}
+
+#[test]
+fn test_key_value_metadata() {
+    use parquet::thrift::TSerializable;
+    use thrift::protocol::TCompactOutputProtocol;
+
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}, {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8"}]}"#;
+    let files = vec![r#"{"id": 1, "name": "a"}"#.to_string()];
+    let options = crate::options::parse_options(
+        r#"{"key_value_metadata": {"source": "billing", "note": null}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let metadata = key_value_metadata(&bytes).unwrap();
+    assert_eq!(
+        serde_json::to_value(&metadata.file).unwrap(),
+        serde_json::json!({"note": null, "source": "billing"})
+    );
+    assert!(metadata.columns.is_empty());
+
+    // The writer has no column metadata, so it's added to the footer here
+    let mut footer = footer(&bytes).unwrap();
+    let chunk = footer.row_groups[0].columns[1].meta_data.as_mut().unwrap();
+    chunk.key_value_metadata = Some(vec![KeyValue::new("unit".to_string(), "none".to_string())]);
+    let length = u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap());
+    let mut rewritten = bytes[..bytes.len() - 8 - length as usize].to_vec();
+    let mut serialized = vec![];
+    footer
+        .write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut serialized))
+        .unwrap();
+    rewritten.extend_from_slice(&serialized);
+    rewritten.extend_from_slice(&(serialized.len() as u32).to_le_bytes());
+    rewritten.extend_from_slice(b"PAR1");
+
+    let metadata = key_value_metadata(&rewritten).unwrap();
+    assert_eq!(
+        metadata.columns,
+        vec![ColumnMetadata {
+            row_group: 0,
+            column: "name".to_string(),
+            metadata: BTreeMap::from([("unit".to_string(), Some("none".to_string()))]),
+        }]
+    );
+    assert!(key_value_metadata(b"PAR1").is_err());
+}
+
+#[test]
+fn test_dump_pages() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..5)
+        .map(|id| format!(r#"{{"id": {}, "tags": ["t{}", "x"]}}"#, id, id % 2))
+        .collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 3}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let pages = dump_pages(bytes.clone(), "tags.list.element").unwrap();
+    let summary = pages
+        .iter()
+        .map(|p| (p.row_group, p.page, p.page_type.as_str(), p.num_values))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            (0, 0, "DICTIONARY_PAGE", Some(3)),
+            (0, 1, "DATA_PAGE", Some(6)),
+            (1, 0, "DICTIONARY_PAGE", Some(3)),
+            (1, 1, "DATA_PAGE", Some(4)),
+        ]
+    );
+    assert_eq!(pages[0].encoding.as_deref(), Some("PLAIN"));
+    assert_eq!(pages[1].encoding.as_deref(), Some("RLE_DICTIONARY"));
+    assert!(pages
+        .iter()
+        .all(|p| p.compressed_size > 0 && p.has_checksum));
+
+    assert_eq!(
+        dump_pages(bytes, "tags").unwrap_err(),
+        "Unknown column tags"
+    );
+}
+
+#[test]
+fn test_storage_report() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "status", "type": "BYTE_ARRAY", "logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..100)
+        .map(|id| {
+            format!(
+                r#"{{"id": {}, "status": "{}"}}"#,
+                id,
+                ["ok", "failed"][id % 2]
+            )
+        })
+        .collect::<Vec<_>>();
+    let options = crate::options::parse_options(
+        r#"{"max_rows_per_row_group": 50, "columns": {"id": {"dictionary_enabled": false}}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let report = storage_report(bytes).unwrap();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[0].pages.column, "id");
+    assert_eq!(report[0].pages.encodings, vec!["PLAIN"]);
+    assert!(!report[0].dictionary);
+    assert_eq!(report[1].pages.encodings, vec!["RLE_DICTIONARY"]);
+    assert!(report[1].dictionary);
+    assert!(!report[1].pages.dictionary_fallback);
+    for column in &report {
+        assert!(column.compressed_bytes > 0 && column.uncompressed_bytes > 0);
+        assert_eq!(
+            column.compression_ratio,
+            Some(column.uncompressed_bytes as f64 / column.compressed_bytes as f64)
+        );
+    }
+    assert_eq!(
+        serde_json::to_value(&report[1]).unwrap()["column"],
+        "status"
+    );
+}
+
+#[test]
+fn test_read_rows_head_stops_early() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}]}"#;
+    let files = (0..6).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    // Corrupt the last row group, which reading the first rows never reaches
+    let file = open(bytes.clone()).unwrap();
+    let chunk = file.metadata().row_group(2).column(0);
+    let (start, _) = chunk.byte_range();
+    let mut corrupt = bytes.clone();
+    corrupt[start as usize] ^= 0xff;
+
+    let head = |bytes: &[u8], n| {
+        let options = ReaderOptions {
+            limit: Some(n),
+            ..Default::default()
+        };
+        read_rows(bytes.to_vec(), options)
+    };
+    let rows = head(&corrupt, 3).unwrap();
+    assert_eq!(
+        rows,
+        vec![
+            serde_json::json!({"id": 0}),
+            serde_json::json!({"id": 1}),
+            serde_json::json!({"id": 2}),
+        ]
+    );
+    assert!(head(&corrupt, 5).is_err());
+    assert_eq!(head(&bytes, 10).unwrap().len(), 6);
+}
+
+#[test]
+fn test_read_rows_row_group() {
+    let schema = r#"{"fields": [{"name": "id", "type": "INT64"}]}"#;
+    let files = (0..7).map(|id| format!(r#"{{"id": {}}}"#, id)).collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 3}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let ids = |options: &str| {
+        let options = crate::options::parse_reader_options(options).unwrap();
+        read_rows(bytes.clone(), options).map(|rows| {
+            rows.iter()
+                .map(|row| row["id"].as_i64().unwrap())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(ids(r#"{"row_group": 0}"#).unwrap(), vec![0, 1, 2]);
+    assert_eq!(ids(r#"{"row_group": 1}"#).unwrap(), vec![3, 4, 5]);
+    assert_eq!(ids(r#"{"row_group": 2}"#).unwrap(), vec![6]);
+    assert_eq!(
+        ids(r#"{"row_group": 1, "offset": 1, "limit": 1}"#).unwrap(),
+        vec![4]
+    );
+    assert_eq!(
+        ids(r#"{"row_group": 1, "filter": {"field": "id", "op": ">", "value": 3}}"#).unwrap(),
+        vec![4, 5]
+    );
+    assert_eq!(
+        ids(r#"{"row_group": 3}"#).unwrap_err(),
+        "Row group 3 out of range, the file has 3"
+    );
+}
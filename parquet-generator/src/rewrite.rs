@@ -0,0 +1,1080 @@
+use crate::computed::Computed;
+use crate::error::WriteError;
+use crate::options::{
+    DedupeOptions, Keep, RewriteOptions, SortingColumnOptions, TranscodeOptions, WriterOptions,
+};
+use crate::reader::{self, Rows};
+use crate::{CompiledSchema, NullPolicy, ParquetSchema};
+use arrow_array::cast::AsArray;
+use arrow_array::{
+    downcast_primitive_array, Array, ArrayRef, ArrowNativeTypeOp, RecordBatch, RecordBatchReader,
+    UInt32Array,
+};
+use arrow_cast::display::{ArrayFormatter, FormatOptions};
+use arrow_schema::{DataType, SchemaRef};
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+use parquet::column::writer::ColumnCloseResult;
+use parquet::file::metadata::{ColumnChunkMetaData, ParquetMetaData};
+use parquet::file::properties::{WriterProperties, WriterPropertiesBuilder};
+use parquet::file::reader::FileReader;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::format::{KeyValue, SortingColumn};
+use parquet::schema::types::{ColumnDescPtr, Type, TypePtr};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The key Arrow writers store the Arrow schema under in the footer, written afresh by each
+/// `ArrowWriter`
+const ARROW_SCHEMA_KEY: &str = "ARROW:schema";
+
+/// The rows decoded at a time when a file is rewritten
+const BATCH_SIZE: usize = 8192;
+
+/// A parquet file being rewritten, read as Arrow record batches so values aren't converted
+/// through JSON
+pub(crate) struct Source {
+    pub(crate) schema: SchemaRef,
+    pub(crate) metadata: Arc<ParquetMetaData>,
+    pub(crate) batches: ParquetRecordBatchReader,
+}
+
+impl Source {
+    pub(crate) fn open(bytes: Vec<u8>) -> Result<Source, String> {
+        Source::open_without(bytes, &[])
+    }
+
+    /// Open a file without the top level fields in `dropped`, whose columns aren't decoded at all
+    pub(crate) fn open_without(bytes: Vec<u8>, dropped: &[String]) -> Result<Source, String> {
+        let mut builder = match ParquetRecordBatchReaderBuilder::try_new(Bytes::from(bytes)) {
+            Ok(builder) => builder,
+            Err(e) => return Err(format!("Error reading parquet file: {}", e)),
+        };
+        if !dropped.is_empty() {
+            let fields = builder.parquet_schema().root_schema().get_fields();
+            if let Some(name) = dropped
+                .iter()
+                .find(|name| !fields.iter().any(|field| field.name() == name.as_str()))
+            {
+                return Err(format!("Unknown field {}", name));
+            }
+            let kept = (0..fields.len())
+                .filter(|i| !dropped.iter().any(|name| name == fields[*i].name()))
+                .collect::<Vec<_>>();
+            if kept.is_empty() {
+                return Err("Can't drop every field".to_string());
+            }
+            let mask = ProjectionMask::roots(builder.parquet_schema(), kept);
+            builder = builder.with_projection(mask);
+        }
+        let metadata = builder.metadata().clone();
+        match builder.with_batch_size(BATCH_SIZE).build() {
+            Ok(batches) => Ok(Source {
+                schema: batches.schema(),
+                metadata,
+                batches,
+            }),
+            Err(e) => Err(format!("Error reading parquet file: {}", e)),
+        }
+    }
+
+    /// Writer properties keeping what the file was written with: its key-value metadata, the
+    /// codec of each column, whether it's dictionary encoded and the size of its row groups
+    pub(crate) fn properties(&self) -> WriterPropertiesBuilder {
+        let mut builder =
+            WriterProperties::builder().set_key_value_metadata(key_value_metadata(&self.metadata));
+        let max_rows = self
+            .metadata
+            .row_groups()
+            .iter()
+            .map(|row_group| row_group.num_rows() as usize)
+            .max();
+        if let Some(max_rows) = max_rows.filter(|rows| *rows > 0) {
+            builder = builder.set_max_row_group_size(max_rows);
+        }
+        if let Some(row_group) = self.metadata.row_groups().first() {
+            for chunk in row_group.columns() {
+                let path = chunk.column_path().clone();
+                builder = builder
+                    .set_column_compression(path.clone(), chunk.compression())
+                    .set_column_dictionary_enabled(path, chunk.dictionary_page_offset().is_some());
+            }
+        }
+        builder
+    }
+
+    /// Every row of the file as a single record batch
+    pub(crate) fn read_all(&mut self) -> Result<RecordBatch, String> {
+        let mut batches = vec![];
+        while let Some(batch) = self.next_batch()? {
+            batches.push(batch);
+        }
+        match arrow_select::concat::concat_batches(&self.schema, &batches) {
+            Ok(batch) => Ok(batch),
+            Err(e) => Err(format!("Error reading record batch: {}", e)),
+        }
+    }
+
+    /// The record batches of the file, in order
+    pub(crate) fn next_batch(&mut self) -> Result<Option<RecordBatch>, String> {
+        match self.batches.next() {
+            Some(Ok(batch)) => Ok(Some(batch)),
+            Some(Err(e)) => Err(format!("Error reading record batch: {}", e)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A file's key-value metadata, without the Arrow schema a rewritten file gets anew
+fn key_value_metadata(metadata: &ParquetMetaData) -> Option<Vec<KeyValue>> {
+    metadata
+        .file_metadata()
+        .key_value_metadata()
+        .map(|entries| {
+            entries
+                .iter()
+                .filter(|entry| entry.key != ARROW_SCHEMA_KEY)
+                .cloned()
+                .collect()
+        })
+}
+
+/// Write record batches as a parquet file
+pub(crate) struct Sink {
+    writer: ArrowWriter<Vec<u8>>,
+    pub(crate) rows: usize,
+}
+
+impl Sink {
+    pub(crate) fn new(schema: SchemaRef, properties: WriterProperties) -> Result<Sink, String> {
+        match ArrowWriter::try_new(vec![], schema, Some(properties)) {
+            Ok(writer) => Ok(Sink { writer, rows: 0 }),
+            Err(_) => Err("Error creating writer".to_string()),
+        }
+    }
+
+    pub(crate) fn write(&mut self, batch: &RecordBatch) -> Result<(), String> {
+        match self.writer.write(batch) {
+            Ok(()) => {
+                self.rows += batch.num_rows();
+                Ok(())
+            }
+            Err(_) => Err("Error writing record batch".to_string()),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Result<Vec<u8>, String> {
+        match self.writer.into_inner() {
+            Ok(bytes) => Ok(bytes),
+            Err(_) => Err("Error closing writer".to_string()),
+        }
+    }
+}
+
+/// Split a parquet file into files of `rows_per_file` rows, the last holding the rest. Each file
+/// has the source's schema, key-value metadata, codecs and row group size. A file without rows is
+/// split into a single empty file
+pub(crate) fn split(bytes: Vec<u8>, rows_per_file: usize) -> Result<Vec<(Vec<u8>, usize)>, String> {
+    if rows_per_file == 0 {
+        return Err("rows_per_file must be greater than 0".to_string());
+    }
+    let mut source = Source::open(bytes)?;
+    let properties = source.properties().build();
+
+    let mut files = vec![];
+    let mut sink: Option<Sink> = None;
+    while let Some(batch) = source.next_batch()? {
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            let current = match &mut sink {
+                Some(current) => current,
+                None => sink.insert(Sink::new(source.schema.clone(), properties.clone())?),
+            };
+            let length = (rows_per_file - current.rows).min(batch.num_rows() - offset);
+            current.write(&batch.slice(offset, length))?;
+            offset += length;
+            if current.rows < rows_per_file {
+                continue;
+            }
+            if let Some(full) = sink.take() {
+                let rows = full.rows;
+                files.push((full.finish()?, rows));
+            }
+        }
+    }
+    match sink {
+        Some(last) => {
+            let rows = last.rows;
+            files.push((last.finish()?, rows));
+        }
+        None if files.is_empty() => {
+            files.push((Sink::new(source.schema, properties)?.finish()?, 0));
+        }
+        None => {}
+    }
+    Ok(files)
+}
+
+/// Rewrite a parquet file with other codecs, encodings or row group sizes, keeping its rows,
+/// schema and key-value metadata, and whatever `options` doesn't change
+pub(crate) fn transcode(bytes: Vec<u8>, options: &TranscodeOptions) -> Result<Vec<u8>, String> {
+    let mut source = Source::open(bytes)?;
+    let descriptor = source.metadata.file_metadata().schema_descr_ptr();
+    let properties = options.writer_properties(source.properties(), &descriptor)?;
+    let mut sink = Sink::new(source.schema.clone(), properties)?;
+    while let Some(batch) = source.next_batch()? {
+        sink.write(&batch)?;
+    }
+    sink.finish()
+}
+
+/// Rewrite a parquet file without the top level fields in `columns`, keeping everything else
+pub(crate) fn drop_columns(bytes: Vec<u8>, columns: &[String]) -> Result<Vec<u8>, String> {
+    let mut source = Source::open_without(bytes, columns)?;
+    let mut sink = Sink::new(source.schema.clone(), source.properties().build())?;
+    while let Some(batch) = source.next_batch()? {
+        sink.write(&batch)?;
+    }
+    sink.finish()
+}
+
+/// Rewrite a parquet file with top level fields renamed and fields appended. The file's column
+/// chunks are copied as they're encoded, only the appended fields' columns are written, a chunk for
+/// each row group. Page indexes and bloom filters aren't copied
+pub(crate) fn rewrite_columns(
+    bytes: Vec<u8>,
+    options: &RewriteOptions,
+) -> Result<Vec<u8>, WriteError> {
+    let source = Bytes::from(bytes);
+    let file = reader::open(source.clone())?;
+    let metadata = file.metadata();
+    let root = metadata.file_metadata().schema();
+
+    if let Some(name) = options
+        .rename
+        .keys()
+        .find(|name| !root.get_fields().iter().any(|f| f.name() == name.as_str()))
+    {
+        return Err(format!("Unknown field {}", name).into());
+    }
+    let mut fields = root
+        .get_fields()
+        .iter()
+        .map(|field| match options.rename.get(field.name()) {
+            Some(name) => renamed(field, name),
+            None => Ok(field.clone()),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut appended = options.append.clone();
+    for field in appended.iter_mut() {
+        match &field.computed {
+            Some(Computed::Callback(_)) => {
+                return Err(format!(
+                    "Appended field {} must be computed by an expression",
+                    field.name
+                )
+                .into())
+            }
+            Some(Computed::Expression(_)) => {}
+            None if field.default.is_some() => field.on_null = NullPolicy::Default,
+            None => {
+                return Err(format!(
+                    "Appended field {} needs a computed expression or a default",
+                    field.name
+                )
+                .into())
+            }
+        }
+    }
+    let appended = CompiledSchema::build(ParquetSchema { fields: appended })?;
+    fields.extend(appended.message_type.get_fields().iter().cloned());
+    let mut names = HashSet::new();
+    if let Some(field) = fields.iter().find(|field| !names.insert(field.name())) {
+        return Err(format!("Duplicate field {}", field.name()).into());
+    }
+    let message_type = Type::group_type_builder(root.name())
+        .with_fields(fields)
+        .build()
+        .map_err(|e| format!("Error building schema: {}", e))?;
+
+    let properties = WriterProperties::builder()
+        .set_key_value_metadata(key_value_metadata(metadata))
+        .build();
+    let mut writer =
+        match SerializedFileWriter::new(vec![], Arc::new(message_type), Arc::new(properties)) {
+            Ok(writer) => writer,
+            Err(_) => return Err("Error creating writer".to_string().into()),
+        };
+    let columns = writer.schema_descr().columns().to_vec();
+    let computed = appended
+        .schema
+        .fields
+        .iter()
+        .any(|field| field.computed.is_some());
+    let mut rows = match computed {
+        true => Some(Rows::new(source.to_vec(), Default::default())?),
+        false => None,
+    };
+
+    for row_group in metadata.row_groups() {
+        let num_rows = row_group.num_rows() as usize;
+        let records = match rows.as_mut() {
+            Some(rows) if num_rows > 0 => rows
+                .next_row_group()?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|row| match row {
+                    Value::Object(row) => renamed_keys(row, options),
+                    _ => Map::new(),
+                })
+                .collect(),
+            _ => vec![Map::new(); num_rows],
+        };
+        let written = match num_rows {
+            _ if num_rows == 0 || appended.schema.fields.is_empty() => None,
+            _ => {
+                let options = WriterOptions {
+                    max_rows_per_row_group: Some(num_rows),
+                    ..Default::default()
+                };
+                let bytes = Bytes::from(crate::write_records(&appended, &records, &options)?);
+                Some((reader::open(bytes.clone())?, bytes))
+            }
+        };
+
+        let mut row_group_writer = match writer.next_row_group() {
+            Ok(row_group_writer) => row_group_writer,
+            Err(_) => return Err("Error creating row group writer".to_string().into()),
+        };
+        let chunks = row_group.columns().iter().map(|chunk| (chunk, &source));
+        let appended_chunks = written.iter().flat_map(|(file, bytes)| {
+            let row_group = file.metadata().row_group(0);
+            row_group.columns().iter().map(move |chunk| (chunk, bytes))
+        });
+        for (i, (chunk, bytes)) in chunks.chain(appended_chunks).enumerate() {
+            let close = ColumnCloseResult {
+                bytes_written: chunk.compressed_size() as u64,
+                rows_written: num_rows as u64,
+                metadata: with_descriptor(chunk, columns[i].clone())?,
+                bloom_filter: None,
+                column_index: None,
+                offset_index: None,
+            };
+            if let Err(e) = row_group_writer.append_column(bytes, close) {
+                return Err(format!("Error copying column chunk: {}", e).into());
+            }
+        }
+        // A row group without rows has nothing to copy for the appended fields, so their empty
+        // chunks are written instead
+        while let Ok(Some(column_writer)) = row_group_writer.next_column() {
+            if column_writer.close().is_err() {
+                return Err("Error closing column writer".to_string().into());
+            }
+        }
+        if row_group_writer.close().is_err() {
+            return Err("Error closing row group writer".to_string().into());
+        }
+    }
+    match writer.into_inner() {
+        Ok(bytes) => Ok(bytes),
+        Err(_) => Err("Error closing writer".to_string().into()),
+    }
+}
+
+/// A copy of a top level field's type with another name
+fn renamed(field: &TypePtr, name: &str) -> Result<TypePtr, String> {
+    let info = field.get_basic_info();
+    let built = match field.as_ref() {
+        Type::PrimitiveType {
+            physical_type,
+            type_length,
+            scale,
+            precision,
+            ..
+        } => Type::primitive_type_builder(name, *physical_type)
+            .with_repetition(info.repetition())
+            .with_logical_type(info.logical_type())
+            .with_converted_type(info.converted_type())
+            .with_length(*type_length)
+            .with_precision(*precision)
+            .with_scale(*scale)
+            .with_id(info.has_id().then(|| info.id()))
+            .build(),
+        Type::GroupType { fields, .. } => {
+            let mut builder = Type::group_type_builder(name)
+                .with_fields(fields.clone())
+                .with_logical_type(info.logical_type())
+                .with_converted_type(info.converted_type())
+                .with_id(info.has_id().then(|| info.id()));
+            if info.has_repetition() {
+                builder = builder.with_repetition(info.repetition());
+            }
+            builder.build()
+        }
+    };
+    match built {
+        Ok(built) => Ok(Arc::new(built)),
+        Err(e) => Err(format!("Error renaming field {}: {}", field.name(), e)),
+    }
+}
+
+/// A row read from the file with its renamed fields under their new names
+fn renamed_keys(row: Map<String, Value>, options: &RewriteOptions) -> Map<String, Value> {
+    row.into_iter()
+        .map(|(key, value)| match options.rename.get(&key) {
+            Some(name) => (name.clone(), value),
+            None => (key, value),
+        })
+        .collect()
+}
+
+/// A column chunk's metadata for the column it's copied to, which may have another path
+fn with_descriptor(
+    chunk: &ColumnChunkMetaData,
+    column: ColumnDescPtr,
+) -> Result<ColumnChunkMetaData, String> {
+    let mut builder = ColumnChunkMetaData::builder(column)
+        .set_compression(chunk.compression())
+        .set_encodings(chunk.encodings().clone())
+        .set_file_offset(chunk.file_offset())
+        .set_total_compressed_size(chunk.compressed_size())
+        .set_total_uncompressed_size(chunk.uncompressed_size())
+        .set_num_values(chunk.num_values())
+        .set_data_page_offset(chunk.data_page_offset())
+        .set_dictionary_page_offset(chunk.dictionary_page_offset());
+    if let Some(statistics) = chunk.statistics() {
+        builder = builder.set_statistics(statistics.clone());
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Error copying column chunk: {}", e))
+}
+
+/// Rewrite a parquet file with its rows sorted by `keys`, in order, and the sorting columns set in
+/// its row groups' metadata. Rows with equal keys keep their order, and nulls sort last unless a
+/// key's `nulls_first` is set, whichever way it's sorted
+pub(crate) fn sort(bytes: Vec<u8>, keys: &[SortingColumnOptions]) -> Result<Vec<u8>, String> {
+    if keys.is_empty() {
+        return Err("No columns to sort by".to_string());
+    }
+    let mut source = Source::open(bytes)?;
+    let descriptor = source.metadata.file_metadata().schema_descr_ptr();
+    let mut sorting_columns = vec![];
+    let mut key_columns = vec![];
+    for key in keys {
+        let leaf = descriptor
+            .columns()
+            .iter()
+            .position(|c| c.path().parts() == [key.column.clone()] && c.max_rep_level() == 0);
+        let column = source.schema.index_of(&key.column).ok();
+        let (Some(leaf), Some(column)) = (leaf, column) else {
+            return Err(format!(
+                "Sorting column {} must be a field with a single, non-repeated column",
+                key.column
+            ));
+        };
+        sorting_columns.push(SortingColumn::new(
+            leaf as i32,
+            key.descending,
+            key.nulls_first,
+        ));
+        key_columns.push(column);
+    }
+
+    let batch = source.read_all()?;
+    let comparators = keys
+        .iter()
+        .zip(key_columns)
+        .map(|(key, column)| comparator(key, batch.column(column)))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut indices = (0..batch.num_rows() as u32).collect::<Vec<_>>();
+    indices.sort_by(|a, b| {
+        comparators
+            .iter()
+            .map(|compare| compare(*a as usize, *b as usize))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    let sorted = take_rows(&batch, indices)?;
+
+    let properties = source
+        .properties()
+        .set_sorting_columns(Some(sorting_columns))
+        .build();
+    let mut sink = Sink::new(source.schema.clone(), properties)?;
+    sink.write(&sorted)?;
+    sink.finish()
+}
+
+/// Rewrite a parquet file without rows whose values for the `key_columns` fields match another
+/// row's, keeping the first or last of them as `options` sets. Nulls compare equal to each other.
+/// Returns the file and the number of rows removed
+pub(crate) fn dedupe(
+    bytes: Vec<u8>,
+    key_columns: &[String],
+    options: &DedupeOptions,
+) -> Result<(Vec<u8>, usize), String> {
+    if key_columns.is_empty() {
+        return Err("No key columns to dedupe on".to_string());
+    }
+    let mut source = Source::open(bytes)?;
+    let columns = key_columns
+        .iter()
+        .map(|name| match source.schema.index_of(name) {
+            Ok(column) => Ok(column),
+            Err(_) => Err(format!("Unknown field {} in key_columns", name)),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let batch = source.read_all()?;
+    let formatters = columns
+        .iter()
+        .map(|column| {
+            ArrayFormatter::try_new(batch.column(*column).as_ref(), &FormatOptions::default())
+                .map_err(|e| format!("Error reading key: {}", e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut seen = HashSet::new();
+    let mut kept = vec![];
+    let rows = 0..batch.num_rows();
+    let rows: Box<dyn Iterator<Item = usize>> = match options.keep {
+        Keep::First => Box::new(rows),
+        Keep::Last => Box::new(rows.rev()),
+    };
+    for row in rows {
+        let key = columns
+            .iter()
+            .zip(&formatters)
+            .map(|(column, formatter)| {
+                let values = batch.column(*column);
+                (!values.is_null(row)).then(|| formatter.value(row).to_string())
+            })
+            .collect::<Vec<_>>();
+        if seen.insert(key) {
+            kept.push(row as u32);
+        }
+    }
+    if options.keep == Keep::Last {
+        kept.reverse();
+    }
+    let removed = batch.num_rows() - kept.len();
+
+    let mut sink = Sink::new(source.schema.clone(), source.properties().build())?;
+    sink.write(&take_rows(&batch, kept)?)?;
+    Ok((sink.finish()?, removed))
+}
+
+/// The rows of a record batch at `indices`, in their order
+fn take_rows(batch: &RecordBatch, indices: Vec<u32>) -> Result<RecordBatch, String> {
+    let indices = UInt32Array::from(indices);
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| arrow_select::take::take(column, &indices, None))
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|columns| RecordBatch::try_new(batch.schema(), columns));
+    match columns {
+        Ok(taken) => Ok(taken),
+        Err(e) => Err(format!("Error selecting rows: {}", e)),
+    }
+}
+
+/// Compare two rows of a sort key's column in the order the key sorts them
+fn comparator<'a>(
+    key: &SortingColumnOptions,
+    column: &'a ArrayRef,
+) -> Result<Box<dyn Fn(usize, usize) -> Ordering + 'a>, String> {
+    let values: Box<dyn Fn(usize, usize) -> Ordering + 'a> = downcast_primitive_array!(
+        column => Box::new(move |a, b| column.value(a).compare(column.value(b))),
+        DataType::Boolean => {
+            let column = column.as_boolean();
+            Box::new(move |a, b| column.value(a).cmp(&column.value(b)))
+        }
+        DataType::Utf8 => {
+            let column = column.as_string::<i32>();
+            Box::new(move |a, b| column.value(a).cmp(column.value(b)))
+        }
+        DataType::LargeUtf8 => {
+            let column = column.as_string::<i64>();
+            Box::new(move |a, b| column.value(a).cmp(column.value(b)))
+        }
+        DataType::Binary => {
+            let column = column.as_binary::<i32>();
+            Box::new(move |a, b| column.value(a).cmp(column.value(b)))
+        }
+        DataType::LargeBinary => {
+            let column = column.as_binary::<i64>();
+            Box::new(move |a, b| column.value(a).cmp(column.value(b)))
+        }
+        DataType::FixedSizeBinary(_) => {
+            let column = column.as_fixed_size_binary();
+            Box::new(move |a, b| column.value(a).cmp(column.value(b)))
+        }
+        data_type => {
+            return Err(format!(
+                "Can't sort by column {} of type {}",
+                key.column, data_type
+            ))
+        }
+    );
+    let (descending, nulls_first) = (key.descending, key.nulls_first);
+    Ok(Box::new(move |a, b| {
+        match (column.is_null(a), column.is_null(b)) {
+            (true, true) => Ordering::Equal,
+            (true, false) if nulls_first => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (false, true) if nulls_first => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) if descending => values(a, b).reverse(),
+            (false, false) => values(a, b),
+        }
+    }))
+}
+
+#[test]
+fn test_split() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..7)
+        .map(|id| {
+            format!(
+                r#"{{"id": {}, "name": "n{}", "tags": ["t{}"]}}"#,
+                id, id, id
+            )
+        })
+        .collect::<Vec<_>>();
+    let options = crate::options::parse_options(
+        r#"{"max_rows_per_row_group": 2, "key_value_metadata": {"source": "billing"}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+    let rows = crate::reader::read_rows(bytes.clone(), Default::default()).unwrap();
+
+    let split_files = split(bytes.clone(), 3).unwrap();
+    assert_eq!(
+        split_files
+            .iter()
+            .map(|(_, rows)| *rows)
+            .collect::<Vec<_>>(),
+        vec![3, 3, 1]
+    );
+    let mut split_rows = vec![];
+    for (file, _) in &split_files {
+        split_rows.extend(crate::reader::read_rows(file.clone(), Default::default()).unwrap());
+        let info = crate::reader::inspect(file.clone()).unwrap();
+        assert!(info
+            .row_groups
+            .iter()
+            .all(|row_group| row_group.num_rows <= 2));
+        let metadata = crate::reader::key_value_metadata(file).unwrap();
+        assert_eq!(metadata.file["source"].as_deref(), Some("billing"));
+    }
+    assert_eq!(split_rows, rows);
+
+    assert_eq!(split(bytes.clone(), 7).unwrap().len(), 1);
+    assert_eq!(
+        split(bytes, 0).unwrap_err(),
+        "rows_per_file must be greater than 0"
+    );
+    let empty = crate::write_parquet(schema.to_string(), vec![], &Default::default()).unwrap();
+    let split_files = split(empty, 3).unwrap();
+    assert_eq!(split_files.len(), 1);
+    assert_eq!(split_files[0].1, 0);
+}
+
+#[test]
+fn test_transcode() {
+    use parquet::basic::Encoding;
+    use parquet::file::reader::FileReader;
+
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..10)
+        .map(|id| {
+            format!(
+                r#"{{"id": {}, "name": "n{}", "tags": ["t{}"]}}"#,
+                id,
+                id % 3,
+                id
+            )
+        })
+        .collect::<Vec<_>>();
+    let options =
+        crate::options::parse_options(r#"{"key_value_metadata": {"source": "billing"}}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+    let rows = crate::reader::read_rows(bytes.clone(), Default::default()).unwrap();
+
+    let options = crate::options::parse_transcode_options(
+        r#"{
+            "compression": "GZIP",
+            "max_rows_per_row_group": 4,
+            "columns": {"id": {"dictionary_enabled": false, "encoding": "DELTA_BINARY_PACKED"}}
+        }"#,
+    )
+    .unwrap();
+    let gzip = transcode(bytes.clone(), &options).unwrap();
+    assert_eq!(
+        crate::reader::read_rows(gzip.clone(), Default::default()).unwrap(),
+        rows
+    );
+    let info = crate::reader::inspect(gzip.clone()).unwrap();
+    assert_eq!(info.codecs, vec!["GZIP(GzipLevel(6))"]);
+    assert_eq!(
+        info.row_groups
+            .iter()
+            .map(|row_group| row_group.num_rows)
+            .collect::<Vec<_>>(),
+        vec![4, 4, 2]
+    );
+    let metadata = crate::reader::key_value_metadata(&gzip).unwrap();
+    assert_eq!(metadata.file["source"].as_deref(), Some("billing"));
+    let file = crate::reader::open(gzip.clone()).unwrap();
+    let columns = file.metadata().row_group(0).columns();
+    assert!(columns[0]
+        .encodings()
+        .contains(&Encoding::DELTA_BINARY_PACKED));
+    assert!(columns[0].dictionary_page_offset().is_none());
+    assert!(columns[1].dictionary_page_offset().is_some());
+
+    let options = crate::options::parse_transcode_options(
+        r#"{"columns": {"name": {"compression": "UNCOMPRESSED"}}}"#,
+    )
+    .unwrap();
+    let mixed = transcode(gzip, &options).unwrap();
+    let info = crate::reader::inspect(mixed.clone()).unwrap();
+    assert_eq!(info.codecs, vec!["GZIP(GzipLevel(6))", "UNCOMPRESSED"]);
+    assert_eq!(info.row_groups.len(), 3);
+    assert_eq!(
+        crate::reader::read_rows(mixed, Default::default()).unwrap(),
+        rows
+    );
+
+    let options = crate::options::parse_transcode_options(r#"{"columns": {"ssn": {}}}"#).unwrap();
+    assert_eq!(
+        transcode(bytes.clone(), &options).unwrap_err(),
+        "Unknown field ssn in columns"
+    );
+    let options = crate::options::parse_transcode_options(
+        r#"{"columns": {"name": {"encoding": "DELTA_BINARY_PACKED"}}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        transcode(bytes, &options).unwrap_err(),
+        "Invalid encoding for field name: DELTA_BINARY_PACKED encoding can't be used for BYTE_ARRAY columns"
+    );
+}
+
+#[test]
+fn test_drop_columns() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "ssn", "type": "BYTE_ARRAY", "logical_type": "UTF8"},
+            {"name": "email", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..5)
+        .map(|id| {
+            format!(
+                r#"{{"id": {}, "ssn": "000-00-000{}", "email": "u{}@example.com", "tags": ["t{}"]}}"#,
+                id, id, id, id
+            )
+        })
+        .collect::<Vec<_>>();
+    let options = crate::options::parse_options(
+        r#"{"max_rows_per_row_group": 2, "key_value_metadata": {"source": "billing"}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let dropped = drop_columns(bytes.clone(), &["ssn".to_string(), "email".to_string()]).unwrap();
+    let rows = crate::reader::read_rows(dropped.clone(), Default::default()).unwrap();
+    assert_eq!(rows.len(), 5);
+    assert_eq!(rows[3], serde_json::json!({"id": 3, "tags": ["t3"]}));
+    assert!(!dropped.windows(3).any(|window| window == b"ssn"));
+    assert!(!dropped.windows(11).any(|window| window == b"example.com"));
+    let info = crate::reader::inspect(dropped.clone()).unwrap();
+    assert_eq!(info.row_groups.len(), 3);
+    let metadata = crate::reader::key_value_metadata(&dropped).unwrap();
+    assert_eq!(metadata.file["source"].as_deref(), Some("billing"));
+
+    assert_eq!(
+        drop_columns(bytes.clone(), &["phone".to_string()]).unwrap_err(),
+        "Unknown field phone"
+    );
+    let every = ["id", "ssn", "email", "tags"].map(String::from);
+    assert_eq!(
+        drop_columns(bytes, &every).unwrap_err(),
+        "Can't drop every field"
+    );
+}
+
+#[test]
+fn test_rewrite_columns() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "first", "type": "BYTE_ARRAY", "logical_type": "UTF8"},
+            {"name": "last", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..5)
+        .map(|id| {
+            format!(
+                r#"{{"id": {}, "first": "f{}", "last": "l{}", "tags": ["t{}"]}}"#,
+                id, id, id, id
+            )
+        })
+        .collect::<Vec<_>>();
+    let options = crate::options::parse_options(
+        r#"{"max_rows_per_row_group": 2, "key_value_metadata": {"source": "billing"}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let options = crate::options::parse_rewrite_options(
+        r#"{
+            "rename": {"first": "first_name", "tags": "labels"},
+            "append": [
+                {"name": "ingest_batch_id", "type": "INT64", "default": 42},
+                {"name": "full_name", "type": "BYTE_ARRAY", "logical_type": "UTF8", "computed": "concat(first_name, ' ', last)"}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let rewritten = rewrite_columns(bytes.clone(), &options).unwrap();
+    let rows = crate::reader::read_rows(rewritten.clone(), Default::default()).unwrap();
+    assert_eq!(rows.len(), 5);
+    assert_eq!(
+        rows[3],
+        serde_json::json!({
+            "id": 3,
+            "first_name": "f3",
+            "last": "l3",
+            "labels": ["t3"],
+            "ingest_batch_id": 42,
+            "full_name": "f3 l3"
+        })
+    );
+    let info = crate::reader::inspect(rewritten.clone()).unwrap();
+    assert_eq!(
+        info.row_groups
+            .iter()
+            .map(|row_group| row_group.num_rows)
+            .collect::<Vec<_>>(),
+        vec![2, 2, 1]
+    );
+    let metadata = crate::reader::key_value_metadata(&rewritten).unwrap();
+    assert_eq!(metadata.file["source"].as_deref(), Some("billing"));
+    assert!(crate::validate::validate(rewritten).valid);
+
+    // Only renaming copies every chunk as is
+    let options =
+        crate::options::parse_rewrite_options(r#"{"rename": {"id": "user_id"}}"#).unwrap();
+    let renamed = rewrite_columns(bytes.clone(), &options).unwrap();
+    let original = reader::open(bytes.clone()).unwrap();
+    let copied = reader::open(renamed.clone()).unwrap();
+    for (original, copied) in original
+        .metadata()
+        .row_groups()
+        .iter()
+        .zip(copied.metadata().row_groups())
+    {
+        for (original, copied) in original.columns().iter().zip(copied.columns()) {
+            assert_eq!(original.compressed_size(), copied.compressed_size());
+            assert_eq!(original.statistics(), copied.statistics());
+        }
+    }
+    assert_eq!(
+        crate::reader::read_rows(renamed, Default::default()).unwrap()[0]["user_id"],
+        0
+    );
+
+    let error = |options: &str| {
+        let options = crate::options::parse_rewrite_options(options).unwrap();
+        rewrite_columns(bytes.clone(), &options)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        error(r#"{"rename": {"email": "mail"}}"#),
+        "Unknown field email"
+    );
+    assert_eq!(
+        error(r#"{"rename": {"first": "last"}}"#),
+        "Duplicate field last"
+    );
+    assert_eq!(
+        error(r#"{"append": [{"name": "batch", "type": "INT64"}]}"#),
+        "Appended field batch needs a computed expression or a default"
+    );
+}
+
+#[test]
+fn test_sort() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "region", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "score", "type": "DOUBLE"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let records = [
+        (0, Some("eu"), 1.5),
+        (1, Some("us"), 0.5),
+        (2, None, 2.5),
+        (3, Some("eu"), 3.5),
+        (4, Some("ap"), 0.5),
+        (5, Some("us"), 2.0),
+    ];
+    let files = records
+        .iter()
+        .map(|(id, region, score)| {
+            serde_json::json!({"id": id, "region": region, "score": score, "tags": [format!("t{}", id)]})
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    let options = crate::options::parse_options(
+        r#"{"max_rows_per_row_group": 4, "key_value_metadata": {"source": "billing"}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+    let ids = |bytes: Vec<u8>| {
+        crate::reader::read_rows(bytes, Default::default())
+            .unwrap()
+            .iter()
+            .map(|row| row["id"].as_i64().unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let keys = crate::options::parse_sorting_columns(
+        r#"[{"column": "region"}, {"column": "score", "descending": true}]"#,
+    )
+    .unwrap();
+    let sorted = sort(bytes.clone(), &keys).unwrap();
+    assert_eq!(ids(sorted.clone()), vec![4, 3, 0, 5, 1, 2]);
+    let rows = crate::reader::read_rows(sorted.clone(), Default::default()).unwrap();
+    assert_eq!(rows[1]["tags"], serde_json::json!(["t3"]));
+    let file = reader::open(sorted.clone()).unwrap();
+    assert_eq!(file.metadata().num_row_groups(), 2);
+    assert_eq!(
+        file.metadata().row_group(0).sorting_columns(),
+        Some(&vec![
+            SortingColumn::new(1, false, false),
+            SortingColumn::new(2, true, false)
+        ])
+    );
+    let metadata = crate::reader::key_value_metadata(&sorted).unwrap();
+    assert_eq!(metadata.file["source"].as_deref(), Some("billing"));
+
+    let keys = crate::options::parse_sorting_columns(
+        r#"[{"column": "region", "descending": true, "nulls_first": true}]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        ids(sort(bytes.clone(), &keys).unwrap()),
+        vec![2, 1, 5, 0, 3, 4]
+    );
+
+    let keys = crate::options::parse_sorting_columns(r#"[{"column": "tags"}]"#).unwrap();
+    assert_eq!(
+        sort(bytes.clone(), &keys).unwrap_err(),
+        "Sorting column tags must be a field with a single, non-repeated column"
+    );
+    assert_eq!(sort(bytes, &[]).unwrap_err(), "No columns to sort by");
+}
+
+#[test]
+fn test_dedupe() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "region", "type": "BYTE_ARRAY", "logical_type": "UTF8", "repetition_type": "OPTIONAL"},
+            {"name": "version", "type": "INT32"}
+        ]
+    }
+    "#;
+    let records = [
+        (1, Some("eu"), 1),
+        (2, Some("us"), 1),
+        (1, Some("eu"), 2),
+        (3, None, 1),
+        (1, Some("us"), 3),
+        (3, None, 2),
+    ];
+    let files = records
+        .iter()
+        .map(|(id, region, version)| {
+            serde_json::json!({"id": id, "region": region, "version": version}).to_string()
+        })
+        .collect::<Vec<_>>();
+    let options = crate::options::parse_options(
+        r#"{"max_rows_per_row_group": 4, "key_value_metadata": {"source": "billing"}}"#,
+    )
+    .unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+    let versions = |bytes: Vec<u8>| {
+        crate::reader::read_rows(bytes, Default::default())
+            .unwrap()
+            .iter()
+            .map(|row| {
+                (
+                    row["id"].as_i64().unwrap(),
+                    row["version"].as_i64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let keys = ["id".to_string(), "region".to_string()];
+
+    let (deduped, removed) = dedupe(bytes.clone(), &keys, &Default::default()).unwrap();
+    assert_eq!(removed, 2);
+    assert_eq!(
+        versions(deduped.clone()),
+        vec![(1, 1), (2, 1), (3, 1), (1, 3)]
+    );
+    let metadata = crate::reader::key_value_metadata(&deduped).unwrap();
+    assert_eq!(metadata.file["source"].as_deref(), Some("billing"));
+
+    let options = crate::options::parse_dedupe_options(r#"{"keep": "last"}"#).unwrap();
+    let (deduped, removed) = dedupe(bytes.clone(), &keys, &options).unwrap();
+    assert_eq!(removed, 2);
+    assert_eq!(versions(deduped), vec![(2, 1), (1, 2), (1, 3), (3, 2)]);
+
+    let (deduped, removed) = dedupe(bytes.clone(), &["id".to_string()], &options).unwrap();
+    assert_eq!(removed, 3);
+    assert_eq!(versions(deduped), vec![(2, 1), (1, 3), (3, 2)]);
+
+    assert_eq!(
+        dedupe(bytes, &["email".to_string()], &Default::default()).unwrap_err(),
+        "Unknown field email in key_columns"
+    );
+}
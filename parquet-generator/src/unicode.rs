@@ -0,0 +1,123 @@
+use crate::options::LoneSurrogatePolicy;
+use crate::{ParquetField, ParquetLogicalType};
+use std::borrow::Cow;
+
+/// Lone surrogates kept by `LoneSurrogatePolicy::Binary` are carried through parsing as the code
+/// point `SURROGATE_BASE + (surrogate - 0xD800)`, the top 2048 code points of the last private use
+/// plane, and turned back into their WTF-8 bytes when written
+const SURROGATE_BASE: u32 = 0x10F800;
+const REPLACEMENT_ESCAPE: &str = "\\uFFFD";
+
+/// Apply the policy to any `\u` escapes of unpaired UTF-16 surrogates in a JSON document, which
+/// serde_json refuses to parse into a string
+pub(crate) fn escape_lone_surrogates(
+    json: &str,
+    policy: LoneSurrogatePolicy,
+) -> Result<Cow<'_, str>, String> {
+    if !has_surrogate_escape(json) {
+        return Ok(Cow::Borrowed(json));
+    }
+
+    let mut escaped = String::with_capacity(json.len());
+    let mut rest = json;
+    while let Some(i) = rest.find('\\') {
+        escaped.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let unit = match rest.get(1..2) {
+            Some("u") => rest.get(2..6).and_then(|h| u16::from_str_radix(h, 16).ok()),
+            _ => None,
+        };
+        let unit = match unit {
+            Some(unit) if is_surrogate(unit) => unit,
+            // Copy any other escape whole, so an escaped backslash can't start another
+            _ => {
+                let len = rest[1..].chars().next().map_or(1, |c| 1 + c.len_utf8());
+                escaped.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+        };
+        let low = rest
+            .get(6..8)
+            .filter(|e| *e == "\\u")
+            .and_then(|_| rest.get(8..12))
+            .and_then(|h| u16::from_str_radix(h, 16).ok());
+        if (0xD800..0xDC00).contains(&unit) && low.is_some_and(|l| (0xDC00..0xE000).contains(&l)) {
+            escaped.push_str(&rest[..12]);
+            rest = &rest[12..];
+            continue;
+        }
+        match policy {
+            LoneSurrogatePolicy::Reject => {
+                return Err(format!("lone surrogate {} in string", &rest[..6]))
+            }
+            LoneSurrogatePolicy::Replace => escaped.push_str(REPLACEMENT_ESCAPE),
+            LoneSurrogatePolicy::Binary => escaped.push(surrogate_char(unit)),
+        }
+        rest = &rest[6..];
+    }
+    escaped.push_str(rest);
+    Ok(Cow::Owned(escaped))
+}
+
+/// Decode UTF-16 code units from JavaScript, applying the policy to unpaired surrogates
+pub(crate) fn decode_utf16(units: &[u16], policy: LoneSurrogatePolicy) -> Result<String, String> {
+    char::decode_utf16(units.iter().copied())
+        .map(|c| match (c, policy) {
+            (Ok(c), _) => Ok(c),
+            (Err(e), LoneSurrogatePolicy::Reject) => Err(format!(
+                "lone surrogate \\u{:04X} in string",
+                e.unpaired_surrogate()
+            )),
+            (Err(_), LoneSurrogatePolicy::Replace) => Ok(char::REPLACEMENT_CHARACTER),
+            (Err(e), LoneSurrogatePolicy::Binary) => Ok(surrogate_char(e.unpaired_surrogate())),
+        })
+        .collect()
+}
+
+/// The bytes of a string kept by `LoneSurrogatePolicy::Binary`, UTF-8 with each lone surrogate
+/// written as the three bytes it would have were it a code point
+pub(crate) fn to_wtf8(s: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        match u32::from(c).checked_sub(SURROGATE_BASE) {
+            Some(offset) => {
+                let unit = 0xD800 + offset;
+                bytes.extend([
+                    0xE0 | (unit >> 12) as u8,
+                    0x80 | ((unit >> 6) & 0x3F) as u8,
+                    0x80 | (unit & 0x3F) as u8,
+                ]);
+            }
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    bytes
+}
+
+/// Drop the UTF8 annotation of fields, and of the elements and values of LISTs and MAPs, whose
+/// strings may hold raw surrogates that aren't valid UTF-8
+pub(crate) fn utf8_to_binary(fields: &mut [ParquetField]) {
+    for field in fields.iter_mut() {
+        if field.logical_type == Some(ParquetLogicalType::Utf8) {
+            field.logical_type = None;
+        }
+        if field.element_logical_type == Some(ParquetLogicalType::Utf8) {
+            field.element_logical_type = None;
+        }
+    }
+}
+
+fn has_surrogate_escape(json: &str) -> bool {
+    json.as_bytes()
+        .windows(4)
+        .any(|w| w[0] == b'\\' && w[1] == b'u' && matches!(w[2], b'd' | b'D') && w[3] >= b'8')
+}
+
+fn is_surrogate(unit: u16) -> bool {
+    (0xD800..0xE000).contains(&unit)
+}
+
+fn surrogate_char(unit: u16) -> char {
+    char::from_u32(SURROGATE_BASE + u32::from(unit) - 0xD800).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
@@ -0,0 +1,212 @@
+use crate::reader;
+use bytes::Bytes;
+use parquet::column::reader::{ColumnReader, ColumnReaderImpl};
+use parquet::data_type::DataType;
+use parquet::errors::ParquetError;
+use parquet::file::reader::{FileReader, RowGroupReader};
+use serde::Serialize;
+
+/// The magic bytes a parquet file starts and ends with
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+/// Records read from a column at a time while decoding it
+const DECODE_BATCH_SIZE: usize = 1024;
+
+/// Whether a parquet file is intact, and where the first problem found is when it isn't
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub(crate) struct ValidationReport {
+    pub(crate) valid: bool,
+    pub(crate) error: Option<String>,
+    pub(crate) location: Option<Location>,
+    /// The pages read before the first problem, or in the whole file
+    pub(crate) pages_checked: usize,
+    /// The pages among them with a CRC32 checksum that was verified
+    pub(crate) checksums_verified: usize,
+}
+
+/// Where in a file a problem was found, as precisely as it's known
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub(crate) struct Location {
+    pub(crate) row_group: Option<usize>,
+    /// The column's path, e.g. `tags.list.element`
+    pub(crate) column: Option<String>,
+    /// The index of the page in its column chunk, counting the dictionary page
+    pub(crate) page: Option<usize>,
+    /// The offset in the file
+    pub(crate) offset: Option<u64>,
+}
+
+/// Check a parquet file is intact: that it starts and ends with the magic bytes, its footer
+/// parses, every page header reads and its data matches the header's CRC32 where it has one, and
+/// every column decodes to the number of rows in its row group. Stops at the first problem
+pub(crate) fn validate(bytes: Vec<u8>) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    if let Err((error, location)) = check(Bytes::from(bytes), &mut report) {
+        report.error = Some(error);
+        report.location = Some(location);
+        return report;
+    }
+    report.valid = true;
+    report
+}
+
+fn check(bytes: Bytes, report: &mut ValidationReport) -> Result<(), (String, Location)> {
+    let at_offset = |offset: u64| Location {
+        offset: Some(offset),
+        ..Default::default()
+    };
+    if !bytes.starts_with(PARQUET_MAGIC) {
+        return Err(("File doesn't start with PAR1".to_string(), at_offset(0)));
+    }
+    if bytes.len() < 2 * PARQUET_MAGIC.len() || !bytes.ends_with(PARQUET_MAGIC) {
+        let offset = bytes.len().saturating_sub(PARQUET_MAGIC.len()) as u64;
+        return Err(("File doesn't end with PAR1".to_string(), at_offset(offset)));
+    }
+    let file = reader::open(bytes.clone()).map_err(|e| (e, Location::default()))?;
+
+    for (i, row_group) in file.metadata().row_groups().iter().enumerate() {
+        for chunk in row_group.columns() {
+            let location = |page: Option<usize>, offset: Option<u64>| Location {
+                row_group: Some(i),
+                column: Some(chunk.column_path().string()),
+                page,
+                offset,
+            };
+            let pages =
+                reader::chunk_pages(&bytes, chunk).map_err(|e| (e, location(None, None)))?;
+            for (page_index, page) in pages.iter().enumerate() {
+                report.pages_checked += 1;
+                let crc = match page.header.crc {
+                    Some(crc) => crc,
+                    None => continue,
+                };
+                if crc != crc32fast::hash(page.data) as i32 {
+                    return Err((
+                        "Page data doesn't match its checksum".to_string(),
+                        location(Some(page_index), Some(page.offset)),
+                    ));
+                }
+                report.checksums_verified += 1;
+            }
+        }
+
+        let row_group_reader = file.get_row_group(i).map_err(|e| {
+            let location = Location {
+                row_group: Some(i),
+                ..Default::default()
+            };
+            (format!("Error reading row group: {}", e), location)
+        })?;
+        for (column, chunk) in row_group.columns().iter().enumerate() {
+            let location = Location {
+                row_group: Some(i),
+                column: Some(chunk.column_path().string()),
+                ..Default::default()
+            };
+            match decode_column(row_group_reader.as_ref(), column) {
+                Ok(rows) if rows == row_group.num_rows() as usize => {}
+                Ok(rows) => {
+                    let error = format!(
+                        "Column has {} rows, the row group has {}",
+                        rows,
+                        row_group.num_rows()
+                    );
+                    return Err((error, location));
+                }
+                Err(e) => return Err((format!("Error decoding column: {}", e), location)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Decode every value of a column chunk, returning the number of rows
+fn decode_column(row_group: &dyn RowGroupReader, column: usize) -> Result<usize, ParquetError> {
+    match row_group.get_column_reader(column)? {
+        ColumnReader::BoolColumnReader(r) => decode_values(r),
+        ColumnReader::Int32ColumnReader(r) => decode_values(r),
+        ColumnReader::Int64ColumnReader(r) => decode_values(r),
+        ColumnReader::Int96ColumnReader(r) => decode_values(r),
+        ColumnReader::FloatColumnReader(r) => decode_values(r),
+        ColumnReader::DoubleColumnReader(r) => decode_values(r),
+        ColumnReader::ByteArrayColumnReader(r) => decode_values(r),
+        ColumnReader::FixedLenByteArrayColumnReader(r) => decode_values(r),
+    }
+}
+
+fn decode_values<T: DataType>(mut reader: ColumnReaderImpl<T>) -> Result<usize, ParquetError> {
+    let (mut values, mut def_levels, mut rep_levels) = (vec![], vec![], vec![]);
+    let mut rows = 0;
+    loop {
+        let (records, _, _) = reader.read_records(
+            DECODE_BATCH_SIZE,
+            Some(&mut def_levels),
+            Some(&mut rep_levels),
+            &mut values,
+        )?;
+        if records == 0 {
+            return Ok(rows);
+        }
+        rows += records;
+        values.clear();
+        def_levels.clear();
+        rep_levels.clear();
+    }
+}
+
+#[test]
+fn test_validate() {
+    let schema = r#"
+    {
+        "fields": [
+            {"name": "id", "type": "INT64"},
+            {"name": "tags", "type": "BYTE_ARRAY", "logical_type": "LIST", "element_logical_type": "UTF8"}
+        ]
+    }
+    "#;
+    let files = (0..5)
+        .map(|id| format!(r#"{{"id": {}, "tags": ["t{}", "x"]}}"#, id, id))
+        .collect();
+    let options = crate::options::parse_options(r#"{"max_rows_per_row_group": 2}"#).unwrap();
+    let bytes = crate::write_parquet(schema.to_string(), files, &options).unwrap();
+
+    let report = validate(bytes.clone());
+    assert!(report.valid, "{:?}", report.error);
+    assert!(report.pages_checked >= 6);
+    assert_eq!(report.checksums_verified, report.pages_checked);
+
+    // Flip the last byte of the first page of the second row group's id column
+    let file = reader::open(bytes.clone()).unwrap();
+    let chunk = file.metadata().row_group(1).column(0);
+    let page = &reader::chunk_pages(&bytes, chunk).unwrap()[0];
+    let corrupt_at = page.data.as_ptr() as usize - bytes.as_ptr() as usize + page.data.len() - 1;
+    let mut corrupt = bytes.clone();
+    corrupt[corrupt_at] ^= 0xff;
+    let report = validate(corrupt);
+    assert!(!report.valid);
+    assert_eq!(
+        report.error.as_deref(),
+        Some("Page data doesn't match its checksum")
+    );
+    assert_eq!(
+        report.location,
+        Some(Location {
+            row_group: Some(1),
+            column: Some("id".to_string()),
+            page: Some(0),
+            offset: Some(page.offset),
+        })
+    );
+
+    let report = validate(bytes[..bytes.len() - 1].to_vec());
+    assert_eq!(report.error.as_deref(), Some("File doesn't end with PAR1"));
+    let report = validate(b"not parquet".to_vec());
+    assert_eq!(
+        report.error.as_deref(),
+        Some("File doesn't start with PAR1")
+    );
+    let mut footer = bytes.clone();
+    let footer_at = footer.len() - 9;
+    footer[footer_at] ^= 0xff;
+    assert!(!validate(footer).valid);
+}
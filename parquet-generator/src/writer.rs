@@ -0,0 +1,2270 @@
+use crate::convert;
+use crate::encryption::{
+    EncryptedFileWriter, EncryptedRowGroupWriter, FileEncryptor, ModuleEncryptor,
+};
+use crate::error::{ValueError, WriteError};
+use crate::filter;
+use crate::js;
+use crate::options::{
+    CoercionOptions, LoneSurrogatePolicy, NonFinitePolicy, SortingColumnOptions, WriterOptions,
+};
+use crate::pages::{self, ColumnPages, ColumnReport};
+use crate::unicode;
+use crate::{
+    InvalidValuePolicy, NullPolicy, OverflowPolicy, ParquetField, ParquetLogicalType,
+    ParquetPrimitiveType, ParquetRepetition,
+};
+use bytes::Bytes;
+use parquet::column::writer::ColumnCloseResult;
+use parquet::data_type::{
+    BoolType, ByteArray, ByteArrayType, DataType, DoubleType, FixedLenByteArray,
+    FixedLenByteArrayType, FloatType, Int32Type, Int64Type, Int96, Int96Type,
+};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::RowGroupMetaDataPtr;
+use parquet::file::properties::WriterPropertiesPtr;
+use parquet::file::reader::{ChunkReader, Length};
+use parquet::file::writer::{
+    SerializedColumnWriter, SerializedFileWriter, SerializedRowGroupWriter,
+};
+use parquet::schema::types::{ColumnDescPtr, SchemaDescriptor, TypePtr};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Write};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+/// The length every FIXED_LEN_BYTE_ARRAY column is declared with in the schema
+pub(crate) const FIXED_LEN_BYTE_ARRAY_LENGTH: i32 = 1024;
+
+/// The directory name hive-style partitioning gives a null partition value
+pub(crate) const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// How each partition field's directory is named by default, hive-style
+const DEFAULT_PARTITION_TEMPLATE: &str = "{col}={value}";
+
+/// How the files in a partition are named by default
+pub(crate) const DEFAULT_FILE_TEMPLATE: &str = "part-{seq:04}.parquet";
+
+/// The records encoded to measure how many bytes a row takes before a file's first row group is
+/// cut to `max_row_group_bytes` or `max_memory_bytes`
+const PROBE_ROWS: usize = 1024;
+
+pub(crate) type Record = Map<String, Value>;
+
+/// Parse each file into a JSON object, the row index of a record is its position in `files`
+pub(crate) fn parse_records(
+    files: &[String],
+    surrogates: LoneSurrogatePolicy,
+) -> Result<Vec<Record>, String> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(row, file)| {
+            let file = unicode::escape_lone_surrogates(file, surrogates)
+                .map_err(|e| format!("Row {}: {}", row, e))?;
+            match serde_json::from_str::<Value>(&file) {
+                Ok(Value::Object(record)) => Ok(record),
+                Ok(_) => Err(format!("Row {}: record is not a JSON object", row)),
+                Err(_) => Err(format!("Row {}: error parsing record", row)),
+            }
+        })
+        .collect()
+}
+
+/// Parse newline-delimited JSON into records, skipping blank lines. Errors report the 1-based
+/// line number since that's what editors show, counting from after `lines_before` earlier lines
+pub(crate) fn parse_ndjson(
+    ndjson: &str,
+    lines_before: usize,
+    surrogates: LoneSurrogatePolicy,
+) -> Result<Vec<Record>, String> {
+    ndjson
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let i = i + lines_before;
+            let line = unicode::escape_lone_surrogates(line, surrogates)
+                .map_err(|e| format!("Line {}: {}", i + 1, e))?;
+            match serde_json::from_str::<Value>(&line) {
+                Ok(Value::Object(record)) => Ok(record),
+                Ok(_) => Err(format!("Line {}: record is not a JSON object", i + 1)),
+                Err(_) => Err(format!("Line {}: error parsing record", i + 1)),
+            }
+        })
+        .collect()
+}
+
+/// Parse a single JSON array of objects into records, with errors reporting the index of the
+/// offending element
+pub(crate) fn parse_json_array(
+    json_array: &str,
+    surrogates: LoneSurrogatePolicy,
+) -> Result<Vec<Record>, String> {
+    let json_array = match unicode::escape_lone_surrogates(json_array, surrogates) {
+        Ok(json_array) => json_array,
+        Err(e) => return Err(format!("Error parsing records: {}", e)),
+    };
+    let elements = match serde_json::from_str::<Value>(&json_array) {
+        Ok(Value::Array(elements)) => elements,
+        Ok(_) => return Err("Records are not a JSON array".to_string()),
+        Err(e) => return Err(format!("Error parsing records: {}", e)),
+    };
+    elements
+        .into_iter()
+        .enumerate()
+        .map(|(row, element)| match element {
+            Value::Object(record) => Ok(record),
+            _ => Err(format!("Row {}: record is not a JSON object", row)),
+        })
+        .collect()
+}
+
+/// Decode MessagePack encoded records, each must be a map with string keys. Binary values have no
+/// JSON equivalent and are rejected
+pub(crate) fn parse_msgpack_records(records: &[Vec<u8>]) -> Result<Vec<Record>, String> {
+    records
+        .iter()
+        .enumerate()
+        .map(
+            |(row, record)| match rmp_serde::from_slice::<Value>(record) {
+                Ok(Value::Object(record)) => Ok(record),
+                Ok(_) => Err(format!("Row {}: record is not a MessagePack map", row)),
+                Err(_) => Err(format!("Row {}: error decoding record", row)),
+            },
+        )
+        .collect()
+}
+
+/// A sink that collects bytes until they're taken, so a file can be handed over in chunks as it's
+/// written. Clones share the same bytes
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChunkBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl ChunkBuffer {
+    /// Take the bytes written since the last take
+    pub(crate) fn take(&self) -> Vec<u8> {
+        match self.0.lock() {
+            Ok(mut bytes) => std::mem::take(&mut *bytes),
+            Err(_) => vec![],
+        }
+    }
+}
+
+impl Write for ChunkBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self.0.lock() {
+            Ok(mut bytes) => bytes.write(buf),
+            Err(_) => Err(std::io::Error::other("Chunk buffer poisoned")),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// How far through writing a file the writer is, passed to progress callbacks
+#[derive(Debug, Default, Clone, Copy, Serialize, PartialEq)]
+pub(crate) struct Progress {
+    /// The records of the row groups written so far
+    pub(crate) records_processed: usize,
+    /// The column chunks written so far, across every row group
+    pub(crate) columns_written: usize,
+    /// The bytes of the file passed to the sink so far, the writer buffers a few KB at a time
+    pub(crate) bytes_written: usize,
+}
+
+/// Where the time writing a file went, in milliseconds
+#[derive(Debug, Default, Clone, Serialize, PartialEq)]
+pub(crate) struct Timings {
+    /// Parsing the records' JSON
+    pub(crate) parse_millis: f64,
+    /// Computing fields, flattening, filtering, deduplicating and sorting the records
+    pub(crate) prepare_millis: f64,
+    /// Looking up and validating each field's values in the records
+    pub(crate) lookup_millis: f64,
+    /// Converting each column's values and encoding its pages, across every row group. Pages are
+    /// compressed as they're encoded, so this includes any compression time
+    pub(crate) columns: Vec<ColumnTiming>,
+    /// The whole write, from parsing to the footer
+    pub(crate) total_millis: f64,
+}
+
+/// The time spent encoding a column, in milliseconds
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub(crate) struct ColumnTiming {
+    /// The column's path in the schema, e.g. `tags.list.element`
+    pub(crate) column: String,
+    pub(crate) encode_millis: f64,
+}
+
+pub(crate) type ProgressCallback = Box<dyn FnMut(Progress) -> Result<(), WriteError>>;
+
+/// Set once a write should stop, shared with the `CancellationToken` that sets it
+pub(crate) type CancelFlag = Rc<Cell<bool>>;
+
+/// Tracks a file's progress, calling the callback if there is one after every `interval` column
+/// chunks, and stops the write between column chunks once it's cancelled
+struct ProgressTracker {
+    progress: Progress,
+    /// The bytes passed to the sink, counted by the `CountingWrite` the file writer owns
+    bytes_written: Arc<AtomicUsize>,
+    callback: Option<(usize, ProgressCallback)>,
+    cancelled: Option<CancelFlag>,
+}
+
+impl ProgressTracker {
+    fn check_cancelled(&self) -> Result<(), WriteError> {
+        match &self.cancelled {
+            Some(cancelled) if cancelled.get() => Err(WriteError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    fn column_written(&mut self) -> Result<(), WriteError> {
+        self.check_cancelled()?;
+        self.progress.columns_written += 1;
+        match self.callback.as_mut() {
+            Some((interval, callback))
+                if self.progress.columns_written.is_multiple_of(*interval) =>
+            {
+                self.progress.bytes_written = self.bytes_written.load(AtomicOrdering::Relaxed);
+                callback(self.progress)?;
+                self.check_cancelled()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn finished(&mut self) -> Result<(), WriteError> {
+        self.progress.bytes_written = self.bytes_written.load(AtomicOrdering::Relaxed);
+        match self.callback.as_mut() {
+            Some((_, callback)) => callback(self.progress),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Counts the bytes written through it
+struct CountingWrite<W: Write> {
+    inner: W,
+    count: Arc<AtomicUsize>,
+}
+
+impl<W: Write> Write for CountingWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count.fetch_add(written, AtomicOrdering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A parquet file being written a row group at a time
+pub(crate) struct FileSink<W: Write + Send> {
+    writer: FileWriter<CountingWrite<W>>,
+    columns: Vec<ColumnDescPtr>,
+    properties: WriterPropertiesPtr,
+    checksums: bool,
+    /// The keys column chunks are encrypted with, when `encryption` is set
+    encryption: Option<Arc<FileEncryptor>>,
+    /// The pages written for each column of each row group so far
+    pages: Vec<Vec<ColumnPages>>,
+    /// The number of records written so far, which errors' rows are offset by
+    rows_written: usize,
+    /// The most memory a row group has needed, its records plus the column chunks held at once
+    peak_memory_bytes: usize,
+    /// The encoded bytes per row of the last column chunks encoded with `max_row_group_bytes` or
+    /// `max_memory_bytes`, how many rows the next row group takes is predicted from
+    bytes_per_row: Option<f64>,
+    /// The size in bytes the file is kept under when it's one of several split by
+    /// `max_file_bytes`, which also caps its row groups
+    max_file_bytes: Option<usize>,
+    progress: ProgressTracker,
+    /// Cleared buffers of column chunks already appended, which later column chunks are encoded
+    /// into instead of allocating their own
+    buffers: Vec<Vec<u8>>,
+    /// The time spent looking up values and encoding each column so far
+    timings: Timings,
+}
+
+impl<W: Write + Send> FileSink<W> {
+    pub(crate) fn new(
+        sink: W,
+        message_type: &TypePtr,
+        options: &WriterOptions,
+    ) -> Result<Self, WriteError> {
+        let properties = Arc::new(options.writer_properties(message_type)?);
+        let bytes_written = Arc::new(AtomicUsize::new(0));
+        let sink = CountingWrite {
+            inner: sink,
+            count: bytes_written.clone(),
+        };
+        let encryption = match &options.encryption {
+            Some(encryption) => {
+                let columns = SchemaDescriptor::new(message_type.clone())
+                    .columns()
+                    .to_vec();
+                Some(Arc::new(FileEncryptor::new(encryption, &columns)?))
+            }
+            None => None,
+        };
+        let writer = match &encryption {
+            Some(encryption) => FileWriter::Encrypted(EncryptedFileWriter::new(
+                sink,
+                message_type.clone(),
+                properties.clone(),
+                encryption.clone(),
+            )?),
+            None => match SerializedFileWriter::new(sink, message_type.clone(), properties.clone())
+            {
+                Ok(w) => FileWriter::Plain(w),
+                Err(_) => return Err(WriteError::from("Error creating writer".to_string())),
+            },
+        };
+        let columns = writer.schema_descr().columns().to_vec();
+        let timings = Timings {
+            columns: columns
+                .iter()
+                .map(|column| ColumnTiming {
+                    column: column.path().string(),
+                    encode_millis: 0.0,
+                })
+                .collect(),
+            ..Timings::default()
+        };
+        Ok(FileSink {
+            columns,
+            properties,
+            writer,
+            checksums: options.page_checksums.unwrap_or(true),
+            encryption,
+            pages: vec![],
+            rows_written: 0,
+            peak_memory_bytes: 0,
+            bytes_per_row: None,
+            max_file_bytes: None,
+            progress: ProgressTracker {
+                progress: Progress::default(),
+                bytes_written,
+                callback: None,
+                cancelled: None,
+            },
+            buffers: vec![],
+            timings,
+        })
+    }
+
+    /// Call `callback` after every `interval` column chunks are written and once the file is
+    /// complete
+    pub(crate) fn on_progress(
+        &mut self,
+        interval: usize,
+        callback: ProgressCallback,
+    ) -> Result<(), WriteError> {
+        if interval == 0 {
+            return Err(WriteError::from(
+                "Progress interval must be greater than 0".to_string(),
+            ));
+        }
+        self.progress.callback = Some((interval, callback));
+        Ok(())
+    }
+
+    /// Stop writing with a `Cancelled` error once `cancelled` is set, checked before each row
+    /// group and after each column chunk
+    pub(crate) fn cancel_on(&mut self, cancelled: CancelFlag) {
+        self.progress.cancelled = Some(cancelled);
+    }
+
+    /// Keep the file under `max_file_bytes`, cutting row groups at that size and leaving records
+    /// unwritten once it's `full`
+    pub(crate) fn split_at(&mut self, max_file_bytes: usize) {
+        self.max_file_bytes = Some(max_file_bytes);
+    }
+
+    /// Whether the next row group is expected to take the file past the `max_file_bytes` it's
+    /// split at, going by the size of the last one
+    pub(crate) fn full(&self) -> bool {
+        let Some(max_file_bytes) = self.max_file_bytes else {
+            return false;
+        };
+        let last = self.writer.flushed_row_groups().last();
+        let last = last.map_or(0, |row_group| row_group.compressed_size() as usize);
+        self.row_group_bytes().saturating_add(last) > max_file_bytes
+    }
+
+    /// The size in bytes row groups are cut at, the smaller of `max_row_group_bytes` and the
+    /// `max_file_bytes` the file is split at
+    fn max_row_group_bytes(&self, options: &WriterOptions) -> Option<usize> {
+        options
+            .max_row_group_bytes
+            .into_iter()
+            .chain(self.max_file_bytes)
+            .min()
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<(), WriteError> {
+        self.progress.check_cancelled()
+    }
+
+    /// Write the records as a row group with a column for every leaf of `fields`, or as several
+    /// when `max_row_group_bytes` or `max_memory_bytes` cuts them. Each column is encoded and
+    /// appended in turn, or with the `threads` feature every column is encoded in parallel before
+    /// they're appended. Returns how many records were written, fewer than all of them when a file
+    /// split at `max_file_bytes` is full
+    pub(crate) fn write_row_group(
+        &mut self,
+        fields: &[ParquetField],
+        records: &[Record],
+        options: &WriterOptions,
+    ) -> Result<usize, WriteError> {
+        let sizes = records.iter().map(record_bytes).collect::<Vec<_>>();
+        self.write_records(fields, records, &sizes, options, false)
+    }
+
+    /// Write the row groups that the records fill up to `max_row_group_bytes` or
+    /// `max_memory_bytes`, given the bytes each record takes, returning how many records were
+    /// written. The rest wait for more records
+    pub(crate) fn write_filled_row_groups(
+        &mut self,
+        fields: &[ParquetField],
+        records: &[Record],
+        sizes: &[usize],
+        options: &WriterOptions,
+    ) -> Result<usize, WriteError> {
+        self.write_records(fields, records, sizes, options, true)
+    }
+
+    fn write_records(
+        &mut self,
+        fields: &[ParquetField],
+        records: &[Record],
+        sizes: &[usize],
+        options: &WriterOptions,
+        filled: bool,
+    ) -> Result<usize, WriteError> {
+        self.progress.check_cancelled()?;
+        let start = js::now_millis();
+        let values = column_values(fields, records, options)
+            .map_err(|e| WriteError::from(e).offset_row(self.rows_written))?;
+        self.timings.lookup_millis += js::now_millis() - start;
+        self.write_columns(fields, &values, sizes, options, filled)
+    }
+
+    /// Write row groups from the values of each field, already deserialized into rows taking
+    /// `sizes` bytes, applying the validation declared on the fields. Returns how many rows were
+    /// written, which is all of them unless `filled` leaves a last row group that isn't full
+    pub(crate) fn write_field_values(
+        &mut self,
+        fields: &[ParquetField],
+        values: &[Vec<Option<Value>>],
+        sizes: &[usize],
+        options: &WriterOptions,
+        filled: bool,
+    ) -> Result<usize, WriteError> {
+        self.progress.check_cancelled()?;
+        let rows = values.first().map_or(0, Vec::len);
+        let start = js::now_millis();
+        let mut validated = vec![Vec::with_capacity(rows); fields.len()];
+        for row in 0..rows {
+            for ((field, values), validated) in fields.iter().zip(values).zip(validated.iter_mut())
+            {
+                let value = values[row].as_ref().filter(|v| !v.is_null());
+                validated.push(
+                    validate_value(field, row, value, options)
+                        .map_err(|e| WriteError::from(e).offset_row(self.rows_written))?,
+                );
+            }
+        }
+        self.timings.lookup_millis += js::now_millis() - start;
+        self.write_columns(fields, &validated, sizes, options, filled)
+    }
+
+    /// Whether `rows` rows taking `record_bytes` are expected to fill a row group up to
+    /// `max_row_group_bytes` or `max_memory_bytes`, going by the bytes per row of the column
+    /// chunks encoded so far. Before any are, enough rows to measure or records that reach a limit
+    /// on their own fill one
+    pub(crate) fn fills(&self, rows: usize, record_bytes: usize, options: &WriterOptions) -> bool {
+        let max_row_group_bytes = self.max_row_group_bytes(options);
+        if max_row_group_bytes.is_none() && options.max_memory_bytes.is_none() {
+            return false;
+        }
+        let max_bytes = max_row_group_bytes.unwrap_or(usize::MAX);
+        let max_memory = options.max_memory_bytes.unwrap_or(usize::MAX);
+        match self.bytes_per_row {
+            Some(bytes_per_row) => {
+                let encoded = bytes_per_row * rows as f64;
+                encoded > max_bytes as f64 || record_bytes as f64 + encoded > max_memory as f64
+            }
+            None => rows >= PROBE_ROWS || record_bytes >= max_bytes.min(max_memory),
+        }
+    }
+
+    /// Write the rows of the values, taking `sizes` bytes each, returning how many were written.
+    /// With `max_row_group_bytes` or `max_memory_bytes` the rows of each row group are predicted
+    /// from the bytes per row of the column chunks encoded so far, and encoded again with fewer
+    /// rows when the chunks turn out too big
+    fn write_columns(
+        &mut self,
+        fields: &[ParquetField],
+        values: &[Vec<Option<&Value>>],
+        sizes: &[usize],
+        options: &WriterOptions,
+        filled: bool,
+    ) -> Result<usize, WriteError> {
+        let columns = field_columns(fields, values, &self.columns)?;
+        let rows = sizes.len();
+        let max_row_group_bytes = self.max_row_group_bytes(options);
+        let sized = max_row_group_bytes.is_some() || options.max_memory_bytes.is_some();
+        if !sized || rows == 0 {
+            if filled {
+                return Ok(0);
+            }
+            self.write_whole_row_group(&columns, rows, sizes.iter().sum(), options)?;
+            return Ok(rows);
+        }
+        let max_bytes = max_row_group_bytes.unwrap_or(usize::MAX);
+        let max_memory = options.max_memory_bytes.unwrap_or(usize::MAX);
+
+        let mut start = 0;
+        // A file split at `max_file_bytes` leaves the rest of the rows to the next file
+        while start < rows && !(start > 0 && self.full()) {
+            let remaining = rows - start;
+            let predicted = self.predicted_rows(&sizes[start..], options);
+            if filled && predicted.is_some_and(|predicted| predicted >= remaining) {
+                break;
+            }
+            let mut take = match predicted {
+                Some(predicted) => predicted,
+                // Enough rows to measure, or fewer when their records alone reach the memory limit
+                None => sizes[start..]
+                    .iter()
+                    .scan(0, |memory, size| {
+                        *memory += size;
+                        Some(*memory)
+                    })
+                    .take_while(|memory| *memory <= max_memory)
+                    .count()
+                    .min(PROBE_ROWS),
+            }
+            .clamp(1, remaining);
+            loop {
+                let encoded = self.encode_columns(&columns, start..start + take, options)?;
+                let bytes: usize = encoded.iter().map(|column| column.buffer.len()).sum();
+                let record_bytes: usize = sizes[start..start + take].iter().sum();
+                let probing = self.bytes_per_row.is_none();
+                self.bytes_per_row = Some(bytes as f64 / take as f64);
+                let fit = self
+                    .predicted_rows(&sizes[start..], options)
+                    .unwrap_or(1)
+                    .clamp(1, remaining);
+                let over = bytes > max_bytes || record_bytes + bytes > max_memory;
+                let retake = if over && take > 1 {
+                    Some(fit.min(take - 1))
+                } else if probing && fit > take {
+                    Some(fit)
+                } else {
+                    None
+                };
+                match retake {
+                    Some(rows) => {
+                        self.recycle(encoded);
+                        take = rows;
+                    }
+                    // The last rows wait for more to fill their row group
+                    None if filled && take == remaining && !over => {
+                        self.recycle(encoded);
+                        return Ok(start);
+                    }
+                    None => {
+                        self.append_row_group(encoded, take, record_bytes)?;
+                        break;
+                    }
+                }
+            }
+            start += take;
+        }
+        Ok(start)
+    }
+
+    /// How many of the rows taking `sizes` bytes are expected to fit in the next row group, going
+    /// by the bytes per row of the column chunks encoded so far. None until a column chunk has
+    /// been measured
+    fn predicted_rows(&self, sizes: &[usize], options: &WriterOptions) -> Option<usize> {
+        let bytes_per_row = self.bytes_per_row?;
+        let max_rows = self
+            .max_row_group_bytes(options)
+            .map_or(usize::MAX, |max_bytes| {
+                (max_bytes as f64 / bytes_per_row) as usize
+            });
+        let max_memory = options.max_memory_bytes.map_or(f64::MAX, |max| max as f64);
+        let mut memory = 0.0;
+        Some(
+            sizes
+                .iter()
+                .take(max_rows)
+                .take_while(|size| {
+                    memory += **size as f64 + bytes_per_row;
+                    memory <= max_memory
+                })
+                .count(),
+        )
+    }
+
+    /// Write the rows as a single row group. Without the `threads` feature each column chunk is
+    /// appended as soon as it's encoded, so only one is held at a time
+    fn write_whole_row_group(
+        &mut self,
+        columns: &[FieldColumn],
+        rows: usize,
+        record_bytes: usize,
+        options: &WriterOptions,
+    ) -> Result<(), WriteError> {
+        #[cfg(feature = "threads")]
+        {
+            let encoded = self.encode_columns(columns, 0..rows, options)?;
+            self.append_row_group(encoded, rows, record_bytes)
+        }
+        #[cfg(not(feature = "threads"))]
+        {
+            let mut row_group = next_row_group(&mut self.writer)?;
+            let mut pages = Vec::with_capacity(columns.len());
+            // The bytes of column chunks held at once
+            let mut held = 0;
+            for (i, column) in columns.iter().enumerate() {
+                let start = js::now_millis();
+                let buffer = self.buffers.pop().unwrap_or_default();
+                let encryption = column_encryptor(self.encryption.as_deref(), self.pages.len(), i)?;
+                let column = encode_column(
+                    column,
+                    buffer,
+                    options,
+                    &self.properties,
+                    self.checksums,
+                    encryption,
+                )
+                .map_err(|e| e.offset_row(self.rows_written))?;
+                self.timings.columns[i].encode_millis += js::now_millis() - start;
+                held = held.max(column.buffer.len());
+                pages.push(append_column(&mut row_group, column, &mut self.buffers)?);
+                self.progress.column_written()?;
+            }
+            close_row_group(row_group)?;
+            self.row_group_written(pages, rows, record_bytes + held);
+            Ok(())
+        }
+    }
+
+    /// Encode `rows` of every column into a buffer each, in parallel with the `threads` feature,
+    /// without appending them
+    fn encode_columns(
+        &mut self,
+        columns: &[FieldColumn],
+        rows: Range<usize>,
+        options: &WriterOptions,
+    ) -> Result<Vec<EncodedColumn>, WriteError> {
+        let (properties, checksums, rows_written) =
+            (&self.properties, self.checksums, self.rows_written);
+        let (encryption, row_group) = (self.encryption.as_deref(), self.pages.len());
+        // Each column chunk along with the time it took to encode
+        let encode = |i: usize, column: &FieldColumn, buffer: Vec<u8>| {
+            let start = js::now_millis();
+            encode_column(
+                &column.rows(rows.clone()),
+                buffer,
+                options,
+                properties,
+                checksums,
+                column_encryptor(encryption, row_group, i)?,
+            )
+            .map(|encoded| (encoded, js::now_millis() - start))
+            .map_err(|e| e.offset_row(rows_written))
+        };
+        let buffers = columns
+            .iter()
+            .map(|_| self.buffers.pop().unwrap_or_default())
+            .collect::<Vec<_>>();
+        #[cfg(feature = "threads")]
+        let encoded = {
+            use rayon::prelude::*;
+            columns
+                .par_iter()
+                .enumerate()
+                .zip(buffers)
+                .map(|((i, column), buffer)| encode(i, column, buffer))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        #[cfg(not(feature = "threads"))]
+        let encoded = columns
+            .iter()
+            .enumerate()
+            .zip(buffers)
+            .map(|((i, column), buffer)| encode(i, column, buffer))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(encoded
+            .into_iter()
+            .enumerate()
+            .map(|(i, (column, millis))| {
+                self.timings.columns[i].encode_millis += millis;
+                column
+            })
+            .collect())
+    }
+
+    /// Append encoded column chunks, all held at once, as a row group of `rows` records
+    fn append_row_group(
+        &mut self,
+        encoded: Vec<EncodedColumn>,
+        rows: usize,
+        record_bytes: usize,
+    ) -> Result<(), WriteError> {
+        let held: usize = encoded.iter().map(|column| column.buffer.len()).sum();
+        let mut row_group = next_row_group(&mut self.writer)?;
+        let mut pages = Vec::with_capacity(encoded.len());
+        for column in encoded {
+            pages.push(append_column(&mut row_group, column, &mut self.buffers)?);
+            self.progress.column_written()?;
+        }
+        close_row_group(row_group)?;
+        self.row_group_written(pages, rows, record_bytes + held);
+        Ok(())
+    }
+
+    fn row_group_written(&mut self, pages: Vec<ColumnPages>, rows: usize, memory_bytes: usize) {
+        self.peak_memory_bytes = self.peak_memory_bytes.max(memory_bytes);
+        self.pages.push(pages);
+        self.rows_written += rows;
+        self.progress.progress.records_processed = self.rows_written;
+    }
+
+    /// Keep the buffers of column chunks that won't be appended for the next ones
+    fn recycle(&mut self, encoded: Vec<EncodedColumn>) {
+        for column in encoded {
+            let mut buffer = column.buffer;
+            buffer.clear();
+            self.buffers.push(buffer);
+        }
+    }
+
+    pub(crate) fn peak_memory_bytes(&self) -> usize {
+        self.peak_memory_bytes
+    }
+
+    pub(crate) fn timings(&self) -> Timings {
+        self.timings.clone()
+    }
+
+    /// The bytes of the file's row groups so far, along with its leading magic bytes
+    pub(crate) fn row_group_bytes(&self) -> usize {
+        let row_groups = self.writer.flushed_row_groups().iter();
+        4 + row_groups
+            .map(|row_group| row_group.compressed_size() as usize)
+            .sum::<usize>()
+    }
+
+    /// Encode column chunks into these buffers before allocating new ones
+    pub(crate) fn reuse_buffers(&mut self, buffers: Vec<Vec<u8>>) {
+        self.buffers = buffers;
+    }
+
+    /// Take the column chunk buffers, cleared, to reuse for another file
+    pub(crate) fn take_buffers(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.buffers)
+    }
+
+    /// Write the footer, returning the sink and a report of each column's pages. A file without
+    /// records still has a single, empty, row group
+    pub(crate) fn finish(mut self) -> Result<(W, Vec<ColumnReport>), WriteError> {
+        if self.pages.is_empty() {
+            let mut row_group = next_row_group(&mut self.writer)?;
+            let mut pages = vec![];
+            for (i, column) in self.columns.iter().enumerate() {
+                let mut buffer = vec![];
+                let (close, column_pages) = pages::write_column_chunk(
+                    &mut buffer,
+                    column.clone(),
+                    self.properties.clone(),
+                    self.checksums,
+                    column_encryptor(self.encryption.as_deref(), 0, i)?,
+                    |_| Ok(()),
+                )?;
+                let column = EncodedColumn {
+                    buffer,
+                    close,
+                    pages: column_pages,
+                };
+                pages.push(append_column(&mut row_group, column, &mut self.buffers)?);
+                self.progress.column_written()?;
+            }
+            close_row_group(row_group)?;
+            self.pages.push(pages);
+        }
+        let reports = pages::column_reports(&self.columns, &self.pages);
+        let sink = self.writer.into_inner()?.inner;
+        self.progress.finished()?;
+        Ok((sink, reports))
+    }
+}
+
+/// Prepared records waiting to be written until there are enough to fill a row group, so records
+/// can be parsed and prepared a row group at a time
+#[derive(Default)]
+pub(crate) struct PendingRecords {
+    records: Vec<Record>,
+    /// The bytes each record takes in memory
+    sizes: Vec<usize>,
+}
+
+impl PendingRecords {
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Keep only the `Uint8Array` bytes the records still refer to, renumbering the references,
+    /// so those of records already written are dropped
+    pub(crate) fn retain_byte_values(&mut self, byte_values: &mut Vec<Vec<u8>>) {
+        let mut retained = Vec::new();
+        for record in &mut self.records {
+            for value in record.values_mut() {
+                move_byte_values(value, byte_values, &mut retained);
+            }
+        }
+        *byte_values = retained;
+    }
+
+    pub(crate) fn extend(&mut self, records: impl IntoIterator<Item = Record>) {
+        for record in records {
+            self.sizes.push(record_bytes(&record));
+            self.records.push(record);
+        }
+    }
+
+    /// Write the row groups the records fill, by `max_rows_per_row_group` or the sink's limits,
+    /// keeping the rest for more records to fill
+    pub(crate) fn write_filled<W: Write + Send>(
+        &mut self,
+        sink: &mut FileSink<W>,
+        fields: &[ParquetField],
+        options: &WriterOptions,
+    ) -> Result<(), WriteError> {
+        let row_groups = row_groups(self.records.len(), options)?;
+        // The last row group may not be full yet
+        let Some((last, full)) = row_groups.split_last() else {
+            return Ok(());
+        };
+        for row_group in full {
+            let (records, sizes) = (
+                &self.records[row_group.clone()],
+                &self.sizes[row_group.clone()],
+            );
+            sink.write_records(fields, records, sizes, options, false)?;
+        }
+        let mut written = last.start;
+        let (records, sizes) = (&self.records[last.clone()], &self.sizes[last.clone()]);
+        if sink.fills(records.len(), sizes.iter().sum(), options) {
+            written += sink.write_filled_row_groups(fields, records, sizes, options)?;
+        }
+        self.records.drain(..written);
+        self.sizes.drain(..written);
+        Ok(())
+    }
+
+    /// Write every record left
+    pub(crate) fn write_all<W: Write + Send>(
+        &mut self,
+        sink: &mut FileSink<W>,
+        fields: &[ParquetField],
+        options: &WriterOptions,
+    ) -> Result<(), WriteError> {
+        for row_group in row_groups(self.records.len(), options)? {
+            let (records, sizes) = (&self.records[row_group.clone()], &self.sizes[row_group]);
+            sink.write_records(fields, records, sizes, options, false)?;
+        }
+        self.records.clear();
+        self.sizes.clear();
+        Ok(())
+    }
+}
+
+fn next_row_group<W: Write + Send>(
+    writer: &mut FileWriter<W>,
+) -> Result<RowGroupWriter<'_, W>, WriteError> {
+    match writer {
+        FileWriter::Plain(writer) => match writer.next_row_group() {
+            Ok(rgw) => Ok(RowGroupWriter::Plain(rgw)),
+            Err(_) => Err(WriteError::from(
+                "Error creating row group writer".to_string(),
+            )),
+        },
+        FileWriter::Encrypted(writer) => Ok(RowGroupWriter::Encrypted(writer.next_row_group())),
+    }
+}
+
+fn close_row_group<W: Write + Send>(row_group: RowGroupWriter<'_, W>) -> Result<(), WriteError> {
+    match row_group {
+        RowGroupWriter::Plain(row_group) => match row_group.close() {
+            Ok(_) => Ok(()),
+            Err(_) => Err(WriteError::from(
+                "Error closing row group writer".to_string(),
+            )),
+        },
+        RowGroupWriter::Encrypted(row_group) => row_group.close(),
+    }
+}
+
+/// The writer of a file, parquet's own unless the file is encrypted
+enum FileWriter<W: Write + Send> {
+    Plain(SerializedFileWriter<W>),
+    Encrypted(EncryptedFileWriter<W>),
+}
+
+impl<W: Write + Send> FileWriter<W> {
+    fn schema_descr(&self) -> &SchemaDescriptor {
+        match self {
+            FileWriter::Plain(writer) => writer.schema_descr(),
+            FileWriter::Encrypted(writer) => writer.schema_descr(),
+        }
+    }
+
+    fn flushed_row_groups(&self) -> &[RowGroupMetaDataPtr] {
+        match self {
+            FileWriter::Plain(writer) => writer.flushed_row_groups(),
+            FileWriter::Encrypted(writer) => writer.flushed_row_groups(),
+        }
+    }
+
+    fn into_inner(self) -> Result<W, WriteError> {
+        match self {
+            FileWriter::Plain(writer) => writer
+                .into_inner()
+                .map_err(|_| WriteError::from("Error closing writer".to_string())),
+            FileWriter::Encrypted(writer) => writer.into_inner(),
+        }
+    }
+}
+
+enum RowGroupWriter<'a, W: Write + Send> {
+    Plain(SerializedRowGroupWriter<'a, W>),
+    Encrypted(EncryptedRowGroupWriter<'a, W>),
+}
+
+/// A leaf column of a field, a MAP field has a column of keys followed by a column of values and
+/// every other field a single column
+#[derive(Clone)]
+struct FieldColumn<'a> {
+    field: &'a ParquetField,
+    /// The field's value in each record
+    values: &'a [Option<&'a Value>],
+    /// Whether this is the column of a MAP field's keys
+    keys: bool,
+    column: ColumnDescPtr,
+}
+
+impl<'a> FieldColumn<'a> {
+    /// The column of only some of the rows
+    fn rows(&self, rows: Range<usize>) -> FieldColumn<'a> {
+        FieldColumn {
+            values: &self.values[rows],
+            ..self.clone()
+        }
+    }
+}
+
+fn field_columns<'a>(
+    fields: &'a [ParquetField],
+    values: &'a [Vec<Option<&'a Value>>],
+    columns: &[ColumnDescPtr],
+) -> Result<Vec<FieldColumn<'a>>, WriteError> {
+    let mut columns = columns.iter();
+    let mut field_columns = vec![];
+    for (field, values) in fields.iter().zip(values) {
+        let map = field.logical_type == Some(ParquetLogicalType::Map);
+        for keys in [true, false].into_iter().filter(|keys| map || !keys) {
+            let column = match columns.next() {
+                Some(column) => column.clone(),
+                None => return Err(WriteError::from("Error creating column writer".to_string())),
+            };
+            field_columns.push(FieldColumn {
+                field,
+                values,
+                keys,
+                column,
+            });
+        }
+    }
+    Ok(field_columns)
+}
+
+/// A column chunk written into its own buffer, through a `ColumnPageWriter`, ready to be appended
+/// to the row group
+struct EncodedColumn {
+    buffer: Vec<u8>,
+    close: ColumnCloseResult,
+    pages: ColumnPages,
+}
+
+/// Encode a column chunk into `buffer`, which is expected to be empty
+fn encode_column(
+    column: &FieldColumn,
+    mut buffer: Vec<u8>,
+    options: &WriterOptions,
+    properties: &WriterPropertiesPtr,
+    checksums: bool,
+    encryption: Option<ModuleEncryptor>,
+) -> Result<EncodedColumn, WriteError> {
+    let field = column.field;
+    let (close, pages) = if column.keys {
+        let keys = map_keys(field, column.values, options)?;
+        pages::write_column_chunk(
+            &mut buffer,
+            column.column.clone(),
+            properties.clone(),
+            checksums,
+            encryption,
+            |col_writer| write_batch::<ByteArrayType>(col_writer, keys),
+        )?
+    } else {
+        pages::write_column_chunk(
+            &mut buffer,
+            column.column.clone(),
+            properties.clone(),
+            checksums,
+            encryption,
+            |col_writer| write_column(col_writer, field, column.values, options),
+        )?
+    };
+    Ok(EncodedColumn {
+        buffer,
+        close,
+        pages,
+    })
+}
+
+/// The keys of a MAP field's entries, as they're written to its key column
+pub(crate) fn map_keys(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+) -> Result<ColumnValues<ByteArray>, WriteError> {
+    collect_map_values(field, values, options, 1, |key, _| {
+        Some(ByteArray::from(key))
+    })
+}
+
+/// The encryptor of a column chunk's pages, when the file is encrypted and so is the column
+fn column_encryptor(
+    encryption: Option<&FileEncryptor>,
+    row_group: usize,
+    column: usize,
+) -> Result<Option<ModuleEncryptor<'_>>, WriteError> {
+    match encryption {
+        Some(encryption) => encryption.column(row_group, column),
+        None => Ok(None),
+    }
+}
+
+/// Append an encoded column chunk to the row group, returning the pages written for it. The
+/// chunk's buffer is cleared and added to `buffers` once it's been copied
+fn append_column<W: Write + Send>(
+    row_group: &mut RowGroupWriter<'_, W>,
+    column: EncodedColumn,
+    buffers: &mut Vec<Vec<u8>>,
+) -> Result<ColumnPages, WriteError> {
+    let row_group = match row_group {
+        RowGroupWriter::Plain(row_group) => row_group,
+        RowGroupWriter::Encrypted(row_group) => {
+            row_group.append_column(&column.buffer, column.close)?;
+            let mut buffer = column.buffer;
+            buffer.clear();
+            buffers.push(buffer);
+            return Ok(column.pages);
+        }
+    };
+    let chunk = ColumnBuffer(Arc::new(column.buffer));
+    let appended = row_group.append_column(&chunk, column.close);
+    if let Ok(mut buffer) = Arc::try_unwrap(chunk.0) {
+        buffer.clear();
+        buffers.push(buffer);
+    }
+    match appended {
+        Ok(_) => Ok(column.pages),
+        Err(_) => Err(WriteError::from("Error appending column chunk".to_string())),
+    }
+}
+
+/// An encoded column chunk, read by the row group writer as it's appended without moving it into
+/// `Bytes`, so its buffer can be reused afterwards
+#[derive(Clone)]
+struct ColumnBuffer(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for ColumnBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Length for ColumnBuffer {
+    fn len(&self) -> u64 {
+        self.0.len() as u64
+    }
+}
+
+impl ChunkReader for ColumnBuffer {
+    type T = Cursor<ColumnBuffer>;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<Self::T> {
+        let mut cursor = Cursor::new(self.clone());
+        cursor.set_position(start);
+        Ok(cursor)
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        let start = start as usize;
+        match self.0.get(start..start + length) {
+            Some(bytes) => Ok(Bytes::copy_from_slice(bytes)),
+            None => Err(ParquetError::EOF("Column chunk is too short".to_string())),
+        }
+    }
+}
+
+/// Buffers kept between files written one after another, so each file doesn't allocate (and grow
+/// wasm memory for) its output and column chunks again
+#[derive(Default)]
+pub(crate) struct BufferPool {
+    output: Vec<u8>,
+    pub(crate) columns: Vec<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// Start a file written into the pool's buffers
+    pub(crate) fn sink(
+        &mut self,
+        message_type: &TypePtr,
+        options: &WriterOptions,
+    ) -> Result<FileSink<Vec<u8>>, WriteError> {
+        let mut output = std::mem::take(&mut self.output);
+        output.clear();
+        if let Some(estimate) = options.estimated_output_bytes {
+            output.reserve(estimate);
+        }
+        let mut sink = FileSink::new(output, message_type, options)?;
+        sink.reuse_buffers(std::mem::take(&mut self.columns));
+        Ok(sink)
+    }
+
+    /// Finish a file started with `sink`, returning its bytes, which stay in the pool until the
+    /// next file
+    pub(crate) fn finish(&mut self, mut sink: FileSink<Vec<u8>>) -> Result<&[u8], WriteError> {
+        self.columns = sink.take_buffers();
+        let (output, _) = sink.finish()?;
+        self.output = output;
+        Ok(&self.output)
+    }
+}
+
+/// The values of a column converted to its physical type, along with their levels
+pub(crate) enum PhysicalColumn {
+    Boolean(ColumnValues<bool>),
+    Int32(ColumnValues<i32>),
+    Int64(ColumnValues<i64>),
+    Int96(ColumnValues<Int96>),
+    Float(ColumnValues<f32>),
+    Double(ColumnValues<f64>),
+    ByteArray(ColumnValues<ByteArray>),
+    FixedLenByteArray(ColumnValues<FixedLenByteArray>),
+}
+
+/// Write the values of `field` from every record into the column
+pub(crate) fn write_column(
+    col_writer: &mut SerializedColumnWriter,
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+) -> Result<(), WriteError> {
+    match convert_column(field, values, options)? {
+        PhysicalColumn::Boolean(column) => write_batch::<BoolType>(col_writer, column),
+        PhysicalColumn::Int32(column) => write_batch::<Int32Type>(col_writer, column),
+        PhysicalColumn::Int64(column) => write_batch::<Int64Type>(col_writer, column),
+        PhysicalColumn::Int96(column) => write_batch::<Int96Type>(col_writer, column),
+        PhysicalColumn::Float(column) => write_batch::<FloatType>(col_writer, column),
+        PhysicalColumn::Double(column) => write_batch::<DoubleType>(col_writer, column),
+        PhysicalColumn::ByteArray(column) => write_batch::<ByteArrayType>(col_writer, column),
+        PhysicalColumn::FixedLenByteArray(column) => {
+            write_batch::<FixedLenByteArrayType>(col_writer, column)
+        }
+    }
+}
+
+/// Convert the values of `field` from every record into the column's physical type, as they're
+/// written to its column, or its value column for a MAP field
+pub(crate) fn convert_column(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+) -> Result<PhysicalColumn, WriteError> {
+    let coercion = &options.coercion;
+    // Values are converted as the elements of a LIST field or the values of a MAP field
+    let nested = crate::nested_leaf(field);
+    let leaf = nested.as_ref().unwrap_or(field);
+
+    match field.primitive_type {
+        ParquetPrimitiveType::Boolean => {
+            let column = collect_values(field, values, options, |v| convert::to_bool(v, coercion))?;
+            Ok(PhysicalColumn::Boolean(column))
+        }
+        ParquetPrimitiveType::Int32 => {
+            // UINT32 values above i32::MAX keep their bits
+            let column = collect_values(field, values, options, |v| {
+                clamp_int(leaf, v, coercion).map(|v| v as i32)
+            })?;
+            Ok(PhysicalColumn::Int32(column))
+        }
+        ParquetPrimitiveType::Int64 => {
+            // UINT64 values above i64::MAX keep their bits
+            let column = collect_values(field, values, options, |v| clamp_int(leaf, v, coercion))?;
+            Ok(PhysicalColumn::Int64(column))
+        }
+        ParquetPrimitiveType::Double => {
+            let column =
+                collect_values(field, values, options, |v| convert::to_float(v, coercion))?;
+            Ok(PhysicalColumn::Double(column))
+        }
+        ParquetPrimitiveType::Float => {
+            let max = f64::from(f32::MAX);
+            let column = collect_values(field, values, options, |v| {
+                convert::to_float(v, coercion)
+                    .map(|f| if f.is_finite() { f.clamp(-max, max) } else { f } as f32)
+            })?;
+            Ok(PhysicalColumn::Float(column))
+        }
+        ParquetPrimitiveType::Binary | ParquetPrimitiveType::ByteArray => {
+            // Strings may hold lone surrogates kept as raw bytes
+            let raw_strings = options.lone_surrogates == LoneSurrogatePolicy::Binary
+                && leaf.logical_type.is_none()
+                && leaf.encoding.is_none();
+            let column = collect_values(field, values, options, |v| match v {
+                Value::String(s) if raw_strings => Some(ByteArray::from(unicode::to_wtf8(s))),
+                _ => convert::to_bytes(leaf, v, None, coercion, &options.byte_values)
+                    .map(ByteArray::from),
+            })?;
+            Ok(PhysicalColumn::ByteArray(column))
+        }
+        ParquetPrimitiveType::FixedLenByteArray => {
+            let length = crate::fixed_len(leaf) as usize;
+            let column = collect_values(field, values, options, |v| {
+                convert::to_bytes(leaf, v, Some(length), coercion, &options.byte_values)
+                    .filter(|b| b.len() == length)
+                    .map(FixedLenByteArray::from)
+            })?;
+            Ok(PhysicalColumn::FixedLenByteArray(column))
+        }
+        ParquetPrimitiveType::Int96 => {
+            let column =
+                collect_values(field, values, options, |v| convert::to_int96(v, coercion))?;
+            Ok(PhysicalColumn::Int96(column))
+        }
+    }
+}
+
+/// Keep the first of each set of records with the same values for the `dedupe_on` fields,
+/// missing values compare equal to nulls. `seen` holds the keys of records kept earlier
+pub(crate) fn dedupe(
+    fields: &[ParquetField],
+    records: &[Record],
+    dedupe_on: &[String],
+    options: &WriterOptions,
+    seen: &mut HashSet<String>,
+) -> Result<Vec<Record>, String> {
+    let mut paths = vec![];
+    for name in dedupe_on {
+        match fields.iter().find(|f| &f.name == name) {
+            Some(field) => paths.push(key_paths(field)?),
+            None => return Err(format!("Unknown field {} in dedupe_on", name)),
+        }
+    }
+
+    Ok(records
+        .iter()
+        .filter(|record| {
+            let key = paths
+                .iter()
+                .map(|p| lookup(record, p, options.case_insensitive_keys).unwrap_or(&Value::Null))
+                .collect::<Vec<_>>();
+            seen.insert(serde_json::to_string(&key).unwrap_or_default())
+        })
+        .cloned()
+        .collect())
+}
+
+/// The records in each hive-style partition directory, e.g. `date=2024-01-01/region=eu`, by the
+/// values of the `partition_by` fields. Records keep their order within a partition
+pub(crate) fn partition(
+    fields: &[ParquetField],
+    records: &[Record],
+    partition_by: &[String],
+    options: &WriterOptions,
+) -> Result<BTreeMap<String, Vec<Record>>, String> {
+    if partition_by.is_empty() {
+        return Err("No fields to partition by".to_string());
+    }
+    let mut paths = vec![];
+    for name in partition_by {
+        let Some(field) = fields.iter().find(|f| &f.name == name) else {
+            return Err(format!("Unknown field {} in partition_by", name));
+        };
+        let nested = matches!(
+            field.logical_type,
+            Some(ParquetLogicalType::List | ParquetLogicalType::Map)
+        );
+        if nested || field.repetition_type == Some(ParquetRepetition::Repeated) {
+            return Err(format!(
+                "Partition field {} must be a single, non-repeated value",
+                name
+            ));
+        }
+        paths.push((name, key_paths(field)?));
+    }
+
+    let template = options
+        .partition_template
+        .as_deref()
+        .unwrap_or(DEFAULT_PARTITION_TEMPLATE);
+    let template = NameTemplate::parse(template, "partition_template", &["col", "value"])?;
+    if !template.has("value") {
+        return Err("partition_template must include {value}".to_string());
+    }
+
+    let mut partitions = BTreeMap::<String, Vec<Record>>::new();
+    for record in records {
+        let directory = paths
+            .iter()
+            .map(|(name, paths)| {
+                let value = match lookup(record, paths, options.case_insensitive_keys) {
+                    None | Some(Value::Null) => NULL_PARTITION.to_string(),
+                    Some(Value::String(s)) => escape_partition_value(s),
+                    Some(value) => escape_partition_value(&value.to_string()),
+                };
+                template.render(|placeholder| match placeholder {
+                    "col" => name.to_string(),
+                    _ => value.clone(),
+                })
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        partitions
+            .entry(directory)
+            .or_default()
+            .push(record.clone());
+    }
+    Ok(partitions)
+}
+
+/// A template of output file or directory names, e.g. `part-{seq:05}-{uuid}.parquet`. Placeholders
+/// are replaced by their values, left-padded with zeros to the width after a `:`, and `{{` and
+/// `}}` are literal braces
+#[derive(Debug)]
+pub(crate) struct NameTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder { name: String, width: usize },
+}
+
+impl NameTemplate {
+    /// Parse the template of an option, which may only use the given placeholders
+    pub(crate) fn parse(
+        template: &str,
+        option: &str,
+        placeholders: &[&str],
+    ) -> Result<NameTemplate, String> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let placeholder = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                    let (name, width) = match placeholder.split_once(':') {
+                        Some((name, width)) => match width.parse::<usize>() {
+                            Ok(width) => (name, width),
+                            Err(_) => {
+                                return Err(format!("Invalid width {} in {}", width, option));
+                            }
+                        },
+                        None => (placeholder.as_str(), 0),
+                    };
+                    if !placeholders.contains(&name) {
+                        return Err(format!("Unknown placeholder {{{}}} in {}", name, option));
+                    }
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Placeholder {
+                        name: name.to_string(),
+                        width,
+                    });
+                }
+                '}' => return Err(format!("Unmatched }} in {}", option)),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        if parts.is_empty() {
+            return Err(format!("{} is empty", option));
+        }
+        Ok(NameTemplate { parts })
+    }
+
+    /// Whether the template uses a placeholder
+    pub(crate) fn has(&self, placeholder: &str) -> bool {
+        self.parts.iter().any(
+            |part| matches!(part, TemplatePart::Placeholder { name, .. } if name == placeholder),
+        )
+    }
+
+    /// The name with each placeholder replaced by its value
+    pub(crate) fn render(&self, value: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(text) => rendered.push_str(text),
+                TemplatePart::Placeholder { name, width } => {
+                    rendered.push_str(&format!("{:0>width$}", value(name), width = width))
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// Percent-encode the characters hive escapes in partition directory names, such as `/` and `=`
+fn escape_partition_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\u{0}'..='\u{1F}'
+            | '"'
+            | '#'
+            | '%'
+            | '\''
+            | '*'
+            | '/'
+            | ':'
+            | '='
+            | '?'
+            | '\\'
+            | '\u{7F}'
+            | '{'
+            | '['
+            | ']'
+            | '^' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    if escaped.is_empty() {
+        return NULL_PARTITION.to_string();
+    }
+    escaped
+}
+
+/// Decode a partition directory's value escaped by `escape_partition_value`
+pub(crate) fn unescape_partition_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = match bytes.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match byte {
+            Some(byte) => {
+                unescaped.push(byte);
+                i += 3;
+            }
+            None => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// The ranges of rows written to each row group, which end after `max_rows_per_row_group` rows.
+/// A row group always has at least one row, and `FileSink` cuts it again when its column chunks
+/// reach `max_row_group_bytes` or `max_memory_bytes`
+pub(crate) fn row_groups(
+    rows: usize,
+    options: &WriterOptions,
+) -> Result<Vec<Range<usize>>, String> {
+    let max_rows = match options.max_rows_per_row_group {
+        Some(0) => return Err("max_rows_per_row_group must be greater than 0".to_string()),
+        Some(max_rows) => max_rows,
+        None => usize::MAX,
+    };
+    if options.max_row_group_bytes == Some(0) {
+        return Err("max_row_group_bytes must be greater than 0".to_string());
+    }
+    if options.max_memory_bytes == Some(0) {
+        return Err("max_memory_bytes must be greater than 0".to_string());
+    }
+    Ok((0..rows)
+        .step_by(max_rows)
+        .map(|start| start..start.saturating_add(max_rows).min(rows))
+        .collect())
+}
+
+/// An empty buffer for a file of `rows` records, allocated with `estimated_output_bytes` or else
+/// the plain encoded size of the records' fixed width values
+pub(crate) fn output_buffer(
+    fields: &[ParquetField],
+    rows: usize,
+    options: &WriterOptions,
+) -> Vec<u8> {
+    let estimate = options.estimated_output_bytes.unwrap_or_else(|| {
+        let row_width: usize = fields.iter().map(fixed_width).sum();
+        row_width.saturating_mul(rows)
+    });
+    Vec::with_capacity(estimate)
+}
+
+/// The bytes a value of `field` takes plain encoded, 0 for values of variable width and the
+/// elements of LIST, MAP and REPEATED fields, which may number anywhere from none upwards
+fn fixed_width(field: &ParquetField) -> usize {
+    if crate::nested_leaf(field).is_some()
+        || field.repetition_type == Some(ParquetRepetition::Repeated)
+    {
+        return 0;
+    }
+    match field.primitive_type {
+        ParquetPrimitiveType::Boolean => 1,
+        ParquetPrimitiveType::Int32 | ParquetPrimitiveType::Float => 4,
+        ParquetPrimitiveType::Int64 | ParquetPrimitiveType::Double => 8,
+        ParquetPrimitiveType::Int96 => 12,
+        ParquetPrimitiveType::FixedLenByteArray => crate::fixed_len(field) as usize,
+        ParquetPrimitiveType::Binary | ParquetPrimitiveType::ByteArray => 0,
+    }
+}
+
+/// The bytes a parsed record takes in memory, counted from the capacity of its strings, arrays
+/// and objects rather than serialized again
+pub(crate) fn record_bytes(record: &Record) -> usize {
+    std::mem::size_of::<Record>() + entries_bytes(record)
+}
+
+pub(crate) fn value_bytes(value: &Value) -> usize {
+    std::mem::size_of::<Value>()
+        + match value {
+            Value::String(s) => s.capacity(),
+            Value::Array(elements) => {
+                let spare = elements.capacity() - elements.len();
+                spare * std::mem::size_of::<Value>()
+                    + elements.iter().map(value_bytes).sum::<usize>()
+            }
+            Value::Object(entries) => entries_bytes(entries),
+            _ => 0,
+        }
+}
+
+/// The bytes of an object's entries, each a key and value along with the hash and index the
+/// ordered map keeps for it
+fn entries_bytes(entries: &Record) -> usize {
+    entries
+        .iter()
+        .map(|(key, value)| {
+            std::mem::size_of::<String>()
+                + key.capacity()
+                + 2 * std::mem::size_of::<usize>()
+                + value_bytes(value)
+        })
+        .sum()
+}
+
+/// Move the `Uint8Array` bytes `value` refers to from `from` to the end of `to`, pointing its
+/// references at their new indexes
+fn move_byte_values(value: &mut Value, from: &mut [Vec<u8>], to: &mut Vec<Vec<u8>>) {
+    if let Some(bytes) = convert::uint8_array_index(value).and_then(|i| from.get_mut(i)) {
+        to.push(std::mem::take(bytes));
+        value[convert::UINT8_ARRAY_KEY] = Value::from(to.len() - 1);
+        return;
+    }
+    match value {
+        Value::Array(elements) => {
+            for element in elements {
+                move_byte_values(element, from, to);
+            }
+        }
+        Value::Object(entries) => {
+            for entry in entries.values_mut() {
+                move_byte_values(entry, from, to);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Stably sort the records by the `sorting_columns`, missing values sort as nulls. Values of
+/// different types, or types other than numbers and strings, compare equal
+pub(crate) fn sort_records(
+    fields: &[ParquetField],
+    records: &[Record],
+    sorting_columns: &[SortingColumnOptions],
+    options: &WriterOptions,
+) -> Result<Vec<Record>, String> {
+    let mut paths = vec![];
+    for sorting in sorting_columns {
+        match fields.iter().find(|f| f.name == sorting.column) {
+            Some(field) => paths.push(key_paths(field)?),
+            None => {
+                return Err(format!(
+                    "Unknown field {} in sorting_columns",
+                    sorting.column
+                ))
+            }
+        }
+    }
+
+    let mut sorted = records.to_vec();
+    sorted.sort_by(|a, b| {
+        sorting_columns
+            .iter()
+            .zip(paths.iter())
+            .map(|(sorting, paths)| {
+                let a = lookup(a, paths, options.case_insensitive_keys).unwrap_or(&Value::Null);
+                let b = lookup(b, paths, options.case_insensitive_keys).unwrap_or(&Value::Null);
+                let nulls = match sorting.nulls_first {
+                    true => Ordering::Less,
+                    false => Ordering::Greater,
+                };
+                match (a.is_null(), b.is_null()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => nulls,
+                    (false, true) => nulls.reverse(),
+                    _ if sorting.descending => filter::compare(b, a).unwrap_or(Ordering::Equal),
+                    _ => filter::compare(a, b).unwrap_or(Ordering::Equal),
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+    Ok(sorted)
+}
+
+/// Downgrade REQUIRED fields to OPTIONAL when any record is missing a value for them
+pub(crate) fn infer_optional(
+    fields: &mut [ParquetField],
+    records: &[Record],
+    options: &WriterOptions,
+) -> Result<(), String> {
+    for field in fields.iter_mut() {
+        if !matches!(
+            field.repetition_type,
+            None | Some(ParquetRepetition::Required)
+        ) || field.on_null == NullPolicy::Default
+        {
+            continue;
+        }
+        let paths = key_paths(field)?;
+        let missing = records.iter().any(|record| {
+            lookup(record, &paths, options.case_insensitive_keys).is_none_or(Value::is_null)
+        });
+        if missing {
+            field.repetition_type = Some(ParquetRepetition::Optional);
+        }
+    }
+    Ok(())
+}
+
+/// Look up the value of each field in each record, applying any validation declared on the
+/// fields, in a single pass over the records that fills every field's values at once. A `None`
+/// means the value should be written as null
+pub(crate) fn column_values<'a>(
+    fields: &'a [ParquetField],
+    records: &'a [Record],
+    options: &WriterOptions,
+) -> Result<Vec<Vec<Option<&'a Value>>>, String> {
+    let paths = fields
+        .iter()
+        .map(key_paths)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut values = vec![Vec::with_capacity(records.len()); fields.len()];
+    for (row, record) in records.iter().enumerate() {
+        for ((field, paths), values) in fields.iter().zip(&paths).zip(values.iter_mut()) {
+            values.push(field_value(field, paths, row, record, options)?);
+        }
+    }
+    Ok(values)
+}
+
+/// Look up the value of `field` in a record, applying any validation declared on the field
+fn field_value<'a>(
+    field: &'a ParquetField,
+    paths: &[Vec<String>],
+    row: usize,
+    record: &'a Record,
+    options: &WriterOptions,
+) -> Result<Option<&'a Value>, String> {
+    let value = lookup(record, paths, options.case_insensitive_keys).filter(|v| !v.is_null());
+    validate_value(field, row, value, options)
+}
+
+/// Apply the validation declared on `field` to its value in a row, `None` when the value is
+/// missing or null
+fn validate_value<'a>(
+    field: &'a ParquetField,
+    row: usize,
+    value: Option<&'a Value>,
+    options: &WriterOptions,
+) -> Result<Option<&'a Value>, String> {
+    let value = match (value, field.on_null) {
+        (None, NullPolicy::Default) => field.default.as_ref(),
+        (None, NullPolicy::Error) => {
+            return Err(format!("Row {}: null value for field {}", row, field.name))
+        }
+        _ => value,
+    };
+    match value {
+        Some(v) if !is_allowed_value(field, v) => match field.on_invalid {
+            InvalidValuePolicy::Null if is_nullable(field) => Ok(None),
+            _ => Err(format!(
+                "Row {}: value {} for field {} is not one of the allowed values",
+                row, v, field.name
+            )),
+        },
+        Some(v) if is_non_finite(field, v, &options.coercion) => match options.non_finite {
+            NonFinitePolicy::Write => Ok(value),
+            NonFinitePolicy::Null if is_nullable(field) => Ok(None),
+            _ => Err(format!(
+                "Row {}: non-finite value {} for field {}",
+                row, v, field.name
+            )),
+        },
+        Some(v) => match out_of_range(field, v, &options.coercion) {
+            Some(range) => match field.on_overflow {
+                OverflowPolicy::Clamp => Ok(value),
+                OverflowPolicy::Null if is_nullable(field) => Ok(None),
+                _ => Err(format!(
+                    "Row {}: value {} for field {} is out of range {}",
+                    row, v, field.name, range
+                )),
+            },
+            None => validate_format(field, row, v).map(|_| value),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Check strings for fields whose logical type gives them a format, so a malformed value is
+/// reported rather than treated like a missing one
+fn validate_format(field: &ParquetField, row: usize, value: &Value) -> Result<(), String> {
+    match (&field.logical_type, value) {
+        (Some(ParquetLogicalType::Date), Value::String(s)) if convert::parse_date(s).is_none() => {
+            Err(format!(
+                "Row {}: invalid date {} for field {}, expected YYYY-MM-DD",
+                row, value, field.name
+            ))
+        }
+        (Some(ParquetLogicalType::Decimal), Value::String(_) | Value::Number(_))
+            if convert::to_decimal(field, value).is_none() =>
+        {
+            Err(format!(
+                "Row {}: decimal {} for field {} doesn't fit DECIMAL({}, {})",
+                row,
+                value,
+                field.name,
+                field.precision.unwrap_or(0),
+                field.scale.unwrap_or(0)
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The key paths a field may be read from, `source_key` (or the name) then any aliases
+pub(crate) fn key_paths(field: &ParquetField) -> Result<Vec<Vec<String>>, String> {
+    let mut paths = vec![key_path(field, field.source_key.as_ref())?];
+    for alias in field.aliases.iter().flatten() {
+        paths.push(key_path(field, Some(alias))?);
+    }
+    Ok(paths)
+}
+
+/// Find the first of `paths` present in the record, exact matches take priority over matches
+/// that only differ in case
+pub(crate) fn lookup<'a>(
+    record: &'a Record,
+    paths: &[Vec<String>],
+    case_insensitive: bool,
+) -> Option<&'a Value> {
+    let exact = paths
+        .iter()
+        .find_map(|path| lookup_path(record, path, false));
+    if exact.is_some() || !case_insensitive {
+        return exact;
+    }
+    paths
+        .iter()
+        .find_map(|path| lookup_path(record, path, true))
+}
+
+/// Walk nested objects following `path`, a missing key or a non-object along the way is `None`
+pub(crate) fn lookup_path<'a>(
+    record: &'a Record,
+    path: &[String],
+    case_insensitive: bool,
+) -> Option<&'a Value> {
+    let (last, parents) = path.split_last()?;
+    let mut object = record;
+    for key in parents {
+        object = get(object, key, case_insensitive)?.as_object()?;
+    }
+    get(object, last, case_insensitive)
+}
+
+fn get<'a>(object: &'a Record, key: &str, case_insensitive: bool) -> Option<&'a Value> {
+    if !case_insensitive {
+        return object.get(key);
+    }
+    object
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v)
+}
+
+/// The path of JSON keys a field is read from. `source_key` (or an alias) is split on dots to
+/// reach into nested objects, with `\.` and `\\` escaping literal dots and backslashes in keys
+fn key_path(field: &ParquetField, source_key: Option<&String>) -> Result<Vec<String>, String> {
+    match source_key {
+        Some(source_key) => split_key_path(source_key)
+            .ok_or_else(|| format!("Invalid source_key {} for field {}", source_key, field.name)),
+        None => Ok(vec![field.name.clone()]),
+    }
+}
+
+/// Split a key on unescaped dots, `None` when it has an empty segment or a bad escape
+pub(crate) fn split_key_path(key: &str) -> Option<Vec<String>> {
+    let mut path = vec![];
+    let mut segment = String::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('.' | '\\')) => segment.push(escaped),
+                _ => return None,
+            },
+            '.' if segment.is_empty() => return None,
+            '.' => path.push(std::mem::take(&mut segment)),
+            _ => segment.push(c),
+        }
+    }
+    if segment.is_empty() {
+        return None;
+    }
+    path.push(segment);
+    Some(path)
+}
+
+/// Whether an ENUM value is in the field's `allowed_values`, every element of a REPEATED
+/// field's array must be allowed
+fn is_allowed_value(field: &ParquetField, value: &Value) -> bool {
+    match (&field.logical_type, &field.allowed_values, value) {
+        (Some(ParquetLogicalType::Enum), Some(_), Value::Array(elements))
+            if field.repetition_type == Some(ParquetRepetition::Repeated) =>
+        {
+            elements.iter().all(|e| is_allowed_value(field, e))
+        }
+        (Some(ParquetLogicalType::Enum), Some(allowed_values), _) => value
+            .as_str()
+            .is_some_and(|s| allowed_values.iter().any(|a| a == s)),
+        _ => true,
+    }
+}
+
+/// The range of a numeric field, formatted for errors, when its value or any element or entry of
+/// it falls outside it
+fn out_of_range(field: &ParquetField, value: &Value, coercion: &CoercionOptions) -> Option<String> {
+    let nested = crate::nested_leaf(field);
+    let leaf = nested.as_ref().unwrap_or(field);
+    if let Some((min, max)) = convert::int_range(leaf) {
+        return any_leaf_value(field, value, |v| {
+            convert::to_wide_int(leaf, v, coercion).is_some_and(|i| i < min || i > max)
+        })
+        .then(|| format!("[{}, {}]", min, max));
+    }
+    if matches!(leaf.primitive_type, ParquetPrimitiveType::Float) {
+        return any_leaf_value(field, value, |v| {
+            convert::to_float(v, coercion)
+                .is_some_and(|f| f.is_finite() && f.abs() > f32::MAX.into())
+        })
+        .then(|| format!("[{:e}, {:e}]", f32::MIN, f32::MAX));
+    }
+    None
+}
+
+/// Whether a FLOAT or DOUBLE field's value, or any element or entry of it, is NaN or infinite
+fn is_non_finite(field: &ParquetField, value: &Value, coercion: &CoercionOptions) -> bool {
+    matches!(
+        field.primitive_type,
+        ParquetPrimitiveType::Float | ParquetPrimitiveType::Double
+    ) && any_leaf_value(field, value, |v| {
+        convert::to_float(v, coercion).is_some_and(|f| !f.is_finite())
+    })
+}
+
+/// Whether `check` holds for a value, or for any element of a REPEATED or LIST field's array or
+/// any value of a MAP field's object
+fn any_leaf_value(field: &ParquetField, value: &Value, check: impl Fn(&Value) -> bool) -> bool {
+    let nested = matches!(
+        field.logical_type,
+        Some(ParquetLogicalType::List | ParquetLogicalType::Map)
+    );
+    match value {
+        Value::Array(elements)
+            if nested || field.repetition_type == Some(ParquetRepetition::Repeated) =>
+        {
+            elements.iter().any(check)
+        }
+        Value::Object(entries) if nested => entries.values().any(check),
+        _ => check(value),
+    }
+}
+
+/// An integer value of a field, clamped to the field's range
+fn clamp_int(field: &ParquetField, value: &Value, coercion: &CoercionOptions) -> Option<i64> {
+    let (min, max) = convert::int_range(field)?;
+    convert::to_wide_int(field, value, coercion).map(|i| i.clamp(min, max) as i64)
+}
+
+pub(crate) fn is_optional(field: &ParquetField) -> bool {
+    field.repetition_type == Some(ParquetRepetition::Optional)
+}
+
+/// OPTIONAL fields can be written as null, REPEATED fields as an empty list
+fn is_nullable(field: &ParquetField) -> bool {
+    field
+        .repetition_type
+        .is_some_and(|r| r != ParquetRepetition::Required)
+}
+
+/// The physical values of a column along with their levels, levels are `None` when the field's
+/// maximum level is 0
+pub(crate) struct ColumnValues<T> {
+    pub(crate) values: Vec<T>,
+    pub(crate) def_levels: Option<Vec<i16>>,
+    pub(crate) rep_levels: Option<Vec<i16>>,
+}
+
+/// Convert the values of a column into their physical representation. Nulls (and values that
+/// can't be converted, unless writing strictly) are only allowed in OPTIONAL fields, where they
+/// are recorded with a definition level of 0
+fn collect_values<T>(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+    convert: impl Fn(&Value) -> Option<T>,
+) -> Result<ColumnValues<T>, WriteError> {
+    if field.repetition_type == Some(ParquetRepetition::Repeated) {
+        return collect_repeated_values(field, values, options, convert);
+    }
+    if field.logical_type == Some(ParquetLogicalType::List) {
+        return collect_list_values(field, values, options, convert);
+    }
+    if field.logical_type == Some(ParquetLogicalType::Map) {
+        return collect_map_values(field, values, options, 2, |_, v| convert(v));
+    }
+
+    let optional = is_optional(field);
+    let mut converted = Vec::with_capacity(values.len());
+    let mut def_levels = Vec::with_capacity(values.len());
+
+    for (row, value) in values.iter().enumerate() {
+        match value.and_then(&convert) {
+            Some(v) => {
+                converted.push(v);
+                def_levels.push(1);
+            }
+            None if optional && !(options.strict && value.is_some()) => def_levels.push(0),
+            None if options.strict => {
+                return Err(value_error(field, row, expected_type(field), *value))
+            }
+            None => {
+                return Err(WriteError::from(format!(
+                    "Row {}: missing or invalid value for required field {}",
+                    row, field.name
+                )))
+            }
+        }
+    }
+
+    Ok(ColumnValues {
+        values: converted,
+        def_levels: optional.then_some(def_levels),
+        rep_levels: None,
+    })
+}
+
+/// Convert the JSON arrays of a REPEATED field into their elements. The first element of each
+/// record starts a new row with a repetition level of 0, and a missing, null or empty array is
+/// written as an empty list with a definition level of 0
+fn collect_repeated_values<T>(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+    convert: impl Fn(&Value) -> Option<T>,
+) -> Result<ColumnValues<T>, WriteError> {
+    let mut converted = Vec::with_capacity(values.len());
+    let mut def_levels = Vec::with_capacity(values.len());
+    let mut rep_levels = Vec::with_capacity(values.len());
+
+    for (row, value) in values.iter().enumerate() {
+        let elements = match value {
+            Some(Value::Array(elements)) => elements.as_slice(),
+            Some(_) if options.strict => {
+                let expected = format!("array of {}", expected_type(field));
+                return Err(value_error(field, row, expected, *value));
+            }
+            Some(_) => {
+                return Err(WriteError::from(format!(
+                    "Row {}: value for repeated field {} is not an array",
+                    row, field.name
+                )))
+            }
+            None => &[],
+        };
+        if elements.is_empty() {
+            def_levels.push(0);
+            rep_levels.push(0);
+        }
+        for (i, element) in elements.iter().enumerate() {
+            match convert(element) {
+                Some(v) => converted.push(v),
+                None if options.strict => {
+                    return Err(value_error(field, row, expected_type(field), Some(element)))
+                }
+                None => {
+                    return Err(WriteError::from(format!(
+                        "Row {}: invalid element {} for repeated field {}",
+                        row, i, field.name
+                    )))
+                }
+            }
+            def_levels.push(1);
+            rep_levels.push(if i == 0 { 0 } else { 1 });
+        }
+    }
+
+    Ok(ColumnValues {
+        values: converted,
+        def_levels: Some(def_levels),
+        rep_levels: Some(rep_levels),
+    })
+}
+
+/// Convert the JSON arrays of a LIST field into their elements. A null list, only allowed when the
+/// field is OPTIONAL, has a definition level of 0, an empty list the next level and null elements
+/// the one after that
+fn collect_list_values<T>(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+    convert: impl Fn(&Value) -> Option<T>,
+) -> Result<ColumnValues<T>, WriteError> {
+    let optional = is_optional(field);
+    let empty_level = i16::from(optional);
+    let mut converted = Vec::with_capacity(values.len());
+    let mut def_levels = Vec::with_capacity(values.len());
+    let mut rep_levels = Vec::with_capacity(values.len());
+
+    for (row, value) in values.iter().enumerate() {
+        let elements = match value {
+            Some(Value::Array(elements)) => elements,
+            None if optional => {
+                def_levels.push(0);
+                rep_levels.push(0);
+                continue;
+            }
+            _ if options.strict => {
+                let expected = format!("array of {}", expected_type(field));
+                return Err(value_error(field, row, expected, *value));
+            }
+            Some(_) => {
+                return Err(WriteError::from(format!(
+                    "Row {}: value for LIST field {} is not an array",
+                    row, field.name
+                )))
+            }
+            None => {
+                return Err(WriteError::from(format!(
+                    "Row {}: missing or invalid value for required field {}",
+                    row, field.name
+                )))
+            }
+        };
+        if elements.is_empty() {
+            def_levels.push(empty_level);
+            rep_levels.push(0);
+        }
+        for (i, element) in elements.iter().enumerate() {
+            match convert(element) {
+                Some(v) => {
+                    converted.push(v);
+                    def_levels.push(empty_level + 2);
+                }
+                None if element.is_null() => def_levels.push(empty_level + 1),
+                None if options.strict => {
+                    return Err(value_error(field, row, expected_type(field), Some(element)))
+                }
+                None => {
+                    return Err(WriteError::from(format!(
+                        "Row {}: invalid element {} for LIST field {}",
+                        row, i, field.name
+                    )))
+                }
+            }
+            rep_levels.push(if i == 0 { 0 } else { 1 });
+        }
+    }
+
+    Ok(ColumnValues {
+        values: converted,
+        def_levels: Some(def_levels),
+        rep_levels: Some(rep_levels),
+    })
+}
+
+/// Convert the JSON objects of a MAP field into one of the columns of its entries, in the order
+/// they appear in the object. Levels follow LIST fields, with `entry_level` the definition level
+/// of an entry relative to an empty map: 1 for keys, which are REQUIRED, and 2 for values, whose
+/// nulls are written one level below
+fn collect_map_values<T>(
+    field: &ParquetField,
+    values: &[Option<&Value>],
+    options: &WriterOptions,
+    entry_level: i16,
+    convert: impl Fn(&str, &Value) -> Option<T>,
+) -> Result<ColumnValues<T>, WriteError> {
+    let optional = is_optional(field);
+    let empty_level = i16::from(optional);
+    let mut converted = Vec::with_capacity(values.len());
+    let mut def_levels = Vec::with_capacity(values.len());
+    let mut rep_levels = Vec::with_capacity(values.len());
+
+    for (row, value) in values.iter().enumerate() {
+        let entries = match value {
+            Some(Value::Object(entries)) => entries,
+            None if optional => {
+                def_levels.push(0);
+                rep_levels.push(0);
+                continue;
+            }
+            _ if options.strict => {
+                let expected = format!("object of {}", expected_type(field));
+                return Err(value_error(field, row, expected, *value));
+            }
+            Some(_) => {
+                return Err(WriteError::from(format!(
+                    "Row {}: value for MAP field {} is not an object",
+                    row, field.name
+                )))
+            }
+            None => {
+                return Err(WriteError::from(format!(
+                    "Row {}: missing or invalid value for required field {}",
+                    row, field.name
+                )))
+            }
+        };
+        if entries.is_empty() {
+            def_levels.push(empty_level);
+            rep_levels.push(0);
+        }
+        for (i, (key, entry)) in entries.iter().enumerate() {
+            match convert(key, entry) {
+                Some(v) => {
+                    converted.push(v);
+                    def_levels.push(empty_level + entry_level);
+                }
+                None if entry.is_null() => def_levels.push(empty_level + 1),
+                None if options.strict => {
+                    return Err(value_error(field, row, expected_type(field), Some(entry)))
+                }
+                None => {
+                    return Err(WriteError::from(format!(
+                        "Row {}: invalid value for key {} of MAP field {}",
+                        row, key, field.name
+                    )))
+                }
+            }
+            rep_levels.push(if i == 0 { 0 } else { 1 });
+        }
+    }
+
+    Ok(ColumnValues {
+        values: converted,
+        def_levels: Some(def_levels),
+        rep_levels: Some(rep_levels),
+    })
+}
+
+/// The name of a field's physical type, as it's written in the schema
+fn expected_type(field: &ParquetField) -> String {
+    match serde_json::to_value(field.primitive_type) {
+        Ok(Value::String(name)) => name,
+        _ => format!("{:?}", field.primitive_type),
+    }
+}
+
+fn value_error(
+    field: &ParquetField,
+    row: usize,
+    expected: String,
+    actual: Option<&Value>,
+) -> WriteError {
+    WriteError::Value(Box::new(ValueError {
+        row,
+        field: field.name.clone(),
+        expected,
+        actual: actual.cloned().unwrap_or(Value::Null),
+    }))
+}
+
+fn write_batch<T: DataType>(
+    col_writer: &mut SerializedColumnWriter,
+    column: ColumnValues<T::T>,
+) -> Result<(), WriteError> {
+    match col_writer.typed::<T>().write_batch(
+        &column.values,
+        column.def_levels.as_deref(),
+        column.rep_levels.as_deref(),
+    ) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(WriteError::from("Error writing column".to_string())),
+    }
+}
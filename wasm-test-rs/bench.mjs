@@ -0,0 +1,22 @@
+// Runs run_benchmarks under node, which unlike Cloudflare Workers lets the clock advance while
+// code runs. Build the package for node first with
+//   wasm-pack build wasm-test-rs/ --release --target nodejs --out-dir target/bench-pkg
+// then pass an optional JSON object of options, e.g.
+//   node wasm-test-rs/bench.mjs '{"sizes": [1000, 100000], "datasets": ["strings"]}'
+import { createRequire } from 'node:module';
+
+const require = createRequire(import.meta.url);
+const { run_benchmarks } = require('./target/bench-pkg/wasm_test_rs.js');
+
+const results = run_benchmarks(process.argv[2]);
+console.table(
+  results.map((r) => ({
+    dataset: r.dataset,
+    rows: r.rows,
+    config: r.config,
+    ms: r.millis.toFixed(1),
+    'rows/s': Math.round(r.rows_per_sec),
+    'MB/s': (r.bytes_per_sec / 1e6).toFixed(1),
+    'output bytes': r.output_bytes,
+  })),
+);
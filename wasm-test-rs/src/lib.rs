@@ -1,7 +1,232 @@
+use parquet_generator::generate_parquet_with_options;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use wasm_bindgen::prelude::wasm_bindgen;
-use wasm_bindgen::JsValue;
+use wasm_bindgen::{JsCast, JsValue};
 
 #[wasm_bindgen]
 pub fn wasm_test() -> Result<JsValue, JsValue> {
     Ok(JsValue::from("Hello from Rust!".to_string()))
 }
+
+/// The numbers of rows each dataset is generated with, unless the caller picks sizes
+const DEFAULT_SIZES: [usize; 1] = [1_000];
+
+/// The number of rows of each dataset written before any are timed
+const WARM_UP_ROWS: usize = 1_000;
+
+/// A synthetic dataset, a schema and a function generating its record for a row
+struct Dataset {
+    name: &'static str,
+    schema: Value,
+    record: fn(usize) -> Value,
+}
+
+/// What `run_benchmarks` runs, every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct BenchmarkOptions {
+    /// The numbers of rows each dataset is generated with
+    sizes: Option<Vec<usize>>,
+    /// The names of the datasets to write, by default all of them
+    datasets: Option<Vec<String>>,
+    /// Writer options to benchmark instead of the defaults
+    configs: Option<Vec<Config>>,
+}
+
+/// Writer options benchmarked against every dataset, under a name for the results
+#[derive(Debug, Deserialize)]
+struct Config {
+    name: String,
+    options: Value,
+}
+
+/// The time taken to write one dataset at one size with one configuration
+#[derive(Debug, Serialize)]
+struct BenchmarkResult {
+    dataset: &'static str,
+    rows: usize,
+    config: String,
+    millis: f64,
+    rows_per_sec: f64,
+    /// The records' size as JSON written per second
+    bytes_per_sec: f64,
+    /// The size of the parquet file written
+    output_bytes: usize,
+}
+
+fn datasets() -> Vec<Dataset> {
+    vec![
+        Dataset {
+            name: "numeric",
+            schema: json!({"fields": [
+                {"name": "id", "type": "INT64"},
+                {"name": "count", "type": "INT32"},
+                {"name": "ratio", "type": "DOUBLE"},
+                {"name": "flag", "type": "BOOLEAN"}
+            ]}),
+            record: |row| {
+                json!({
+                    "id": row,
+                    "count": row % 1_000,
+                    "ratio": row as f64 / 7.0,
+                    "flag": row % 3 == 0
+                })
+            },
+        },
+        Dataset {
+            name: "strings",
+            schema: json!({"fields": [
+                {"name": "level", "type": "BYTE_ARRAY", "logical_type": "UTF8"},
+                {"name": "message", "type": "BYTE_ARRAY", "logical_type": "UTF8"},
+                {"name": "host", "type": "BYTE_ARRAY", "logical_type": "UTF8"}
+            ]}),
+            record: |row| {
+                let level = ["DEBUG", "INFO", "WARN", "ERROR"][row % 4];
+                json!({
+                    "level": level,
+                    "message": format!("request {} served in {}ms", row, row % 250),
+                    "host": format!("host-{}", row % 32)
+                })
+            },
+        },
+        Dataset {
+            name: "lists",
+            schema: json!({"fields": [
+                {"name": "id", "type": "INT64"},
+                {
+                    "name": "tags",
+                    "type": "BYTE_ARRAY",
+                    "logical_type": "LIST",
+                    "element_logical_type": "UTF8"
+                },
+                {"name": "scores", "type": "INT32", "logical_type": "LIST"}
+            ]}),
+            record: |row| {
+                json!({
+                    "id": row,
+                    "tags": ["api", format!("shard-{}", row % 16)],
+                    "scores": [row % 10, row % 100, row % 1_000]
+                })
+            },
+        },
+    ]
+}
+
+fn default_configs() -> Vec<Config> {
+    [
+        ("default", json!({})),
+        (
+            "row groups of 10000",
+            json!({"max_rows_per_row_group": 10_000}),
+        ),
+        ("gzip", json!({"compression": "GZIP"})),
+        ("no dictionary", json!({"dictionary_enabled": false})),
+        ("v2 data pages", json!({"writer_version": "2.0"})),
+    ]
+    .into_iter()
+    .map(|(name, options)| Config {
+        name: name.to_string(),
+        options,
+    })
+    .collect()
+}
+
+/// The current time in milliseconds from `performance.now()`, or `Date.now()` where there's no
+/// `performance`
+fn now() -> f64 {
+    let performance = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("performance"));
+    match performance.ok().filter(|p| p.is_object()) {
+        Some(performance) => js_sys::Reflect::get(&performance, &JsValue::from_str("now"))
+            .ok()
+            .and_then(|now| now.dyn_into::<js_sys::Function>().ok())
+            .and_then(|now| now.call0(&performance).ok())
+            .and_then(|millis| millis.as_f64())
+            .unwrap_or_else(js_sys::Date::now),
+        None => js_sys::Date::now(),
+    }
+}
+
+/// Write synthetic datasets of numbers, strings and lists with each configuration of codec,
+/// batching and encoding settings, returning an array of `{dataset, rows, config, millis,
+/// rows_per_sec, bytes_per_sec, output_bytes}` to compare settings on this hardware with.
+///
+/// Run it under node or in a browser. Cloudflare Workers stop the clock while code runs, so every
+/// write would take no time there, and the benchmarks fail rather than report that.
+///
+/// # Arguments
+///
+/// * `options`: An optional JSON object of what to run, e.g.
+///   `{"sizes": [1000, 100000], "datasets": ["strings"], "configs": [{"name": "gzip", "options":
+///   {"compression": "GZIP"}}]}`. By default every dataset is written at 1,000 rows with the
+///   default configs
+#[wasm_bindgen]
+pub fn run_benchmarks(options: Option<String>) -> Result<JsValue, JsValue> {
+    let options = match options {
+        Some(options) => serde_json::from_str::<BenchmarkOptions>(&options)
+            .map_err(|e| JsValue::from_str(&format!("Error parsing options: {}", e)))?,
+        None => BenchmarkOptions::default(),
+    };
+    let sizes = options.sizes.unwrap_or_else(|| DEFAULT_SIZES.to_vec());
+    let configs = options.configs.unwrap_or_else(default_configs);
+    let mut datasets = datasets();
+    if let Some(names) = &options.datasets {
+        if let Some(name) = names
+            .iter()
+            .find(|n| !datasets.iter().any(|d| d.name == **n))
+        {
+            return Err(JsValue::from_str(&format!("Unknown dataset {}", name)));
+        }
+        datasets.retain(|dataset| names.iter().any(|name| name == dataset.name));
+    }
+
+    // The first writes run before the engine has optimised the module, so they're left untimed
+    for dataset in datasets.iter() {
+        let files = (0..WARM_UP_ROWS)
+            .map(|row| (dataset.record)(row).to_string())
+            .collect();
+        generate_parquet_with_options(dataset.schema.to_string(), files, "{}".to_string())?;
+    }
+
+    let mut results = vec![];
+    for dataset in datasets {
+        for &rows in &sizes {
+            let files: Vec<String> = (0..rows)
+                .map(|row| (dataset.record)(row).to_string())
+                .collect();
+            let input_bytes: usize = files.iter().map(|file| file.len()).sum();
+            for config in configs.iter() {
+                // The write takes its input by value, so it's copied before the clock starts
+                let input = files.clone();
+                let start = now();
+                let output = generate_parquet_with_options(
+                    dataset.schema.to_string(),
+                    input,
+                    config.options.to_string(),
+                )?;
+                let millis = now() - start;
+                if millis <= 0.0 {
+                    return Err(JsValue::from_str(
+                        "The clock didn't advance during a write, as in Cloudflare Workers, run \
+                         the benchmarks under node or in a browser",
+                    ));
+                }
+                let seconds = millis / 1_000.0;
+                results.push(BenchmarkResult {
+                    dataset: dataset.name,
+                    rows,
+                    config: config.name.clone(),
+                    millis,
+                    rows_per_sec: rows as f64 / seconds,
+                    bytes_per_sec: input_bytes as f64 / seconds,
+                    output_bytes: output.0.len(),
+                });
+            }
+        }
+    }
+
+    match serde_json::to_string(&results) {
+        Ok(json) => js_sys::JSON::parse(&json),
+        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    }
+}